@@ -0,0 +1,1042 @@
+use crate::eds::parse_integer;
+use crate::measurement::ValueType;
+use std::collections::HashMap;
+use std::path::Path;
+
+// CAN_ID_MASTER / CAN_ID_SLAVE 最高位元表示 29 位元 ID
+const CAN_ID_EXTENDED: u32 = 0x8000_0000;
+
+/// 詞元：一般字詞或雙引號字串
+struct Token {
+    text: String,
+    quoted: bool,
+    line: usize,
+}
+
+enum Item {
+    Word(String),
+    Block(Block),
+}
+
+/// `/begin KIND ... /end KIND` 區塊
+struct Block {
+    kind: String,
+    items: Vec<Item>,
+}
+
+impl Block {
+    fn words(&self) -> Vec<&str> {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Word(word) => Some(word.as_str()),
+                Item::Block(_) => None,
+            })
+            .collect()
+    }
+
+    fn blocks<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Block> + 'a {
+        self.items.iter().filter_map(move |item| match item {
+            Item::Block(block) if block.kind == kind => Some(block),
+            _ => None,
+        })
+    }
+
+    /// 遞迴找出所有指定種類的區塊
+    fn find<'a>(&'a self, kind: &str, found: &mut Vec<&'a Block>) {
+        for item in &self.items {
+            if let Item::Block(block) = item {
+                if block.kind == kind {
+                    found.push(block);
+                }
+                block.find(kind, found);
+            }
+        }
+    }
+
+    /// 跳過前面 `fixed` 個必要參數後，找關鍵字後面的參數
+    fn keyword(&self, fixed: usize, key: &str) -> Option<Vec<&str>> {
+        let words = self.words();
+        let position = words.iter().skip(fixed).position(|w| *w == key)?;
+        Some(words[fixed + position + 1..].to_vec())
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'*') => {
+                let start = line;
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            previous = c;
+                        }
+                        None => return Err(format!("第 {} 行: 註解沒有結束", start)),
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let start = line;
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // "" 與 \" 都是字串裡的引號
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            text.push('"');
+                        }
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some(c) => text.push(c),
+                            None => {}
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            text.push(c);
+                        }
+                        None => return Err(format!("第 {} 行: 字串沒有結束", start)),
+                    }
+                }
+                tokens.push(Token {
+                    text,
+                    quoted: true,
+                    line: start,
+                });
+            }
+            c => {
+                let mut text = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '"' {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                tokens.push(Token {
+                    text,
+                    quoted: false,
+                    line,
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_items(
+    tokens: &[Token],
+    position: &mut usize,
+    kind: Option<&Token>,
+) -> Result<Vec<Item>, String> {
+    let mut items = Vec::new();
+    while let Some(token) = tokens.get(*position) {
+        *position += 1;
+        if token.quoted {
+            items.push(Item::Word(token.text.clone()));
+            continue;
+        }
+        match token.text.as_str() {
+            "/begin" => {
+                let Some(name) = tokens.get(*position) else {
+                    return Err(format!("第 {} 行: /begin 後面缺少區塊名稱", token.line));
+                };
+                *position += 1;
+                let children = parse_items(tokens, position, Some(name))?;
+                items.push(Item::Block(Block {
+                    kind: name.text.clone(),
+                    items: children,
+                }));
+            }
+            "/end" => {
+                let name = tokens.get(*position).map(|t| t.text.as_str());
+                *position += 1;
+                return match kind {
+                    Some(begin) if name == Some(begin.text.as_str()) => Ok(items),
+                    Some(begin) => Err(format!(
+                        "第 {} 行: /end {} 與第 {} 行的 /begin {} 不符",
+                        token.line,
+                        name.unwrap_or(""),
+                        begin.line,
+                        begin.text
+                    )),
+                    None => Err(format!("第 {} 行: 多餘的 /end", token.line)),
+                };
+            }
+            word => items.push(Item::Word(word.to_string())),
+        }
+    }
+    match kind {
+        Some(begin) => Err(format!(
+            "第 {} 行: /begin {} 沒有對應的 /end",
+            begin.line, begin.text
+        )),
+        None => Ok(items),
+    }
+}
+
+fn number(text: &str) -> Option<f64> {
+    parse_integer(text)
+        .map(|v| v as f64)
+        .or_else(|| text.parse::<f64>().ok())
+}
+
+fn value_type(name: &str) -> Option<ValueType> {
+    Some(match name {
+        "UBYTE" => ValueType::U8,
+        "SBYTE" => ValueType::I8,
+        "UWORD" => ValueType::U16,
+        "SWORD" => ValueType::I16,
+        "ULONG" => ValueType::U32,
+        "SLONG" => ValueType::I32,
+        "A_UINT64" => ValueType::U64,
+        "A_INT64" => ValueType::I64,
+        "FLOAT32_IEEE" => ValueType::F32,
+        "FLOAT64_IEEE" => ValueType::F64,
+        _ => return None,
+    })
+}
+
+/// 原始值與物理值的轉換
+#[derive(Debug, Clone)]
+pub enum Conversion {
+    Identical,
+    // 物理值 = a * 原始值 + b
+    Linear(f64, f64),
+    // 原始值 = (a x² + b x + c) / (d x² + e x + f)，x 是物理值
+    Rational([f64; 6]),
+    // (原始值, 物理值)，true 表示內插
+    Table(Vec<(f64, f64)>, bool),
+    Verbal(Vec<(f64, String)>, Option<String>),
+}
+
+/// COMPU_METHOD
+#[derive(Debug, Clone)]
+pub struct CompuMethod {
+    pub conversion: Conversion,
+    pub unit: String,
+    // FORMAT "%8.3" 的小數位數
+    pub decimals: Option<usize>,
+}
+
+impl Default for CompuMethod {
+    fn default() -> Self {
+        Self {
+            conversion: Conversion::Identical,
+            unit: String::new(),
+            decimals: None,
+        }
+    }
+}
+
+fn interpolate(points: &[(f64, f64)], x: f64, interpolate: bool) -> f64 {
+    let Some(first) = points.first() else {
+        return x;
+    };
+    if x <= first.0 {
+        return first.1;
+    }
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if x < x1 {
+            return if interpolate && x1 != x0 {
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            } else {
+                y0
+            };
+        }
+    }
+    points.last().unwrap().1
+}
+
+impl CompuMethod {
+    pub fn to_physical(&self, raw: f64) -> f64 {
+        match &self.conversion {
+            Conversion::Identical | Conversion::Verbal(..) => raw,
+            Conversion::Linear(a, b) => a * raw + b,
+            // 解析時已確認 a = d = 0，反函數是線性分式
+            Conversion::Rational([_, b, c, _, e, f]) => {
+                let denominator = e * raw - b;
+                if denominator == 0.0 {
+                    f64::NAN
+                } else {
+                    (c - f * raw) / denominator
+                }
+            }
+            Conversion::Table(points, interpolated) => interpolate(points, raw, *interpolated),
+        }
+    }
+
+    pub fn to_raw(&self, physical: f64) -> f64 {
+        match &self.conversion {
+            Conversion::Identical | Conversion::Verbal(..) => physical,
+            Conversion::Linear(a, b) => (physical - b) / a,
+            Conversion::Rational([a, b, c, d, e, f]) => {
+                let x = physical;
+                (a * x * x + b * x + c) / (d * x * x + e * x + f)
+            }
+            Conversion::Table(points, interpolated) => {
+                let mut inverse: Vec<(f64, f64)> = points.iter().map(|&(r, p)| (p, r)).collect();
+                inverse.sort_by(|a, b| a.0.total_cmp(&b.0));
+                interpolate(&inverse, physical, *interpolated)
+            }
+        }
+    }
+
+    pub fn format(&self, raw: f64) -> String {
+        if let Conversion::Verbal(table, default) = &self.conversion {
+            return match table.iter().find(|(value, _)| *value == raw) {
+                Some((_, text)) => text.clone(),
+                None => default.clone().unwrap_or_else(|| raw.to_string()),
+            };
+        }
+        let physical = self.to_physical(raw);
+        let text = match self.decimals {
+            Some(decimals) => format!("{:.*}", decimals, physical),
+            None => physical.to_string(),
+        };
+        if self.unit.is_empty() {
+            text
+        } else {
+            format!("{} {}", text, self.unit)
+        }
+    }
+
+    /// 使用者輸入的物理值 (或文字表的文字) 轉回原始值
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        let text = text.trim();
+        if let Conversion::Verbal(table, _) = &self.conversion {
+            if let Some((value, _)) = table.iter().find(|(_, t)| t == text) {
+                return Some(*value);
+            }
+        }
+        let physical = text
+            .strip_suffix(self.unit.as_str())
+            .filter(|_| !self.unit.is_empty())
+            .unwrap_or(text)
+            .trim()
+            .parse::<f64>()
+            .ok()?;
+        Some(self.to_raw(physical))
+    }
+}
+
+/// MEASUREMENT
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub name: String,
+    pub description: String,
+    pub value_type: ValueType,
+    pub conversion: String,
+    pub address: u32,
+    pub extension: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacteristicKind {
+    Value,
+    ValBlk,
+    Curve,
+    Map,
+}
+
+/// AXIS_DESCR
+#[derive(Debug, Clone)]
+pub struct AxisDescr {
+    pub attribute: String,
+    pub conversion: String,
+    pub max_points: usize,
+    // FIX_AXIS 的軸點
+    pub fixed: Option<Vec<f64>>,
+}
+
+/// CHARACTERISTIC
+#[derive(Debug, Clone)]
+pub struct Characteristic {
+    pub name: String,
+    pub description: String,
+    pub kind: CharacteristicKind,
+    pub address: u32,
+    pub extension: u8,
+    pub deposit: String,
+    pub conversion: String,
+    pub lower: f64,
+    pub upper: f64,
+    pub axes: Vec<AxisDescr>,
+    // VAL_BLK 的元素數
+    pub number: usize,
+}
+
+/// RECORD_LAYOUT 中一個項目的 (位置, 型別)
+#[derive(Debug, Clone, Copy)]
+struct LayoutItem {
+    position: u32,
+    value_type: ValueType,
+}
+
+/// RECORD_LAYOUT (只取 FNC_VALUES、AXIS_PTS_X/Y 與 NO_AXIS_PTS_X/Y)
+#[derive(Debug, Clone, Default)]
+pub struct RecordLayout {
+    fnc_values: Option<LayoutItem>,
+    column_dir: bool,
+    axis_pts: [Option<LayoutItem>; 2],
+    no_axis_pts: [Option<LayoutItem>; 2],
+}
+
+/// 標定參數在記憶體中的排列，位移相對於參數位址
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub value_type: ValueType,
+    pub dims: [usize; 2],
+    pub size: usize,
+    values: usize,
+    column_dir: bool,
+    axes: [Option<(usize, ValueType)>; 2],
+    counts: [Option<(usize, ValueType)>; 2],
+}
+
+impl Layout {
+    pub fn value_offset(&self, x: usize, y: usize) -> usize {
+        let index = if self.column_dir {
+            x * self.dims[1] + y
+        } else {
+            y * self.dims[0] + x
+        };
+        self.values + index * self.value_type.size()
+    }
+
+    pub fn value(&self, data: &[u8], x: usize, y: usize, big_endian: bool) -> Option<f64> {
+        let offset = self.value_offset(x, y);
+        let bytes = data.get(offset..offset + self.value_type.size())?;
+        Some(self.value_type.decode(bytes, big_endian))
+    }
+
+    /// 記憶體中的實際軸點數 (NO_AXIS_PTS)，沒有時為最大值
+    pub fn count(&self, data: &[u8], axis: usize, big_endian: bool) -> usize {
+        let max = self.dims[axis];
+        match self.counts[axis] {
+            Some((offset, value_type)) => data
+                .get(offset..offset + value_type.size())
+                .map_or(max, |b| {
+                    (value_type.decode(b, big_endian) as usize).min(max)
+                }),
+            None => max,
+        }
+    }
+
+    /// 存在記憶體中的軸點原始值
+    pub fn axis(&self, data: &[u8], axis: usize, big_endian: bool) -> Option<Vec<f64>> {
+        let (offset, value_type) = self.axes[axis]?;
+        (0..self.dims[axis])
+            .map(|i| {
+                let start = offset + i * value_type.size();
+                data.get(start..start + value_type.size())
+                    .map(|b| value_type.decode(b, big_endian))
+            })
+            .collect()
+    }
+}
+
+/// XCP IF_DATA 裡的 DAQ 事件
+#[derive(Debug, Clone)]
+pub struct DaqEvent {
+    pub channel: u16,
+    pub name: String,
+}
+
+/// ASAP2 (.a2l) 檔中 XCP 量測與標定需要的部分
+#[derive(Debug, Default)]
+pub struct A2l {
+    pub measurements: Vec<Measurement>,
+    pub characteristics: Vec<Characteristic>,
+    pub compu_methods: HashMap<String, CompuMethod>,
+    pub record_layouts: HashMap<String, RecordLayout>,
+    // XCP_ON_CAN 的 (CRO ID, DTO ID, 29 位元)
+    pub can_ids: Option<(u32, u32, bool)>,
+    pub events: Vec<DaqEvent>,
+    // 解析時略過、改用原始值的項目
+    pub warnings: Vec<String>,
+}
+
+impl A2l {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let bytes = std::fs::read(path.as_ref()).map_err(|e| format!("無法讀取 A2L: {}", e))?;
+        // 和 DBC 一樣，非 UTF-8 時逐位元組轉換
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+        };
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let root = Block {
+            kind: String::new(),
+            items: parse_items(&tokens, &mut 0, None)?,
+        };
+        let mut a2l = A2l::default();
+        let find = |kind: &str| {
+            let mut found = Vec::new();
+            root.find(kind, &mut found);
+            found
+        };
+
+        // 1. **轉換方法**
+        let mut tables: HashMap<&str, Conversion> = HashMap::new();
+        for block in find("COMPU_VTAB") {
+            let words = block.words();
+            let Some(&name) = words.first() else {
+                continue;
+            };
+            let count = words.get(3).and_then(|w| number(w)).unwrap_or(0.0) as usize;
+            let pairs = words
+                .get(4..)
+                .unwrap_or_default()
+                .chunks_exact(2)
+                .take(count)
+                .filter_map(|p| Some((number(p[0])?, p[1].to_string())))
+                .collect();
+            let default = block
+                .keyword(4, "DEFAULT_VALUE")
+                .and_then(|w| Some(w.first()?.to_string()));
+            tables.insert(name, Conversion::Verbal(pairs, default));
+        }
+        for block in find("COMPU_TAB") {
+            let words = block.words();
+            let Some(&kind) = words.get(2) else {
+                continue;
+            };
+            let mut points: Vec<(f64, f64)> = words
+                .get(4..)
+                .unwrap_or_default()
+                .chunks_exact(2)
+                .map_while(|p| Some((number(p[0])?, number(p[1])?)))
+                .collect();
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            tables.insert(words[0], Conversion::Table(points, kind == "TAB_INTP"));
+        }
+        for block in find("COMPU_METHOD") {
+            let words = block.words();
+            if words.len() < 5 {
+                continue;
+            }
+            let coefficients = |key: &str, count: usize| {
+                let values: Vec<f64> = block
+                    .keyword(5, key)?
+                    .iter()
+                    .take(count)
+                    .map_while(|w| number(w))
+                    .collect();
+                (values.len() == count).then_some(values)
+            };
+            let conversion = match words[2] {
+                "LINEAR" => {
+                    coefficients("COEFFS_LINEAR", 2).map(|c| Conversion::Linear(c[0], c[1]))
+                }
+                // 只有 a = d = 0 (線性分式) 的反函數是唯一的，其他的改用原始值
+                "RAT_FUNC" => match coefficients("COEFFS", 6) {
+                    Some(c) if c[0] == 0.0 && c[3] == 0.0 => {
+                        Some(Conversion::Rational([c[0], c[1], c[2], c[3], c[4], c[5]]))
+                    }
+                    Some(_) => {
+                        a2l.warnings.push(format!(
+                            "COMPU_METHOD {}: 不支援二次項不為 0 的 RAT_FUNC，改用原始值",
+                            words[0]
+                        ));
+                        None
+                    }
+                    None => None,
+                },
+                "TAB_VERB" | "TAB_INTP" | "TAB_NOINTP" => block
+                    .keyword(5, "COMPU_TAB_REF")
+                    .and_then(|w| tables.get(w.first()?).cloned()),
+                _ => None,
+            };
+            let decimals = words[3]
+                .split_once('.')
+                .and_then(|(_, d)| d.trim_end_matches(|c: char| c.is_alphabetic()).parse().ok());
+            a2l.compu_methods.insert(
+                words[0].to_string(),
+                CompuMethod {
+                    conversion: conversion.unwrap_or(Conversion::Identical),
+                    unit: words[4].to_string(),
+                    decimals,
+                },
+            );
+        }
+
+        // 2. **記錄排列**
+        for block in find("RECORD_LAYOUT") {
+            let words = block.words();
+            let Some(name) = words.first() else {
+                continue;
+            };
+            let item = |key: &str| {
+                let args = block.keyword(1, key)?;
+                Some(LayoutItem {
+                    position: number(args.first()?)? as u32,
+                    value_type: value_type(args.get(1)?)?,
+                })
+            };
+            let layout = RecordLayout {
+                fnc_values: item("FNC_VALUES"),
+                column_dir: block
+                    .keyword(1, "FNC_VALUES")
+                    .is_some_and(|args| args.get(2) == Some(&"COLUMN_DIR")),
+                axis_pts: [item("AXIS_PTS_X"), item("AXIS_PTS_Y")],
+                no_axis_pts: [item("NO_AXIS_PTS_X"), item("NO_AXIS_PTS_Y")],
+            };
+            a2l.record_layouts.insert(name.to_string(), layout);
+        }
+
+        // 3. **量測值**
+        for block in find("MEASUREMENT") {
+            let words = block.words();
+            if words.len() < 8 {
+                continue;
+            }
+            let Some(value_type) = value_type(words[2]) else {
+                continue;
+            };
+            let Some(address) = block
+                .keyword(8, "ECU_ADDRESS")
+                .and_then(|w| number(w.first()?))
+            else {
+                continue;
+            };
+            a2l.measurements.push(Measurement {
+                name: words[0].to_string(),
+                description: words[1].to_string(),
+                value_type,
+                conversion: words[3].to_string(),
+                address: address as u32,
+                extension: extension(block, 8),
+            });
+        }
+
+        // 4. **標定參數**
+        for block in find("CHARACTERISTIC") {
+            let words = block.words();
+            if words.len() < 9 {
+                continue;
+            }
+            let kind = match words[2] {
+                "VALUE" => CharacteristicKind::Value,
+                "VAL_BLK" => CharacteristicKind::ValBlk,
+                "CURVE" => CharacteristicKind::Curve,
+                "MAP" => CharacteristicKind::Map,
+                _ => continue,
+            };
+            let Some(address) = number(words[3]) else {
+                continue;
+            };
+            let axes = block
+                .blocks("AXIS_DESCR")
+                .filter_map(|axis| {
+                    let args = axis.words();
+                    let fixed = if let Some(p) = axis.keyword(6, "FIX_AXIS_PAR") {
+                        // 軸點 = offset + i * 2^shift
+                        let (offset, shift, count) =
+                            (number(p.first()?)?, number(p.get(1)?)?, number(p.get(2)?)?);
+                        Some(
+                            (0..count as usize)
+                                .map(|i| offset + i as f64 * 2f64.powf(shift))
+                                .collect(),
+                        )
+                    } else if let Some(p) = axis.keyword(6, "FIX_AXIS_PAR_DIST") {
+                        let (offset, distance, count) =
+                            (number(p.first()?)?, number(p.get(1)?)?, number(p.get(2)?)?);
+                        Some(
+                            (0..count as usize)
+                                .map(|i| offset + i as f64 * distance)
+                                .collect(),
+                        )
+                    } else {
+                        axis.blocks("FIX_AXIS_PAR_LIST")
+                            .next()
+                            .map(|list| list.words().iter().filter_map(|w| number(w)).collect())
+                    };
+                    Some(AxisDescr {
+                        attribute: args.first()?.to_string(),
+                        conversion: args.get(2)?.to_string(),
+                        max_points: number(args.get(3)?)? as usize,
+                        fixed,
+                    })
+                })
+                .collect();
+            let number_of_values = block
+                .keyword(9, "NUMBER")
+                .or_else(|| block.keyword(9, "MATRIX_DIM"))
+                .and_then(|w| number(w.first()?))
+                .unwrap_or(1.0) as usize;
+            a2l.characteristics.push(Characteristic {
+                name: words[0].to_string(),
+                description: words[1].to_string(),
+                kind,
+                address: address as u32,
+                extension: extension(block, 9),
+                deposit: words[4].to_string(),
+                conversion: words[6].to_string(),
+                lower: number(words[7]).unwrap_or(f64::MIN),
+                upper: number(words[8]).unwrap_or(f64::MAX),
+                axes,
+                number: number_of_values.max(1),
+            });
+        }
+
+        // 5. **XCP on CAN 設定 (只看 MODULE 層的 IF_DATA)**
+        for module in find("MODULE") {
+            for if_data in module.blocks("IF_DATA") {
+                if !if_data
+                    .words()
+                    .first()
+                    .is_some_and(|w| w.starts_with("XCP"))
+                {
+                    continue;
+                }
+                let mut found = Vec::new();
+                if_data.find("XCP_ON_CAN", &mut found);
+                if let Some(can) = found.first() {
+                    let id = |key: &str| {
+                        can.keyword(1, key)
+                            .and_then(|w| number(w.first()?))
+                            .map(|v| v as u32)
+                    };
+                    if let (Some(master), Some(slave)) = (id("CAN_ID_MASTER"), id("CAN_ID_SLAVE")) {
+                        a2l.can_ids = Some((
+                            master & !CAN_ID_EXTENDED,
+                            slave & !CAN_ID_EXTENDED,
+                            master & CAN_ID_EXTENDED != 0,
+                        ));
+                    }
+                }
+                let mut events = Vec::new();
+                if_data.find("EVENT", &mut events);
+                for event in events {
+                    let words = event.words();
+                    if let Some(channel) = words.get(2).and_then(|w| number(w)) {
+                        a2l.events.push(DaqEvent {
+                            channel: channel as u16,
+                            name: words[0].to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(a2l)
+    }
+
+    pub fn compu_method(&self, name: &str) -> CompuMethod {
+        self.compu_methods.get(name).cloned().unwrap_or_default()
+    }
+
+    /// 依 RECORD_LAYOUT 與軸描述算出標定參數的記憶體排列
+    pub fn layout(&self, characteristic: &Characteristic) -> Result<Layout, String> {
+        let record = self
+            .record_layouts
+            .get(&characteristic.deposit)
+            .ok_or_else(|| format!("找不到 RECORD_LAYOUT {}", characteristic.deposit))?;
+        let fnc_values = record
+            .fnc_values
+            .ok_or_else(|| format!("RECORD_LAYOUT {} 沒有 FNC_VALUES", characteristic.deposit))?;
+        let mut dims = [1, 1];
+        match characteristic.kind {
+            CharacteristicKind::Value => {}
+            CharacteristicKind::ValBlk => dims[0] = characteristic.number,
+            CharacteristicKind::Curve | CharacteristicKind::Map => {
+                let count = if characteristic.kind == CharacteristicKind::Map {
+                    2
+                } else {
+                    1
+                };
+                if characteristic.axes.len() < count {
+                    return Err(format!("{} 缺少 AXIS_DESCR", characteristic.name));
+                }
+                for (dim, axis) in dims.iter_mut().zip(&characteristic.axes[..count]) {
+                    *dim = axis.max_points.max(1);
+                }
+            }
+        }
+
+        // 依位置順序緊密排列
+        enum Part {
+            Values,
+            Axis(usize),
+            Count(usize),
+        }
+        let mut parts = vec![(fnc_values.position, Part::Values, fnc_values.value_type)];
+        for axis in 0..2 {
+            // 只有 STD_AXIS 的軸點存在參數本身
+            let stored = characteristic
+                .axes
+                .get(axis)
+                .is_some_and(|a| a.attribute == "STD_AXIS");
+            if !stored {
+                continue;
+            }
+            if let Some(item) = record.axis_pts[axis] {
+                parts.push((item.position, Part::Axis(axis), item.value_type));
+            }
+            if let Some(item) = record.no_axis_pts[axis] {
+                parts.push((item.position, Part::Count(axis), item.value_type));
+            }
+        }
+        parts.sort_by_key(|(position, _, _)| *position);
+
+        let mut layout = Layout {
+            value_type: fnc_values.value_type,
+            dims,
+            size: 0,
+            values: 0,
+            column_dir: record.column_dir,
+            axes: [None; 2],
+            counts: [None; 2],
+        };
+        for (_, part, value_type) in parts {
+            let offset = layout.size;
+            layout.size += match part {
+                Part::Values => {
+                    layout.values = offset;
+                    value_type.size() * dims[0] * dims[1]
+                }
+                Part::Axis(axis) => {
+                    layout.axes[axis] = Some((offset, value_type));
+                    value_type.size() * dims[axis]
+                }
+                Part::Count(axis) => {
+                    layout.counts[axis] = Some((offset, value_type));
+                    value_type.size()
+                }
+            };
+        }
+        Ok(layout)
+    }
+}
+
+fn extension(block: &Block, fixed: usize) -> u8 {
+    block
+        .keyword(fixed, "ECU_ADDRESS_EXTENSION")
+        .and_then(|w| number(w.first()?))
+        .unwrap_or(0.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_blocks_are_skipped() {
+        let text = r#"
+/begin PROJECT P ""
+  /begin MODULE M ""
+    /begin COMPU_VTAB
+    /end COMPU_VTAB
+    /begin COMPU_VTAB OnOff "" TAB_VERB 2
+      0 "Off" 1 "On"
+    /end COMPU_VTAB
+    /begin COMPU_METHOD CM_OnOff "" TAB_VERB "%.0" ""
+      COMPU_TAB_REF OnOff
+    /end COMPU_METHOD
+    /begin MEASUREMENT Switch "" UBYTE CM_OnOff 0 0 0 1
+      ECU_ADDRESS 0x1008
+    /end MEASUREMENT
+  /end MODULE
+/end PROJECT
+"#;
+        let a2l = A2l::parse(text).unwrap();
+        assert_eq!(a2l.measurements.len(), 1);
+        let method = a2l.compu_method("CM_OnOff");
+        assert_eq!(method.format(1.0), "On");
+    }
+
+    #[test]
+    fn rational_functions() {
+        let text = r#"
+/begin PROJECT P ""
+  /begin MODULE M ""
+    /begin COMPU_METHOD CM_Half "" RAT_FUNC "%.1" "V"
+      COEFFS 0 1 0 0 0 2
+    /end COMPU_METHOD
+    /begin COMPU_METHOD CM_Square "" RAT_FUNC "%.1" "V"
+      COEFFS 1 0 0 0 0 1
+    /end COMPU_METHOD
+  /end MODULE
+/end PROJECT
+"#;
+        let a2l = A2l::parse(text).unwrap();
+        // 原始值 = 物理值 / 2
+        let half = a2l.compu_method("CM_Half");
+        assert_eq!(half.to_raw(10.0), 5.0);
+        assert_eq!(half.to_physical(5.0), 10.0);
+        assert_eq!(half.format(5.0), "10.0 V");
+
+        // 二次式的反函數不唯一，不套用轉換並留下警告
+        let square = a2l.compu_method("CM_Square");
+        assert!(matches!(square.conversion, Conversion::Identical));
+        assert_eq!(square.to_physical(4.0), 4.0);
+        assert_eq!(a2l.warnings.len(), 1);
+        assert!(a2l.warnings[0].contains("CM_Square"));
+    }
+
+    const LAYOUTS: &str = r#"
+/begin PROJECT P ""
+  /begin MODULE M ""
+    /begin RECORD_LAYOUT RL_Curve
+      NO_AXIS_PTS_X 1 UBYTE
+      AXIS_PTS_X 2 UWORD INDEX_INCR DIRECT
+      FNC_VALUES 3 SWORD COLUMN_DIR DIRECT
+    /end RECORD_LAYOUT
+    /begin RECORD_LAYOUT RL_Row
+      FNC_VALUES 1 UBYTE ROW_DIR DIRECT
+    /end RECORD_LAYOUT
+    /begin RECORD_LAYOUT RL_Column
+      FNC_VALUES 1 UBYTE COLUMN_DIR DIRECT
+    /end RECORD_LAYOUT
+    /begin CHARACTERISTIC Curve "" CURVE 0x2000 RL_Curve 0 NO_COMPU_METHOD -100 100
+      /begin AXIS_DESCR STD_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 4 0 1000
+      /end AXIS_DESCR
+    /end CHARACTERISTIC
+    /begin CHARACTERISTIC SharedCurve "" CURVE 0x2100 RL_Curve 0 NO_COMPU_METHOD -100 100
+      /begin AXIS_DESCR COM_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 4 0 1000
+        AXIS_PTS_REF Axis
+      /end AXIS_DESCR
+    /end CHARACTERISTIC
+    /begin CHARACTERISTIC RowMap "" MAP 0x3000 RL_Row 0 NO_COMPU_METHOD 0 255
+      /begin AXIS_DESCR FIX_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 3 0 30
+        FIX_AXIS_PAR_DIST 0 10 3
+      /end AXIS_DESCR
+      /begin AXIS_DESCR FIX_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 2 0 2
+        FIX_AXIS_PAR 0 0 2
+      /end AXIS_DESCR
+    /end CHARACTERISTIC
+    /begin CHARACTERISTIC ColumnMap "" MAP 0x3100 RL_Column 0 NO_COMPU_METHOD 0 255
+      /begin AXIS_DESCR FIX_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 3 0 30
+        FIX_AXIS_PAR_DIST 0 10 3
+      /end AXIS_DESCR
+      /begin AXIS_DESCR FIX_AXIS NO_INPUT_QUANTITY NO_COMPU_METHOD 2 0 2
+        /begin FIX_AXIS_PAR_LIST 5 7
+        /end FIX_AXIS_PAR_LIST
+      /end AXIS_DESCR
+    /end CHARACTERISTIC
+    /begin IF_DATA XCP
+      /begin DAQ
+        DYNAMIC 0 2 0 OPTIMISATION_TYPE_DEFAULT ADDRESS_EXTENSION_FREE
+        /begin EVENT "10ms" "10ms" 0 DAQ 1 10 6 0
+        /end EVENT
+        /begin EVENT "100ms" "100ms" 1 DAQ 1 100 6 0
+        /end EVENT
+      /end DAQ
+      /begin XCP_ON_CAN 0x0100
+        CAN_ID_MASTER 0x80000700
+        CAN_ID_SLAVE 0x80000701
+        BAUDRATE 500000
+      /end XCP_ON_CAN
+    /end IF_DATA
+  /end MODULE
+/end PROJECT
+"#;
+
+    fn characteristic<'a>(a2l: &'a A2l, name: &str) -> &'a Characteristic {
+        a2l.characteristics.iter().find(|c| c.name == name).unwrap()
+    }
+
+    #[test]
+    fn curve_with_stored_axis_and_count() {
+        let a2l = A2l::parse(LAYOUTS).unwrap();
+        let curve = characteristic(&a2l, "Curve");
+        assert_eq!(curve.kind, CharacteristicKind::Curve);
+        let layout = a2l.layout(curve).unwrap();
+        // NO_AXIS_PTS_X (1) + AXIS_PTS_X (4 x 2) + FNC_VALUES (4 x 2)
+        assert_eq!((layout.dims, layout.size), ([4, 1], 17));
+        assert_eq!(layout.value_offset(0, 0), 9);
+        assert_eq!(layout.value_offset(3, 0), 15);
+
+        let mut data = vec![3];
+        for v in [0u16, 100, 200, 300] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        for v in [-5i16, 0, 5, 10] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        assert_eq!(layout.count(&data, 0, true), 3);
+        assert_eq!(
+            layout.axis(&data, 0, true),
+            Some(vec![0.0, 100.0, 200.0, 300.0])
+        );
+        assert_eq!(layout.value(&data, 0, 0, true), Some(-5.0));
+        assert_eq!(layout.value(&data, 3, 0, true), Some(10.0));
+        // 軸點數超過 AXIS_DESCR 的最大值時截斷
+        data[0] = 9;
+        assert_eq!(layout.count(&data, 0, true), 4);
+        // 資料不足時讀不到軸點
+        assert_eq!(layout.axis(&data[..5], 0, true), None);
+
+        // COM_AXIS 的軸點不在參數裡，同一個 RECORD_LAYOUT 只剩數值
+        let shared = a2l.layout(characteristic(&a2l, "SharedCurve")).unwrap();
+        assert_eq!((shared.size, shared.value_offset(1, 0)), (8, 2));
+        assert_eq!(shared.count(&[], 0, false), 4);
+        assert_eq!(shared.axis(&[], 0, false), None);
+    }
+
+    #[test]
+    fn map_row_and_column_order() {
+        let a2l = A2l::parse(LAYOUTS).unwrap();
+        let row = a2l.layout(characteristic(&a2l, "RowMap")).unwrap();
+        let column = a2l.layout(characteristic(&a2l, "ColumnMap")).unwrap();
+        assert_eq!((row.dims, row.size), ([3, 2], 6));
+        assert_eq!((column.dims, column.size), ([3, 2], 6));
+
+        let data = [0, 1, 2, 3, 4, 5];
+        // ROW_DIR：同一列 (y) 的 x 連續
+        assert_eq!(row.value_offset(2, 0), 2);
+        assert_eq!(row.value_offset(0, 1), 3);
+        assert_eq!(row.value(&data, 1, 1, false), Some(4.0));
+        // COLUMN_DIR：同一行 (x) 的 y 連續
+        assert_eq!(column.value_offset(0, 1), 1);
+        assert_eq!(column.value_offset(2, 0), 4);
+        assert_eq!(column.value(&data, 1, 1, false), Some(3.0));
+
+        let axes = &characteristic(&a2l, "ColumnMap").axes;
+        assert_eq!(axes[0].fixed, Some(vec![0.0, 10.0, 20.0]));
+        assert_eq!(axes[1].fixed, Some(vec![5.0, 7.0]));
+        assert_eq!(
+            characteristic(&a2l, "RowMap").axes[1].fixed,
+            Some(vec![0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn xcp_on_can_ids_and_events() {
+        let a2l = A2l::parse(LAYOUTS).unwrap();
+        assert_eq!(a2l.can_ids, Some((0x700, 0x701, true)));
+        let events: Vec<_> = a2l
+            .events
+            .iter()
+            .map(|e| (e.channel, e.name.as_str()))
+            .collect();
+        assert_eq!(events, [(0, "10ms"), (1, "100ms")]);
+    }
+}
@@ -0,0 +1,434 @@
+use crate::canbus::CanFrame;
+use crate::dbc::Dbc;
+use eframe::egui;
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// 保留的每秒樣本數 (10 分鐘)
+const HISTORY_LEN: usize = 600;
+const CHART_HEIGHT: f32 = 140.0;
+// 主動錯誤旗標 6 + 其他節點重疊最多 6 + 錯誤界定 8 + 訊框間隔 3
+const ERROR_FRAME_BITS: u64 = 23;
+
+/// 一幀在匯流排上佔用的位元數 (含最壞情況的填充位元與 3 位元訊框間隔)
+pub fn frame_bits(frame: &CanFrame) -> u64 {
+    if frame.error {
+        return ERROR_FRAME_BITS;
+    }
+    let data_bits = if frame.remote {
+        0
+    } else {
+        8 * frame.len.min(8) as u64
+    };
+    // 會被填充的範圍：SOF 到 CRC 結束
+    // 標準: SOF 1 + ID 11 + RTR 1 + IDE 1 + r0 1 + DLC 4 + 資料 + CRC 15
+    // 擴展: SOF 1 + ID 11 + SRR 1 + IDE 1 + ID 18 + RTR 1 + r1 r0 2 + DLC 4 + 資料 + CRC 15
+    let stuffed = if frame.extended { 54 } else { 34 } + data_bits;
+    // 最壞情況：第一個填充位元在 5 個相同位元後出現，之後每 4 個位元一個
+    let stuff_bits = (stuffed - 1) / 4;
+    // CRC 界定 1 + ACK 2 + EOF 7 + 訊框間隔 3
+    stuffed + stuff_bits + 13
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    bits: u64,
+    frames: u64,
+    errors: u64,
+}
+
+#[derive(Default)]
+struct ChannelStats {
+    second: u64,
+    current: Sample,
+    history: VecDeque<Sample>,
+    total_frames: u64,
+    total_errors: u64,
+    peak_1s: u64,
+    // 10 秒平均的最大值 (每秒位元數)
+    peak_10s: f64,
+}
+
+impl ChannelStats {
+    // 收下到 second 為止已結束的秒，中間沒有流量的秒補 0
+    fn advance(&mut self, second: u64) {
+        if self.history.is_empty() && self.current.frames == 0 {
+            self.second = second;
+            return;
+        }
+        let gap = second.saturating_sub(self.second);
+        for i in 0..gap.min(HISTORY_LEN as u64) {
+            let sample = if i == 0 {
+                std::mem::take(&mut self.current)
+            } else {
+                Sample::default()
+            };
+            self.push(sample);
+        }
+        if gap > 0 {
+            self.current = Sample::default();
+            self.second = second;
+        }
+    }
+
+    fn push(&mut self, sample: Sample) {
+        self.history.push_back(sample);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.peak_1s = self.peak_1s.max(sample.bits);
+        if self.history.len() >= 10 {
+            let sum: u64 = self.history.iter().rev().take(10).map(|s| s.bits).sum();
+            self.peak_10s = self.peak_10s.max(sum as f64 / 10.0);
+        }
+    }
+
+    fn last(&self) -> Sample {
+        self.history.back().copied().unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct IdRate {
+    second: u64,
+    count: u64,
+    // second 的前一秒的幀數
+    rate: u64,
+    total: u64,
+}
+
+impl IdRate {
+    // 以通道目前的秒為準，取上一個完整秒的幀數；停止發送的 ID 歸零
+    fn rate_at(&self, second: u64) -> u64 {
+        if self.second == second {
+            self.rate
+        } else if self.second + 1 == second {
+            self.count
+        } else {
+            0
+        }
+    }
+}
+
+/// 匯流排負載與流量統計，以幀時間戳每秒分桶
+pub struct BusStats {
+    pub bitrate: u32,
+    channels: BTreeMap<u32, ChannelStats>,
+    ids: HashMap<(u32, bool, u32), IdRate>,
+    selected_channel: u32,
+}
+
+impl Default for BusStats {
+    fn default() -> Self {
+        Self {
+            bitrate: 250_000,
+            channels: BTreeMap::new(),
+            ids: HashMap::new(),
+            selected_channel: 0,
+        }
+    }
+}
+
+fn percent(bits: f64, bitrate: u32) -> f64 {
+    bits * 100.0 / bitrate.max(1) as f64
+}
+
+impl BusStats {
+    pub fn clear(&mut self) {
+        self.channels.clear();
+        self.ids.clear();
+    }
+
+    pub fn update(&mut self, frame: &CanFrame) {
+        let second = frame.timestamp_us / 1_000_000;
+        let channel = self.channels.entry(frame.channel).or_default();
+        channel.advance(second);
+        channel.current.bits += frame_bits(frame);
+        channel.current.frames += 1;
+        channel.total_frames += 1;
+        if frame.error {
+            channel.current.errors += 1;
+            channel.total_errors += 1;
+            return;
+        }
+
+        let id = self
+            .ids
+            .entry((frame.channel, frame.extended, frame.id))
+            .or_default();
+        if second != id.second {
+            id.rate = if second == id.second + 1 { id.count } else { 0 };
+            id.second = second;
+            id.count = 0;
+        }
+        id.count += 1;
+        id.total += 1;
+    }
+
+    /// 即時接收時每個畫面呼叫，匯流排安靜時也讓統計往前走
+    pub fn tick(&mut self, now_us: u64) {
+        let second = now_us / 1_000_000;
+        for channel in self.channels.values_mut() {
+            channel.advance(second);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, dbc: Option<&Dbc>) {
+        ui.label(format!(
+            "位元率 {} kbps，負載以最壞情況填充位元計算",
+            self.bitrate / 1000
+        ));
+        if self.channels.is_empty() {
+            ui.label("尚無資料");
+            return;
+        }
+        let bitrate = self.bitrate;
+
+        // 1. **每通道摘要**
+        egui::Grid::new("bus_stats_channels")
+            .striped(true)
+            .num_columns(8)
+            .show(ui, |ui| {
+                for header in [
+                    "通道",
+                    "負載",
+                    "1 s 峰值",
+                    "10 s 峰值",
+                    "幀/s",
+                    "錯誤幀/s",
+                    "總幀數",
+                    "總錯誤幀",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (channel, stats) in &self.channels {
+                    let last = stats.last();
+                    ui.selectable_value(&mut self.selected_channel, *channel, channel.to_string());
+                    ui.label(format!("{:.1} %", percent(last.bits as f64, bitrate)));
+                    ui.label(format!("{:.1} %", percent(stats.peak_1s as f64, bitrate)));
+                    ui.label(format!("{:.1} %", percent(stats.peak_10s, bitrate)));
+                    ui.label(last.frames.to_string());
+                    ui.label(last.errors.to_string());
+                    ui.label(stats.total_frames.to_string());
+                    ui.label(stats.total_errors.to_string());
+                    ui.end_row();
+                }
+            });
+
+        // 2. **選取通道的負載歷史**
+        if let Some(stats) = self.channels.get(&self.selected_channel) {
+            ui.separator();
+            ui.label(format!("通道 {} 負載歷史 (每秒)", self.selected_channel));
+            history_chart(ui, stats, bitrate);
+        }
+
+        // 3. **每個 ID 的幀率**
+        ui.separator();
+        let now = self
+            .channels
+            .get(&self.selected_channel)
+            .map_or(0, |c| c.second);
+        let mut rows: Vec<_> = self
+            .ids
+            .iter()
+            .filter(|((ch, _, _), _)| *ch == self.selected_channel)
+            .map(|(key, rate)| (key, rate.rate_at(now), rate.total))
+            .collect();
+        rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        egui::ScrollArea::vertical()
+            .id_salt("bus_stats_ids")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("bus_stats_ids_grid")
+                    .striped(true)
+                    .num_columns(4)
+                    .show(ui, |ui| {
+                        for header in ["ID", "名稱", "幀/s", "總數"] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for ((_, extended, id), rate, total) in rows {
+                            if *extended {
+                                ui.monospace(format!("{:08X}", id));
+                            } else {
+                                ui.monospace(format!("{:03X}", id));
+                            }
+                            let name = dbc
+                                .and_then(|d| d.message(*id, *extended))
+                                .map(|m| m.name.as_str())
+                                .unwrap_or("");
+                            ui.label(name);
+                            ui.label(rate.to_string());
+                            ui.label(total.to_string());
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+fn history_chart(ui: &mut egui::Ui, stats: &ChannelStats, bitrate: u32) {
+    let size = Vec2::new(ui.available_width().max(200.0), CHART_HEIGHT);
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    let data_rect = Rect::from_min_max(
+        Pos2::new(rect.left() + 36.0, rect.top() + 6.0),
+        Pos2::new(rect.right() - 6.0, rect.bottom() - 6.0),
+    );
+    let loads: Vec<f64> = stats
+        .history
+        .iter()
+        .map(|s| percent(s.bits as f64, bitrate))
+        .collect();
+    // Y 軸至少到 100%，超過時 (位元率設錯) 跟著放大
+    let y_max = loads.iter().cloned().fold(100.0, f64::max);
+    let to_y = |v: f64| data_rect.bottom() - (v / y_max) as f32 * data_rect.height();
+    let to_x = |i: usize| {
+        data_rect.right()
+            - (HISTORY_LEN - 1 - i) as f32 * data_rect.width() / (HISTORY_LEN - 1) as f32
+    };
+
+    let grid = ui.visuals().weak_text_color().gamma_multiply(0.3);
+    for pct in [0.0, 25.0, 50.0, 75.0, 100.0] {
+        let y = to_y(pct);
+        painter.line_segment(
+            [
+                Pos2::new(data_rect.left(), y),
+                Pos2::new(data_rect.right(), y),
+            ],
+            Stroke::new(1.0, grid),
+        );
+        painter.text(
+            Pos2::new(data_rect.left() - 4.0, y),
+            Align2::RIGHT_CENTER,
+            format!("{}%", pct),
+            FontId::monospace(9.0),
+            ui.visuals().text_color(),
+        );
+    }
+
+    // 最新的一秒畫在最右邊
+    let offset = HISTORY_LEN - loads.len();
+    let load_line: Vec<Pos2> = loads
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| Pos2::new(to_x(offset + i), to_y(v)))
+        .collect();
+    let avg_line: Vec<Pos2> = (0..loads.len())
+        .map(|i| {
+            let window = &loads[i.saturating_sub(9)..=i];
+            let avg = window.iter().sum::<f64>() / window.len() as f64;
+            Pos2::new(to_x(offset + i), to_y(avg))
+        })
+        .collect();
+    let load_color = Color32::from_rgb(31, 119, 180);
+    let avg_color = Color32::from_rgb(255, 127, 14);
+    painter.add(Shape::line(load_line, Stroke::new(1.0, load_color)));
+    painter.add(Shape::line(avg_line, Stroke::new(1.5, avg_color)));
+    painter.text(
+        Pos2::new(data_rect.left() + 4.0, data_rect.top()),
+        Align2::LEFT_TOP,
+        "1 s",
+        FontId::proportional(10.0),
+        load_color,
+    );
+    painter.text(
+        Pos2::new(data_rect.left() + 28.0, data_rect.top()),
+        Align2::LEFT_TOP,
+        "10 s 平均",
+        FontId::proportional(10.0),
+        avg_color,
+    );
+
+    if let Some(pos) = response.hover_pos() {
+        let i = ((pos.x - data_rect.left()) / data_rect.width() * (HISTORY_LEN - 1) as f32).round()
+            as usize;
+        if let Some(sample) = i.checked_sub(offset).and_then(|i| stats.history.get(i)) {
+            let ago = HISTORY_LEN - 1 - i;
+            response.on_hover_text_at_pointer(format!(
+                "{} 秒前: {:.1} %, {} 幀, {} 錯誤幀",
+                ago,
+                percent(sample.bits as f64, bitrate),
+                sample.frames,
+                sample.errors
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp_us: u64, id: u32, len: u8) -> CanFrame {
+        CanFrame {
+            timestamp_us,
+            id,
+            len,
+            ..Default::default()
+        }
+    }
+
+    fn error(timestamp_us: u64) -> CanFrame {
+        CanFrame {
+            timestamp_us,
+            error: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn worst_case_frame_bits() {
+        assert_eq!(frame_bits(&frame(0, 0x100, 8)), 135);
+        assert_eq!(frame_bits(&frame(0, 0x100, 0)), 55);
+        let extended = CanFrame {
+            extended: true,
+            ..frame(0, 0x18FE_F100, 8)
+        };
+        assert_eq!(frame_bits(&extended), 160);
+        // 遠端幀沒有資料欄位
+        let remote = CanFrame {
+            remote: true,
+            ..frame(0, 0x100, 8)
+        };
+        assert_eq!(frame_bits(&remote), 55);
+        assert_eq!(frame_bits(&error(0)), ERROR_FRAME_BITS);
+    }
+
+    #[test]
+    fn error_frames_are_counted_per_second() {
+        let mut stats = BusStats::default();
+        stats.update(&frame(0, 0x100, 8));
+        stats.update(&error(100));
+        stats.update(&error(200));
+        stats.update(&frame(1_000_000, 0x100, 8));
+        stats.update(&error(1_500_000));
+        stats.tick(2_000_000);
+
+        let channel = &stats.channels[&0];
+        let history: Vec<_> = channel
+            .history
+            .iter()
+            .map(|s| (s.frames, s.errors, s.bits))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (3, 2, 135 + 2 * ERROR_FRAME_BITS),
+                (2, 1, 135 + ERROR_FRAME_BITS)
+            ]
+        );
+        assert_eq!((channel.total_frames, channel.total_errors), (5, 3));
+        // 錯誤幀不算進任何 ID 的幀率
+        assert_eq!(stats.ids.len(), 1);
+        assert_eq!(stats.ids[&(0, false, 0x100)].total, 2);
+
+        // 沒有流量的秒補 0
+        stats.tick(4_000_000);
+        assert_eq!(stats.channels[&0].last().errors, 0);
+        assert_eq!(stats.channels[&0].history.len(), 4);
+    }
+}
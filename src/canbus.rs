@@ -1,11 +1,38 @@
 use flume::Sender;
 use libloading::Library;
+use std::fmt;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{thread, time::Duration};
 
+const RECEIVE_BATCH: usize = 2500;
+// 多久向板卡讀一次錯誤狀態；兩次之間的錯誤數由錯誤計數器的增量估算，不受輪詢頻率限制
+const ERROR_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// VCI_ReadErrInfo 的 CAN 錯誤碼 (高位元是裝置錯誤，不產生錯誤幀)
+const ERR_CAN_OVERFLOW: u32 = 0x0001;
+const ERR_CAN_ERRALARM: u32 = 0x0002;
+const ERR_CAN_PASSIVE: u32 = 0x0004;
+const ERR_CAN_LOSE: u32 = 0x0008;
+const ERR_CAN_BUSERR: u32 = 0x0010;
+const ERR_CAN_BUSOFF: u32 = 0x0020;
+
+// 錯誤幀沿用 SocketCAN 的格式：ID 是錯誤類別，資料是細節
+const CAN_ERR_LOSTARB: u32 = 0x0002;
+const CAN_ERR_CRTL: u32 = 0x0004;
+const CAN_ERR_PROT: u32 = 0x0008;
+const CAN_ERR_BUSOFF: u32 = 0x0040;
+const CAN_ERR_CNT: u32 = 0x0200;
+const CAN_ERR_CRTL_RX_OVERFLOW: u8 = 0x01;
+const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+const CAN_ERR_PROT_TX: u8 = 0x80;
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct VciCanObj {
@@ -32,6 +59,113 @@ pub struct VciInitConfig {
     pub mode: u8,
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct VciErrInfo {
+    pub err_code: u32,
+    // SJA1000 的 ECC、接收錯誤計數、發送錯誤計數
+    pub passive_err_data: [u8; 3],
+    // SJA1000 的 ALC
+    pub ar_lost_err_data: u8,
+}
+
+impl VciErrInfo {
+    /// 控制器錯誤轉成錯誤幀；沒有 CAN 錯誤時為 None
+    pub fn to_frame(&self, channel: u32, timestamp_us: u64) -> Option<CanFrame> {
+        if self.err_code & 0xFF == 0 {
+            return None;
+        }
+        let [ecc, rec, tec] = self.passive_err_data;
+        // 板卡只回報警告或被動，不分方向，看哪個計數器較大
+        let (warning, passive) = if rec > tec {
+            (CAN_ERR_CRTL_RX_WARNING, CAN_ERR_CRTL_RX_PASSIVE)
+        } else {
+            (CAN_ERR_CRTL_TX_WARNING, CAN_ERR_CRTL_TX_PASSIVE)
+        };
+        let mut id = CAN_ERR_CNT;
+        let mut data = [0u8; 8];
+        if self.err_code & ERR_CAN_OVERFLOW != 0 {
+            id |= CAN_ERR_CRTL;
+            data[1] |= CAN_ERR_CRTL_RX_OVERFLOW;
+        }
+        if self.err_code & ERR_CAN_ERRALARM != 0 {
+            id |= CAN_ERR_CRTL;
+            data[1] |= warning;
+        }
+        if self.err_code & ERR_CAN_PASSIVE != 0 {
+            id |= CAN_ERR_CRTL;
+            data[1] |= passive;
+        }
+        if self.err_code & ERR_CAN_LOSE != 0 {
+            id |= CAN_ERR_LOSTARB;
+            data[0] = self.ar_lost_err_data & 0x1F;
+        }
+        if self.err_code & ERR_CAN_BUSERR != 0 {
+            // ECC: bit7-6 錯誤種類 (位元/格式/填充/其他)，bit5 為 1 表示接收，bit4-0 錯誤位置
+            id |= CAN_ERR_PROT;
+            data[2] = match ecc >> 6 {
+                0 => 0x01,
+                1 => 0x02,
+                2 => 0x04,
+                _ => 0x00,
+            };
+            if ecc & 0x20 == 0 {
+                data[2] |= CAN_ERR_PROT_TX;
+            }
+            data[3] = ecc & 0x1F;
+        }
+        if self.err_code & ERR_CAN_BUSOFF != 0 {
+            id |= CAN_ERR_BUSOFF;
+        }
+        data[6] = tec;
+        data[7] = rec;
+        Some(CanFrame {
+            timestamp_us,
+            channel,
+            id,
+            error: true,
+            len: 8,
+            data,
+            ..Default::default()
+        })
+    }
+}
+
+/// 記住上次讀到的控制器狀態，只把新事件轉成錯誤幀；
+/// 停在警告或被動狀態不會每次輪詢都產生錯誤幀
+#[derive(Debug, Default)]
+pub struct ErrorTracker {
+    // 0 正常、1 警告、2 被動、3 匯流排關閉
+    severity: u8,
+    rec: u8,
+    tec: u8,
+}
+
+impl ErrorTracker {
+    /// 錯誤計數器增加、ALC/ECC/溢位事件或狀態變嚴重時產生錯誤幀；
+    /// 接收錯誤計數 +1、發送錯誤計數 +8 各算一個錯誤，輪詢間隔內的多個錯誤都會計入
+    pub fn frames(&mut self, info: &VciErrInfo, channel: u32, timestamp_us: u64) -> Vec<CanFrame> {
+        let [_, rec, tec] = info.passive_err_data;
+        let counted = rec.saturating_sub(self.rec) as usize
+            + tec.saturating_sub(self.tec).div_ceil(8) as usize;
+        let event = info.err_code & (ERR_CAN_OVERFLOW | ERR_CAN_LOSE | ERR_CAN_BUSERR) != 0;
+        // 只有狀態變嚴重 (警告 -> 被動 -> 匯流排關閉) 才算事件，恢復不算
+        let severity = [ERR_CAN_BUSOFF, ERR_CAN_PASSIVE, ERR_CAN_ERRALARM]
+            .iter()
+            .position(|&bit| info.err_code & bit != 0)
+            .map_or(0, |i| 3 - i as u8);
+        let escalated = severity > self.severity;
+        self.severity = severity;
+        self.rec = rec;
+        self.tec = tec;
+
+        let count = counted.max((event || escalated) as usize);
+        info.to_frame(channel, timestamp_us)
+            .map(|frame| vec![frame; count])
+            .unwrap_or_default()
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct VciBoardInfo {
@@ -62,6 +196,95 @@ impl Default for VciBoardInfo {
     }
 }
 
+/// 擷取到的一幀 CAN 資料，時間戳為 Unix epoch 起算的微秒
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CanFrame {
+    pub timestamp_us: u64,
+    pub channel: u32,
+    pub id: u32,
+    pub extended: bool,
+    pub remote: bool,
+    pub error: bool,
+    pub len: u8,
+    pub data: [u8; 8],
+}
+
+impl CanFrame {
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.len.min(8) as usize)]
+    }
+}
+
+impl fmt::Display for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ID=0x{:X}, Data={:?}", self.id, self.payload())
+    }
+}
+
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// 解析 "01 02 A0" 或 "0102A0" 形式的十六進位資料
+pub fn parse_hex_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|s| s.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    if !digits.is_ascii() {
+        return Err(format!("無效的十六進位資料: {}", text.trim()));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("十六進位資料長度不是偶數: {}", text.trim()));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("無效的十六進位資料: {}", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// 把板卡 0.1ms 為單位、會溢位的 time_stamp 換算成 epoch 微秒
+struct HwClock {
+    host_base_us: u64,
+    hw_base: u32,
+    last_hw: u32,
+    wraps: u64,
+}
+
+impl HwClock {
+    fn new() -> Self {
+        Self {
+            host_base_us: 0,
+            hw_base: 0,
+            last_hw: 0,
+            wraps: 0,
+        }
+    }
+
+    fn epoch_us(&mut self, obj: &VciCanObj) -> u64 {
+        if obj.time_flag == 0 {
+            return now_us();
+        }
+        if self.host_base_us == 0 {
+            self.host_base_us = now_us();
+            self.hw_base = obj.time_stamp;
+            self.last_hw = obj.time_stamp;
+        }
+        if obj.time_stamp < self.last_hw {
+            self.wraps += 1;
+        }
+        self.last_hw = obj.time_stamp;
+        let ticks = (self.wraps << 32) + obj.time_stamp as u64 - self.hw_base as u64;
+        self.host_base_us + ticks * 100
+    }
+}
+
 pub struct CanLibrary {
     _lib: Arc<Library>,
     pub vci_open_device: unsafe extern "stdcall" fn(u32, u32, u32) -> i32,
@@ -69,7 +292,9 @@ pub struct CanLibrary {
     pub vci_init_can: unsafe extern "stdcall" fn(u32, u32, u32, *const VciInitConfig) -> i32,
     pub vci_start_can: unsafe extern "stdcall" fn(u32, u32, u32) -> i32,
     pub vci_receive: unsafe extern "stdcall" fn(u32, u32, u32, *mut VciCanObj, u32, i32) -> i32,
+    pub vci_transmit: unsafe extern "stdcall" fn(u32, u32, u32, *const VciCanObj, u32) -> i32,
     pub vci_read_board_info: unsafe extern "stdcall" fn(u32, u32, *mut VciBoardInfo) -> i32,
+    pub vci_read_err_info: unsafe extern "stdcall" fn(u32, u32, u32, *mut VciErrInfo) -> i32,
 }
 
 impl CanLibrary {
@@ -89,9 +314,15 @@ impl CanLibrary {
                     .get(b"VCI_StartCAN")
                     .expect("Failed to get VCI_StartCAN"),
                 vci_receive: *lib.get(b"VCI_Receive").expect("Failed to get VCI_Receive"),
+                vci_transmit: *lib
+                    .get(b"VCI_Transmit")
+                    .expect("Failed to get VCI_Transmit"),
                 vci_read_board_info: *lib
                     .get(b"VCI_ReadBoardInfo")
                     .expect("Failed to get VCI_ReadBoardInfo"),
+                vci_read_err_info: *lib
+                    .get(b"VCI_ReadErrInfo")
+                    .expect("Failed to get VCI_ReadErrInfo"),
             })
         }
     }
@@ -186,7 +417,7 @@ impl CanApp {
         dev_index: u32,
         can_channel: u32,
         log_tx: Sender<String>,
-        data_tx: Sender<String>,
+        data_tx: Sender<CanFrame>,
     ) {
         let receiving_flag = Arc::clone(&self.receiving);
         let can_lib = Arc::clone(&self.can_lib);
@@ -207,21 +438,93 @@ impl CanApp {
         receiving_flag.store(true, Ordering::SeqCst);
 
         thread::spawn(move || {
+            let mut clock = HwClock::new();
+            let mut buffer: Vec<VciCanObj> =
+                (0..RECEIVE_BATCH).map(|_| VciCanObj::default()).collect();
+            let mut last_error_poll = Instant::now();
+            let mut errors = ErrorTracker::default();
             while receiving_flag.load(Ordering::SeqCst) {
-                let mut can_obj = VciCanObj::default();
+                // 控制器錯誤不會出現在接收緩衝區，定期讀出來轉成錯誤幀
+                if last_error_poll.elapsed() >= ERROR_POLL_INTERVAL {
+                    last_error_poll = Instant::now();
+                    let mut info = VciErrInfo::default();
+                    let status = unsafe {
+                        (can_lib.vci_read_err_info)(dev_type, dev_index, can_channel, &mut info)
+                    };
+                    if status == 1 {
+                        for frame in errors.frames(&info, can_channel, now_us()) {
+                            let _ = data_tx.send(frame);
+                        }
+                    }
+                }
+
                 let received_frames = unsafe {
-                    (can_lib.vci_receive)(dev_type, dev_index, can_channel, &mut can_obj, 1, 500)
+                    (can_lib.vci_receive)(
+                        dev_type,
+                        dev_index,
+                        can_channel,
+                        buffer.as_mut_ptr(),
+                        RECEIVE_BATCH as u32,
+                        500,
+                    )
                 };
 
-                if received_frames > 0 {
-                    let data = &can_obj.data[..(can_obj.data_len as usize)];
-                    let msg = format!("ID=0x{:X}, Data={:?}", can_obj.id, data);
-                    let _ = data_tx.send(msg);
+                // 一次取回一批，沒有資料時才休息，避免高負載時被 sleep 限速
+                if received_frames <= 0 {
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                for can_obj in &buffer[..(received_frames as usize).min(RECEIVE_BATCH)] {
+                    let frame = CanFrame {
+                        timestamp_us: clock.epoch_us(can_obj),
+                        channel: can_channel,
+                        id: can_obj.id,
+                        extended: can_obj.extern_flag != 0,
+                        remote: can_obj.remote_flag != 0,
+                        error: false,
+                        len: can_obj.data_len.min(8),
+                        data: can_obj.data,
+                    };
+                    let _ = data_tx.send(frame);
                 }
+            }
+        });
+    }
 
-                thread::sleep(Duration::from_millis(10));
+    /// 啟動發送執行緒，回傳的 Sender 即為發送路徑；所有 Sender 被丟棄後執行緒結束
+    pub fn start_transmitter(
+        &self,
+        dev_type: u32,
+        dev_index: u32,
+        log_tx: Sender<String>,
+    ) -> Sender<CanFrame> {
+        let (tx, rx) = flume::unbounded::<CanFrame>();
+        let can_lib = Arc::clone(&self.can_lib);
+        let initialized = Arc::clone(&self.is_can_initialized);
+
+        thread::spawn(move || {
+            while let Ok(frame) = rx.recv() {
+                if !initialized.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let obj = VciCanObj {
+                    id: frame.id,
+                    send_type: 0,
+                    remote_flag: frame.remote as u8,
+                    extern_flag: frame.extended as u8,
+                    data_len: frame.len.min(8),
+                    data: frame.data,
+                    ..Default::default()
+                };
+                let sent =
+                    unsafe { (can_lib.vci_transmit)(dev_type, dev_index, frame.channel, &obj, 1) };
+                if sent != 1 {
+                    let _ = log_tx.send(format!("發送失敗: {}", frame));
+                }
             }
         });
+
+        tx
     }
 
     pub fn stop_receiving(&self) {
@@ -306,3 +609,103 @@ impl CanApp {
         let _ = log_tx.send(msg);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_info_to_frame() {
+        assert_eq!(VciErrInfo::default().to_frame(0, 0), None);
+        // 只有裝置錯誤 (高位元) 不算匯流排錯誤
+        let device = VciErrInfo {
+            err_code: 0x0100,
+            ..Default::default()
+        };
+        assert_eq!(device.to_frame(0, 0), None);
+
+        // 接收時的填充錯誤，接收錯誤計數超過警告界線
+        let info = VciErrInfo {
+            err_code: ERR_CAN_BUSERR | ERR_CAN_ERRALARM,
+            passive_err_data: [0xA0 | 0x08, 100, 3],
+            ar_lost_err_data: 0,
+        };
+        let frame = info.to_frame(1, 42).unwrap();
+        assert!(frame.error);
+        assert_eq!((frame.channel, frame.timestamp_us), (1, 42));
+        assert_eq!(frame.id, CAN_ERR_CRTL | CAN_ERR_PROT | CAN_ERR_CNT);
+        assert_eq!(
+            frame.data,
+            [0, CAN_ERR_CRTL_RX_WARNING, 0x04, 0x08, 0, 0, 3, 100]
+        );
+
+        // 發送時的位元錯誤導致匯流排關閉
+        let info = VciErrInfo {
+            err_code: ERR_CAN_BUSERR | ERR_CAN_BUSOFF,
+            passive_err_data: [0x19, 0, 255],
+            ar_lost_err_data: 0,
+        };
+        let frame = info.to_frame(0, 0).unwrap();
+        assert_eq!(frame.id, CAN_ERR_PROT | CAN_ERR_BUSOFF | CAN_ERR_CNT);
+        assert_eq!(frame.data[2], 0x01 | CAN_ERR_PROT_TX);
+        assert_eq!(frame.data[3], 0x19);
+    }
+
+    #[test]
+    fn error_tracker_reports_only_new_events() {
+        let mut tracker = ErrorTracker::default();
+        let passive = VciErrInfo {
+            err_code: ERR_CAN_PASSIVE,
+            passive_err_data: [0, 130, 0],
+            ar_lost_err_data: 0,
+        };
+        // 進入被動狀態時計數器從 0 跳到 130，每次 +1 算一個錯誤
+        assert_eq!(tracker.frames(&passive, 0, 0).len(), 130);
+        // 停在被動狀態不再產生錯誤幀
+        assert!(tracker.frames(&passive, 0, 0).is_empty());
+        assert!(tracker.frames(&passive, 0, 0).is_empty());
+
+        // 輪詢間隔內 3 個接收錯誤
+        let more = VciErrInfo {
+            err_code: ERR_CAN_PASSIVE | ERR_CAN_BUSERR,
+            passive_err_data: [0xA8, 133, 0],
+            ar_lost_err_data: 0,
+        };
+        let frames = tracker.frames(&more, 2, 7);
+        assert_eq!(frames.len(), 3);
+        assert!(frames
+            .iter()
+            .all(|f| f.error && f.channel == 2 && f.timestamp_us == 7));
+
+        // 兩個發送錯誤各 +8
+        let tx = VciErrInfo {
+            err_code: ERR_CAN_PASSIVE | ERR_CAN_BUSERR,
+            passive_err_data: [0x08, 133, 16],
+            ar_lost_err_data: 0,
+        };
+        assert_eq!(tracker.frames(&tx, 0, 0).len(), 2);
+
+        // 計數器沒變的 ECC / ALC 事件仍算一個
+        let lost = VciErrInfo {
+            err_code: ERR_CAN_PASSIVE | ERR_CAN_LOSE,
+            passive_err_data: [0, 133, 16],
+            ar_lost_err_data: 4,
+        };
+        assert_eq!(tracker.frames(&lost, 0, 0).len(), 1);
+
+        // 計數器下降 (恢復中) 不是錯誤
+        let recovering = VciErrInfo {
+            err_code: ERR_CAN_ERRALARM,
+            passive_err_data: [0, 100, 16],
+            ar_lost_err_data: 0,
+        };
+        assert!(tracker.frames(&recovering, 0, 0).is_empty());
+        // 再次進入被動狀態是狀態變嚴重
+        let passive_again = VciErrInfo {
+            err_code: ERR_CAN_PASSIVE,
+            passive_err_data: [0, 100, 16],
+            ar_lost_err_data: 0,
+        };
+        assert_eq!(tracker.frames(&passive_again, 0, 0).len(), 1);
+    }
+}
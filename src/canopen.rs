@@ -0,0 +1,1009 @@
+use crate::canbus::{parse_hex_bytes, CanFrame};
+use crate::eds::{pdo_mappings, ObjectDictionary, PdoMapping, OBJECT_VAR};
+use crate::sdo::{SdoClient, SdoRequest, SdoResult};
+use eframe::egui::{self, Color32};
+use std::collections::{BTreeMap, VecDeque};
+
+const NMT_ID: u32 = 0x000;
+const EMCY_BASE: u32 = 0x080;
+const HEARTBEAT_BASE: u32 = 0x700;
+// 每個節點保留的 EMCY 筆數
+const EMCY_HISTORY: usize = 20;
+const SDO_HISTORY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmtCommand {
+    Start,
+    Stop,
+    PreOperational,
+    ResetNode,
+    ResetCommunication,
+}
+
+impl NmtCommand {
+    pub const ALL: [NmtCommand; 5] = [
+        NmtCommand::Start,
+        NmtCommand::Stop,
+        NmtCommand::PreOperational,
+        NmtCommand::ResetNode,
+        NmtCommand::ResetCommunication,
+    ];
+
+    fn code(self) -> u8 {
+        match self {
+            NmtCommand::Start => 0x01,
+            NmtCommand::Stop => 0x02,
+            NmtCommand::PreOperational => 0x80,
+            NmtCommand::ResetNode => 0x81,
+            NmtCommand::ResetCommunication => 0x82,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            NmtCommand::Start => "Start",
+            NmtCommand::Stop => "Stop",
+            NmtCommand::PreOperational => "Pre-op",
+            NmtCommand::ResetNode => "Reset Node",
+            NmtCommand::ResetCommunication => "Reset Comm",
+        }
+    }
+}
+
+/// 心跳或節點守護回報的 NMT 狀態
+pub fn nmt_state_name(state: u8) -> &'static str {
+    match state {
+        0x00 => "Boot-up",
+        0x04 => "Stopped",
+        0x05 => "Operational",
+        0x7F => "Pre-operational",
+        _ => "未知",
+    }
+}
+
+/// EMCY 錯誤碼的類別 (CiA 301)
+pub fn emcy_code_name(code: u16) -> &'static str {
+    match code {
+        0x0000 => "錯誤重置 / 無錯誤",
+        0x8110 => "CAN 溢位",
+        0x8120 => "CAN error passive",
+        0x8130 => "節點守護或心跳錯誤",
+        0x8140 => "從 bus off 恢復",
+        0x8150 => "CAN ID 衝突",
+        0x8210 => "PDO 長度不足",
+        0x8220 => "PDO 長度超過",
+        0x8240 => "DAM 資料錯誤",
+        0x8250 => "RPDO 逾時",
+        _ => match code >> 8 {
+            0x10 => "一般錯誤",
+            0x20..=0x2F => "電流",
+            0x30..=0x3F => "電壓",
+            0x40..=0x4F => "溫度",
+            0x50 => "裝置硬體",
+            0x60..=0x6F => "裝置軟體",
+            0x70 => "附加模組",
+            0x80..=0x8F => "監控 / 通訊",
+            0x90 => "外部錯誤",
+            0xF0 => "附加功能",
+            0xFF => "裝置自訂",
+            _ => "未知",
+        },
+    }
+}
+
+/// 錯誤暫存器 (1001h) 各位元
+fn error_register_bits(register: u8) -> String {
+    const BITS: [&str; 8] = [
+        "一般",
+        "電流",
+        "電壓",
+        "溫度",
+        "通訊",
+        "裝置規範",
+        "保留",
+        "製造商",
+    ];
+    let names: Vec<&str> = (0..8)
+        .filter(|bit| register & 1 << bit != 0)
+        .map(|bit| BITS[bit])
+        .collect();
+    names.join("/")
+}
+
+pub struct Emcy {
+    pub timestamp_us: u64,
+    pub code: u16,
+    pub register: u8,
+    pub data: [u8; 5],
+}
+
+/// 節點清單中的一個節點
+#[derive(Default)]
+pub struct NodeInfo {
+    pub state: Option<u8>,
+    pub last_heartbeat_us: Option<u64>,
+    pub heartbeat_lost: bool,
+    pub guarded: bool,
+    pub last_guard_us: Option<u64>,
+    // 下一個守護回應預期的切換位元
+    guard_toggle: u8,
+    pub guard_lost: bool,
+    pub emcy: VecDeque<Emcy>,
+}
+
+/// CANopen 主站：NMT、心跳/節點守護監控、EMCY 與 SDO 用戶端
+pub struct CanOpenMaster {
+    pub channel: u32,
+    pub nodes: BTreeMap<u8, NodeInfo>,
+    // 超過這個時間沒收到心跳就標示逾時，0 表示不檢查
+    pub heartbeat_timeout_ms: u64,
+    pub guard_time_ms: u64,
+    pub life_time_factor: u64,
+    pub sdo: SdoClient,
+    pub outbox: Vec<CanFrame>,
+    pub events: Vec<String>,
+    next_guard_us: u64,
+}
+
+impl Default for CanOpenMaster {
+    fn default() -> Self {
+        Self {
+            channel: 0,
+            nodes: BTreeMap::new(),
+            heartbeat_timeout_ms: 1500,
+            guard_time_ms: 1000,
+            life_time_factor: 3,
+            sdo: SdoClient::default(),
+            outbox: Vec::new(),
+            events: Vec::new(),
+            next_guard_us: 0,
+        }
+    }
+}
+
+impl CanOpenMaster {
+    /// node 為 0 時送給所有節點
+    pub fn nmt(&mut self, command: NmtCommand, node: u8) {
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: NMT_ID,
+            len: 2,
+            data: [command.code(), node, 0, 0, 0, 0, 0, 0],
+            ..Default::default()
+        });
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        self.sdo.handle(frame, now_us);
+        if frame.channel != self.channel || frame.extended || frame.error {
+            return;
+        }
+        let node = (frame.id & 0x7F) as u8;
+        if node == 0 {
+            return;
+        }
+        match frame.id & !0x7F {
+            // 1. **心跳、開機訊息與守護回應**
+            HEARTBEAT_BASE if !frame.remote && frame.len >= 1 => {
+                let state = frame.data[0] & 0x7F;
+                let info = self.nodes.entry(node).or_default();
+                // 守護中的節點回應帶切換位元，其他的當作心跳
+                if info.guarded {
+                    let toggle = frame.data[0] >> 7;
+                    if info.last_guard_us.is_some() && toggle != info.guard_toggle {
+                        self.events
+                            .push(format!("CANopen: 節點 {} 守護切換位元錯誤", node));
+                    }
+                    info.guard_toggle = toggle ^ 1;
+                    info.last_guard_us = Some(now_us);
+                    if info.guard_lost {
+                        info.guard_lost = false;
+                        self.events.push(format!("CANopen: 節點 {} 守護恢復", node));
+                    }
+                } else {
+                    info.last_heartbeat_us = Some(now_us);
+                    if info.heartbeat_lost {
+                        info.heartbeat_lost = false;
+                        self.events.push(format!("CANopen: 節點 {} 心跳恢復", node));
+                    }
+                }
+                if state == 0x00 {
+                    self.events.push(format!("CANopen: 節點 {} 開機", node));
+                    info.guard_toggle = 0;
+                } else if info.state != Some(state) {
+                    self.events.push(format!(
+                        "CANopen: 節點 {} -> {}",
+                        node,
+                        nmt_state_name(state)
+                    ));
+                }
+                info.state = Some(state);
+            }
+            // 2. **EMCY**
+            EMCY_BASE if !frame.remote && frame.len == 8 => {
+                let d = frame.data;
+                let emcy = Emcy {
+                    timestamp_us: frame.timestamp_us,
+                    code: u16::from_le_bytes([d[0], d[1]]),
+                    register: d[2],
+                    data: [d[3], d[4], d[5], d[6], d[7]],
+                };
+                self.events.push(format!(
+                    "CANopen: 節點 {} EMCY 0x{:04X} {}",
+                    node,
+                    emcy.code,
+                    emcy_code_name(emcy.code)
+                ));
+                let info = self.nodes.entry(node).or_default();
+                info.emcy.push_front(emcy);
+                info.emcy.truncate(EMCY_HISTORY);
+            }
+            _ => {}
+        }
+    }
+
+    /// 每個畫面呼叫一次：送節點守護請求、檢查心跳與守護逾時
+    pub fn poll(&mut self, now_us: u64) {
+        self.sdo.channel = self.channel;
+        self.sdo.poll(now_us);
+
+        let heartbeat_timeout_us = self.heartbeat_timeout_ms * 1000;
+        let life_time_us = self.guard_time_ms * self.life_time_factor * 1000;
+        for (node, info) in &mut self.nodes {
+            if heartbeat_timeout_us > 0
+                && !info.heartbeat_lost
+                && info
+                    .last_heartbeat_us
+                    .is_some_and(|t| now_us > t + heartbeat_timeout_us)
+            {
+                info.heartbeat_lost = true;
+                self.events.push(format!("CANopen: 節點 {} 心跳逾時", node));
+            }
+            if info.guarded
+                && !info.guard_lost
+                && info
+                    .last_guard_us
+                    .is_some_and(|t| now_us > t + life_time_us)
+            {
+                info.guard_lost = true;
+                self.events.push(format!("CANopen: 節點 {} 守護逾時", node));
+            }
+        }
+
+        if now_us >= self.next_guard_us {
+            self.next_guard_us = now_us + self.guard_time_ms * 1000;
+            for (node, info) in &mut self.nodes {
+                if !info.guarded {
+                    continue;
+                }
+                // 第一次守護從送出請求開始計時
+                info.last_guard_us.get_or_insert(now_us);
+                self.outbox.push(CanFrame {
+                    channel: self.channel,
+                    id: HEARTBEAT_BASE + *node as u32,
+                    remote: true,
+                    len: 1,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    /// 取出要送的幀
+    pub fn drain_outbox(&mut self) -> impl Iterator<Item = CanFrame> + '_ {
+        self.outbox.drain(..).chain(self.sdo.outbox.drain(..))
+    }
+}
+
+/// CANopen 面板：NMT、節點清單、SDO、物件字典與 PDO
+pub struct CanOpenPanel {
+    pub master: CanOpenMaster,
+    nmt_node: u8,
+    add_node: u8,
+    sdo_node: u8,
+    sdo_index: u16,
+    sdo_sub_index: u8,
+    sdo_block: bool,
+    sdo_data_text: String,
+    history: VecDeque<SdoResult>,
+    eds_path: String,
+    dictionary: Option<ObjectDictionary>,
+    od_filter: String,
+    selected: Option<(u16, u8)>,
+    edit_text: String,
+    // SDO 從裝置讀到的值
+    device_values: BTreeMap<(u16, u8), Vec<u8>>,
+    reading_mapping: bool,
+    pdos: Vec<PdoMapping>,
+    // PDO 解出的值 (時間, 原始值, 位元數)
+    pdo_values: BTreeMap<(u16, u8), (u64, u64, u32)>,
+}
+
+impl Default for CanOpenPanel {
+    fn default() -> Self {
+        Self {
+            master: CanOpenMaster::default(),
+            nmt_node: 0,
+            add_node: 1,
+            sdo_node: 1,
+            sdo_index: 0x1000,
+            sdo_sub_index: 0,
+            sdo_block: false,
+            sdo_data_text: String::new(),
+            history: VecDeque::new(),
+            eds_path: String::new(),
+            dictionary: None,
+            od_filter: String::new(),
+            selected: None,
+            edit_text: String::new(),
+            device_values: BTreeMap::new(),
+            reading_mapping: false,
+            pdos: Vec::new(),
+            pdo_values: BTreeMap::new(),
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// 讀到的值另外顯示成小端整數與文字
+fn describe_value(data: &[u8]) -> String {
+    let mut text = hex(data);
+    if (1..=4).contains(&data.len()) {
+        let mut bytes = [0u8; 4];
+        bytes[..data.len()].copy_from_slice(data);
+        text += &format!("  = {}", u32::from_le_bytes(bytes));
+    } else if !data.is_empty() && data.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        text += &format!("  \"{}\"", String::from_utf8_lossy(data));
+    }
+    text
+}
+
+fn le_value(data: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    let n = data.len().min(8);
+    bytes[..n].copy_from_slice(&data[..n]);
+    u64::from_le_bytes(bytes)
+}
+
+fn is_mapping_index(index: u16) -> bool {
+    (0x1600..0x1800).contains(&index) || (0x1A00..0x1C00).contains(&index)
+}
+
+impl CanOpenPanel {
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        self.master.handle(frame, now_us);
+        if frame.channel != self.master.channel || frame.remote || frame.error {
+            return;
+        }
+        for pdo in &self.pdos {
+            if pdo.cob_id != frame.id || pdo.extended != frame.extended {
+                continue;
+            }
+            for (index, sub_index, raw, bits) in pdo.decode(frame.payload()) {
+                self.pdo_values
+                    .insert((index, sub_index), (frame.timestamp_us, raw, bits));
+            }
+        }
+    }
+
+    /// 收集完成的 SDO 結果
+    pub fn process(&mut self, log: &mut Vec<String>) {
+        log.append(&mut self.master.events);
+        while let Some(result) = self.master.sdo.completed.pop_front() {
+            match &result.result {
+                Err(e) => log.push(format!("SDO {}: {}", result.request.describe(), e)),
+                Ok(data) if result.request.write.is_none() => {
+                    let key = (result.request.index, result.request.sub_index);
+                    self.device_values.insert(key, data.clone());
+                    // 讀到映射的筆數後接著讀每一筆映射
+                    if self.reading_mapping && key.1 == 0 && is_mapping_index(key.0) {
+                        for sub_index in 1..=data.first().copied().unwrap_or(0).min(64) {
+                            self.master.sdo.request(SdoRequest {
+                                sub_index,
+                                block: false,
+                                ..result.request.clone()
+                            });
+                        }
+                    }
+                }
+                Ok(_) => {}
+            }
+            self.history.push_front(result);
+        }
+        self.history.truncate(SDO_HISTORY);
+
+        if self.reading_mapping && self.master.sdo.is_idle() {
+            self.reading_mapping = false;
+            let values = &self.device_values;
+            self.pdos = pdo_mappings(|index, sub_index| {
+                values.get(&(index, sub_index)).map(|data| le_value(data))
+            });
+            self.pdo_values.clear();
+            log.push(format!("CANopen: 從裝置讀到 {} 個 PDO", self.pdos.len()));
+        }
+    }
+
+    // 有物件字典時依型別顯示，否則用通用格式
+    fn format_value(&self, index: u16, sub_index: u8, data: &[u8]) -> String {
+        match self
+            .dictionary
+            .as_ref()
+            .and_then(|d| d.entry(index, sub_index))
+        {
+            Some(entry) => entry.data_type.format(data),
+            None => describe_value(data),
+        }
+    }
+
+    fn read_pdo_mapping(&mut self) {
+        self.device_values
+            .retain(|(index, _), _| !(0x1400..0x1C00).contains(index));
+        for number in 0..4 {
+            for (index, sub_index) in [
+                (0x1400 + number, 1),
+                (0x1600 + number, 0),
+                (0x1800 + number, 1),
+                (0x1A00 + number, 0),
+            ] {
+                self.master.sdo.request(SdoRequest {
+                    node: self.sdo_node,
+                    index,
+                    sub_index,
+                    write: None,
+                    block: false,
+                });
+            }
+        }
+        self.reading_mapping = true;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool, now_us: u64) {
+        // 1. **NMT**
+        ui.add_enabled_ui(can_send, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("NMT 節點 (0 = 全部):");
+                ui.add(egui::DragValue::new(&mut self.nmt_node).range(0..=127));
+                for command in NmtCommand::ALL {
+                    if ui.button(command.label()).clicked() {
+                        self.master.nmt(command, self.nmt_node);
+                    }
+                }
+            });
+        });
+
+        // 2. **節點清單**
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("心跳逾時:");
+            ui.add(
+                egui::DragValue::new(&mut self.master.heartbeat_timeout_ms)
+                    .range(0..=60_000)
+                    .suffix(" ms"),
+            );
+            ui.label("守護時間:");
+            ui.add(
+                egui::DragValue::new(&mut self.master.guard_time_ms)
+                    .range(10..=60_000)
+                    .suffix(" ms"),
+            );
+            ui.label("壽命因子:");
+            ui.add(egui::DragValue::new(&mut self.master.life_time_factor).range(1..=255));
+            ui.add(egui::DragValue::new(&mut self.add_node).range(1..=127));
+            if ui.button("加入節點").clicked() {
+                self.master.nodes.entry(self.add_node).or_default();
+            }
+            if ui.button("清除").clicked() {
+                self.master.nodes.clear();
+            }
+        });
+        egui::Grid::new("canopen_nodes")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                for header in ["節點", "狀態", "心跳", "守護", "最近 EMCY"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (node, info) in &mut self.master.nodes {
+                    ui.monospace(format!("{}", node));
+                    ui.label(info.state.map_or("-", nmt_state_name));
+                    match info.last_heartbeat_us {
+                        Some(t) => {
+                            let age = format!("{} ms 前", now_us.saturating_sub(t) / 1000);
+                            if info.heartbeat_lost {
+                                ui.colored_label(Color32::RED, format!("逾時 ({})", age));
+                            } else {
+                                ui.label(age);
+                            }
+                        }
+                        None => {
+                            ui.label("-");
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut info.guarded, "").changed() {
+                            info.last_guard_us = None;
+                            info.guard_lost = false;
+                            info.guard_toggle = 0;
+                        }
+                        if info.guard_lost {
+                            ui.colored_label(Color32::RED, "逾時");
+                        }
+                    });
+                    match info.emcy.front() {
+                        Some(emcy) => {
+                            ui.label(format!(
+                                "0x{:04X} {} (共 {} 筆)",
+                                emcy.code,
+                                emcy_code_name(emcy.code),
+                                info.emcy.len()
+                            ))
+                            .on_hover_ui(|ui| {
+                                for emcy in &info.emcy {
+                                    ui.monospace(format!(
+                                        "{:>10.3}s  0x{:04X} {}  暫存器 {:02X} [{}]  {}",
+                                        emcy.timestamp_us as f64 / 1e6,
+                                        emcy.code,
+                                        emcy_code_name(emcy.code),
+                                        emcy.register,
+                                        error_register_bits(emcy.register),
+                                        hex(&emcy.data)
+                                    ));
+                                }
+                            });
+                        }
+                        None => {
+                            ui.label("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+        // 3. **SDO**
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("SDO 節點:");
+            if ui
+                .add(egui::DragValue::new(&mut self.sdo_node).range(1..=127))
+                .changed()
+            {
+                self.device_values.clear();
+            }
+            ui.label("索引:");
+            ui.add(egui::DragValue::new(&mut self.sdo_index).hexadecimal(4, false, true));
+            ui.label("子索引:");
+            ui.add(egui::DragValue::new(&mut self.sdo_sub_index).hexadecimal(2, false, true));
+            ui.checkbox(&mut self.sdo_block, "區塊傳輸");
+            ui.label("逾時:");
+            ui.add(
+                egui::DragValue::new(&mut self.master.sdo.timeout_ms)
+                    .range(10..=60_000)
+                    .suffix(" ms"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("寫入資料:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.sdo_data_text)
+                    .hint_text("十六進位，小端")
+                    .desired_width(240.0)
+                    .font(egui::TextStyle::Monospace),
+            );
+            ui.add_enabled_ui(can_send, |ui| {
+                let mut request = SdoRequest {
+                    node: self.sdo_node,
+                    index: self.sdo_index,
+                    sub_index: self.sdo_sub_index,
+                    write: None,
+                    block: self.sdo_block,
+                };
+                if ui.button("讀取").clicked() {
+                    self.master.sdo.request(request.clone());
+                }
+                if ui.button("寫入").clicked() {
+                    match parse_hex_bytes(&self.sdo_data_text) {
+                        Ok(data) => {
+                            request.write = Some(data);
+                            self.master.sdo.request(request);
+                        }
+                        Err(e) => self.master.events.push(format!("SDO: {}", e)),
+                    }
+                }
+            });
+            if !self.master.sdo.is_idle() {
+                ui.spinner();
+                if ui.button("中止").clicked() {
+                    self.master.sdo.cancel();
+                }
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for result in &self.history {
+                    let text = match &result.result {
+                        Ok(data) if result.request.write.is_none() => {
+                            self.format_value(result.request.index, result.request.sub_index, data)
+                        }
+                        Ok(_) => "完成".to_string(),
+                        Err(e) => e.clone(),
+                    };
+                    ui.monospace(format!(
+                        "{}  {} ms  {}",
+                        result.request.describe(),
+                        result.elapsed_ms,
+                        text
+                    ));
+                }
+            });
+
+        // 4. **物件字典**
+        ui.separator();
+        egui::CollapsingHeader::new("物件字典 (EDS/DCF)")
+            .default_open(false)
+            .show(ui, |ui| self.dictionary_ui(ui, can_send));
+
+        // 5. **PDO**
+        egui::CollapsingHeader::new("PDO")
+            .default_open(false)
+            .show(ui, |ui| self.pdo_ui(ui, can_send, now_us));
+    }
+
+    fn dictionary_ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        ui.horizontal(|ui| {
+            ui.label("EDS/DCF:");
+            ui.text_edit_singleline(&mut self.eds_path);
+            if ui.button("載入").clicked() {
+                match ObjectDictionary::load(&self.eds_path) {
+                    Ok(dictionary) => {
+                        self.master.events.push(format!(
+                            "CANopen: 物件字典已載入: {} 個物件",
+                            dictionary.objects.len()
+                        ));
+                        // DCF 指定了節點 ID 就直接套用
+                        if let Some(node) = dictionary.node_id.filter(|n| (1..=127).contains(n)) {
+                            self.sdo_node = node;
+                        }
+                        self.dictionary = Some(dictionary);
+                        self.selected = None;
+                    }
+                    Err(e) => self.master.events.push(format!("CANopen: {}", e)),
+                }
+            }
+        });
+        let Some(dictionary) = &self.dictionary else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            if let Some(name) = &dictionary.product_name {
+                ui.label(name);
+            }
+            ui.label("篩選:");
+            ui.text_edit_singleline(&mut self.od_filter);
+            if ui
+                .add_enabled(can_send, egui::Button::new("讀取全部"))
+                .clicked()
+            {
+                for entry in dictionary.objects.values().flat_map(|o| &o.entries) {
+                    if entry.access.readable() {
+                        self.master.sdo.request(SdoRequest {
+                            node: self.sdo_node,
+                            index: entry.index,
+                            sub_index: entry.sub_index,
+                            write: None,
+                            block: false,
+                        });
+                    }
+                }
+            }
+        });
+
+        let filter = self.od_filter.to_lowercase();
+        egui::ScrollArea::vertical()
+            .id_salt("canopen_od")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("canopen_od_grid")
+                    .striped(true)
+                    .num_columns(7)
+                    .show(ui, |ui| {
+                        for header in ["索引", "名稱", "型別", "存取", "PDO", "預設/DCF", "裝置值"]
+                        {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for object in dictionary.objects.values() {
+                            for entry in &object.entries {
+                                let name = if object.object_type != OBJECT_VAR
+                                    && entry.name != object.name
+                                {
+                                    format!("{}: {}", object.name, entry.name)
+                                } else {
+                                    entry.name.clone()
+                                };
+                                let key = (entry.index, entry.sub_index);
+                                let label = format!("{:04X}sub{:X}", entry.index, entry.sub_index);
+                                if !filter.is_empty()
+                                    && !name.to_lowercase().contains(&filter)
+                                    && !label.to_lowercase().contains(&filter)
+                                {
+                                    continue;
+                                }
+                                ui.monospace(label);
+                                let device_value = self
+                                    .device_values
+                                    .get(&key)
+                                    .map(|data| entry.data_type.format(data));
+                                if ui
+                                    .selectable_label(self.selected == Some(key), name)
+                                    .clicked()
+                                {
+                                    self.selected = Some(key);
+                                    self.edit_text = device_value
+                                        .clone()
+                                        .or_else(|| entry.value_text().map(str::to_string))
+                                        .unwrap_or_default();
+                                }
+                                ui.label(entry.data_type.name());
+                                ui.label(entry.access.label());
+                                ui.label(if entry.pdo_mapping { "✔" } else { "" });
+                                ui.label(entry.value_text().unwrap_or(""));
+                                ui.label(device_value.unwrap_or_default());
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
+
+        // 選取的物件：讀取或依型別寫入
+        let Some(entry) = self.selected.and_then(|(i, s)| dictionary.entry(i, s)) else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{:04X}sub{:X} {}",
+                entry.index, entry.sub_index, entry.name
+            ));
+            ui.text_edit_singleline(&mut self.edit_text);
+            let request = SdoRequest {
+                node: self.sdo_node,
+                index: entry.index,
+                sub_index: entry.sub_index,
+                write: None,
+                block: false,
+            };
+            if ui
+                .add_enabled(
+                    can_send && entry.access.readable(),
+                    egui::Button::new("讀取"),
+                )
+                .clicked()
+            {
+                self.master.sdo.request(request.clone());
+            }
+            if ui
+                .add_enabled(
+                    can_send && entry.access.writable(),
+                    egui::Button::new("寫入"),
+                )
+                .clicked()
+            {
+                match entry.data_type.encode(&self.edit_text) {
+                    Ok(data) => self.master.sdo.request(SdoRequest {
+                        write: Some(data),
+                        ..request
+                    }),
+                    Err(e) => self.master.events.push(format!("CANopen: {}", e)),
+                }
+            }
+        });
+    }
+
+    fn pdo_ui(&mut self, ui: &mut egui::Ui, can_send: bool, now_us: u64) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    can_send && !self.reading_mapping,
+                    egui::Button::new("從裝置讀取映射"),
+                )
+                .clicked()
+            {
+                self.read_pdo_mapping();
+            }
+            if self.reading_mapping {
+                ui.spinner();
+            }
+            if let Some(dictionary) = &self.dictionary {
+                if ui.button("使用 EDS/DCF 的映射").clicked() {
+                    self.pdos = dictionary.pdo_mappings(self.sdo_node);
+                    self.pdo_values.clear();
+                }
+            }
+        });
+        for pdo in &self.pdos {
+            ui.label(pdo.label());
+            egui::Grid::new(("canopen_pdo", pdo.transmit, pdo.number))
+                .striped(true)
+                .num_columns(4)
+                .show(ui, |ui| {
+                    for &(index, sub_index, bits) in &pdo.entries {
+                        let entry = self
+                            .dictionary
+                            .as_ref()
+                            .and_then(|d| d.entry(index, sub_index));
+                        ui.monospace(format!("{:04X}sub{:X} ({} bit)", index, sub_index, bits));
+                        ui.label(entry.map_or("", |e| e.name.as_str()));
+                        match self.pdo_values.get(&(index, sub_index)) {
+                            Some(&(timestamp_us, raw, bits)) => {
+                                ui.label(match entry {
+                                    Some(entry) => entry.data_type.format_raw(raw, bits),
+                                    None => raw.to_string(),
+                                });
+                                ui.label(format!(
+                                    "{} ms 前",
+                                    now_us.saturating_sub(timestamp_us) / 1000
+                                ));
+                            }
+                            None => {
+                                ui.label("-");
+                                ui.label("");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u32, payload: &[u8], now_us: u64) -> CanFrame {
+        let mut data = [0u8; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        CanFrame {
+            timestamp_us: now_us,
+            id,
+            len: payload.len() as u8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn heartbeat_state_and_timeout() {
+        let mut master = CanOpenMaster::default();
+        master.handle(&frame(0x705, &[0x00], 0), 0);
+        assert_eq!(master.nodes[&5].state, Some(0x00));
+        assert_eq!(master.events, ["CANopen: 節點 5 開機"]);
+
+        master.handle(&frame(0x705, &[0x7F], 100_000), 100_000);
+        master.handle(&frame(0x705, &[0x7F], 200_000), 200_000);
+        assert_eq!(master.events[1..], ["CANopen: 節點 5 -> Pre-operational"]);
+        // 其他通道的不理會
+        master.handle(
+            &CanFrame {
+                channel: 1,
+                ..frame(0x706, &[0x05], 200_000)
+            },
+            200_000,
+        );
+        assert!(!master.nodes.contains_key(&6));
+        master.events.clear();
+
+        // 1500 ms 逾時：剛好到不算，超過才算，只報一次
+        master.poll(1_700_000);
+        assert!(!master.nodes[&5].heartbeat_lost);
+        master.poll(1_700_001);
+        master.poll(2_000_000);
+        assert!(master.nodes[&5].heartbeat_lost);
+        assert_eq!(master.events, ["CANopen: 節點 5 心跳逾時"]);
+        master.events.clear();
+
+        master.handle(&frame(0x705, &[0x05], 2_100_000), 2_100_000);
+        assert!(!master.nodes[&5].heartbeat_lost);
+        assert_eq!(
+            master.events,
+            ["CANopen: 節點 5 心跳恢復", "CANopen: 節點 5 -> Operational"]
+        );
+        // 守護請求只送給守護中的節點
+        assert!(master.outbox.is_empty());
+    }
+
+    #[test]
+    fn nmt_command_bytes() {
+        let mut master = CanOpenMaster {
+            channel: 2,
+            ..Default::default()
+        };
+        master.nmt(NmtCommand::Start, 5);
+        master.nmt(NmtCommand::ResetCommunication, 0);
+        let frames: Vec<CanFrame> = master.drain_outbox().collect();
+        assert_eq!(frames.len(), 2);
+        for f in &frames {
+            assert_eq!((f.id, f.len, f.channel, f.remote), (0x000, 2, 2, false));
+        }
+        assert_eq!(frames[0].payload(), [0x01, 0x05]);
+        assert_eq!(frames[1].payload(), [0x82, 0x00]);
+        let codes: Vec<u8> = NmtCommand::ALL.iter().map(|c| c.code()).collect();
+        assert_eq!(codes, [0x01, 0x02, 0x80, 0x81, 0x82]);
+    }
+
+    #[test]
+    fn node_guarding_toggle_and_life_time() {
+        let mut master = CanOpenMaster::default();
+        master.nodes.entry(3).or_default().guarded = true;
+
+        // 每 guard_time 送一次 RTR
+        master.poll(0);
+        let request = master.outbox.pop().unwrap();
+        assert_eq!((request.id, request.remote, request.len), (0x703, true, 1));
+        master.poll(500_000);
+        assert!(master.outbox.is_empty());
+
+        // 切換位元 0、1 交替
+        master.handle(&frame(0x703, &[0x05], 10_000), 10_000);
+        master.poll(1_000_000);
+        assert_eq!(master.outbox.drain(..).count(), 1);
+        master.handle(&frame(0x703, &[0x85], 1_010_000), 1_010_000);
+        assert_eq!(master.events, ["CANopen: 節點 3 -> Operational"]);
+        assert!(master.nodes[&3].last_heartbeat_us.is_none());
+
+        // 同一個切換位元再來一次是錯誤
+        master.handle(&frame(0x703, &[0x85], 1_020_000), 1_020_000);
+        assert_eq!(master.events[1..], ["CANopen: 節點 3 守護切換位元錯誤"]);
+        master.events.clear();
+
+        // life time = 1000 ms × 3
+        master.poll(4_020_000);
+        assert!(!master.nodes[&3].guard_lost);
+        master.poll(4_020_001);
+        assert!(master.nodes[&3].guard_lost);
+        assert_eq!(master.events, ["CANopen: 節點 3 守護逾時"]);
+        master.events.clear();
+
+        // 開機訊息把切換位元歸零
+        master.handle(&frame(0x703, &[0x00], 4_100_000), 4_100_000);
+        assert_eq!(
+            master.events,
+            ["CANopen: 節點 3 守護恢復", "CANopen: 節點 3 開機"]
+        );
+        master.events.clear();
+        master.handle(&frame(0x703, &[0x7F], 4_200_000), 4_200_000);
+        assert_eq!(master.events, ["CANopen: 節點 3 -> Pre-operational"]);
+    }
+
+    #[test]
+    fn emcy_decoding() {
+        let mut master = CanOpenMaster::default();
+        let emcy = [0x10, 0x81, 0x11, 0x01, 0x02, 0x03, 0x04, 0x05];
+        master.handle(&frame(0x085, &emcy, 5_000), 5_000);
+        // 長度不是 8 的不算 EMCY
+        master.handle(&frame(0x085, &emcy[..7], 6_000), 6_000);
+        assert_eq!(master.events, ["CANopen: 節點 5 EMCY 0x8110 CAN 溢位"]);
+        let entry = &master.nodes[&5].emcy[0];
+        assert_eq!((entry.timestamp_us, entry.code), (5_000, 0x8110));
+        assert_eq!(entry.register, 0x11);
+        assert_eq!(entry.data, [0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(error_register_bits(entry.register), "一般/通訊");
+        assert_eq!(emcy_code_name(0x4210), "溫度");
+        assert_eq!(emcy_code_name(0x0000), "錯誤重置 / 無錯誤");
+
+        // 最新的在前，只保留 EMCY_HISTORY 筆
+        for i in 0..25u8 {
+            master.handle(&frame(0x085, &[i, 0x10, 0, 0, 0, 0, 0, 0], 0), 0);
+        }
+        let history = &master.nodes[&5].emcy;
+        assert_eq!(history.len(), EMCY_HISTORY);
+        assert_eq!(history[0].code, 0x1018);
+    }
+}
@@ -0,0 +1,566 @@
+use crate::canbus::CanFrame;
+use crate::measurement::{
+    Access, CommandQueue, CommandResult, DaqSample, DaqVariable, OdtEntry, OdtLayout, VariableTable,
+};
+use eframe::egui;
+use std::collections::VecDeque;
+
+// 命令碼 (CCP 2.1)
+const CMD_CONNECT: u8 = 0x01;
+const CMD_SET_MTA: u8 = 0x02;
+const CMD_DNLOAD: u8 = 0x03;
+const CMD_UPLOAD: u8 = 0x04;
+const CMD_START_STOP: u8 = 0x06;
+const CMD_DISCONNECT: u8 = 0x07;
+const CMD_SHORT_UP: u8 = 0x0F;
+const CMD_GET_DAQ_SIZE: u8 = 0x14;
+const CMD_SET_DAQ_PTR: u8 = 0x15;
+const CMD_WRITE_DAQ: u8 = 0x16;
+const CMD_GET_CCP_VERSION: u8 = 0x1B;
+const CMD_DNLOAD_6: u8 = 0x23;
+// DTO 的封包識別：命令回應與事件，其餘是 DAQ 的 ODT 編號
+const PID_CRM: u8 = 0xFF;
+const PID_EVENT: u8 = 0xFE;
+// 每個 DAQ-DTO 在 PID 之後有 7 bytes 資料
+const ODT_SIZE: usize = 7;
+
+pub fn error_name(code: u8) -> &'static str {
+    match code {
+        0x01 => "DAQ 處理器過載",
+        0x10 => "命令處理器忙碌",
+        0x11 => "DAQ 處理器忙碌",
+        0x12 => "內部逾時",
+        0x13 => "需要金鑰",
+        0x14 => "需要 session status",
+        0x20 => "要求冷啟動",
+        0x21 => "要求初始化標定資料",
+        0x22 => "要求初始化 DAQ 清單",
+        0x23 => "要求更新程式碼",
+        0x30 => "未知命令",
+        0x31 => "命令語法錯誤",
+        0x32 => "參數超出範圍",
+        0x33 => "拒絕存取",
+        0x34 => "過載",
+        0x35 => "存取鎖定",
+        0x36 => "資源或功能不可用",
+        _ => "未知錯誤",
+    }
+}
+
+/// 命令的用途，完成時跟結果一起回傳
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Connect,
+    Disconnect,
+    Version,
+    // 呼叫端自訂的鍵 (例如變數索引)
+    Upload(usize),
+    Download(usize),
+    DaqSetup,
+    DaqStop,
+}
+
+/// 一組命令的結果；Upload 成功時是讀到的資料，其餘是最後一個 CRM 的參數
+pub type CcpResult = CommandResult<Tag>;
+
+/// CCP 2.1 主站：命令帶計數器一次一個，其餘排隊；DAQ-DTO 解成 DaqSample
+///
+/// CCP 的 DAQ 沒有時間戳記，樣本時間用主機收到最後一個 ODT 的時間。
+pub struct CcpMaster {
+    pub cro_id: u32,
+    pub dto_id: u32,
+    pub extended_id: bool,
+    pub channel: u32,
+    pub timeout_ms: u64,
+    pub station_address: u16,
+    // 位址與資料用 Motorola 位元組順序 (依 ECU 而定)
+    pub big_endian: bool,
+    pub connected: bool,
+    pub version: Option<(u8, u8)>,
+    pub daq_running: bool,
+    pub outbox: Vec<CanFrame>,
+    pub completed: VecDeque<CcpResult>,
+    pub samples: VecDeque<DaqSample>,
+    pub events: Vec<String>,
+    commands: CommandQueue<Tag>,
+    // ODT 配置與第一個 ODT 的 PID
+    daq: Option<(OdtLayout, u8)>,
+    // 等 GET_DAQ_SIZE 回來才知道有幾個 ODT
+    daq_request: Option<(Vec<DaqVariable>, u8, u8, u16)>,
+}
+
+impl Default for CcpMaster {
+    fn default() -> Self {
+        Self {
+            cro_id: 0x6F0,
+            dto_id: 0x6F1,
+            extended_id: false,
+            channel: 0,
+            timeout_ms: 1000,
+            station_address: 0x0001,
+            big_endian: true,
+            connected: false,
+            version: None,
+            daq_running: false,
+            outbox: Vec::new(),
+            completed: VecDeque::new(),
+            samples: VecDeque::new(),
+            events: Vec::new(),
+            commands: CommandQueue::default(),
+            daq: None,
+            daq_request: None,
+        }
+    }
+}
+
+impl CcpMaster {
+    pub fn is_idle(&self) -> bool {
+        self.commands.is_idle()
+    }
+
+    fn word(&self, value: u16) -> [u8; 2] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn dword(&self, value: u32) -> [u8; 4] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    pub fn connect(&mut self) {
+        // 站位址固定是 Intel 順序
+        let [low, high] = self.station_address.to_le_bytes();
+        self.commands
+            .enqueue(vec![vec![CMD_CONNECT, 0, low, high]], Tag::Connect);
+    }
+
+    pub fn disconnect(&mut self) {
+        let [low, high] = self.station_address.to_le_bytes();
+        self.commands.enqueue(
+            vec![vec![CMD_DISCONNECT, 0, 0x01, 0x00, low, high]],
+            Tag::Disconnect,
+        );
+    }
+
+    pub fn get_version(&mut self) {
+        self.commands
+            .enqueue(vec![vec![CMD_GET_CCP_VERSION, 0, 2, 1]], Tag::Version);
+    }
+
+    /// 讀取：最多 5 bytes 用一個 SHORT_UP，較長的用 SET_MTA 加上多個 UPLOAD
+    pub fn read(&mut self, address: u32, extension: u8, len: usize, key: usize) {
+        if len <= 5 {
+            let mut command = vec![CMD_SHORT_UP, 0, len as u8, extension];
+            command.extend_from_slice(&self.dword(address));
+            self.commands.enqueue(vec![command], Tag::Upload(key));
+            return;
+        }
+        let mut command = vec![CMD_SET_MTA, 0, 0, extension];
+        command.extend_from_slice(&self.dword(address));
+        let mut commands = vec![command];
+        // 每個 UPLOAD 之後從站的 MTA 會自動往後移
+        for offset in (0..len).step_by(5) {
+            commands.push(vec![CMD_UPLOAD, 0, (len - offset).min(5) as u8]);
+        }
+        self.commands.enqueue(commands, Tag::Upload(key));
+    }
+
+    /// 寫入：SET_MTA 之後用 DNLOAD_6 與 DNLOAD 寫完
+    pub fn write(&mut self, address: u32, extension: u8, data: &[u8], key: usize) {
+        let mut command = vec![CMD_SET_MTA, 0, 0, extension];
+        command.extend_from_slice(&self.dword(address));
+        let mut commands = vec![command];
+        for chunk in data.chunks(6) {
+            commands.push(match chunk.len() {
+                6 => [&[CMD_DNLOAD_6, 0][..], chunk].concat(),
+                n => [&[CMD_DNLOAD, 0, n as u8][..], chunk].concat(),
+            });
+        }
+        self.commands.enqueue(commands, Tag::Download(key));
+    }
+
+    /// 設定 DAQ 清單並啟動；先用 GET_DAQ_SIZE 清空清單並取得 ODT 數量
+    pub fn start_daq(&mut self, variables: Vec<DaqVariable>, list: u8, event: u8, prescaler: u16) {
+        self.daq = None;
+        self.daq_request = Some((variables, list, event, prescaler));
+        let mut command = vec![CMD_GET_DAQ_SIZE, 0, list, 0];
+        command.extend_from_slice(&self.dword(self.dto_id));
+        self.commands.enqueue(vec![command], Tag::DaqSetup);
+    }
+
+    pub fn stop_daq(&mut self, list: u8) {
+        self.commands.enqueue(
+            vec![vec![CMD_START_STOP, 0, 0x00, list, 0, 0, 0, 0]],
+            Tag::DaqStop,
+        );
+    }
+
+    // 把變數切成 1/2/4 bytes 的元素放進每個 7 bytes 的 ODT
+    fn configure_daq(&mut self, odt_count: usize, first_pid: u8) -> Result<(), String> {
+        let Some((variables, list, event, prescaler)) = self.daq_request.take() else {
+            return Ok(());
+        };
+        let mut odts: Vec<Vec<OdtEntry>> = vec![Vec::new()];
+        let mut room = ODT_SIZE;
+        for (index, variable) in variables.iter().enumerate() {
+            let mut offset = 0;
+            while offset < variable.size {
+                let remaining = variable.size - offset;
+                let size = [4, 2, 1]
+                    .into_iter()
+                    .find(|&s| s <= remaining && s <= room)
+                    .unwrap_or(0);
+                if size == 0 {
+                    odts.push(Vec::new());
+                    room = ODT_SIZE;
+                    continue;
+                }
+                odts.last_mut().unwrap().push(OdtEntry {
+                    variable: index,
+                    offset,
+                    size,
+                });
+                offset += size;
+                room -= size;
+            }
+        }
+        if odts.len() > odt_count {
+            return Err(format!(
+                "需要 {} 個 ODT，DAQ 清單 {} 只有 {} 個",
+                odts.len(),
+                list,
+                odt_count
+            ));
+        }
+
+        let mut commands = Vec::new();
+        for (odt, entries) in odts.iter().enumerate() {
+            for (element, entry) in entries.iter().enumerate() {
+                commands.push(vec![CMD_SET_DAQ_PTR, 0, list, odt as u8, element as u8]);
+                let variable = &variables[entry.variable];
+                let mut command = vec![CMD_WRITE_DAQ, 0, entry.size as u8, variable.extension];
+                command.extend_from_slice(&self.dword(variable.address + entry.offset as u32));
+                commands.push(command);
+            }
+        }
+        let mut command = vec![CMD_START_STOP, 0, 0x01, list, (odts.len() - 1) as u8, event];
+        command.extend_from_slice(&self.word(prescaler.max(1)));
+        commands.push(command);
+
+        self.daq = Some((OdtLayout::new(variables, odts), first_pid));
+        self.commands.enqueue(commands, Tag::DaqSetup);
+        Ok(())
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame) {
+        if frame.channel != self.channel
+            || frame.id != self.dto_id
+            || frame.extended != self.extended_id
+            || frame.remote
+            || frame.error
+        {
+            return;
+        }
+        let data = frame.payload();
+        match data.first() {
+            Some(&PID_CRM) if data.len() >= 3 => self.on_response(data),
+            Some(&PID_EVENT) => self.events.push(format!(
+                "CCP 事件: {}",
+                error_name(data.get(1).copied().unwrap_or(0))
+            )),
+            Some(&pid) => self.on_daq(pid, data, frame.timestamp_us),
+            None => {}
+        }
+    }
+
+    fn on_response(&mut self, data: &[u8]) {
+        // 計數器不符的是過期的回應
+        if self.commands.pending_counter() != Some(data[2]) {
+            return;
+        }
+        let queued = self.commands.complete().unwrap();
+        let fail = |master: &mut Self, message: String| {
+            master.commands.abort(&queued);
+            if queued.tag == Tag::DaqSetup {
+                master.daq = None;
+                master.daq_request = None;
+            }
+            master.completed.push_back(CcpResult {
+                tag: queued.tag,
+                result: Err(message),
+            });
+        };
+        if data[1] != 0x00 {
+            let message = format!(
+                "0x{:02X} {} (命令 0x{:02X})",
+                data[1],
+                error_name(data[1]),
+                queued.command[0]
+            );
+            fail(self, message);
+            return;
+        }
+        let parameters = &data[3..];
+
+        // 1. **主站自己需要的回應**
+        match queued.command[0] {
+            CMD_CONNECT => {
+                self.connected = true;
+                self.daq_running = false;
+            }
+            CMD_DISCONNECT => {
+                self.connected = false;
+                self.daq_running = false;
+            }
+            CMD_GET_CCP_VERSION if parameters.len() >= 2 => {
+                self.version = Some((parameters[0], parameters[1]));
+            }
+            CMD_GET_DAQ_SIZE if parameters.len() >= 2 => {
+                if let Err(e) = self.configure_daq(parameters[0] as usize, parameters[1]) {
+                    fail(self, e);
+                    return;
+                }
+            }
+            CMD_START_STOP => self.daq_running = queued.command[2] == 0x01,
+            _ => {}
+        }
+
+        // 2. **回報**
+        let upload = matches!(queued.command[0], CMD_SHORT_UP | CMD_UPLOAD);
+        if upload {
+            let requested = (queued.command[2] as usize).min(parameters.len());
+            self.commands
+                .accumulated
+                .extend_from_slice(&parameters[..requested]);
+        }
+        // GET_DAQ_SIZE 之後的設定命令才是這組的結尾
+        if queued.last && queued.command[0] != CMD_GET_DAQ_SIZE {
+            let result = if upload {
+                std::mem::take(&mut self.commands.accumulated)
+            } else {
+                parameters.to_vec()
+            };
+            self.completed.push_back(CcpResult {
+                tag: queued.tag,
+                result: Ok(result),
+            });
+        }
+    }
+
+    fn on_daq(&mut self, pid: u8, data: &[u8], host_time_us: u64) {
+        let Some((odts, first_pid)) = self.daq.as_mut() else {
+            return;
+        };
+        let Some(odt) = pid.checked_sub(*first_pid) else {
+            return;
+        };
+        odts.feed(
+            odt as usize,
+            &data[1..],
+            None,
+            host_time_us,
+            &mut self.samples,
+        );
+    }
+
+    /// 每個畫面呼叫一次：檢查逾時並送出下一個命令
+    pub fn poll(&mut self, now_us: u64) {
+        let Some((command, counter)) =
+            self.commands
+                .poll(now_us, self.timeout_ms, &mut self.completed)
+        else {
+            return;
+        };
+        let mut data = [0u8; 8];
+        data[..command.len()].copy_from_slice(command);
+        // 第 2 個 byte 是計數器，送出時才填
+        data[1] = counter;
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: self.cro_id,
+            extended: self.extended_id,
+            len: 8,
+            data,
+            ..Default::default()
+        });
+    }
+}
+
+/// CCP 面板：連線、變數表、輪詢與 DAQ
+pub struct CcpPanel {
+    pub master: CcpMaster,
+    pub table: VariableTable,
+    daq_list: u8,
+    event_channel: u8,
+    prescaler: u16,
+    status: String,
+}
+
+impl Default for CcpPanel {
+    fn default() -> Self {
+        Self {
+            master: CcpMaster::default(),
+            table: VariableTable::default(),
+            daq_list: 0,
+            event_channel: 0,
+            prescaler: 1,
+            status: String::new(),
+        }
+    }
+}
+
+impl CcpPanel {
+    /// 處理完成的命令與 DAQ 樣本
+    pub fn process(&mut self, now_us: u64, log: &mut Vec<String>) {
+        log.append(&mut self.master.events);
+        let big_endian = self.master.big_endian;
+        while let Some(result) = self.master.completed.pop_front() {
+            match (&result.tag, &result.result) {
+                (tag, Err(e)) => {
+                    log.push(format!("CCP {:?}: {}", tag, e));
+                    self.table.report(format!("{:?}: {}", tag, e));
+                }
+                (Tag::Upload(key), Ok(data)) => {
+                    self.table.set_value(*key, data, big_endian, now_us)
+                }
+                (Tag::Connect, Ok(_)) => {
+                    self.status = format!("已連線: 站位址 0x{:04X}", self.master.station_address)
+                }
+                (Tag::Disconnect, Ok(_)) => self.status = "未連線".to_string(),
+                (Tag::Version, Ok(_)) => {
+                    if let Some((main, release)) = self.master.version {
+                        self.status = format!("CCP 版本 {}.{}", main, release);
+                    }
+                }
+                (tag, Ok(_)) => self.table.report(format!("{:?}: 完成", tag)),
+            }
+        }
+
+        self.table
+            .apply_samples(&mut self.master.samples, big_endian);
+        let accesses = self.table.poll(now_us, self.master.is_idle());
+        self.access(accesses);
+    }
+
+    fn access(&mut self, accesses: Vec<Access>) {
+        for access in accesses {
+            match access {
+                Access::Read {
+                    key,
+                    address,
+                    extension,
+                    size,
+                } => self.master.read(address, extension, size, key),
+                Access::Write {
+                    key,
+                    address,
+                    extension,
+                    data,
+                } => self.master.write(address, extension, &data, key),
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        // 1. **連線**
+        let connected = self.master.connected;
+        ui.add_enabled_ui(!connected, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("CRO ID:");
+                ui.add(egui::DragValue::new(&mut self.master.cro_id).hexadecimal(3, false, true));
+                ui.label("DTO ID:");
+                ui.add(egui::DragValue::new(&mut self.master.dto_id).hexadecimal(3, false, true));
+                ui.checkbox(&mut self.master.extended_id, "29 位元 ID");
+                ui.label("站位址:");
+                ui.add(
+                    egui::DragValue::new(&mut self.master.station_address)
+                        .hexadecimal(4, false, true),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.master.big_endian, "Motorola 位元組順序");
+                ui.label("逾時:");
+                ui.add(
+                    egui::DragValue::new(&mut self.master.timeout_ms)
+                        .range(10..=10_000)
+                        .suffix(" ms"),
+                );
+            });
+        });
+        ui.add_enabled_ui(can_send, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("CONNECT").clicked() {
+                    self.master.connect();
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("GET_CCP_VERSION"))
+                    .clicked()
+                {
+                    self.master.get_version();
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("DISCONNECT"))
+                    .clicked()
+                {
+                    self.table.polling = false;
+                    self.master.disconnect();
+                }
+                if !self.master.is_idle() {
+                    ui.spinner();
+                }
+            });
+        });
+        ui.label(&self.status);
+
+        // 2. **變數**
+        ui.separator();
+        let accesses = self.table.ui(
+            ui,
+            "ccp",
+            self.master.daq_running,
+            can_send && connected,
+            self.master.big_endian,
+        );
+        self.access(accesses);
+
+        // 3. **輪詢與 DAQ**
+        ui.separator();
+        ui.add_enabled_ui(can_send && connected, |ui| {
+            self.table.polling_ui(ui, "SHORT_UP 輪詢");
+            ui.horizontal(|ui| {
+                ui.label("DAQ 清單:");
+                ui.add_enabled(
+                    !self.master.daq_running,
+                    egui::DragValue::new(&mut self.daq_list),
+                );
+                ui.label("事件通道:");
+                ui.add(egui::DragValue::new(&mut self.event_channel));
+                ui.label("預分頻:");
+                ui.add(egui::DragValue::new(&mut self.prescaler).range(1..=u16::MAX));
+                if self.master.daq_running {
+                    if ui.button("停止 DAQ").clicked() {
+                        self.master.stop_daq(self.daq_list);
+                    }
+                } else if ui.button("設定並啟動 DAQ").clicked() {
+                    let variables = self.table.daq_variables();
+                    if !variables.is_empty() {
+                        self.master.start_daq(
+                            variables,
+                            self.daq_list,
+                            self.event_channel,
+                            self.prescaler,
+                        );
+                    }
+                }
+            });
+        });
+        self.table.history_ui(ui, "ccp");
+    }
+}
@@ -0,0 +1,586 @@
+use crate::canbus::CanFrame;
+use std::collections::HashMap;
+use std::path::Path;
+
+const EXTENDED_ID_FLAG: u32 = 0x8000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Intel,
+    Motorola,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    None,
+    Multiplexor,
+    Multiplexed(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub name: String,
+    pub start_bit: u32,
+    pub size: u32,
+    pub byte_order: ByteOrder,
+    pub signed: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub receivers: Vec<String>,
+    pub multiplex: Multiplex,
+    pub comment: Option<String>,
+    pub value_descriptions: Vec<(i64, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    pub dlc: u8,
+    pub transmitter: String,
+    pub signals: Vec<Signal>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dbc {
+    pub messages: Vec<Message>,
+    pub value_tables: HashMap<String, Vec<(i64, String)>>,
+    pub comment: Option<String>,
+    index: HashMap<u32, usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedSignal<'a> {
+    pub signal: &'a Signal,
+    pub raw: i64,
+    pub value: f64,
+}
+
+impl DecodedSignal<'_> {
+    pub fn description(&self) -> Option<&str> {
+        self.signal
+            .value_descriptions
+            .iter()
+            .find(|(v, _)| *v == self.raw)
+            .map(|(_, d)| d.as_str())
+    }
+
+    pub fn display_value(&self) -> String {
+        let mut text = if self.signal.factor.fract() == 0.0 && self.signal.offset.fract() == 0.0 {
+            format!("{}", self.value)
+        } else {
+            format!("{:.4}", self.value)
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        };
+        if !self.signal.unit.is_empty() {
+            text.push(' ');
+            text.push_str(&self.signal.unit);
+        }
+        if let Some(desc) = self.description() {
+            text.push_str(&format!(" ({})", desc));
+        }
+        text
+    }
+}
+
+impl Signal {
+    /// 取出原始值 (已做符號延伸)
+    pub fn raw_value(&self, data: &[u8]) -> i64 {
+        let mut bytes = [0u8; 8];
+        let n = data.len().min(8);
+        bytes[..n].copy_from_slice(&data[..n]);
+        let size = self.size.clamp(1, 64);
+        let mask = if size == 64 {
+            u64::MAX
+        } else {
+            (1u64 << size) - 1
+        };
+
+        let raw = match self.byte_order {
+            ByteOrder::Intel => (u64::from_le_bytes(bytes) >> self.start_bit.min(63)) & mask,
+            ByteOrder::Motorola => {
+                // DBC 的 Motorola 起始位元是 MSB，換算成大端序的線性位置
+                let msb = (self.start_bit / 8) * 8 + (7 - self.start_bit % 8);
+                let shift = 64i32 - msb as i32 - size as i32;
+                if shift < 0 {
+                    0
+                } else {
+                    (u64::from_be_bytes(bytes) >> shift) & mask
+                }
+            }
+        };
+
+        if self.signed && size < 64 && raw & (1 << (size - 1)) != 0 {
+            (raw | !mask) as i64
+        } else {
+            raw as i64
+        }
+    }
+}
+
+impl Message {
+    /// 解出所有訊號；多工訊號只在 multiplexor 值相符時輸出
+    pub fn decode<'a>(&'a self, data: &[u8]) -> Vec<DecodedSignal<'a>> {
+        let mux_value = self
+            .signals
+            .iter()
+            .find(|s| s.multiplex == Multiplex::Multiplexor)
+            .map(|s| s.raw_value(data) as u64);
+
+        self.signals
+            .iter()
+            .filter(|s| match s.multiplex {
+                Multiplex::Multiplexed(v) => mux_value == Some(v),
+                _ => true,
+            })
+            .map(|signal| {
+                let raw = signal.raw_value(data);
+                DecodedSignal {
+                    signal,
+                    raw,
+                    value: raw as f64 * signal.factor + signal.offset,
+                }
+            })
+            .collect()
+    }
+
+    /// 只解單一訊號的物理值；多工訊號在 multiplexor 不符時回傳 None
+    pub fn signal_value(&self, name: &str, data: &[u8]) -> Option<f64> {
+        let signal = self.signals.iter().find(|s| s.name == name)?;
+        if let Multiplex::Multiplexed(v) = signal.multiplex {
+            let mux = self
+                .signals
+                .iter()
+                .find(|s| s.multiplex == Multiplex::Multiplexor)?;
+            if mux.raw_value(data) as u64 != v {
+                return None;
+            }
+        }
+        Some(signal.raw_value(data) as f64 * signal.factor + signal.offset)
+    }
+}
+
+impl Dbc {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let bytes = std::fs::read(path.as_ref()).map_err(|e| format!("無法讀取 DBC: {}", e))?;
+        // DBC 常以 Windows-1252 存檔，非 UTF-8 時逐位元組轉換
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+        };
+        Self::parse(&text)
+    }
+
+    pub fn message(&self, id: u32, extended: bool) -> Option<&Message> {
+        let key = if extended { id | EXTENDED_ID_FLAG } else { id };
+        self.index.get(&key).map(|&i| &self.messages[i])
+    }
+
+    pub fn decode<'a>(&'a self, frame: &CanFrame) -> Option<(&'a Message, Vec<DecodedSignal<'a>>)> {
+        let message = self.message(frame.id, frame.extended)?;
+        Some((message, message.decode(frame.payload())))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut dbc = Dbc::default();
+        let mut lines = text.lines().enumerate();
+
+        while let Some((idx, line)) = lines.next() {
+            let line_no = idx + 1;
+            let trimmed = line.trim();
+            let keyword = trimmed.split_whitespace().next().unwrap_or("");
+            let err = |msg: &str| format!("DBC 第 {} 行: {}", line_no, msg);
+
+            match keyword {
+                "BO_" => {
+                    let message = parse_message(trimmed).ok_or_else(|| err("BO_ 格式錯誤"))?;
+                    dbc.messages.push(message);
+                }
+                "SG_" => {
+                    let signal = parse_signal(trimmed).ok_or_else(|| err("SG_ 格式錯誤"))?;
+                    dbc.messages
+                        .last_mut()
+                        .ok_or_else(|| err("SG_ 之前沒有 BO_"))?
+                        .signals
+                        .push(signal);
+                }
+                "CM_" | "VAL_" | "VAL_TABLE_" => {
+                    // 這些敘述以 ';' 結尾，字串內可以換行
+                    let mut statement = trimmed.to_string();
+                    while !statement_complete(&statement) {
+                        match lines.next() {
+                            Some((_, next)) => {
+                                statement.push('\n');
+                                statement.push_str(next);
+                            }
+                            None => return Err(err("敘述缺少結尾的 ';'")),
+                        }
+                    }
+                    let tokens = tokenize(&statement);
+                    match keyword {
+                        "CM_" => dbc.apply_comment(&tokens),
+                        "VAL_" => dbc.apply_value_descriptions(&tokens),
+                        _ => {
+                            if let Some(Token::Word(name)) = tokens.get(1) {
+                                let table = parse_value_pairs(&tokens[2..]);
+                                dbc.value_tables.insert(name.clone(), table);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        dbc.index = dbc
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                let key = if m.extended {
+                    m.id | EXTENDED_ID_FLAG
+                } else {
+                    m.id
+                };
+                (key, i)
+            })
+            .collect();
+        Ok(dbc)
+    }
+
+    fn message_mut(&mut self, raw_id: &str) -> Option<&mut Message> {
+        let raw: u32 = raw_id.parse().ok()?;
+        let extended = raw & EXTENDED_ID_FLAG != 0;
+        let id = raw & !EXTENDED_ID_FLAG;
+        self.messages
+            .iter_mut()
+            .find(|m| m.id == id && m.extended == extended)
+    }
+
+    fn apply_comment(&mut self, tokens: &[Token]) {
+        match tokens {
+            [_, Token::Str(text), ..] => self.comment = Some(text.clone()),
+            [_, Token::Word(kind), Token::Word(id), Token::Str(text), ..] if kind == "BO_" => {
+                if let Some(m) = self.message_mut(id) {
+                    m.comment = Some(text.clone());
+                }
+            }
+            [_, Token::Word(kind), Token::Word(id), Token::Word(sig), Token::Str(text), ..]
+                if kind == "SG_" =>
+            {
+                if let Some(s) = self
+                    .message_mut(id)
+                    .and_then(|m| m.signals.iter_mut().find(|s| &s.name == sig))
+                {
+                    s.comment = Some(text.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_value_descriptions(&mut self, tokens: &[Token]) {
+        let [_, Token::Word(id), Token::Word(sig), rest @ ..] = tokens else {
+            return;
+        };
+        // VAL_ 也可以直接引用 VAL_TABLE_ 的名稱
+        let pairs = match rest {
+            [Token::Word(table), ..] if self.value_tables.contains_key(table) => {
+                self.value_tables[table].clone()
+            }
+            _ => parse_value_pairs(rest),
+        };
+        if let Some(s) = self
+            .message_mut(id)
+            .and_then(|m| m.signals.iter_mut().find(|s| &s.name == sig))
+        {
+            s.value_descriptions = pairs;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Semicolon,
+}
+
+fn statement_complete(statement: &str) -> bool {
+    let mut in_str = false;
+    let mut chars = statement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_str => {
+                chars.next();
+            }
+            '"' => in_str = !in_str,
+            ';' if !in_str => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn tokenize(statement: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = statement.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            chars.next();
+            tokens.push(Token::Semicolon);
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(next) = chars.next() {
+                            text.push(next);
+                        }
+                    }
+                    '"' => break,
+                    _ => text.push(c),
+                }
+            }
+            tokens.push(Token::Str(text));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ';' || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    tokens
+}
+
+fn parse_value_pairs(tokens: &[Token]) -> Vec<(i64, String)> {
+    let mut pairs = Vec::new();
+    let mut iter = tokens.iter();
+    while let (Some(Token::Word(value)), Some(Token::Str(desc))) = (iter.next(), iter.next()) {
+        if let Ok(v) = value.parse::<f64>() {
+            pairs.push((v as i64, desc.clone()));
+        }
+    }
+    pairs
+}
+
+// BO_ <id> <name>: <dlc> <transmitter>
+fn parse_message(line: &str) -> Option<Message> {
+    let rest = line.strip_prefix("BO_")?.trim();
+    let (head, tail) = rest.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let raw: u32 = head.next()?.parse().ok()?;
+    let name = head.next()?.to_string();
+    let mut tail = tail.split_whitespace();
+    let dlc = tail.next()?.parse().ok()?;
+    let transmitter = tail.next().unwrap_or("").to_string();
+    Some(Message {
+        id: raw & !EXTENDED_ID_FLAG,
+        extended: raw & EXTENDED_ID_FLAG != 0,
+        name,
+        dlc,
+        transmitter,
+        signals: Vec::new(),
+        comment: None,
+    })
+}
+
+// SG_ <name> [M|mN] : <start>|<size>@<order><sign> (<factor>,<offset>) [<min>|<max>] "<unit>" <receivers>
+fn parse_signal(line: &str) -> Option<Signal> {
+    let rest = line.strip_prefix("SG_")?.trim();
+    let (head, tail) = rest.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_string();
+    let multiplex = match head.next() {
+        None => Multiplex::None,
+        Some("M") => Multiplex::Multiplexor,
+        // 延伸多工 "m3M" 視為被 3 多工
+        Some(m) => Multiplex::Multiplexed(m.strip_prefix('m')?.trim_end_matches('M').parse().ok()?),
+    };
+
+    let tail = tail.trim();
+    let (bits, tail) = tail.split_once(char::is_whitespace)?;
+    let (start, bits) = bits.split_once('|')?;
+    let (size, order) = bits.split_once('@')?;
+    let mut order = order.chars();
+    let byte_order = match order.next()? {
+        '0' => ByteOrder::Motorola,
+        '1' => ByteOrder::Intel,
+        _ => return None,
+    };
+    let signed = order.next()? == '-';
+
+    let (_, tail) = tail.split_once('(')?;
+    let (scale, tail) = tail.split_once(')')?;
+    let (factor, offset) = scale.split_once(',')?;
+    let (_, tail) = tail.split_once('[')?;
+    let (range, tail) = tail.split_once(']')?;
+    let (min, max) = range.split_once('|')?;
+    let (_, tail) = tail.split_once('"')?;
+    let (unit, tail) = tail.split_once('"')?;
+    let receivers = tail
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    Some(Signal {
+        name,
+        start_bit: start.trim().parse().ok()?,
+        size: size.trim().parse().ok()?,
+        byte_order,
+        signed,
+        factor: factor.trim().parse().ok()?,
+        offset: offset.trim().parse().ok()?,
+        min: min.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        unit: unit.to_string(),
+        receivers,
+        multiplex,
+        comment: None,
+        value_descriptions: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"VERSION ""
+
+BO_ 256 Engine: 8 ECU
+ SG_ Rpm : 4|12@1+ (0.5,0) [0|2047.5] "rpm" Dash
+ SG_ Torque : 16|8@1- (1,-10) [-138|117] "Nm" Dash
+ SG_ Gear : 31|16@0+ (1,0) [0|65535] "" Dash
+ SG_ Temp : 43|8@0- (1,0) [-128|127] "degC" Dash
+
+BO_ 2147484160 Mux: 8 ECU
+ SG_ Page M : 0|8@1+ (1,0) [0|255] "" Dash
+ SG_ Volt m1 : 8|16@1+ (0.01,0) [0|655.35] "V" Dash
+ SG_ Amp m2 : 8|8@1- (1,0) [-128|127] "A" Dash
+
+CM_ "Test network";
+CM_ BO_ 256 "Engine status";
+CM_ SG_ 256 Rpm "Engine speed,
+second line";
+VAL_TABLE_ Pages 1 "Voltage" 2 "Current" ;
+VAL_ 2147484160 Page Pages;
+VAL_ 256 Torque -1 "Invalid" 0 "Zero" ;
+"#;
+
+    fn signal<'a>(dbc: &'a Dbc, id: u32, extended: bool, name: &str) -> &'a Signal {
+        dbc.message(id, extended)
+            .unwrap()
+            .signals
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn intel_and_motorola_bits() {
+        let dbc = Dbc::parse(SAMPLE).unwrap();
+        let data = [0x5A, 0xC3, 0x00, 0x12, 0x34, 0x0A, 0xB0, 0x00];
+        // Intel: 位元 4..15 跨越 byte 0/1
+        assert_eq!(signal(&dbc, 0x100, false, "Rpm").raw_value(&data), 0xC35);
+        // Motorola: MSB 在 byte 3 bit 7，接著 byte 4
+        assert_eq!(signal(&dbc, 0x100, false, "Gear").raw_value(&data), 0x1234);
+        // Motorola 從 byte 5 bit 3 開始，跨到 byte 6 的高 4 位元
+        assert_eq!(signal(&dbc, 0x100, false, "Temp").raw_value(&data), -85);
+        let temp = Signal {
+            signed: false,
+            ..signal(&dbc, 0x100, false, "Temp").clone()
+        };
+        assert_eq!(temp.raw_value(&data), 0xAB);
+    }
+
+    #[test]
+    fn sign_extension() {
+        let dbc = Dbc::parse(SAMPLE).unwrap();
+        let torque = signal(&dbc, 0x100, false, "Torque");
+        assert_eq!(torque.raw_value(&[0, 0, 0xFF]), -1);
+        assert_eq!(torque.raw_value(&[0, 0, 0x80]), -128);
+        assert_eq!(torque.raw_value(&[0, 0, 0x7F]), 127);
+
+        let (message, decoded) = dbc
+            .decode(&CanFrame {
+                id: 0x100,
+                len: 8,
+                data: [0, 0, 0xFF, 0, 0, 0, 0, 0],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(message.name, "Engine");
+        let torque = decoded.iter().find(|d| d.signal.name == "Torque").unwrap();
+        assert_eq!((torque.raw, torque.value), (-1, -11.0));
+        assert_eq!(torque.display_value(), "-11 Nm (Invalid)");
+    }
+
+    #[test]
+    fn multiplexed_signals() {
+        let dbc = Dbc::parse(SAMPLE).unwrap();
+        let mux = dbc.message(0x200, true).unwrap();
+        assert!(dbc.message(0x200, false).is_none());
+        assert_eq!(mux.signals[0].multiplex, Multiplex::Multiplexor);
+        assert_eq!(mux.signals[1].multiplex, Multiplex::Multiplexed(1));
+
+        let names = |data: &[u8]| -> Vec<String> {
+            mux.decode(data)
+                .iter()
+                .map(|d| d.signal.name.clone())
+                .collect()
+        };
+        assert_eq!(names(&[1, 0x10, 0x27]), ["Page", "Volt"]);
+        assert_eq!(names(&[2, 0xFE]), ["Page", "Amp"]);
+        assert_eq!(names(&[3, 0]), ["Page"]);
+
+        assert_eq!(mux.signal_value("Volt", &[1, 0x10, 0x27]), Some(100.0));
+        assert_eq!(mux.signal_value("Volt", &[2, 0x10, 0x27]), None);
+        assert_eq!(mux.signal_value("Amp", &[2, 0xFE]), Some(-2.0));
+    }
+
+    #[test]
+    fn comments_and_value_descriptions() {
+        let dbc = Dbc::parse(SAMPLE).unwrap();
+        assert_eq!(dbc.comment.as_deref(), Some("Test network"));
+        let engine = dbc.message(0x100, false).unwrap();
+        assert_eq!(engine.comment.as_deref(), Some("Engine status"));
+        assert_eq!(
+            engine.signals[0].comment.as_deref(),
+            Some("Engine speed,\nsecond line")
+        );
+        assert_eq!(
+            engine.signals[1].value_descriptions,
+            [(-1, "Invalid".to_string()), (0, "Zero".to_string())]
+        );
+        // VAL_ 引用 VAL_TABLE_
+        let page = &dbc.message(0x200, true).unwrap().signals[0];
+        assert_eq!(
+            page.value_descriptions,
+            [(1, "Voltage".to_string()), (2, "Current".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let err = Dbc::parse("BO_ 1 A: 8 X\n SG_ broken\n").unwrap_err();
+        assert_eq!(err, "DBC 第 2 行: SG_ 格式錯誤");
+        let err = Dbc::parse("CM_ \"unterminated\n").unwrap_err();
+        assert_eq!(err, "DBC 第 1 行: 敘述缺少結尾的 ';'");
+    }
+}
@@ -0,0 +1,466 @@
+use crate::canbus::CanFrame;
+use crate::obd::FUNCTIONAL_ID;
+use crate::uds::{read_did_request, Outcome, UdsClient, SID_SESSION_CONTROL, SID_TESTER_PRESENT};
+use eframe::egui;
+
+// 掃描完成後讀取的識別 DID
+const IDENTIFICATION_DIDS: [(u16, &str); 3] =
+    [(0xF190, "VIN"), (0xF18C, "序號"), (0xF195, "軟體版本")];
+
+/// 探測用的請求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    TesterPresent,
+    DefaultSession,
+}
+
+impl Probe {
+    fn request(self) -> [u8; 2] {
+        match self {
+            Probe::TesterPresent => [SID_TESTER_PRESENT, 0x00],
+            Probe::DefaultSession => [SID_SESSION_CONTROL, 0x01],
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Probe::TesterPresent => "TesterPresent (3E 00)",
+            Probe::DefaultSession => "DefaultSession (10 01)",
+        }
+    }
+}
+
+/// 有回應的 ECU
+pub struct FoundEcu {
+    pub request_id: u32,
+    pub response_id: u32,
+    pub extended: bool,
+    // 與 IDENTIFICATION_DIDS 對應，還沒讀到是 None
+    pub identification: [Option<String>; 3],
+}
+
+enum Phase {
+    Idle,
+    // 目前探測第 index 個目標，sent_us 為送出時間
+    Sweep { index: usize, sent_us: Option<u64> },
+    Identify { ecu: usize },
+}
+
+/// 逐一對診斷請求 ID 送探測請求，記錄回應的 ID，再讀取各 ECU 的識別 DID
+pub struct EcuScanner {
+    pub first_id: u32,
+    pub last_id: u32,
+    // 29 位元 normal fixed：請求 18DA<目標><測試器>，回應 18DA<測試器><目標>
+    pub include_29bit: bool,
+    pub tester_address: u8,
+    pub probe: Probe,
+    pub wait_ms: u64,
+    pub read_identification: bool,
+    pub channel: u32,
+    pub found: Vec<FoundEcu>,
+    pub outbox: Vec<CanFrame>,
+    pub events: Vec<String>,
+    targets: Vec<(u32, bool)>,
+    phase: Phase,
+    reader: UdsClient,
+}
+
+impl Default for EcuScanner {
+    fn default() -> Self {
+        Self {
+            first_id: 0x700,
+            last_id: 0x7FF,
+            include_29bit: false,
+            tester_address: 0xF1,
+            probe: Probe::TesterPresent,
+            wait_ms: 50,
+            read_identification: true,
+            channel: 0,
+            found: Vec::new(),
+            outbox: Vec::new(),
+            events: Vec::new(),
+            targets: Vec::new(),
+            phase: Phase::Idle,
+            reader: UdsClient::default(),
+        }
+    }
+}
+
+fn normal_fixed_id(target: u8, source: u8) -> u32 {
+    0x18DA_0000 | (target as u32) << 8 | source as u32
+}
+
+impl EcuScanner {
+    pub fn is_running(&self) -> bool {
+        !matches!(self.phase, Phase::Idle)
+    }
+
+    pub fn start(&mut self) {
+        // 廣播 ID 會讓所有 ECU 一起回應，不列入掃描
+        self.targets = (self.first_id..=self.last_id.min(0x7FF))
+            .filter(|&id| id != FUNCTIONAL_ID)
+            .map(|id| (id, false))
+            .collect();
+        if self.include_29bit {
+            self.targets.extend(
+                (0..=0xFFu8)
+                    .filter(|&target| target != self.tester_address)
+                    .map(|target| (normal_fixed_id(target, self.tester_address), true)),
+            );
+        }
+        self.found.clear();
+        if self.targets.is_empty() {
+            return;
+        }
+        self.phase = Phase::Sweep {
+            index: 0,
+            sent_us: None,
+        };
+        self.events
+            .push(format!("ECU 掃描: 開始，共 {} 個 ID", self.targets.len()));
+    }
+
+    pub fn stop(&mut self) {
+        self.phase = Phase::Idle;
+        self.reader.cancel();
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        match self.phase {
+            Phase::Sweep {
+                index,
+                sent_us: Some(_),
+            } => {
+                let (request_id, extended) = self.targets[index];
+                if frame.channel != self.channel
+                    || frame.extended != extended
+                    || frame.remote
+                    || frame.error
+                    || frame.id == request_id
+                {
+                    return;
+                }
+                // 29 位元只接受對應的 normal fixed 回應 ID
+                if extended
+                    && frame.id != normal_fixed_id(self.tester_address, (request_id >> 8) as u8)
+                {
+                    return;
+                }
+                if !self.is_probe_response(frame.payload())
+                    || self.found.iter().any(|ecu| ecu.response_id == frame.id)
+                {
+                    return;
+                }
+                self.events
+                    .push(format!("ECU 掃描: 0x{:X} -> 0x{:X}", request_id, frame.id));
+                self.found.push(FoundEcu {
+                    request_id,
+                    response_id: frame.id,
+                    extended,
+                    identification: Default::default(),
+                });
+            }
+            Phase::Identify { .. } => self.reader.handle(frame, now_us),
+            _ => {}
+        }
+    }
+
+    // 單幀的正回應或對探測服務的負回應
+    fn is_probe_response(&self, payload: &[u8]) -> bool {
+        let sid = self.probe.request()[0];
+        match payload {
+            [length, response, ..] if (2..=7).contains(length) && *response == sid + 0x40 => true,
+            [0x03, 0x7F, service, _, ..] => *service == sid,
+            _ => false,
+        }
+    }
+
+    fn probe_frame(&self, id: u32, extended: bool) -> CanFrame {
+        let [sid, sub] = self.probe.request();
+        CanFrame {
+            channel: self.channel,
+            id,
+            extended,
+            len: 8,
+            data: [0x02, sid, sub, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC],
+            ..Default::default()
+        }
+    }
+
+    /// 取出要送的幀
+    pub fn drain_outbox(&mut self) -> impl Iterator<Item = CanFrame> + '_ {
+        self.outbox
+            .drain(..)
+            .chain(self.reader.link.outbox.drain(..))
+    }
+
+    pub fn poll(&mut self, now_us: u64) {
+        match self.phase {
+            Phase::Idle => {}
+            Phase::Sweep { index, sent_us } => match sent_us {
+                None => {
+                    let (id, extended) = self.targets[index];
+                    self.outbox.push(self.probe_frame(id, extended));
+                    self.phase = Phase::Sweep {
+                        index,
+                        sent_us: Some(now_us),
+                    };
+                }
+                Some(sent) if now_us >= sent + self.wait_ms * 1000 => {
+                    if index + 1 < self.targets.len() {
+                        self.phase = Phase::Sweep {
+                            index: index + 1,
+                            sent_us: None,
+                        };
+                    } else {
+                        self.events
+                            .push(format!("ECU 掃描: 完成，找到 {} 個 ECU", self.found.len()));
+                        self.next_identification(0);
+                    }
+                }
+                Some(_) => {}
+            },
+            Phase::Identify { ecu } => {
+                self.reader.link.channel = self.channel;
+                self.reader.poll(now_us);
+                while let Some(exchange) = self.reader.completed.pop_front() {
+                    let did = u16::from_be_bytes([exchange.request[1], exchange.request[2]]);
+                    let Some(slot) = IDENTIFICATION_DIDS.iter().position(|(d, _)| *d == did) else {
+                        continue;
+                    };
+                    let text = match &exchange.outcome {
+                        Outcome::Positive(data) => data
+                            .get(3..)
+                            .unwrap_or(&[])
+                            .iter()
+                            .filter(|b| b.is_ascii_graphic() || **b == b' ')
+                            .map(|&b| b as char)
+                            .collect(),
+                        outcome => format!("({})", outcome.describe(&exchange.request)),
+                    };
+                    self.found[ecu].identification[slot] = Some(text);
+                }
+                if self.reader.is_idle() {
+                    self.next_identification(ecu + 1);
+                }
+            }
+        }
+    }
+
+    // 換下一個 ECU 讀識別 DID，沒有了就結束
+    fn next_identification(&mut self, ecu: usize) {
+        let Some(target) = self.found.get(ecu).filter(|_| self.read_identification) else {
+            self.phase = Phase::Idle;
+            return;
+        };
+        self.reader.cancel();
+        self.reader.link.config.tx_id = target.request_id;
+        self.reader.link.config.rx_id = target.response_id;
+        self.reader.link.config.extended_id = target.extended;
+        for (did, _) in IDENTIFICATION_DIDS {
+            self.reader.request(read_did_request(did));
+        }
+        self.phase = Phase::Identify { ecu };
+    }
+
+    /// 回傳使用者要套用到 UDS 面板的 (請求 ID, 回應 ID, 29 位元)
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool) -> Option<(u32, u32, bool)> {
+        let running = self.is_running();
+        ui.add_enabled_ui(!running, |ui| {
+            egui::Grid::new("ecu_scan_config")
+                .num_columns(4)
+                .show(ui, |ui| {
+                    ui.label("11 位元範圍:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut self.first_id)
+                                .hexadecimal(3, false, true)
+                                .range(0..=0x7FF),
+                        );
+                        ui.label("~");
+                        ui.add(
+                            egui::DragValue::new(&mut self.last_id)
+                                .hexadecimal(3, false, true)
+                                .range(0..=0x7FF),
+                        );
+                    });
+                    ui.checkbox(&mut self.include_29bit, "含 29 位元 normal fixed");
+                    ui.add(
+                        egui::DragValue::new(&mut self.tester_address)
+                            .hexadecimal(2, false, true)
+                            .prefix("測試器 "),
+                    );
+                    ui.end_row();
+                    ui.label("探測:");
+                    egui::ComboBox::from_id_salt("ecu_scan_probe")
+                        .selected_text(self.probe.label())
+                        .show_ui(ui, |ui| {
+                            for probe in [Probe::TesterPresent, Probe::DefaultSession] {
+                                ui.selectable_value(&mut self.probe, probe, probe.label());
+                            }
+                        });
+                    ui.label("每個 ID 等待:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.wait_ms)
+                            .range(10..=1000)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
+                    ui.checkbox(&mut self.read_identification, "讀取識別 DID");
+                    ui.end_row();
+                });
+        });
+
+        ui.horizontal(|ui| {
+            if running {
+                if ui.button("停止").clicked() {
+                    self.stop();
+                }
+            } else if ui
+                .add_enabled(
+                    can_send && self.first_id <= self.last_id,
+                    egui::Button::new("開始掃描"),
+                )
+                .on_disabled_hover_text("需要先打開裝置")
+                .clicked()
+            {
+                self.start();
+            }
+            match self.phase {
+                Phase::Sweep { index, .. } => {
+                    let (id, _) = self.targets[index];
+                    ui.add(
+                        egui::ProgressBar::new(index as f32 / self.targets.len() as f32)
+                            .text(format!("0x{:X}", id)),
+                    );
+                }
+                Phase::Identify { ecu } => {
+                    ui.spinner();
+                    ui.label(format!("讀取識別 {}/{}", ecu + 1, self.found.len()));
+                }
+                Phase::Idle => {}
+            }
+        });
+
+        ui.separator();
+        let mut apply = None;
+        egui::Grid::new("ecu_scan_found")
+            .striped(true)
+            .num_columns(6)
+            .show(ui, |ui| {
+                ui.strong("請求 ID");
+                ui.strong("回應 ID");
+                for (_, name) in IDENTIFICATION_DIDS {
+                    ui.strong(name);
+                }
+                ui.label("");
+                ui.end_row();
+                for ecu in &self.found {
+                    ui.monospace(format!("{:X}", ecu.request_id));
+                    ui.monospace(format!("{:X}", ecu.response_id));
+                    for value in &ecu.identification {
+                        ui.label(value.as_deref().unwrap_or("-"));
+                    }
+                    if ui.small_button("用於 UDS").clicked() {
+                        apply = Some((ecu.request_id, ecu.response_id, ecu.extended));
+                    }
+                    ui.end_row();
+                }
+            });
+        apply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_bus::VirtualBus;
+
+    /// 掃描器接在虛擬匯流排上，每 1 ms 推進一次，回傳匯流排上看到的 (時間, ID)
+    fn run(
+        scanner: &mut EcuScanner,
+        bus: &mut VirtualBus,
+        tx: &flume::Sender<CanFrame>,
+        now: &mut u64,
+        ms: usize,
+    ) -> Vec<(u64, u32)> {
+        let (data_tx, data_rx) = flume::unbounded();
+        let mut seen = Vec::new();
+        for _ in 0..ms {
+            *now += 1_000;
+            scanner.poll(*now);
+            for frame in scanner.drain_outbox() {
+                tx.send(frame).unwrap();
+            }
+            bus.poll(*now, 0, &data_tx);
+            while let Ok(frame) = data_rx.try_recv() {
+                seen.push((*now, frame.id));
+                scanner.handle(&frame, *now);
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn sweep_finds_simulated_ecu_and_reads_identification() {
+        let (mut bus, tx) = VirtualBus::start();
+        let mut scanner = EcuScanner {
+            first_id: 0x7DE,
+            last_id: 0x7E2,
+            ..Default::default()
+        };
+        let mut now = 0;
+        scanner.start();
+        let seen = run(&mut scanner, &mut bus, &tx, &mut now, 1_000);
+        assert!(!scanner.is_running());
+
+        // 廣播 ID 0x7DF 不探測；沒有回應的 ID 等 50 ms 後換下一個
+        let probes: Vec<_> = seen
+            .iter()
+            .filter(|(_, id)| (0x7DE..=0x7E2).contains(id))
+            .collect();
+        let probe_ids: Vec<u32> = probes.iter().map(|(_, id)| *id).take(4).collect();
+        assert_eq!(probe_ids, [0x7DE, 0x7E0, 0x7E1, 0x7E2]);
+        assert!(probes[1].0 - probes[0].0 >= scanner.wait_ms * 1000);
+
+        assert_eq!(scanner.found.len(), 1);
+        let ecu = &scanner.found[0];
+        assert_eq!(
+            (ecu.request_id, ecu.response_id, ecu.extended),
+            (0x7E0, 0x7E8, false)
+        );
+        assert_eq!(
+            ecu.identification,
+            [
+                Some("SIMULATEDECU00001".to_string()),
+                Some("SN-000042".to_string()),
+                Some("SW 1.0.0".to_string()),
+            ]
+        );
+        assert!(scanner.events.iter().any(|e| e.contains("找到 1 個 ECU")));
+    }
+
+    #[test]
+    fn silent_ids_time_out() {
+        let (mut bus, tx) = VirtualBus::start();
+        let mut scanner = EcuScanner {
+            first_id: 0x100,
+            last_id: 0x102,
+            wait_ms: 20,
+            ..Default::default()
+        };
+        let mut now = 0;
+        scanner.start();
+        let seen = run(&mut scanner, &mut bus, &tx, &mut now, 30);
+        assert_eq!(
+            seen.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+            [0x100, 0x101]
+        );
+        assert!(scanner.is_running());
+
+        run(&mut scanner, &mut bus, &tx, &mut now, 50);
+        assert!(!scanner.is_running());
+        assert!(scanner.found.is_empty());
+        assert!(scanner.events.last().unwrap().contains("找到 0 個 ECU"));
+    }
+}
@@ -0,0 +1,726 @@
+use crate::canbus::parse_hex_bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// 物件類型 (CiA 306)
+const OBJECT_NULL: u8 = 0x00;
+const OBJECT_DOMAIN: u8 = 0x02;
+const OBJECT_DEFTYPE: u8 = 0x05;
+const OBJECT_DEFSTRUCT: u8 = 0x06;
+pub const OBJECT_VAR: u8 = 0x07;
+const OBJECT_ARRAY: u8 = 0x08;
+const OBJECT_RECORD: u8 = 0x09;
+// PDO 通訊參數與映射參數的索引範圍
+const RPDO_COMMUNICATION: u16 = 0x1400;
+const TPDO_COMMUNICATION: u16 = 0x1800;
+const PDO_MAPPING_OFFSET: u16 = 0x0200;
+const PDO_COUNT: u16 = 512;
+// COB-ID 最高位元：PDO 無效；bit 29：29 位元 ID
+const COB_ID_INVALID: u64 = 0x8000_0000;
+const COB_ID_EXTENDED: u64 = 0x2000_0000;
+
+/// CANopen 資料型別 (物件字典 0001h ~ 001Bh)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataType(pub u16);
+
+impl DataType {
+    pub fn name(self) -> &'static str {
+        match self.0 {
+            0x01 => "BOOLEAN",
+            0x02 => "INTEGER8",
+            0x03 => "INTEGER16",
+            0x04 => "INTEGER32",
+            0x05 => "UNSIGNED8",
+            0x06 => "UNSIGNED16",
+            0x07 => "UNSIGNED32",
+            0x08 => "REAL32",
+            0x09 => "VISIBLE_STRING",
+            0x0A => "OCTET_STRING",
+            0x0B => "UNICODE_STRING",
+            0x0C => "TIME_OF_DAY",
+            0x0D => "TIME_DIFFERENCE",
+            0x0F => "DOMAIN",
+            0x10 => "INTEGER24",
+            0x11 => "REAL64",
+            0x12 => "INTEGER40",
+            0x13 => "INTEGER48",
+            0x14 => "INTEGER56",
+            0x15 => "INTEGER64",
+            0x16 => "UNSIGNED24",
+            0x18 => "UNSIGNED40",
+            0x19 => "UNSIGNED48",
+            0x1A => "UNSIGNED56",
+            0x1B => "UNSIGNED64",
+            _ => "?",
+        }
+    }
+
+    /// 數值型別的位元寬度；字串與 DOMAIN 為 None
+    pub fn bits(self) -> Option<u32> {
+        match self.0 {
+            0x01 => Some(1),
+            0x02 | 0x05 => Some(8),
+            0x03 | 0x06 => Some(16),
+            0x10 | 0x16 => Some(24),
+            0x04 | 0x07 | 0x08 => Some(32),
+            0x12 | 0x18 => Some(40),
+            0x13 | 0x19 => Some(48),
+            0x14 | 0x1A => Some(56),
+            0x15 | 0x1B | 0x11 => Some(64),
+            _ => None,
+        }
+    }
+
+    fn signed(self) -> bool {
+        matches!(self.0, 0x02..=0x04 | 0x10 | 0x12..=0x15)
+    }
+
+    /// PDO 或 SDO 取出的原始值 (bits 位元，小端) 轉成文字
+    pub fn format_raw(self, raw: u64, bits: u32) -> String {
+        match self.0 {
+            0x01 => (raw & 1 != 0).to_string(),
+            0x08 if bits == 32 => f32::from_bits(raw as u32).to_string(),
+            0x11 if bits == 64 => f64::from_bits(raw).to_string(),
+            _ if self.signed() && (1..=64).contains(&bits) => {
+                let shift = 64 - bits;
+                (((raw << shift) as i64) >> shift).to_string()
+            }
+            _ => raw.to_string(),
+        }
+    }
+
+    /// SDO 讀到的資料轉成文字
+    pub fn format(self, data: &[u8]) -> String {
+        match self.0 {
+            0x09 => String::from_utf8_lossy(data).into_owned(),
+            0x0B => {
+                let units: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            _ if self.bits().is_some() && (1..=8).contains(&data.len()) => {
+                let mut bytes = [0u8; 8];
+                bytes[..data.len()].copy_from_slice(data);
+                self.format_raw(u64::from_le_bytes(bytes), data.len() as u32 * 8)
+            }
+            _ => data
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// 使用者輸入的值轉成 SDO 要寫的資料
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, String> {
+        let text = text.trim();
+        match self.0 {
+            0x09 => return Ok(text.as_bytes().to_vec()),
+            0x0B => return Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            0x08 => {
+                let value: f32 = text
+                    .parse()
+                    .map_err(|_| format!("無效的 REAL32: {}", text))?;
+                return Ok(value.to_le_bytes().to_vec());
+            }
+            0x11 => {
+                let value: f64 = text
+                    .parse()
+                    .map_err(|_| format!("無效的 REAL64: {}", text))?;
+                return Ok(value.to_le_bytes().to_vec());
+            }
+            _ => {}
+        }
+        let Some(bits) = self.bits() else {
+            return parse_hex_bytes(text);
+        };
+        let value = match text {
+            "true" => 1,
+            "false" => 0,
+            _ => parse_integer(text).ok_or_else(|| format!("無效的數值: {}", text))?,
+        };
+        let width = bits.max(8);
+        let (min, max) = if self.signed() {
+            (-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1)
+        } else {
+            (0, (1i128 << width) - 1)
+        };
+        if value < min || value > max {
+            return Err(format!("{} 超出 {} 的範圍", text, self.name()));
+        }
+        Ok(value.to_le_bytes()[..width as usize / 8].to_vec())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    // rwr / rww：可讀寫，建議映射到 TPDO / RPDO
+    ReadWriteInput,
+    ReadWriteOutput,
+    Const,
+}
+
+impl Access {
+    fn parse(text: &str) -> Option<Self> {
+        Some(match text.to_ascii_lowercase().as_str() {
+            "ro" => Access::ReadOnly,
+            "wo" => Access::WriteOnly,
+            "rw" => Access::ReadWrite,
+            "rwr" => Access::ReadWriteInput,
+            "rww" => Access::ReadWriteOutput,
+            "const" => Access::Const,
+            _ => return None,
+        })
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Access::ReadOnly => "ro",
+            Access::WriteOnly => "wo",
+            Access::ReadWrite => "rw",
+            Access::ReadWriteInput => "rwr",
+            Access::ReadWriteOutput => "rww",
+            Access::Const => "const",
+        }
+    }
+
+    pub fn readable(self) -> bool {
+        self != Access::WriteOnly
+    }
+
+    pub fn writable(self) -> bool {
+        !matches!(self, Access::ReadOnly | Access::Const)
+    }
+}
+
+/// 物件字典中的一個子索引
+#[derive(Debug, Clone)]
+pub struct OdEntry {
+    pub index: u16,
+    pub sub_index: u8,
+    pub name: String,
+    pub data_type: DataType,
+    pub access: Access,
+    pub pdo_mapping: bool,
+    pub default_value: Option<String>,
+    // DCF 的 ParameterValue
+    pub parameter_value: Option<String>,
+}
+
+impl OdEntry {
+    /// DCF 有設定值就用設定值，否則用預設值
+    pub fn value_text(&self) -> Option<&str> {
+        self.parameter_value
+            .as_deref()
+            .or(self.default_value.as_deref())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OdObject {
+    pub name: String,
+    pub object_type: u8,
+    pub entries: Vec<OdEntry>,
+}
+
+/// 從 EDS 或 DCF 讀進來的物件字典
+#[derive(Debug, Clone, Default)]
+pub struct ObjectDictionary {
+    pub objects: BTreeMap<u16, OdObject>,
+    pub product_name: Option<String>,
+    // DCF 的 [DeviceComissioning] NodeID
+    pub node_id: Option<u8>,
+}
+
+/// 一個 PDO 的 COB-ID 與映射 (索引, 子索引, 位元長度)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdoMapping {
+    pub transmit: bool,
+    pub number: u16,
+    pub cob_id: u32,
+    pub extended: bool,
+    pub entries: Vec<(u16, u8, u8)>,
+}
+
+impl PdoMapping {
+    pub fn label(&self) -> String {
+        format!(
+            "{}PDO{} 0x{:X}",
+            if self.transmit { "T" } else { "R" },
+            self.number,
+            self.cob_id
+        )
+    }
+
+    /// 依映射取出各物件的原始值
+    pub fn decode(&self, data: &[u8]) -> Vec<(u16, u8, u64, u32)> {
+        let mut bytes = [0u8; 8];
+        let n = data.len().min(8);
+        bytes[..n].copy_from_slice(&data[..n]);
+        let payload = u64::from_le_bytes(bytes);
+        let mut offset = 0u32;
+        let mut values = Vec::new();
+        for &(index, sub_index, bits) in &self.entries {
+            let bits = bits as u32;
+            if bits == 0 || offset + bits > n as u32 * 8 {
+                break;
+            }
+            let mask = if bits == 64 {
+                u64::MAX
+            } else {
+                (1u64 << bits) - 1
+            };
+            // 0002h ~ 0007h 的索引是佔位用的空白映射
+            if index >= 0x0008 {
+                values.push((index, sub_index, payload >> offset & mask, bits));
+            }
+            offset += bits;
+        }
+        values
+    }
+}
+
+/// 由物件值查詢函式組出所有有效的 PDO；裝置讀回的值與 DCF 共用
+pub fn pdo_mappings(value: impl Fn(u16, u8) -> Option<u64>) -> Vec<PdoMapping> {
+    let mut mappings = Vec::new();
+    for (base, transmit) in [(RPDO_COMMUNICATION, false), (TPDO_COMMUNICATION, true)] {
+        for number in 0..PDO_COUNT {
+            let communication = base + number;
+            let Some(cob_id) = value(communication, 1) else {
+                continue;
+            };
+            if cob_id & COB_ID_INVALID != 0 {
+                continue;
+            }
+            let mapping = communication + PDO_MAPPING_OFFSET;
+            let count = value(mapping, 0).unwrap_or(0).min(64) as u8;
+            let entries = (1..=count)
+                .filter_map(|sub| value(mapping, sub))
+                .map(|v| ((v >> 16) as u16, (v >> 8) as u8, v as u8))
+                .collect();
+            mappings.push(PdoMapping {
+                transmit,
+                number: number + 1,
+                cob_id: (cob_id & 0x1FFF_FFFF) as u32,
+                extended: cob_id & COB_ID_EXTENDED != 0,
+                entries,
+            });
+        }
+    }
+    mappings
+}
+
+/// 整數：十進位 (可帶負號) 或 0x 開頭的十六進位
+pub fn parse_integer(text: &str) -> Option<i128> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, text),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// EDS 的數值可以寫成 "$NODEID+0x180"，用實際的節點 ID 代入
+pub fn resolve_value(text: &str, node_id: u8) -> Option<u64> {
+    let replaced = text
+        .to_ascii_uppercase()
+        .replace("$NODEID", &node_id.to_string());
+    let mut sum: i128 = 0;
+    for term in replaced.split('+') {
+        sum += parse_integer(&term.to_ascii_lowercase())?;
+    }
+    Some(sum as u64)
+}
+
+type Section = HashMap<String, String>;
+
+// INI 解析：區段名稱轉大寫，鍵轉小寫
+fn parse_sections(text: &str) -> Result<HashMap<String, Section>, String> {
+    let mut sections: HashMap<String, Section> = HashMap::new();
+    let mut current: Option<String> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                return Err(format!("第 {} 行: 區段名稱缺少 ']'", line_no));
+            };
+            let name = name.trim().to_ascii_uppercase();
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("第 {} 行: 不是 key=value", line_no));
+        };
+        let Some(section) = current.as_ref() else {
+            return Err(format!("第 {} 行: 在任何區段之前", line_no));
+        };
+        sections
+            .get_mut(section)
+            .unwrap()
+            .insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+    Ok(sections)
+}
+
+fn parse_hex_index(text: &str) -> Option<u16> {
+    if text.len() != 4 {
+        return None;
+    }
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn non_empty(section: &Section, key: &str) -> Option<String> {
+    section.get(key).filter(|v| !v.is_empty()).cloned()
+}
+
+fn parse_entry(
+    section: &Section,
+    name: &str,
+    index: u16,
+    sub_index: u8,
+) -> Result<OdEntry, String> {
+    let field = |key: &str| section.get(key).map(String::as_str).unwrap_or("");
+    // DOMAIN 常省略 DataType，視為 DOMAIN (000Fh)
+    let domain = parse_integer(field("objecttype")) == Some(OBJECT_DOMAIN as i128);
+    let data_type = match field("datatype") {
+        "" if domain => 0x0F,
+        text => parse_integer(text)
+            .filter(|v| (0..=0xFFFF).contains(v))
+            .ok_or_else(|| format!("[{}] DataType 無效", name))? as u16,
+    };
+    let access = Access::parse(field("accesstype"))
+        .ok_or_else(|| format!("[{}] AccessType 無效: {}", name, field("accesstype")))?;
+    Ok(OdEntry {
+        index,
+        sub_index,
+        name: field("parametername").to_string(),
+        data_type: DataType(data_type),
+        access,
+        pdo_mapping: parse_integer(field("pdomapping")).unwrap_or(0) != 0,
+        default_value: non_empty(section, "defaultvalue"),
+        parameter_value: non_empty(section, "parametervalue"),
+    })
+}
+
+impl ObjectDictionary {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let bytes = std::fs::read(path.as_ref()).map_err(|e| format!("無法讀取 EDS/DCF: {}", e))?;
+        // 和 DBC 一樣，非 UTF-8 時逐位元組轉換
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+        };
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let sections = parse_sections(text)?;
+        let mut dictionary = ObjectDictionary {
+            product_name: sections
+                .get("DEVICEINFO")
+                .and_then(|s| non_empty(s, "productname")),
+            node_id: sections
+                .get("DEVICECOMISSIONING")
+                .and_then(|s| s.get("nodeid"))
+                .and_then(|v| parse_integer(v))
+                .and_then(|v| u8::try_from(v).ok()),
+            ..Default::default()
+        };
+
+        // 1. **物件**：區段名稱是 4 位十六進位
+        for (name, section) in &sections {
+            let Some(index) = parse_hex_index(name) else {
+                continue;
+            };
+            let object_type = parse_integer(section.get("objecttype").map_or("", |v| v))
+                .map_or(OBJECT_VAR, |v| v as u8);
+            // 型別定義不是可存取的物件，直接略過
+            if matches!(object_type, OBJECT_NULL | OBJECT_DEFTYPE | OBJECT_DEFSTRUCT) {
+                continue;
+            }
+            let object_name = section.get("parametername").cloned().unwrap_or_default();
+            let mut entries = Vec::new();
+            if !matches!(object_type, OBJECT_ARRAY | OBJECT_RECORD) {
+                entries.push(parse_entry(section, name, index, 0)?);
+            } else if let Some(count) = section
+                .get("compactsubobj")
+                .and_then(|v| parse_integer(v))
+                .filter(|&v| v > 0)
+            {
+                // 精簡寫法：子索引 1..N 共用同一組屬性，名稱另外列在 [xxxxName]
+                let names = sections.get(&format!("{}NAME", name));
+                entries.push(OdEntry {
+                    index,
+                    sub_index: 0,
+                    name: "NrOfObjects".to_string(),
+                    data_type: DataType(0x05),
+                    access: Access::ReadOnly,
+                    pdo_mapping: false,
+                    default_value: Some(count.to_string()),
+                    parameter_value: None,
+                });
+                for sub in 1..=count.min(254) as u8 {
+                    let mut entry = parse_entry(section, name, index, sub)?;
+                    entry.name = names
+                        .and_then(|n| n.get(&sub.to_string()))
+                        .cloned()
+                        .unwrap_or_else(|| format!("{}{}", object_name, sub));
+                    let values = sections.get(&format!("{}VALUE", name));
+                    entry.parameter_value = values.and_then(|v| v.get(&sub.to_string())).cloned();
+                    entries.push(entry);
+                }
+            } else {
+                let prefix = format!("{}SUB", name);
+                for (sub_name, sub_section) in &sections {
+                    let Some(sub) = sub_name
+                        .strip_prefix(&prefix)
+                        .and_then(|s| u8::from_str_radix(s, 16).ok())
+                    else {
+                        continue;
+                    };
+                    entries.push(parse_entry(sub_section, sub_name, index, sub)?);
+                }
+                entries.sort_by_key(|e| e.sub_index);
+            }
+            dictionary.objects.insert(
+                index,
+                OdObject {
+                    name: object_name,
+                    object_type,
+                    entries,
+                },
+            );
+        }
+        if dictionary.objects.is_empty() {
+            return Err("沒有任何物件".to_string());
+        }
+        Ok(dictionary)
+    }
+
+    pub fn entry(&self, index: u16, sub_index: u8) -> Option<&OdEntry> {
+        self.objects
+            .get(&index)?
+            .entries
+            .iter()
+            .find(|e| e.sub_index == sub_index)
+    }
+
+    /// DCF (或 EDS 預設值) 裡設定的 PDO
+    pub fn pdo_mappings(&self, node_id: u8) -> Vec<PdoMapping> {
+        pdo_mappings(|index, sub_index| {
+            self.entry(index, sub_index)
+                .and_then(|e| e.value_text())
+                .and_then(|text| resolve_value(text, node_id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCF: &str = "\
+[DeviceInfo]
+ProductName=Test Node
+
+[DeviceComissioning]
+NodeID=0x10
+
+[1800]
+ParameterName=TPDO1 communication parameter
+ObjectType=0x9
+SubNumber=2
+
+[1800sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=const
+DefaultValue=2
+
+[1800sub1]
+ParameterName=COB-ID used by TPDO
+DataType=0x0007
+AccessType=rw
+DefaultValue=0x80000000
+ParameterValue=$NODEID+0x180
+
+[1801]
+ParameterName=TPDO2 communication parameter
+ObjectType=0x9
+SubNumber=2
+
+[1801sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=const
+DefaultValue=2
+
+[1801sub1]
+ParameterName=COB-ID used by TPDO
+DataType=0x0007
+AccessType=rw
+DefaultValue=$NODEID+0x80000280
+
+[1A00]
+ParameterName=TPDO1 mapping parameter
+ObjectType=0x9
+SubNumber=3
+
+[1A00sub0]
+ParameterName=Number of mapped objects
+DataType=0x0005
+AccessType=rw
+ParameterValue=2
+
+[1A00sub1]
+ParameterName=Mapped object 1
+DataType=0x0007
+AccessType=rw
+ParameterValue=0x20000108
+
+[1A00sub2]
+ParameterName=Mapped object 2
+DataType=0x0007
+AccessType=rw
+ParameterValue=0x20000208
+
+[2000]
+ParameterName=Sensor
+ObjectType=0x8
+DataType=0x0002
+AccessType=rwr
+PDOMapping=1
+CompactSubObj=2
+
+[2000Name]
+NrOfEntries=1
+1=Temperature
+
+[2000Value]
+NrOfEntries=1
+1=-5
+";
+
+    #[test]
+    fn compact_sub_objects() {
+        let dictionary = ObjectDictionary::parse(DCF).unwrap();
+        assert_eq!(dictionary.product_name.as_deref(), Some("Test Node"));
+        let entries = &dictionary.objects[&0x2000].entries;
+        assert_eq!(entries.len(), 3);
+        // 子索引 0 是自動補上的項目數
+        assert_eq!(entries[0].value_text(), Some("2"));
+        // 有名稱就用 [2000Name]，否則用物件名稱加子索引
+        let first = dictionary.entry(0x2000, 1).unwrap();
+        assert_eq!(first.name, "Temperature");
+        assert_eq!(first.data_type, DataType(0x02));
+        assert_eq!(first.access, Access::ReadWriteInput);
+        assert!(first.pdo_mapping);
+        assert_eq!(first.value_text(), Some("-5"));
+        let second = dictionary.entry(0x2000, 2).unwrap();
+        assert_eq!(second.name, "Sensor2");
+        assert_eq!(second.value_text(), None);
+    }
+
+    #[test]
+    fn resolves_node_id() {
+        assert_eq!(resolve_value("$NODEID+0x180", 5), Some(0x185));
+        assert_eq!(resolve_value("0x180+$nodeid", 0x7F), Some(0x1FF));
+        assert_eq!(resolve_value("$NODEID", 3), Some(3));
+        assert_eq!(resolve_value("$NODEID+abc", 3), None);
+    }
+
+    #[test]
+    fn pdo_mapping_from_dcf() {
+        let dictionary = ObjectDictionary::parse(DCF).unwrap();
+        assert_eq!(dictionary.node_id, Some(0x10));
+        // TPDO2 的 COB-ID 設了無效位元，不列出
+        let mappings = dictionary.pdo_mappings(0x10);
+        assert_eq!(
+            mappings,
+            [PdoMapping {
+                transmit: true,
+                number: 1,
+                cob_id: 0x190,
+                extended: false,
+                entries: vec![(0x2000, 1, 8), (0x2000, 2, 8)],
+            }]
+        );
+        let values = mappings[0].decode(&[0xFB, 0x07]);
+        assert_eq!(values, [(0x2000, 1, 0xFB, 8), (0x2000, 2, 0x07, 8)]);
+        assert_eq!(DataType(0x02).format_raw(values[0].2, values[0].3), "-5");
+    }
+
+    #[test]
+    fn formats_signed_64_bit() {
+        assert_eq!(DataType(0x15).format_raw(u64::MAX, 64), "-1");
+        assert_eq!(DataType(0x15).format(&[0xFF; 8]), "-1");
+        assert_eq!(DataType(0x1B).format(&[0xFF; 8]), u64::MAX.to_string());
+        assert_eq!(DataType(0x10).format(&[0xFE, 0xFF, 0xFF]), "-2");
+    }
+
+    #[test]
+    fn skips_type_definitions_and_defaults_domain() {
+        let eds = "\
+[0040]
+ParameterName=DEFSTRUCT Identity
+ObjectType=0x6
+SubNumber=2
+
+[0040sub0]
+ParameterName=Number of entries
+ObjectType=0x7
+AccessType=ro
+DefaultValue=1
+
+[0040sub1]
+ParameterName=Vendor-ID
+ObjectType=0x7
+AccessType=ro
+DefaultValue=0x0007
+
+[1F50]
+ParameterName=Program data
+ObjectType=0x2
+AccessType=rw
+
+[2001]
+ParameterName=Counter
+ObjectType=0x7
+DataType=0x0006
+AccessType=ro
+";
+        let dictionary = ObjectDictionary::parse(eds).unwrap();
+        // DEFSTRUCT 是型別定義，不列入物件字典
+        assert!(!dictionary.objects.contains_key(&0x0040));
+        let domain = dictionary.entry(0x1F50, 0).unwrap();
+        assert_eq!(domain.data_type, DataType(0x0F));
+        assert_eq!(domain.access, Access::ReadWrite);
+        assert_eq!(
+            dictionary.entry(0x2001, 0).unwrap().data_type,
+            DataType(0x06)
+        );
+
+        // 一般 VAR 缺 DataType 仍是錯誤
+        let broken = "[2002]\nParameterName=Broken\nObjectType=0x7\nAccessType=ro\n";
+        assert!(ObjectDictionary::parse(broken)
+            .unwrap_err()
+            .contains("DataType"));
+    }
+}
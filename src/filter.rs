@@ -0,0 +1,718 @@
+use crate::canbus::CanFrame;
+use crate::dbc::Dbc;
+use crate::frame_store::FrameStore;
+use eframe::egui;
+
+// 每個 UI 畫面最多掃描的幀數，換篩選條件時不會卡住畫面
+const SCAN_BUDGET: u64 = 500_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Id,
+    Ext,
+    Std,
+    Rtr,
+    Err,
+    Dlc,
+    Channel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Field(Field),
+    Data(Box<Expr>),
+    Signal {
+        message: Option<String>,
+        name: String,
+    },
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    BitNot(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    // `x in a..b` 或 `x in {a, b..c}`，範圍包含兩端
+    In(Box<Expr>, Vec<(Expr, Option<Expr>)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 23] = [
+    "..=", "&&", "||", "==", "!=", "<=", ">=", "..", "<", ">", "!", "&", "|", "^", "~", "-", "(",
+    ")", "[", "]", "{", "}", ",",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Tok)>, String> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap_or(' ');
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'))
+                .unwrap_or(rest.len());
+            // 數字後面緊接的 ".." 屬於範圍運算子
+            let len = rest[..len].find("..").unwrap_or(len);
+            let literal = rest[..len].replace('_', "");
+            let value = if let Some(hex) = literal.strip_prefix("0x").or(literal.strip_prefix("0X"))
+            {
+                u64::from_str_radix(hex, 16).ok().map(|v| v as f64)
+            } else if let Some(bin) = literal.strip_prefix("0b").or(literal.strip_prefix("0B")) {
+                u64::from_str_radix(bin, 2).ok().map(|v| v as f64)
+            } else {
+                literal.parse::<f64>().ok()
+            };
+            let value = value
+                .ok_or_else(|| error_at(text, i, &format!("無效的數字 '{}'", &rest[..len])))?;
+            tokens.push((i, Tok::Num(value)));
+            i += len;
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            // 名稱可以含 '.'，給 signal(訊息.訊號) 使用
+            let len = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '.'))
+                .unwrap_or(rest.len());
+            let len = rest[..len].find("..").unwrap_or(len);
+            tokens.push((i, Tok::Ident(rest[..len].to_string())));
+            i += len;
+            continue;
+        }
+        match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            Some(op) => {
+                tokens.push((i, Tok::Op(op)));
+                i += op.len();
+            }
+            None => return Err(error_at(text, i, &format!("無法辨識的字元 '{}'", c))),
+        }
+    }
+    Ok(tokens)
+}
+
+fn error_at(text: &str, byte_pos: usize, msg: &str) -> String {
+    let column = text[..byte_pos.min(text.len())].chars().count() + 1;
+    format!("位置 {}: {}", column, msg)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, Tok)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(p, _)| *p)
+            .unwrap_or(self.text.len())
+    }
+
+    fn error(&self, msg: &str) -> String {
+        let found = match self.peek() {
+            None => "結尾".to_string(),
+            Some(Tok::Num(v)) => format!("'{}'", v),
+            Some(Tok::Ident(s)) => format!("'{}'", s),
+            Some(Tok::Op(op)) => format!("'{}'", op),
+        };
+        error_at(
+            self.text,
+            self.offset(),
+            &format!("{}，但遇到 {}", msg, found),
+        )
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Op(o)) if *o == op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Ident(s)) if s.eq_ignore_ascii_case(word)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("預期 '{}'", op)))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and()?;
+        while self.eat_op("||") || self.eat_word("or") {
+            let rhs = self.and()?;
+            lhs = Expr::Binary(BinOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.not()?;
+        while self.eat_op("&&") || self.eat_word("and") {
+            let rhs = self.not()?;
+            lhs = Expr::Binary(BinOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat_op("!") || self.eat_word("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let lhs = self.bit_or()?;
+        if self.eat_word("in") {
+            return self.set(lhs);
+        }
+        let op = match self.peek() {
+            Some(Tok::Op("==")) => BinOp::Eq,
+            Some(Tok::Op("!=")) => BinOp::Ne,
+            Some(Tok::Op("<")) => BinOp::Lt,
+            Some(Tok::Op("<=")) => BinOp::Le,
+            Some(Tok::Op(">")) => BinOp::Gt,
+            Some(Tok::Op(">=")) => BinOp::Ge,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.bit_or()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    // `in a..b` 或 `in {a, b..c}`
+    fn set(&mut self, lhs: Expr) -> Result<Expr, String> {
+        let braced = self.eat_op("{");
+        let mut items = Vec::new();
+        loop {
+            let low = self.bit_or()?;
+            let high = if self.eat_op("..=") || self.eat_op("..") {
+                Some(self.bit_or()?)
+            } else {
+                None
+            };
+            items.push((low, high));
+            if !braced || !self.eat_op(",") {
+                break;
+            }
+        }
+        if braced {
+            self.expect_op("}")?;
+        }
+        Ok(Expr::In(Box::new(lhs), items))
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.bit_xor()?;
+        while self.peek() == Some(&Tok::Op("|")) {
+            self.pos += 1;
+            let rhs = self.bit_xor()?;
+            lhs = Expr::Binary(BinOp::BitOr, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.bit_and()?;
+        while self.peek() == Some(&Tok::Op("^")) {
+            self.pos += 1;
+            let rhs = self.bit_and()?;
+            lhs = Expr::Binary(BinOp::BitXor, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Tok::Op("&")) {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(BinOp::BitAnd, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_op("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat_op("~") {
+            return Ok(Expr::BitNot(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let start = self.offset();
+        match self.peek().cloned() {
+            Some(Tok::Num(v)) => {
+                self.pos += 1;
+                Ok(Expr::Num(v))
+            }
+            Some(Tok::Op("(")) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                self.expect_op(")")?;
+                Ok(expr)
+            }
+            Some(Tok::Ident(word)) => {
+                self.pos += 1;
+                let field = match word.to_ascii_lowercase().as_str() {
+                    "id" => Field::Id,
+                    "ext" => Field::Ext,
+                    "std" => Field::Std,
+                    "rtr" | "remote" => Field::Rtr,
+                    "err" | "error" => Field::Err,
+                    "dlc" | "len" => Field::Dlc,
+                    "channel" | "ch" => Field::Channel,
+                    "data" => {
+                        self.expect_op("[")?;
+                        let index = self.or()?;
+                        self.expect_op("]")?;
+                        return Ok(Expr::Data(Box::new(index)));
+                    }
+                    "signal" => return self.signal(),
+                    _ => {
+                        return Err(error_at(
+                            self.text,
+                            start,
+                            &format!("未知的欄位 '{}'", word),
+                        ))
+                    }
+                };
+                Ok(Expr::Field(field))
+            }
+            _ => Err(self.error("預期數值、欄位或 '('")),
+        }
+    }
+
+    // signal(Name) 或 signal(Message.Name)
+    fn signal(&mut self) -> Result<Expr, String> {
+        self.expect_op("(")?;
+        let start = self.offset();
+        let Some(Tok::Ident(path)) = self.peek().cloned() else {
+            return Err(self.error("預期訊號名稱"));
+        };
+        self.pos += 1;
+        let (message, name) = match path.split_once('.') {
+            Some((m, n)) => (Some(m.to_string()), n.to_string()),
+            None => (None, path),
+        };
+        if name.is_empty() || name.contains('.') {
+            return Err(error_at(
+                self.text,
+                start,
+                "訊號名稱格式應為 名稱 或 訊息.名稱",
+            ));
+        }
+        self.expect_op(")")?;
+        Ok(Expr::Signal { message, name })
+    }
+}
+
+fn truthy(v: Option<f64>) -> bool {
+    v.is_some_and(|v| v != 0.0)
+}
+
+fn flag(b: bool) -> Option<f64> {
+    Some(if b { 1.0 } else { 0.0 })
+}
+
+impl Expr {
+    // 取不到的值 (資料位元組不存在、訊號不在此幀) 為 None，比較結果一律為假
+    fn eval(&self, frame: &CanFrame, dbc: Option<&Dbc>) -> Option<f64> {
+        match self {
+            Expr::Num(v) => Some(*v),
+            Expr::Field(field) => match field {
+                Field::Id => Some(frame.id as f64),
+                Field::Ext => flag(frame.extended),
+                Field::Std => flag(!frame.extended),
+                Field::Rtr => flag(frame.remote),
+                Field::Err => flag(frame.error),
+                Field::Dlc => Some(frame.len as f64),
+                Field::Channel => Some(frame.channel as f64),
+            },
+            Expr::Data(index) => {
+                let index = index.eval(frame, dbc)?;
+                if frame.remote || index < 0.0 {
+                    return None;
+                }
+                frame.payload().get(index as usize).map(|&b| b as f64)
+            }
+            Expr::Signal { message, name } => {
+                let m = dbc?.message(frame.id, frame.extended)?;
+                if message.as_ref().is_some_and(|n| *n != m.name) {
+                    return None;
+                }
+                m.signal_value(name, frame.payload())
+            }
+            Expr::Not(e) => flag(!truthy(e.eval(frame, dbc))),
+            Expr::Neg(e) => e.eval(frame, dbc).map(|v| -v),
+            Expr::BitNot(e) => e.eval(frame, dbc).map(|v| !(v as i64) as f64),
+            Expr::Binary(BinOp::And, a, b) => {
+                flag(truthy(a.eval(frame, dbc)) && truthy(b.eval(frame, dbc)))
+            }
+            Expr::Binary(BinOp::Or, a, b) => {
+                flag(truthy(a.eval(frame, dbc)) || truthy(b.eval(frame, dbc)))
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(frame, dbc)?, b.eval(frame, dbc)?);
+                match op {
+                    BinOp::Eq => flag(a == b),
+                    BinOp::Ne => flag(a != b),
+                    BinOp::Lt => flag(a < b),
+                    BinOp::Le => flag(a <= b),
+                    BinOp::Gt => flag(a > b),
+                    BinOp::Ge => flag(a >= b),
+                    BinOp::BitOr => Some(((a as i64) | (b as i64)) as f64),
+                    BinOp::BitXor => Some(((a as i64) ^ (b as i64)) as f64),
+                    BinOp::BitAnd => Some(((a as i64) & (b as i64)) as f64),
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+            }
+            Expr::In(value, items) => {
+                let v = value.eval(frame, dbc)?;
+                let hit = items.iter().any(|(low, high)| {
+                    let Some(low) = low.eval(frame, dbc) else {
+                        return false;
+                    };
+                    match high {
+                        Some(high) => high.eval(frame, dbc).is_some_and(|h| v >= low && v <= h),
+                        None => v == low,
+                    }
+                });
+                flag(hit)
+            }
+        }
+    }
+}
+
+/// 編譯好的顯示篩選運算式
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text,
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err("篩選條件是空的".to_string());
+        }
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("預期運算子或結尾"));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, frame: &CanFrame, dbc: Option<&Dbc>) -> bool {
+        truthy(self.expr.eval(frame, dbc))
+    }
+}
+
+/// 套用在擷取緩衝區上的顯示篩選；只影響畫面、曲線與匯出，不動硬體濾波
+#[derive(Default)]
+pub struct DisplayFilter {
+    text: String,
+    error: Option<String>,
+    filter: Option<Filter>,
+    // 符合條件的幀的絕對序號 (沒有篩選時不建立)
+    indices: Vec<u64>,
+    // 下一個要掃描的絕對序號
+    next: u64,
+}
+
+impl DisplayFilter {
+    pub fn matches(&self, frame: &CanFrame, dbc: Option<&Dbc>) -> bool {
+        self.filter.as_ref().is_none_or(|f| f.matches(frame, dbc))
+    }
+
+    /// 篩選後的列 (絕對序號)；None 表示顯示全部
+    pub fn rows(&self) -> Option<&[u64]> {
+        self.filter.as_ref().map(|_| self.indices.as_slice())
+    }
+
+    /// 從頭重新掃描，用在換條件、換 DBC 或緩衝區被清空時
+    pub fn reset(&mut self) {
+        self.indices.clear();
+        self.next = 0;
+    }
+
+    /// 已掃描到的絕對序號 (不含)；畫面只顯示這之前的幀
+    pub fn scanned_end(&self) -> u64 {
+        self.next
+    }
+
+    /// 還沒掃描的幀數
+    pub fn pending(&self, store: &FrameStore) -> u64 {
+        let end = store.first_index() + store.len() as u64;
+        end.saturating_sub(self.next.max(store.first_index()))
+    }
+
+    /// 掃描到 until 為止的新幀，符合條件的交給 on_match (用來餵曲線與固定模式)；
+    /// 凍結畫面時 until 停在凍結當下，解除後再從這裡追上
+    pub fn scan(
+        &mut self,
+        store: &FrameStore,
+        dbc: Option<&Dbc>,
+        until: u64,
+        mut on_match: impl FnMut(&CanFrame),
+    ) {
+        // 1. **丟掉已被緩衝區淘汰的序號**
+        let first = store.first_index();
+        let stale = self.indices.partition_point(|&i| i < first);
+        self.indices.drain(..stale);
+
+        // 2. **在預算內掃描新進的幀**
+        let start = self.next.max(first);
+        let end = (first + store.len() as u64)
+            .min(start + SCAN_BUDGET)
+            .min(until)
+            .max(start);
+        for index in start..end {
+            let Some(frame) = store.get((index - first) as usize) else {
+                break;
+            };
+            match &self.filter {
+                Some(filter) if !filter.matches(frame, dbc) => continue,
+                Some(_) => self.indices.push(index),
+                None => {}
+            }
+            on_match(frame);
+        }
+        self.next = end;
+    }
+
+    /// 篩選列；回傳 true 表示條件變了，呼叫端要清掉衍生的顯示並重新掃描
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("篩選:");
+            let edit = ui.add(
+                egui::TextEdit::singleline(&mut self.text)
+                    .hint_text("例: id in 0x100..0x1FF && data[0] & 0x80")
+                    .desired_width(360.0),
+            );
+            let submitted = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("套用").clicked() || submitted {
+                if self.text.trim().is_empty() {
+                    changed = self.filter.take().is_some();
+                    self.error = None;
+                } else {
+                    match Filter::parse(&self.text) {
+                        Ok(filter) => {
+                            self.filter = Some(filter);
+                            self.error = None;
+                            changed = true;
+                        }
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+            }
+            if self.filter.is_some() && ui.button("清除篩選").clicked() {
+                self.filter = None;
+                self.text.clear();
+                self.error = None;
+                changed = true;
+            }
+            if self.filter.is_some() {
+                ui.label(format!("符合 {} 幀", self.indices.len()));
+            }
+        })
+        .response
+        .on_hover_text(
+            "欄位: id ext std rtr err dlc channel data[n] signal(名稱) signal(訊息.名稱)\n\
+             運算子: && || ! == != < <= > >= & | ^ ~ in a..b in {a, b..c}",
+        );
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if changed {
+            self.reset();
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u32, data: &[u8]) -> CanFrame {
+        let mut frame = CanFrame {
+            id,
+            len: data.len() as u8,
+            ..Default::default()
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        frame
+    }
+
+    fn matches(text: &str, frame: &CanFrame) -> bool {
+        Filter::parse(text).unwrap().matches(frame, None)
+    }
+
+    #[test]
+    fn bit_operators_bind_tighter_than_comparison() {
+        let set = frame(0x100, &[0x81]);
+        let clear = frame(0x100, &[0x01]);
+        assert!(matches("data[0] & 0x80 == 0x80", &set));
+        assert!(!matches("data[0] & 0x80 == 0x80", &clear));
+        assert!(matches("data[0] & 0x0F | 0x10 == 0x11", &clear));
+        // 資料位元組不存在時比較結果為假
+        assert!(!matches("data[1] == 0", &set));
+        assert!(!matches("data[1] != 0", &set));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let f = frame(0x100, &[1]);
+        assert!(matches("id == 0x200 and dlc == 2 or data[0] == 1", &f));
+        assert!(!matches("id == 0x200 and (dlc == 2 or data[0] == 1)", &f));
+        assert!(matches("not id == 0x200 && dlc == 1", &f));
+        assert!(!matches("!(id == 0x100 || dlc == 5)", &f));
+        assert!(matches("not not id == 0x100", &f));
+    }
+
+    #[test]
+    fn in_ranges_and_sets() {
+        let f = frame(0x150, &[5]);
+        assert!(matches("id in 0x100..0x1FF", &f));
+        assert!(matches("id in 0x100..=0x150", &f));
+        assert!(!matches("id in 0x151..0x1FF", &f));
+        assert!(matches("id in {0x10, 0x150}", &f));
+        assert!(matches("data[0] in {1, 4..6, 9}", &f));
+        assert!(!matches("data[0] in {1, 6..8}", &f));
+        assert!(matches("not id in {1, 2}", &f));
+    }
+
+    #[test]
+    fn ext_and_channel_fields() {
+        let mut f = frame(0x18FE_F100, &[]);
+        f.extended = true;
+        f.channel = 1;
+        assert!(matches("ext && channel == 1", &f));
+        assert!(!matches("std", &f));
+        assert!(!matches("ch == 0", &f));
+        f.extended = false;
+        assert!(matches("std and !ext", &f));
+    }
+
+    #[test]
+    fn signal_lookup_through_dbc() {
+        let dbc = Dbc::parse(
+            "BO_ 256 Engine: 8 ECU\n \
+             SG_ Speed : 0|16@1+ (0.1,0) [0|6553.5] \"km/h\" Vector__XXX\n\
+             BO_ 512 Brake: 8 ECU\n \
+             SG_ Speed : 0|8@1+ (1,0) [0|255] \"km/h\" Vector__XXX\n",
+        )
+        .unwrap();
+        let engine = frame(0x100, &[0xE8, 0x03]);
+        let brake = frame(0x200, &[50]);
+        let filter = Filter::parse("signal(Speed) > 90").unwrap();
+        assert!(filter.matches(&engine, Some(&dbc)));
+        assert!(!filter.matches(&brake, Some(&dbc)));
+        assert!(!filter.matches(&engine, None));
+
+        let filter = Filter::parse("signal(Brake.Speed) == 50").unwrap();
+        assert!(filter.matches(&brake, Some(&dbc)));
+        assert!(!filter.matches(&engine, Some(&dbc)));
+    }
+
+    #[test]
+    fn errors_report_character_columns() {
+        assert_eq!(
+            Filter::parse("id == 0x1G").unwrap_err(),
+            "位置 7: 無效的數字 '0x1G'"
+        );
+        assert_eq!(
+            Filter::parse("id $ 1").unwrap_err(),
+            "位置 4: 無法辨識的字元 '$'"
+        );
+        assert_eq!(
+            Filter::parse("foo == 1").unwrap_err(),
+            "位置 1: 未知的欄位 'foo'"
+        );
+        assert_eq!(
+            Filter::parse("id == && 1").unwrap_err(),
+            "位置 7: 預期數值、欄位或 '('，但遇到 '&&'"
+        );
+        // 欄位以字元計算，不是位元組
+        assert_eq!(
+            Filter::parse("signal(速度) ==").unwrap_err(),
+            "位置 14: 預期數值、欄位或 '('，但遇到 結尾"
+        );
+        assert_eq!(
+            Filter::parse("id == 1 2").unwrap_err(),
+            "位置 9: 預期運算子或結尾，但遇到 '2'"
+        );
+    }
+
+    #[test]
+    fn scan_collects_matching_indices() {
+        let mut store = FrameStore::default();
+        store.extend((0..10u32).map(|i| frame(0x100 + i, &[i as u8])));
+        let mut filter = DisplayFilter {
+            filter: Some(Filter::parse("data[0] & 1 == 1").unwrap()),
+            ..Default::default()
+        };
+
+        let mut seen = Vec::new();
+        filter.scan(&store, None, 6, |f| seen.push(f.id));
+        assert_eq!(filter.rows(), Some(&[1, 3, 5][..]));
+        assert_eq!(filter.scanned_end(), 6);
+        assert_eq!(filter.pending(&store), 4);
+
+        filter.scan(&store, None, u64::MAX, |f| seen.push(f.id));
+        assert_eq!(filter.rows(), Some(&[1, 3, 5, 7, 9][..]));
+        assert_eq!(seen, [0x101, 0x103, 0x105, 0x107, 0x109]);
+        assert_eq!(filter.pending(&store), 0);
+
+        filter.reset();
+        assert_eq!(filter.rows(), Some(&[][..]));
+        assert_eq!(filter.pending(&store), 10);
+    }
+}
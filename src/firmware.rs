@@ -0,0 +1,230 @@
+use std::path::Path;
+
+/// 一段連續位址的資料
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// 要刷寫的映像檔，區段依位址排序且相鄰的已合併
+#[derive(Debug, Clone, Default)]
+pub struct FirmwareImage {
+    pub segments: Vec<Segment>,
+}
+
+impl FirmwareImage {
+    pub fn total_len(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    /// 依副檔名判斷格式；原始二進位檔放在 base_address
+    pub fn load(path: &str, base_address: u32) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let image = match extension.as_str() {
+            "hex" | "ihx" | "ihex" => {
+                let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+                parse_intel_hex(&text)?
+            }
+            "s19" | "s28" | "s37" | "srec" | "mot" | "sx" => {
+                let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+                parse_srecord(&text)?
+            }
+            _ => {
+                let data = std::fs::read(path).map_err(|e| e.to_string())?;
+                from_records(vec![(base_address, data)])
+            }
+        };
+        if image.segments.is_empty() {
+            return Err(format!("{} 沒有資料", path));
+        }
+        Ok(image)
+    }
+}
+
+// 依位址排序並把首尾相接的記錄合併成區段
+fn from_records(mut records: Vec<(u32, Vec<u8>)>) -> FirmwareImage {
+    records.sort_by_key(|(address, _)| *address);
+    let mut segments: Vec<Segment> = Vec::new();
+    for (address, data) in records {
+        if data.is_empty() {
+            continue;
+        }
+        match segments.last_mut() {
+            Some(last) if last.address as u64 + last.data.len() as u64 == address as u64 => {
+                last.data.extend_from_slice(&data);
+            }
+            _ => segments.push(Segment { address, data }),
+        }
+    }
+    FirmwareImage { segments }
+}
+
+fn decode_hex_line(line: &str, line_no: usize) -> Result<Vec<u8>, String> {
+    if !line.len().is_multiple_of(2) || !line.is_ascii() {
+        return Err(format!("第 {} 行: 長度不正確", line_no));
+    }
+    (0..line.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&line[i..i + 2], 16)
+                .map_err(|_| format!("第 {} 行: 無效的十六進位字元", line_no))
+        })
+        .collect()
+}
+
+/// Intel HEX：支援資料、EOF、延伸區段位址 (02) 與延伸線性位址 (04)
+pub fn parse_intel_hex(text: &str) -> Result<FirmwareImage, String> {
+    let mut records = Vec::new();
+    let mut base: u32 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(hex) = line.strip_prefix(':') else {
+            return Err(format!("第 {} 行: 缺少 ':'", line_no));
+        };
+        let bytes = decode_hex_line(hex, line_no)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(format!("第 {} 行: 記錄長度不符", line_no));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(format!("第 {} 行: 檢查碼錯誤", line_no));
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => records.push((base.wrapping_add(offset), data.to_vec())),
+            0x01 => break,
+            0x02 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // 起始位址記錄與刷寫無關
+            0x03 | 0x05 => {}
+            kind => return Err(format!("第 {} 行: 不支援的記錄類型 {:02X}", line_no, kind)),
+        }
+    }
+    Ok(from_records(records))
+}
+
+/// Motorola S-record：S1/S2/S3 資料記錄，其餘記錄略過
+pub fn parse_srecord(text: &str) -> Result<FirmwareImage, String> {
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() < 4 || !line.is_ascii() || !line.starts_with(['S', 's']) {
+            return Err(format!("第 {} 行: 不是 S-record", line_no));
+        }
+        let kind = line.as_bytes()[1];
+        let bytes = decode_hex_line(&line[2..], line_no)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(format!("第 {} 行: 記錄長度不符", line_no));
+        }
+        // 檢查碼是長度、位址、資料總和的補數
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+            return Err(format!("第 {} 行: 檢查碼錯誤", line_no));
+        }
+        let address_len = match kind {
+            b'1' => 2,
+            b'2' => 3,
+            b'3' => 4,
+            b'0' | b'5' | b'6' | b'7' | b'8' | b'9' => continue,
+            _ => {
+                return Err(format!(
+                    "第 {} 行: 不支援的記錄類型 S{}",
+                    line_no, kind as char
+                ))
+            }
+        };
+        if bytes.len() < address_len + 2 {
+            return Err(format!("第 {} 行: 記錄長度不符", line_no));
+        }
+        let address = bytes[1..=address_len]
+            .iter()
+            .fold(0u32, |acc, b| acc << 8 | *b as u32);
+        records.push((address, bytes[address_len + 1..bytes.len() - 1].to_vec()));
+    }
+    Ok(from_records(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_hex_records() {
+        // 04 設定高 16 位元，02 是區段位址 (×16)，相鄰記錄合併
+        let text = "\
+:020000040800F2
+:10000000000102030405060708090A0B0C0D0E0F78
+:10001000101112131415161718191A1B1C1D1E1F68
+:020000021000EC
+:04000000AABBCCDDEE
+:0400000508000000EF
+:00000001FF
+";
+        let image = parse_intel_hex(text).unwrap();
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.segments[0].address, 0x0001_0000);
+        assert_eq!(image.segments[0].data, [0xAA, 0xBB, 0xCC, 0xDD]);
+        assert_eq!(image.segments[1].address, 0x0800_0000);
+        assert_eq!(image.segments[1].data, (0..32).collect::<Vec<u8>>());
+        assert_eq!(image.total_len(), 36);
+    }
+
+    #[test]
+    fn intel_hex_errors() {
+        let bad_checksum = ":10000000000102030405060708090A0B0C0D0E0F79";
+        assert!(parse_intel_hex(bad_checksum)
+            .unwrap_err()
+            .contains("第 1 行: 檢查碼"));
+        let bad_length = ":00000001FF\n:0500000000010203F7";
+        assert!(parse_intel_hex(&bad_length[12..])
+            .unwrap_err()
+            .contains("長度不符"));
+        assert!(parse_intel_hex("\n10000000")
+            .unwrap_err()
+            .contains("第 2 行"));
+        assert!(parse_intel_hex(":00000006FA")
+            .unwrap_err()
+            .contains("記錄類型 06"));
+    }
+
+    #[test]
+    fn srecord_records() {
+        let text = "\
+S00600004844521B
+S1130000000102030405060708090A0B0C0D0E0F74
+S208010000112233444C
+S30900020000DEADBEEFBC
+S5030003F9
+S9030000FC
+";
+        let image = parse_srecord(text).unwrap();
+        let segments: Vec<(u32, usize)> = image
+            .segments
+            .iter()
+            .map(|s| (s.address, s.data.len()))
+            .collect();
+        assert_eq!(segments, [(0x0000, 16), (0x01_0000, 4), (0x0002_0000, 4)]);
+        assert_eq!(image.segments[2].data, [0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let bad_checksum = "S1130000000102030405060708090A0B0C0D0E0F75";
+        assert!(parse_srecord(bad_checksum)
+            .unwrap_err()
+            .contains("第 1 行: 檢查碼"));
+        assert!(parse_srecord("S4030000FC").unwrap_err().contains("S4"));
+        assert!(parse_srecord(":020000")
+            .unwrap_err()
+            .contains("不是 S-record"));
+    }
+}
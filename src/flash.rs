@@ -0,0 +1,436 @@
+use crate::firmware::FirmwareImage;
+use crate::isotp::MAX_PAYLOAD;
+use crate::security::{KeyAlgorithm, Unlock};
+use crate::uds::{routine_request, Exchange, Outcome, UdsClient};
+use eframe::egui;
+
+const SID_REQUEST_DOWNLOAD: u8 = 0x34;
+const SID_TRANSFER_DATA: u8 = 0x36;
+const SID_TRANSFER_EXIT: u8 = 0x37;
+// 位址與長度各 4 bytes
+const ADDRESS_AND_LENGTH_FORMAT: u8 = 0x44;
+
+#[derive(Debug, Clone)]
+pub struct FlashConfig {
+    pub level: u8,
+    pub erase_routine: u16,
+    pub check_routine: u16,
+    pub reset_after: bool,
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        Self {
+            level: 0x01,
+            erase_routine: 0xFF00,
+            check_routine: 0xFF01,
+            reset_after: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Session,
+    Unlock,
+    Erase(usize),
+    Download(usize),
+    Transfer(usize),
+    Exit(usize),
+    Check,
+    Reset,
+    Done,
+}
+
+fn address_and_length(address: u32, len: usize) -> [u8; 9] {
+    let mut field = [ADDRESS_AND_LENGTH_FORMAT, 0, 0, 0, 0, 0, 0, 0, 0];
+    field[1..5].copy_from_slice(&address.to_be_bytes());
+    field[5..9].copy_from_slice(&(len as u32).to_be_bytes());
+    field
+}
+
+/// 一次刷寫：programming session、解鎖，每個區段抹除/下載/傳輸/結束，最後檢查與重置
+///
+/// 任一步失敗會停在該步並保留狀態，resume 重送同一個請求 (TransferData 用同一個序號)。
+pub struct FlashJob {
+    image: FirmwareImage,
+    config: FlashConfig,
+    step: Step,
+    unlock: Option<Unlock>,
+    // 目前區段已傳的位元組與下一個區塊序號
+    offset: usize,
+    bsc: u8,
+    // 每個 TransferData 可帶的資料長度
+    chunk_len: usize,
+    sent: usize,
+    last_request: Option<Vec<u8>>,
+    pub error: Option<String>,
+    started_us: u64,
+    finished_us: Option<u64>,
+}
+
+impl FlashJob {
+    pub fn new(
+        image: FirmwareImage,
+        config: FlashConfig,
+        client: &mut UdsClient,
+        now_us: u64,
+    ) -> Self {
+        let mut job = Self {
+            image,
+            config,
+            step: Step::Session,
+            unlock: None,
+            offset: 0,
+            bsc: 1,
+            chunk_len: 0,
+            sent: 0,
+            last_request: None,
+            error: None,
+            started_us: now_us,
+            finished_us: None,
+        };
+        job.issue(client);
+        job
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == Step::Done
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.is_done() && self.error.is_none()
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.sent as f32 / self.image.total_len().max(1) as f32
+    }
+
+    fn step_name(&self) -> String {
+        let count = self.image.segments.len();
+        match self.step {
+            Step::Session => "進入 programming session".to_string(),
+            Step::Unlock => format!("SecurityAccess 0x{:02X}", self.config.level),
+            Step::Erase(i) => format!("抹除區段 {}/{}", i + 1, count),
+            Step::Download(i) => format!("RequestDownload 區段 {}/{}", i + 1, count),
+            Step::Transfer(i) => format!("傳輸區段 {}/{} (區塊 {})", i + 1, count, self.bsc),
+            Step::Exit(i) => format!("RequestTransferExit 區段 {}/{}", i + 1, count),
+            Step::Check => "檢查程式".to_string(),
+            Step::Reset => "ECU 重置".to_string(),
+            Step::Done => "完成".to_string(),
+        }
+    }
+
+    fn request(&mut self, request: Vec<u8>, client: &mut UdsClient) {
+        self.last_request = Some(request.clone());
+        client.request(request);
+    }
+
+    // 送出目前這一步的請求
+    fn issue(&mut self, client: &mut UdsClient) {
+        match self.step {
+            Step::Session => self.request(vec![0x10, 0x02], client),
+            Step::Unlock => self.unlock = Some(Unlock::start(self.config.level, None, client)),
+            Step::Erase(i) => {
+                let segment = &self.image.segments[i];
+                let field = address_and_length(segment.address, segment.data.len());
+                let request = routine_request(0x01, self.config.erase_routine, &field);
+                self.request(request, client);
+            }
+            Step::Download(i) => {
+                let segment = &self.image.segments[i];
+                let mut request = vec![SID_REQUEST_DOWNLOAD, 0x00];
+                request.extend_from_slice(&address_and_length(segment.address, segment.data.len()));
+                self.request(request, client);
+            }
+            Step::Transfer(i) => {
+                let data = &self.image.segments[i].data;
+                let end = (self.offset + self.chunk_len).min(data.len());
+                let mut request = vec![SID_TRANSFER_DATA, self.bsc];
+                request.extend_from_slice(&data[self.offset..end]);
+                self.request(request, client);
+            }
+            Step::Exit(_) => self.request(vec![SID_TRANSFER_EXIT], client),
+            Step::Check => {
+                let request = routine_request(0x01, self.config.check_routine, &[]);
+                self.request(request, client);
+            }
+            Step::Reset => self.request(vec![0x11, 0x01], client),
+            Step::Done => {}
+        }
+    }
+
+    fn next_step(&self) -> Step {
+        let count = self.image.segments.len();
+        match self.step {
+            Step::Session => Step::Unlock,
+            Step::Unlock => Step::Erase(0),
+            Step::Erase(i) => Step::Download(i),
+            Step::Download(i) => Step::Transfer(i),
+            Step::Transfer(i) if self.offset < self.image.segments[i].data.len() => {
+                Step::Transfer(i)
+            }
+            Step::Transfer(i) => Step::Exit(i),
+            Step::Exit(i) if i + 1 < count => Step::Erase(i + 1),
+            Step::Exit(_) => Step::Check,
+            Step::Check if self.config.reset_after => Step::Reset,
+            Step::Check | Step::Reset | Step::Done => Step::Done,
+        }
+    }
+
+    fn fail(&mut self, message: String) {
+        self.error = Some(format!("{}失敗: {}", self.step_name(), message));
+    }
+
+    /// 處理一筆完成的請求，不是這個流程送的會被略過
+    pub fn on_exchange(
+        &mut self,
+        exchange: &Exchange,
+        algorithm: Option<&KeyAlgorithm>,
+        client: &mut UdsClient,
+        now_us: u64,
+    ) {
+        if !self.is_running() {
+            return;
+        }
+        if self.step == Step::Unlock {
+            let Some(unlock) = self.unlock.as_mut() else {
+                return;
+            };
+            match unlock.on_exchange(exchange, algorithm, client) {
+                Some(Ok(())) => {
+                    self.unlock = None;
+                    self.advance(client, now_us);
+                }
+                Some(Err(e)) => {
+                    self.unlock = None;
+                    self.fail(e);
+                }
+                None => {}
+            }
+            return;
+        }
+        if self.last_request.as_ref() != Some(&exchange.request) {
+            return;
+        }
+        self.last_request = None;
+        let Outcome::Positive(data) = &exchange.outcome else {
+            let message = exchange.outcome.describe(&exchange.request);
+            self.fail(message);
+            return;
+        };
+        match self.step {
+            Step::Download(_) => {
+                // 74 LFID maxNumberOfBlockLength，長度欄位的位元組數在 LFID 高 4 位元
+                let len = (data.get(1).copied().unwrap_or(0) >> 4) as usize;
+                let Some(field) = data.get(2..2 + len).filter(|f| !f.is_empty()) else {
+                    self.fail("RequestDownload 回應格式錯誤".to_string());
+                    return;
+                };
+                let max_block = field.iter().fold(0usize, |acc, b| acc << 8 | *b as usize);
+                if max_block < 3 {
+                    self.fail(format!("maxNumberOfBlockLength {} 太小", max_block));
+                    return;
+                }
+                // 區塊長度含 SID 與序號，再受 ISO-TP 上限限制
+                self.chunk_len = max_block.min(MAX_PAYLOAD) - 2;
+                self.offset = 0;
+                self.bsc = 1;
+            }
+            Step::Transfer(_) => {
+                let chunk = exchange.request.len() - 2;
+                self.offset += chunk;
+                self.sent += chunk;
+                self.bsc = self.bsc.wrapping_add(1);
+            }
+            _ => {}
+        }
+        self.advance(client, now_us);
+    }
+
+    fn advance(&mut self, client: &mut UdsClient, now_us: u64) {
+        self.step = self.next_step();
+        if self.step == Step::Done {
+            self.finished_us = Some(now_us);
+        }
+        self.issue(client);
+    }
+
+    /// 從失敗的那一步繼續
+    pub fn resume(&mut self, client: &mut UdsClient) {
+        self.error = None;
+        self.issue(client);
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let elapsed_us = self
+            .finished_us
+            .unwrap_or(self.started_us)
+            .saturating_sub(self.started_us);
+        ui.add(egui::ProgressBar::new(self.progress()).text(format!(
+            "{} / {} bytes",
+            self.sent,
+            self.image.total_len()
+        )));
+        match (&self.error, self.step) {
+            (Some(e), _) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+            (None, Step::Done) => {
+                ui.label(format!("刷寫完成，耗時 {:.1} s", elapsed_us as f64 / 1e6));
+            }
+            (None, _) => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(self.step_name());
+                });
+            }
+        }
+    }
+}
+
+/// UDS 面板裡的刷寫區塊
+pub struct FlashPanel {
+    pub job: Option<FlashJob>,
+    config: FlashConfig,
+    path: String,
+    base_address: u32,
+    image: Option<FirmwareImage>,
+}
+
+impl Default for FlashPanel {
+    fn default() -> Self {
+        Self {
+            job: None,
+            config: FlashConfig::default(),
+            path: String::new(),
+            base_address: 0x0800_0000,
+            image: None,
+        }
+    }
+}
+
+impl FlashPanel {
+    pub fn is_running(&self) -> bool {
+        self.job.as_ref().is_some_and(|job| job.is_running())
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        client: &mut UdsClient,
+        can_send: bool,
+        now_us: u64,
+        log: &mut Vec<String>,
+    ) {
+        let running = self.is_running();
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("檔案 (.hex/.s19/.bin):");
+                ui.text_edit_singleline(&mut self.path);
+                if ui.button("載入").clicked() {
+                    match FirmwareImage::load(&self.path, self.base_address) {
+                        Ok(image) => {
+                            log.push(format!(
+                                "已載入映像檔: {} 個區段，共 {} bytes",
+                                image.segments.len(),
+                                image.total_len()
+                            ));
+                            self.image = Some(image);
+                        }
+                        Err(e) => log.push(e),
+                    }
+                }
+            });
+            egui::Grid::new("flash_config")
+                .num_columns(4)
+                .show(ui, |ui| {
+                    ui.label("二進位檔起始位址:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.base_address).hexadecimal(8, false, true),
+                    );
+                    ui.label("SecurityAccess 等級:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.config.level)
+                            .range(1..=0x7D)
+                            .hexadecimal(2, false, true),
+                    );
+                    ui.end_row();
+                    ui.label("抹除 Routine:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.config.erase_routine)
+                            .hexadecimal(4, false, true),
+                    );
+                    ui.label("檢查 Routine:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.config.check_routine)
+                            .hexadecimal(4, false, true),
+                    );
+                    ui.end_row();
+                });
+            ui.checkbox(&mut self.config.reset_after, "完成後重置 ECU");
+        });
+
+        if let Some(image) = &self.image {
+            for segment in &image.segments {
+                ui.monospace(format!(
+                    "0x{:08X} - 0x{:08X}  {} bytes",
+                    segment.address,
+                    segment.address as u64 + segment.data.len() as u64 - 1,
+                    segment.data.len()
+                ));
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let ready = can_send && self.image.is_some() && !running;
+            if ui
+                .add_enabled(ready, egui::Button::new("開始刷寫"))
+                .clicked()
+            {
+                let image = self.image.clone().unwrap();
+                log.push(format!("開始刷寫 {}", self.path));
+                self.job = Some(FlashJob::new(image, self.config.clone(), client, now_us));
+            }
+            if let Some(job) = self.job.as_mut() {
+                if job.error.is_some()
+                    && ui
+                        .add_enabled(can_send, egui::Button::new("從失敗處繼續"))
+                        .clicked()
+                {
+                    job.resume(client);
+                }
+                if running && ui.button("中止").clicked() {
+                    client.cancel();
+                    job.fail("使用者中止".to_string());
+                }
+            }
+        });
+        if let Some(job) = &self.job {
+            job.ui(ui);
+        }
+    }
+
+    pub fn on_exchange(
+        &mut self,
+        exchange: &Exchange,
+        algorithm: Option<&KeyAlgorithm>,
+        client: &mut UdsClient,
+        now_us: u64,
+        log: &mut Vec<String>,
+    ) {
+        let Some(job) = self.job.as_mut() else {
+            return;
+        };
+        let (had_error, was_done) = (job.error.is_some(), job.is_done());
+        job.on_exchange(exchange, algorithm, client, now_us);
+        if !had_error {
+            if let Some(e) = &job.error {
+                log.push(e.clone());
+            }
+        }
+        if !was_done && job.is_done() {
+            log.push("刷寫完成".to_string());
+        }
+    }
+}
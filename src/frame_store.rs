@@ -0,0 +1,220 @@
+use crate::canbus::CanFrame;
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+// 每個區塊固定容量，滿了就開新區塊；超過記憶體上限時整塊丟掉最舊的
+const CHUNK_LEN: usize = 64 * 1024;
+pub const DEFAULT_BUDGET_MB: usize = 512;
+
+/// 分塊儲存的擷取緩衝區，可容納數百萬幀並受記憶體上限約束
+pub struct FrameStore {
+    chunks: VecDeque<Vec<CanFrame>>,
+    len: usize,
+    // 已因超過上限被丟棄的幀數，用來換算絕對序號
+    dropped: u64,
+    // 擷取開始的時間，不會因丟棄舊幀而改變
+    start_us: Option<u64>,
+    max_chunks: usize,
+}
+
+impl Default for FrameStore {
+    fn default() -> Self {
+        Self::with_budget_mb(DEFAULT_BUDGET_MB)
+    }
+}
+
+impl FrameStore {
+    pub fn with_budget_mb(mb: usize) -> Self {
+        let mut store = Self {
+            chunks: VecDeque::new(),
+            len: 0,
+            dropped: 0,
+            start_us: None,
+            max_chunks: 1,
+        };
+        store.set_budget_mb(mb);
+        store
+    }
+
+    pub fn set_budget_mb(&mut self, mb: usize) {
+        let chunk_bytes = CHUNK_LEN * size_of::<CanFrame>();
+        self.max_chunks = (mb * 1024 * 1024 / chunk_bytes).max(1);
+        self.enforce_budget();
+    }
+
+    pub fn budget_frames(&self) -> usize {
+        self.max_chunks * CHUNK_LEN
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.chunks.len() * CHUNK_LEN * size_of::<CanFrame>()
+    }
+
+    fn enforce_budget(&mut self) {
+        while self.chunks.len() > self.max_chunks {
+            if let Some(chunk) = self.chunks.pop_front() {
+                self.len -= chunk.len();
+                self.dropped += chunk.len() as u64;
+            }
+        }
+    }
+
+    pub fn push(&mut self, frame: CanFrame) {
+        self.start_us.get_or_insert(frame.timestamp_us);
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.len() < CHUNK_LEN => chunk.push(frame),
+            _ => {
+                let mut chunk = Vec::with_capacity(CHUNK_LEN);
+                chunk.push(frame);
+                self.chunks.push_back(chunk);
+                self.enforce_budget();
+            }
+        }
+        self.len += 1;
+    }
+
+    pub fn extend(&mut self, frames: impl IntoIterator<Item = CanFrame>) {
+        for frame in frames {
+            self.push(frame);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+        self.dropped = 0;
+        self.start_us = None;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 目前第一幀的絕對序號 (從擷取開始算)
+    pub fn first_index(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn start_us(&self) -> Option<u64> {
+        self.start_us
+    }
+
+    pub fn get(&self, index: usize) -> Option<&CanFrame> {
+        // 只在尾端新增、整塊丟掉最舊的，所以除了最後一塊，其餘區塊都是滿的
+        self.chunks.get(index / CHUNK_LEN)?.get(index % CHUNK_LEN)
+    }
+
+    pub fn first(&self) -> Option<&CanFrame> {
+        self.chunks.front().and_then(|c| c.first())
+    }
+
+    pub fn last(&self) -> Option<&CanFrame> {
+        self.chunks.back().and_then(|c| c.last())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CanFrame> + Clone + '_ {
+        self.chunks.iter().flat_map(|c| c.iter())
+    }
+
+    /// 第一個時間戳 >= timestamp_us 的位置 (假設依時間排序)
+    pub fn index_at_time(&self, timestamp_us: u64) -> usize {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.get(mid) {
+                Some(f) if f.timestamp_us < timestamp_us => lo = mid + 1,
+                _ => hi = mid,
+            }
+        }
+        lo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(i: usize, timestamp_us: u64) -> CanFrame {
+        CanFrame {
+            timestamp_us,
+            id: i as u32,
+            ..Default::default()
+        }
+    }
+
+    // 剛好容納兩個區塊的上限
+    fn two_chunk_store() -> FrameStore {
+        let mb = 2 * CHUNK_LEN * size_of::<CanFrame>() / (1024 * 1024);
+        let store = FrameStore::with_budget_mb(mb);
+        assert_eq!(store.budget_frames(), 2 * CHUNK_LEN);
+        store
+    }
+
+    #[test]
+    fn evicts_whole_chunks() {
+        assert_eq!(FrameStore::with_budget_mb(0).budget_frames(), CHUNK_LEN);
+
+        let mut store = two_chunk_store();
+        store.extend((0..2 * CHUNK_LEN).map(|i| frame(i, 1_000 + i as u64)));
+        assert_eq!((store.len(), store.first_index()), (2 * CHUNK_LEN, 0));
+
+        // 第三塊的第一幀讓最舊的一整塊被丟掉
+        store.extend((2 * CHUNK_LEN..2 * CHUNK_LEN + 10).map(|i| frame(i, 1_000 + i as u64)));
+        assert_eq!(store.len(), CHUNK_LEN + 10);
+        assert_eq!(store.first_index(), CHUNK_LEN as u64);
+        assert_eq!(store.memory_bytes(), 2 * CHUNK_LEN * size_of::<CanFrame>());
+        assert_eq!(store.first().unwrap().id, CHUNK_LEN as u32);
+        assert_eq!(store.last().unwrap().id, 2 * CHUNK_LEN as u32 + 9);
+        // 開始時間不因丟棄而改變
+        assert_eq!(store.start_us(), Some(1_000));
+
+        // 縮小上限時立即丟到只剩一塊
+        store.set_budget_mb(0);
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.first_index(), 2 * CHUNK_LEN as u64);
+        assert_eq!(store.iter().count(), 10);
+
+        store.clear();
+        assert_eq!(
+            (store.len(), store.first_index(), store.start_us()),
+            (0, 0, None)
+        );
+        assert!(store.get(0).is_none());
+    }
+
+    #[test]
+    fn get_across_chunks() {
+        let mut store = two_chunk_store();
+        store.extend((0..CHUNK_LEN + 3).map(|i| frame(i, i as u64)));
+        for index in [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 2] {
+            assert_eq!(store.get(index).unwrap().id, index as u32);
+        }
+        assert!(store.get(CHUNK_LEN + 3).is_none());
+
+        // 丟掉一塊後序號從剩下的第一幀重新算
+        store.extend((CHUNK_LEN + 3..2 * CHUNK_LEN + 1).map(|i| frame(i, i as u64)));
+        assert_eq!(store.get(0).unwrap().id, CHUNK_LEN as u32);
+        assert_eq!(store.get(CHUNK_LEN).unwrap().id, 2 * CHUNK_LEN as u32);
+        assert!(store.get(CHUNK_LEN + 1).is_none());
+    }
+
+    #[test]
+    fn index_at_time_binary_search() {
+        let mut store = two_chunk_store();
+        assert_eq!(store.index_at_time(0), 0);
+        // 每個時間戳兩幀：第 2k、2k+1 幀在 10k
+        let len = CHUNK_LEN + 6;
+        store.extend((0..len).map(|i| frame(i, (i / 2) as u64 * 10)));
+        assert_eq!(store.index_at_time(0), 0);
+        assert_eq!(store.index_at_time(10), 2);
+        assert_eq!(store.index_at_time(5), 2);
+        assert_eq!(store.index_at_time(11), 4);
+        // 區塊邊界
+        let boundary = (CHUNK_LEN / 2) as u64 * 10;
+        assert_eq!(store.index_at_time(boundary), CHUNK_LEN);
+        assert_eq!(store.index_at_time(boundary - 1), CHUNK_LEN);
+        assert_eq!(store.index_at_time(boundary - 10), CHUNK_LEN - 2);
+        // 晚於最後一幀
+        assert_eq!(store.index_at_time(u64::MAX), len);
+    }
+}
@@ -0,0 +1,798 @@
+use crate::canbus::{parse_hex_bytes, CanFrame};
+use eframe::egui;
+use std::collections::VecDeque;
+
+/// 一般 CAN 上 ISO-TP 的最大長度 (FF_DL 12 位元)
+pub const MAX_PAYLOAD: usize = 4095;
+const HISTORY_LEN: usize = 200;
+// 面板送出後接收回應的時間，其餘時間不接收以免和 UDS 連線重複送 FC
+const RESPONSE_WINDOW_MS: u64 = 5000;
+
+const PCI_SF: u8 = 0x0;
+const PCI_FF: u8 = 0x1;
+const PCI_CF: u8 = 0x2;
+const PCI_FC: u8 = 0x3;
+const FS_CTS: u8 = 0;
+const FS_WAIT: u8 = 1;
+const FS_OVERFLOW: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addressing {
+    Normal,
+    // 第一個位元組是目標位址 N_TA
+    Extended,
+    // 第一個位元組是位址延伸 N_AE
+    Mixed,
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoTpConfig {
+    pub tx_id: u32,
+    pub rx_id: u32,
+    pub extended_id: bool,
+    pub addressing: Addressing,
+    // Extended/Mixed 定址時送出幀的第一個位元組
+    pub tx_address: u8,
+    // Extended/Mixed 定址時接收幀應有的第一個位元組
+    pub rx_address: u8,
+    // None 表示 DLC 取最短長度
+    pub padding: Option<u8>,
+    // 我方接收時在 FC 裡要求的 BS 與 STmin (原始編碼)
+    pub block_size: u8,
+    pub st_min: u8,
+    pub n_as_ms: u64,
+    pub n_bs_ms: u64,
+    pub n_cr_ms: u64,
+}
+
+impl Default for IsoTpConfig {
+    fn default() -> Self {
+        Self {
+            tx_id: 0x7E0,
+            rx_id: 0x7E8,
+            extended_id: false,
+            addressing: Addressing::Normal,
+            tx_address: 0,
+            rx_address: 0,
+            padding: Some(0xCC),
+            block_size: 0,
+            st_min: 0,
+            n_as_ms: 1000,
+            n_bs_ms: 1000,
+            n_cr_ms: 1000,
+        }
+    }
+}
+
+impl IsoTpConfig {
+    fn address_len(&self) -> usize {
+        match self.addressing {
+            Addressing::Normal => 0,
+            Addressing::Extended | Addressing::Mixed => 1,
+        }
+    }
+
+    /// 單幀可容納的最大資料長度
+    pub fn single_frame_max(&self) -> usize {
+        7 - self.address_len()
+    }
+
+    /// 連線設定表格，ISO-TP 與 UDS 面板共用
+    pub fn ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        let mut padding = self.padding.is_some();
+        let mut padding_byte = self.padding.unwrap_or(0xCC);
+        egui::Grid::new(id_salt).num_columns(4).show(ui, |ui| {
+            ui.label("請求 ID:");
+            ui.add(egui::DragValue::new(&mut self.tx_id).hexadecimal(3, false, true));
+            ui.label("回應 ID:");
+            ui.add(egui::DragValue::new(&mut self.rx_id).hexadecimal(3, false, true));
+            ui.end_row();
+            ui.checkbox(&mut self.extended_id, "29 位元 ID");
+            egui::ComboBox::from_id_salt((id_salt, "addressing"))
+                .selected_text(format!("{:?}", self.addressing))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.addressing, Addressing::Normal, "Normal");
+                    ui.selectable_value(&mut self.addressing, Addressing::Extended, "Extended");
+                    ui.selectable_value(&mut self.addressing, Addressing::Mixed, "Mixed");
+                });
+            if self.addressing != Addressing::Normal {
+                ui.label("位址 送/收:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.tx_address).hexadecimal(2, false, true));
+                    ui.add(egui::DragValue::new(&mut self.rx_address).hexadecimal(2, false, true));
+                });
+            }
+            ui.end_row();
+            ui.checkbox(&mut padding, "填充");
+            ui.add_enabled(
+                padding,
+                egui::DragValue::new(&mut padding_byte).hexadecimal(2, false, true),
+            );
+            ui.label("BS / STmin:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.block_size));
+                ui.add(egui::DragValue::new(&mut self.st_min).hexadecimal(2, false, true));
+            });
+            ui.end_row();
+            ui.label("N_As / N_Bs / N_Cr ms:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.n_as_ms).range(1..=10_000));
+                ui.add(egui::DragValue::new(&mut self.n_bs_ms).range(1..=10_000));
+                ui.add(egui::DragValue::new(&mut self.n_cr_ms).range(1..=10_000));
+            });
+            ui.end_row();
+        });
+        self.padding = padding.then_some(padding_byte);
+    }
+}
+
+/// STmin 原始編碼換成微秒；保留值依規範當作 127 ms
+pub fn st_min_us(raw: u8) -> u64 {
+    match raw {
+        0x00..=0x7F => raw as u64 * 1000,
+        0xF1..=0xF9 => (raw - 0xF0) as u64 * 100,
+        _ => 127_000,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsoTpEvent {
+    Received(Vec<u8>),
+    // 收到 FF，之後還有 CF (長度)
+    FirstFrame(usize),
+    Sent,
+    Error(String),
+}
+
+enum TxStage {
+    WaitFc {
+        deadline_us: u64,
+    },
+    // 依 STmin 送 CF；block_left 為 None 表示 BS = 0 不限
+    Sending {
+        next_us: u64,
+        st_min_us: u64,
+        block_left: Option<u8>,
+    },
+}
+
+struct TxState {
+    data: Vec<u8>,
+    offset: usize,
+    seq: u8,
+    stage: TxStage,
+}
+
+struct RxState {
+    length: usize,
+    data: Vec<u8>,
+    seq: u8,
+    block_count: u8,
+    deadline_us: u64,
+}
+
+/// 一條 ISO-TP 連線 (一對請求/回應 ID)，半雙工，同時只有一筆傳送與一筆接收
+///
+/// 不直接碰硬體：呼叫端把收到的幀交給 handle、每個畫面呼叫 poll，
+/// 要送出的幀放在 outbox，結果放在 events。CF 間隔的精度受 poll 頻率限制，
+/// 但不會短於對方要求的 STmin。
+#[derive(Default)]
+pub struct IsoTpLink {
+    pub config: IsoTpConfig,
+    pub channel: u32,
+    pub outbox: Vec<CanFrame>,
+    pub events: VecDeque<IsoTpEvent>,
+    tx: Option<TxState>,
+    rx: Option<RxState>,
+}
+
+impl IsoTpLink {
+    pub fn new(config: IsoTpConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.tx.is_some() || self.rx.is_some()
+    }
+
+    pub fn reset(&mut self) {
+        self.tx = None;
+        self.rx = None;
+        self.outbox.clear();
+        self.events.clear();
+    }
+
+    fn frame(&self, pci: &[u8]) -> CanFrame {
+        let mut data = [0u8; 8];
+        let mut len = 0;
+        if self.config.address_len() == 1 {
+            data[0] = self.config.tx_address;
+            len = 1;
+        }
+        data[len..len + pci.len()].copy_from_slice(pci);
+        len += pci.len();
+        if let Some(pad) = self.config.padding {
+            data[len..].fill(pad);
+            len = 8;
+        }
+        CanFrame {
+            channel: self.channel,
+            id: self.config.tx_id,
+            extended: self.config.extended_id,
+            len: len as u8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn flow_control(&self, status: u8) -> CanFrame {
+        self.frame(&[
+            PCI_FC << 4 | status,
+            self.config.block_size,
+            self.config.st_min,
+        ])
+    }
+
+    /// 開始送出一筆資料；單幀直接送出，多幀先送 FF 再等 FC
+    pub fn send(&mut self, data: &[u8], now_us: u64) -> Result<(), String> {
+        if data.is_empty() || data.len() > MAX_PAYLOAD {
+            return Err(format!("ISO-TP 資料長度須為 1~{} bytes", MAX_PAYLOAD));
+        }
+        if self.tx.is_some() {
+            return Err("ISO-TP 上一筆資料還在傳送中".to_string());
+        }
+        if data.len() <= self.config.single_frame_max() {
+            let mut pci = vec![PCI_SF << 4 | data.len() as u8];
+            pci.extend_from_slice(data);
+            self.outbox.push(self.frame(&pci));
+            self.events.push_back(IsoTpEvent::Sent);
+            return Ok(());
+        }
+        let first = 6 - self.config.address_len();
+        let mut pci = vec![PCI_FF << 4 | (data.len() >> 8) as u8, data.len() as u8];
+        pci.extend_from_slice(&data[..first]);
+        self.outbox.push(self.frame(&pci));
+        self.tx = Some(TxState {
+            data: data.to_vec(),
+            offset: first,
+            seq: 1,
+            stage: TxStage::WaitFc {
+                deadline_us: now_us + self.config.n_bs_ms * 1000,
+            },
+        });
+        Ok(())
+    }
+
+    /// 餵匯流排上收到的幀，不屬於這條連線的會被略過
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        if frame.channel != self.channel
+            || frame.id != self.config.rx_id
+            || frame.extended != self.config.extended_id
+            || frame.remote
+            || frame.error
+        {
+            return;
+        }
+        let mut payload = frame.payload();
+        if self.config.address_len() == 1 {
+            match payload.split_first() {
+                Some((&address, rest)) if address == self.config.rx_address => payload = rest,
+                _ => return,
+            }
+        }
+        let Some(&pci) = payload.first() else {
+            return;
+        };
+        match pci >> 4 {
+            PCI_SF => self.on_single(payload),
+            PCI_FF => self.on_first(payload, now_us),
+            PCI_CF => self.on_consecutive(payload, now_us),
+            PCI_FC => self.on_flow_control(payload, now_us),
+            _ => {}
+        }
+    }
+
+    fn abort_rx(&mut self, reason: &str) {
+        if self.rx.take().is_some() {
+            self.events
+                .push_back(IsoTpEvent::Error(format!("ISO-TP 接收中斷: {}", reason)));
+        }
+    }
+
+    fn on_single(&mut self, payload: &[u8]) {
+        let len = (payload[0] & 0x0F) as usize;
+        if len == 0 || len > self.config.single_frame_max() || len >= payload.len() {
+            return;
+        }
+        self.abort_rx("收到新的單幀");
+        self.events
+            .push_back(IsoTpEvent::Received(payload[1..=len].to_vec()));
+    }
+
+    fn on_first(&mut self, payload: &[u8], now_us: u64) {
+        if payload.len() < 2 {
+            return;
+        }
+        let length = ((payload[0] & 0x0F) as usize) << 8 | payload[1] as usize;
+        // 長度為 0 是 CAN FD 的 32 位元長度，一般 CAN 不支援
+        if length == 0 {
+            self.abort_rx("收到新的首幀");
+            self.outbox.push(self.flow_control(FS_OVERFLOW));
+            return;
+        }
+        if length <= self.config.single_frame_max() {
+            return;
+        }
+        self.abort_rx("收到新的首幀");
+        self.rx = Some(RxState {
+            length,
+            data: payload[2..].to_vec(),
+            seq: 1,
+            block_count: 0,
+            deadline_us: now_us + self.config.n_cr_ms * 1000,
+        });
+        self.outbox.push(self.flow_control(FS_CTS));
+        self.events.push_back(IsoTpEvent::FirstFrame(length));
+    }
+
+    fn on_consecutive(&mut self, payload: &[u8], now_us: u64) {
+        let Some(rx) = self.rx.as_mut() else {
+            return;
+        };
+        let seq = payload[0] & 0x0F;
+        if seq != rx.seq {
+            let expected = rx.seq;
+            self.abort_rx(&format!("序號錯誤，應為 {} 收到 {}", expected, seq));
+            return;
+        }
+        let take = (rx.length - rx.data.len()).min(payload.len() - 1);
+        rx.data.extend_from_slice(&payload[1..1 + take]);
+        rx.seq = (rx.seq + 1) & 0x0F;
+        rx.block_count += 1;
+        rx.deadline_us = now_us + self.config.n_cr_ms * 1000;
+        if rx.data.len() >= rx.length {
+            let rx = self.rx.take().unwrap();
+            self.events.push_back(IsoTpEvent::Received(rx.data));
+        } else if self.config.block_size > 0 && rx.block_count >= self.config.block_size {
+            rx.block_count = 0;
+            self.outbox.push(self.flow_control(FS_CTS));
+        }
+    }
+
+    fn on_flow_control(&mut self, payload: &[u8], now_us: u64) {
+        let Some(tx) = self.tx.as_mut() else {
+            return;
+        };
+        if !matches!(tx.stage, TxStage::WaitFc { .. }) || payload.len() < 3 {
+            return;
+        }
+        match payload[0] & 0x0F {
+            FS_CTS => {
+                tx.stage = TxStage::Sending {
+                    next_us: now_us,
+                    st_min_us: st_min_us(payload[2]),
+                    block_left: (payload[1] > 0).then_some(payload[1]),
+                };
+            }
+            FS_WAIT => {
+                tx.stage = TxStage::WaitFc {
+                    deadline_us: now_us + self.config.n_bs_ms * 1000,
+                };
+            }
+            FS_OVERFLOW => {
+                self.tx = None;
+                self.events.push_back(IsoTpEvent::Error(
+                    "ISO-TP 對方緩衝區不足 (FC overflow)".to_string(),
+                ));
+            }
+            status => {
+                self.tx = None;
+                self.events.push_back(IsoTpEvent::Error(format!(
+                    "ISO-TP 無效的 FC 狀態 {}",
+                    status
+                )));
+            }
+        }
+    }
+
+    /// 每個畫面呼叫一次：依 STmin 送出 CF 並檢查逾時
+    pub fn poll(&mut self, now_us: u64) {
+        // 1. **接收端 N_Cr**
+        if self.rx.as_ref().is_some_and(|rx| now_us > rx.deadline_us) {
+            self.abort_rx("等待連續幀逾時 (N_Cr)");
+        }
+
+        // 2. **傳送端**
+        let cf_len = 7 - self.config.address_len();
+        loop {
+            let Some(tx) = self.tx.as_mut() else {
+                return;
+            };
+            match tx.stage {
+                TxStage::WaitFc { deadline_us } => {
+                    if now_us > deadline_us {
+                        self.tx = None;
+                        self.events.push_back(IsoTpEvent::Error(
+                            "ISO-TP 等待流量控制逾時 (N_Bs)".to_string(),
+                        ));
+                    }
+                    return;
+                }
+                TxStage::Sending {
+                    next_us,
+                    st_min_us,
+                    block_left,
+                } => {
+                    if now_us < next_us {
+                        return;
+                    }
+                    // 該送的幀晚太久才輪到 (例如畫面卡住)，對方的 N_Cr 也早就過了
+                    if now_us - next_us > self.config.n_as_ms * 1000 {
+                        self.tx = None;
+                        self.events.push_back(IsoTpEvent::Error(
+                            "ISO-TP 幀未能及時送出 (N_As)".to_string(),
+                        ));
+                        return;
+                    }
+                    let end = (tx.offset + cf_len).min(tx.data.len());
+                    let mut pci = vec![PCI_CF << 4 | tx.seq];
+                    pci.extend_from_slice(&tx.data[tx.offset..end]);
+                    tx.offset = end;
+                    tx.seq = (tx.seq + 1) & 0x0F;
+                    let done = end >= tx.data.len();
+                    let block_left = block_left.map(|n| n - 1);
+                    tx.stage = if block_left == Some(0) {
+                        TxStage::WaitFc {
+                            deadline_us: now_us + self.config.n_bs_ms * 1000,
+                        }
+                    } else {
+                        TxStage::Sending {
+                            next_us: now_us + st_min_us,
+                            st_min_us,
+                            block_left,
+                        }
+                    };
+                    let frame = self.frame(&pci);
+                    self.outbox.push(frame);
+                    if done {
+                        self.tx = None;
+                        self.events.push_back(IsoTpEvent::Sent);
+                        return;
+                    }
+                    // STmin 不為 0 時每次 poll 最多送一幀，保證間隔不小於 STmin
+                    if st_min_us > 0 {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct HistoryEntry {
+    timestamp_us: u64,
+    sent: bool,
+    data: Vec<u8>,
+}
+
+/// 手動送收 ISO-TP 資料的面板
+pub struct IsoTpPanel {
+    pub link: IsoTpLink,
+    history: VecDeque<HistoryEntry>,
+    pending: Vec<u8>,
+    listen_until_us: u64,
+    payload_text: String,
+}
+
+impl Default for IsoTpPanel {
+    fn default() -> Self {
+        Self {
+            link: IsoTpLink::default(),
+            history: VecDeque::new(),
+            pending: Vec::new(),
+            listen_until_us: 0,
+            payload_text: "3E 00".to_string(),
+        }
+    }
+}
+
+impl IsoTpPanel {
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        if self.link.is_busy() || now_us < self.listen_until_us {
+            self.link.handle(frame, now_us);
+        }
+    }
+
+    /// 取出連線的事件寫進歷史與訊息記錄
+    pub fn process_events(&mut self, now_us: u64, log: &mut Vec<String>) {
+        while let Some(event) = self.link.events.pop_front() {
+            match event {
+                IsoTpEvent::Received(data) => self.push_history(now_us, false, data),
+                IsoTpEvent::Sent => {
+                    let data = std::mem::take(&mut self.pending);
+                    self.push_history(now_us, true, data);
+                    self.listen_until_us = now_us + RESPONSE_WINDOW_MS * 1000;
+                }
+                IsoTpEvent::FirstFrame(_) => {}
+                IsoTpEvent::Error(e) => log.push(e),
+            }
+        }
+    }
+
+    fn push_history(&mut self, timestamp_us: u64, sent: bool, data: Vec<u8>) {
+        self.history.push_back(HistoryEntry {
+            timestamp_us,
+            sent,
+            data,
+        });
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool, now_us: u64, log: &mut Vec<String>) {
+        self.link.config.ui(ui, "isotp_config");
+
+        ui.separator();
+        ui.label("資料 (十六進位):");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.payload_text)
+                .desired_rows(3)
+                .desired_width(f32::INFINITY)
+                .font(egui::TextStyle::Monospace),
+        );
+        ui.horizontal(|ui| {
+            let busy = self.link.is_busy();
+            if ui
+                .add_enabled(can_send && !busy, egui::Button::new("送出"))
+                .on_disabled_hover_text("需要先打開裝置，且沒有進行中的傳輸")
+                .clicked()
+            {
+                match parse_hex_bytes(&self.payload_text) {
+                    Ok(data) => match self.link.send(&data, now_us) {
+                        Ok(()) => self.pending = data,
+                        Err(e) => log.push(e),
+                    },
+                    Err(e) => log.push(e),
+                }
+            }
+            if busy {
+                ui.spinner();
+                if ui.button("中止").clicked() {
+                    self.link.reset();
+                }
+            }
+            if ui.button("清除紀錄").clicked() {
+                self.history.clear();
+            }
+        });
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .id_salt("isotp_history")
+            .max_height(300.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                let start = self.history.front().map_or(0, |h| h.timestamp_us);
+                for entry in &self.history {
+                    let hex: Vec<String> =
+                        entry.data.iter().map(|b| format!("{:02X}", b)).collect();
+                    ui.monospace(format!(
+                        "{:>10.3} {} [{}] {}",
+                        (entry.timestamp_us - start) as f64 / 1e6,
+                        if entry.sent { "送" } else { "收" },
+                        entry.data.len(),
+                        hex.join(" ")
+                    ));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一對互相對應的連線：b 的送/收 ID 與位址和 a 相反
+    fn pair(config: IsoTpConfig) -> (IsoTpLink, IsoTpLink) {
+        let peer = IsoTpConfig {
+            tx_id: config.rx_id,
+            rx_id: config.tx_id,
+            tx_address: config.rx_address,
+            rx_address: config.tx_address,
+            ..config.clone()
+        };
+        (IsoTpLink::new(config), IsoTpLink::new(peer))
+    }
+
+    /// 雙方各 poll 一次並交換幀，回傳 a 與 b 送出的幀
+    fn step(a: &mut IsoTpLink, b: &mut IsoTpLink, now_us: u64) -> (Vec<CanFrame>, Vec<CanFrame>) {
+        a.poll(now_us);
+        b.poll(now_us);
+        let sent = std::mem::take(&mut a.outbox);
+        for frame in &sent {
+            b.handle(frame, now_us);
+        }
+        let replies = std::mem::take(&mut b.outbox);
+        for frame in &replies {
+            a.handle(frame, now_us);
+        }
+        (sent, replies)
+    }
+
+    #[test]
+    fn segmentation_with_block_size_and_st_min() {
+        let (mut a, mut b) = pair(IsoTpConfig::default());
+        b.config.block_size = 2;
+        b.config.st_min = 5;
+        let data: Vec<u8> = (0..40).collect();
+        a.send(&data, 0).unwrap();
+
+        // 1 ms 一次，記錄 a 送出的連續幀與 b 送出的 FC
+        let mut consecutive = Vec::new();
+        let mut flow_controls = 0;
+        for ms in 0..100 {
+            let now = ms * 1000;
+            let (sent, replies) = step(&mut a, &mut b, now);
+            for frame in sent {
+                if frame.data[0] >> 4 == PCI_CF {
+                    consecutive.push((now, frame.data[0] & 0x0F));
+                }
+            }
+            for frame in replies {
+                assert_eq!(frame.data[..3], [PCI_FC << 4 | FS_CTS, 2, 5]);
+                flow_controls += 1;
+            }
+        }
+        assert_eq!(b.events.pop_front(), Some(IsoTpEvent::FirstFrame(40)));
+        assert_eq!(b.events.pop_front(), Some(IsoTpEvent::Received(data)));
+        assert_eq!(a.events.pop_front(), Some(IsoTpEvent::Sent));
+        assert!(!a.is_busy() && !b.is_busy());
+
+        // FF 帶 6 bytes，其餘 34 bytes 分成 5 個 CF，序號從 1 開始
+        let seqs: Vec<u8> = consecutive.iter().map(|&(_, seq)| seq).collect();
+        assert_eq!(seqs, [1, 2, 3, 4, 5]);
+        assert_eq!(flow_controls, 3);
+        // 同一個區塊內的 CF 間隔不小於 STmin
+        for block in consecutive.chunks(2) {
+            if let [(t0, _), (t1, _)] = block {
+                assert!(t1 - t0 >= 5_000, "{} {}", t0, t1);
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_flow_control_after_each_block() {
+        let (mut a, _) = pair(IsoTpConfig::default());
+        a.send(&[0x55; 20], 0).unwrap();
+        a.outbox.clear();
+        // 對方要求 BS = 1：每個 CF 之後都要再等 FC
+        let fc = |status: u8| CanFrame {
+            id: 0x7E8,
+            len: 3,
+            data: [PCI_FC << 4 | status, 1, 0, 0, 0, 0, 0, 0],
+            ..Default::default()
+        };
+        a.handle(&fc(FS_CTS), 1_000);
+        a.poll(2_000);
+        a.poll(3_000);
+        assert_eq!(a.outbox.len(), 1);
+        // FC WAIT 重新計算 N_Bs
+        a.handle(&fc(FS_WAIT), 900_000);
+        a.poll(1_500_000);
+        assert!(a.is_busy());
+        a.handle(&fc(FS_CTS), 1_600_000);
+        a.poll(1_600_000);
+        assert_eq!(a.outbox.len(), 2);
+        assert_eq!(a.outbox[1].data[0], PCI_CF << 4 | 2);
+        assert_eq!(a.events.pop_front(), Some(IsoTpEvent::Sent));
+    }
+
+    #[test]
+    fn padding_and_addressing() {
+        for addressing in [Addressing::Extended, Addressing::Mixed] {
+            for padding in [None, Some(0xAA)] {
+                let (mut a, mut b) = pair(IsoTpConfig {
+                    addressing,
+                    tx_address: 0x10,
+                    rx_address: 0xF1,
+                    padding,
+                    ..Default::default()
+                });
+                // 單幀少一個位元組：位址 + PCI + 6 bytes
+                assert_eq!(a.config.single_frame_max(), 6);
+                a.send(&[1, 2, 3], 0).unwrap();
+                let single = a.outbox[0];
+                assert_eq!(single.payload()[..5], [0x10, 0x03, 1, 2, 3]);
+                match padding {
+                    Some(pad) => assert_eq!(single.payload()[5..], [pad; 3]),
+                    None => assert_eq!(single.len, 5),
+                }
+
+                let data: Vec<u8> = (0..20).collect();
+                a.send(&data, 0).unwrap();
+                let mut frames = Vec::new();
+                for ms in 0..20 {
+                    frames.extend(step(&mut a, &mut b, ms * 1000).0);
+                }
+                assert_eq!(
+                    b.events.pop_front(),
+                    Some(IsoTpEvent::Received(vec![1, 2, 3]))
+                );
+                assert_eq!(b.events.pop_front(), Some(IsoTpEvent::FirstFrame(20)));
+                assert_eq!(b.events.pop_front(), Some(IsoTpEvent::Received(data)));
+                assert!(frames.iter().all(|f| f.data[0] == 0x10));
+                // FF 帶 5 bytes，CF 每幀 6 bytes：最後一個 CF 只剩 3 bytes
+                let last = frames.last().unwrap();
+                assert_eq!(last.data[1], PCI_CF << 4 | 3);
+                match padding {
+                    Some(pad) => assert_eq!(last.payload()[5..], [pad; 3]),
+                    None => assert_eq!(last.len, 5),
+                }
+            }
+        }
+
+        // 位址不符的幀不屬於這條連線
+        let (mut a, mut b) = pair(IsoTpConfig {
+            addressing: Addressing::Extended,
+            tx_address: 0x10,
+            rx_address: 0xF1,
+            ..Default::default()
+        });
+        a.config.tx_address = 0x11;
+        a.send(&[1], 0).unwrap();
+        step(&mut a, &mut b, 0);
+        assert!(b.events.is_empty());
+    }
+
+    #[test]
+    fn first_frame_without_length_is_rejected() {
+        let (_, mut b) = pair(IsoTpConfig::default());
+        // FF_DL = 0 是 CAN FD 的長格式，回 FC overflow 且不開始接收
+        b.handle(
+            &CanFrame {
+                id: 0x7E0,
+                len: 8,
+                data: [0x10, 0x00, 0, 0, 0x10, 0x00, 1, 2],
+                ..Default::default()
+            },
+            0,
+        );
+        assert_eq!(b.outbox.len(), 1);
+        assert_eq!(b.outbox[0].id, 0x7E8);
+        assert_eq!(b.outbox[0].data[0], PCI_FC << 4 | FS_OVERFLOW);
+        assert!(!b.is_busy());
+        assert!(b.events.is_empty());
+    }
+
+    #[test]
+    fn flow_control_and_consecutive_frame_timeouts() {
+        // 1. **N_Bs：送出 FF 後等不到 FC**
+        let (mut a, mut b) = pair(IsoTpConfig::default());
+        a.send(&[0; 20], 0).unwrap();
+        a.poll(1_000_000);
+        assert!(a.is_busy());
+        a.poll(1_000_001);
+        assert!(!a.is_busy());
+        match a.events.pop_front() {
+            Some(IsoTpEvent::Error(e)) => assert!(e.contains("N_Bs"), "{}", e),
+            other => panic!("{:?}", other),
+        }
+
+        // 2. **N_Cr：收到 FF 後等不到 CF**
+        let ff = a.outbox[0];
+        b.handle(&ff, 0);
+        assert_eq!(b.events.pop_front(), Some(IsoTpEvent::FirstFrame(20)));
+        b.poll(1_000_000);
+        assert!(b.is_busy());
+        b.poll(1_000_001);
+        assert!(!b.is_busy());
+        match b.events.pop_front() {
+            Some(IsoTpEvent::Error(e)) => assert!(e.contains("N_Cr"), "{}", e),
+            other => panic!("{:?}", other),
+        }
+    }
+}
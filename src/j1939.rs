@@ -0,0 +1,672 @@
+use crate::canbus::CanFrame;
+use eframe::egui;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+pub const PGN_ACKNOWLEDGMENT: u32 = 0xE800;
+pub const PGN_REQUEST: u32 = 0xEA00;
+pub const PGN_ADDRESS_CLAIMED: u32 = 0xEE00;
+pub const PGN_TP_CM: u32 = 0xEC00;
+pub const PGN_TP_DT: u32 = 0xEB00;
+pub const PGN_DM1: u32 = 0xFECA;
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+pub const TP_CM_RTS: u8 = 16;
+pub const TP_CM_CTS: u8 = 17;
+pub const TP_CM_EOM_ACK: u8 = 19;
+pub const TP_CM_BAM: u8 = 32;
+pub const TP_CM_ABORT: u8 = 255;
+
+// 傳輸協定的 T1 逾時：封包之間超過 750 ms 視為中斷
+const TP_TIMEOUT_US: u64 = 750_000;
+const MAX_MESSAGES: usize = 500;
+
+/// 29-bit ID 拆出的 J1939 欄位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct J1939Id {
+    pub priority: u8,
+    pub pgn: u32,
+    pub sa: u8,
+    // PDU1 (PF < 240) 才有目的位址，PDU2 一律是廣播
+    pub da: u8,
+}
+
+impl J1939Id {
+    pub fn from_raw(id: u32) -> Self {
+        let priority = ((id >> 26) & 0x7) as u8;
+        let dp = (id >> 24) & 0x3;
+        let pf = (id >> 16) & 0xFF;
+        let ps = ((id >> 8) & 0xFF) as u8;
+        let sa = (id & 0xFF) as u8;
+        if pf < 240 {
+            Self {
+                priority,
+                pgn: (dp << 16) | (pf << 8),
+                sa,
+                da: ps,
+            }
+        } else {
+            Self {
+                priority,
+                pgn: (dp << 16) | (pf << 8) | ps as u32,
+                sa,
+                da: GLOBAL_ADDRESS,
+            }
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        let pf = (self.pgn >> 8) & 0xFF;
+        let ps = if pf < 240 {
+            self.da as u32
+        } else {
+            self.pgn & 0xFF
+        };
+        ((self.priority as u32 & 0x7) << 26)
+            | ((self.pgn & 0x3_FF00) << 8)
+            | (ps << 8)
+            | self.sa as u32
+    }
+}
+
+/// 單幀或重組完成的 J1939 訊息
+#[derive(Debug, Clone)]
+pub struct J1939Message {
+    pub timestamp_us: u64,
+    pub channel: u32,
+    pub id: J1939Id,
+    pub data: Vec<u8>,
+    // 經由 BAM 或 RTS/CTS 重組
+    pub transport: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dtc {
+    pub spn: u32,
+    pub fmi: u8,
+    pub oc: u8,
+}
+
+/// DM1：燈號狀態與目前有效的故障碼
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dm1 {
+    // MIL, 紅燈, 琥珀燈, 保護燈 (各 2 位元)
+    pub lamps: [u8; 4],
+    pub dtcs: Vec<Dtc>,
+}
+
+pub fn decode_dm1(data: &[u8]) -> Option<Dm1> {
+    if data.len() < 2 {
+        return None;
+    }
+    let lamp = data[0];
+    let lamps = [(lamp >> 6) & 3, (lamp >> 4) & 3, (lamp >> 2) & 3, lamp & 3];
+    let dtcs = data[2..]
+        .chunks_exact(4)
+        .map(|d| Dtc {
+            // SPN 轉換方式 4：低 16 位元在前，最高 3 位元在第三個位元組
+            spn: d[0] as u32 | (d[1] as u32) << 8 | ((d[2] as u32 & 0xE0) << 11),
+            fmi: d[2] & 0x1F,
+            oc: d[3] & 0x7F,
+        })
+        // 全 0 (或全 1 填充) 代表沒有故障碼
+        .filter(|dtc| dtc.spn != 0 && dtc.spn != 0x7FFFF)
+        .collect();
+    Some(Dm1 { lamps, dtcs })
+}
+
+struct Session {
+    pgn: u32,
+    size: usize,
+    packets: u8,
+    next_seq: u8,
+    data: Vec<u8>,
+    last_us: u64,
+    kind: &'static str,
+}
+
+/// BAM 與 RTS/CTS 的被動重組 (只觀察匯流排，不回應 CTS)
+#[derive(Default)]
+pub struct Reassembler {
+    // (通道, 來源, 目的)
+    sessions: HashMap<(u32, u8, u8), Session>,
+    pub aborted: u64,
+}
+
+impl Reassembler {
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+        self.aborted = 0;
+    }
+
+    /// 餵一幀，完成的訊息 (含一般單幀) 回傳出去；TP 封包本身不輸出
+    pub fn feed(&mut self, frame: &CanFrame) -> Option<J1939Message> {
+        if !frame.extended || frame.remote || frame.error {
+            return None;
+        }
+        let id = J1939Id::from_raw(frame.id);
+        let data = frame.payload();
+
+        // 逾時的 session 直接丟掉
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, s| frame.timestamp_us.saturating_sub(s.last_us) <= TP_TIMEOUT_US);
+        self.aborted += (before - self.sessions.len()) as u64;
+
+        let key = (frame.channel, id.sa, id.da);
+        match id.pgn {
+            PGN_TP_CM if data.len() >= 8 => {
+                let pgn = data[5] as u32 | (data[6] as u32) << 8 | (data[7] as u32) << 16;
+                match data[0] {
+                    TP_CM_BAM | TP_CM_RTS => {
+                        let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+                        let session = Session {
+                            pgn,
+                            size,
+                            packets: data[3],
+                            next_seq: 1,
+                            data: Vec::with_capacity(size),
+                            last_us: frame.timestamp_us,
+                            kind: if data[0] == TP_CM_BAM {
+                                "BAM"
+                            } else {
+                                "RTS/CTS"
+                            },
+                        };
+                        if self.sessions.insert(key, session).is_some() {
+                            self.aborted += 1;
+                        }
+                    }
+                    TP_CM_ABORT => {
+                        // Abort 可能由任一方送出
+                        let reverse = (frame.channel, id.da, id.sa);
+                        if self
+                            .sessions
+                            .remove(&key)
+                            .or_else(|| self.sessions.remove(&reverse))
+                            .is_some()
+                        {
+                            self.aborted += 1;
+                        }
+                    }
+                    _ => {}
+                }
+                None
+            }
+            PGN_TP_DT if !data.is_empty() => {
+                let session = self.sessions.get_mut(&key)?;
+                if data[0] != session.next_seq {
+                    self.sessions.remove(&key);
+                    self.aborted += 1;
+                    return None;
+                }
+                session.next_seq = session.next_seq.wrapping_add(1);
+                session.last_us = frame.timestamp_us;
+                session.data.extend_from_slice(&data[1..]);
+                if session.data.len() < session.size && data[0] < session.packets {
+                    return None;
+                }
+                let mut session = self.sessions.remove(&key)?;
+                session.data.truncate(session.size);
+                Some(J1939Message {
+                    timestamp_us: frame.timestamp_us,
+                    channel: frame.channel,
+                    id: J1939Id {
+                        pgn: session.pgn,
+                        ..id
+                    },
+                    data: session.data,
+                    transport: Some(session.kind),
+                })
+            }
+            PGN_TP_CM | PGN_TP_DT => None,
+            _ => Some(J1939Message {
+                timestamp_us: frame.timestamp_us,
+                channel: frame.channel,
+                id,
+                data: data.to_vec(),
+                transport: None,
+            }),
+        }
+    }
+}
+
+/// PGN 與 SPN 名稱表
+///
+/// 檔案格式每行一筆，以 ';' 分隔，'#' 開頭為註解：
+/// `PGN;61444;EEC1;Electronic Engine Controller 1`
+/// `SPN;190;Engine Speed`
+pub struct Definitions {
+    pub pgns: HashMap<u32, (String, String)>,
+    pub spns: HashMap<u32, String>,
+}
+
+impl Default for Definitions {
+    fn default() -> Self {
+        let pgns = [
+            (0xEA00, "RQST", "Request"),
+            (0xE800, "ACKM", "Acknowledgment"),
+            (0xEB00, "TP.DT", "Transport Protocol - Data Transfer"),
+            (
+                0xEC00,
+                "TP.CM",
+                "Transport Protocol - Connection Management",
+            ),
+            (0xEE00, "AC", "Address Claimed"),
+            (0xF003, "EEC2", "Electronic Engine Controller 2"),
+            (0xF004, "EEC1", "Electronic Engine Controller 1"),
+            (0xFECA, "DM1", "Active Diagnostic Trouble Codes"),
+            (0xFECB, "DM2", "Previously Active Diagnostic Trouble Codes"),
+            (0xFEE5, "HOURS", "Engine Hours, Revolutions"),
+            (0xFEEC, "VI", "Vehicle Identification"),
+            (0xFEEE, "ET1", "Engine Temperature 1"),
+            (0xFEEF, "EFL/P1", "Engine Fluid Level/Pressure 1"),
+            (0xFEF1, "CCVS1", "Cruise Control/Vehicle Speed 1"),
+            (0xFEF2, "LFE1", "Fuel Economy (Liquid)"),
+            (0xFEF5, "AMB", "Ambient Conditions"),
+            (0xFEF6, "IC1", "Intake/Exhaust Conditions 1"),
+            (0xFEF7, "VEP1", "Vehicle Electrical Power 1"),
+        ]
+        .into_iter()
+        .map(|(pgn, acronym, name)| (pgn, (acronym.to_string(), name.to_string())))
+        .collect();
+        Self {
+            pgns,
+            spns: HashMap::new(),
+        }
+    }
+}
+
+impl Definitions {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("無法讀取 PGN 定義: {}", e))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut defs = Self::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| format!("PGN 定義第 {} 行: {}", idx + 1, msg);
+            let fields: Vec<&str> = line.split(';').map(str::trim).collect();
+            let number = |s: &str| match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            };
+            match fields.as_slice() {
+                ["PGN", pgn, acronym, name, ..] => {
+                    let pgn = number(pgn).ok_or_else(|| err("PGN 不是數字"))?;
+                    defs.pgns
+                        .insert(pgn, (acronym.to_string(), name.to_string()));
+                }
+                ["SPN", spn, name, ..] => {
+                    let spn = number(spn).ok_or_else(|| err("SPN 不是數字"))?;
+                    defs.spns.insert(spn, name.to_string());
+                }
+                _ => return Err(err("格式應為 PGN;編號;縮寫;名稱 或 SPN;編號;名稱")),
+            }
+        }
+        Ok(defs)
+    }
+
+    pub fn pgn_name(&self, pgn: u32) -> Option<&str> {
+        self.pgns.get(&pgn).map(|(acronym, _)| acronym.as_str())
+    }
+
+    /// 追蹤表格「名稱」欄用的簡短描述
+    pub fn describe(&self, raw_id: u32) -> String {
+        let id = J1939Id::from_raw(raw_id);
+        let name = self
+            .pgn_name(id.pgn)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("PGN {}", id.pgn));
+        if id.da == GLOBAL_ADDRESS {
+            format!("{} {:02X}→全部", name, id.sa)
+        } else {
+            format!("{} {:02X}→{:02X}", name, id.sa, id.da)
+        }
+    }
+}
+
+fn lamp_text(state: u8) -> &'static str {
+    match state {
+        0 => "滅",
+        1 => "亮",
+        _ => "-",
+    }
+}
+
+/// J1939 解碼面板：重組後的訊息列表與 DM1 故障碼
+#[derive(Default)]
+pub struct J1939Panel {
+    pub enabled: bool,
+    pub definitions: Definitions,
+    definitions_path: String,
+    reassembler: Reassembler,
+    messages: VecDeque<J1939Message>,
+    hide_single: bool,
+}
+
+impl J1939Panel {
+    pub fn clear(&mut self) {
+        self.reassembler.clear();
+        self.messages.clear();
+    }
+
+    pub fn feed(&mut self, frame: &CanFrame) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(message) = self.reassembler.feed(frame) {
+            self.messages.push_back(message);
+            if self.messages.len() > MAX_MESSAGES {
+                self.messages.pop_front();
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, log: &mut Vec<String>) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "啟用 J1939 解碼");
+            ui.checkbox(&mut self.hide_single, "只顯示多封包與 DM1");
+            if ui.button("清除").clicked() {
+                self.clear();
+            }
+            ui.label(format!("中斷的傳輸: {}", self.reassembler.aborted));
+        });
+        ui.horizontal(|ui| {
+            ui.label("PGN/SPN 定義:");
+            ui.text_edit_singleline(&mut self.definitions_path);
+            if ui.button("載入").clicked() {
+                match Definitions::load(&self.definitions_path) {
+                    Ok(defs) => {
+                        log.push(format!(
+                            "J1939 定義已載入: {} 個 PGN, {} 個 SPN",
+                            defs.pgns.len(),
+                            defs.spns.len()
+                        ));
+                        self.definitions = defs;
+                    }
+                    Err(e) => log.push(e),
+                }
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .id_salt("j1939_messages")
+            .max_height(360.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                let mut shown = 0;
+                for (i, message) in self.messages.iter().enumerate() {
+                    let is_dm1 = message.id.pgn == PGN_DM1;
+                    if self.hide_single && message.transport.is_none() && !is_dm1 {
+                        continue;
+                    }
+                    shown += 1;
+                    let id = message.id;
+                    let (acronym, name) = self
+                        .definitions
+                        .pgns
+                        .get(&id.pgn)
+                        .map(|(a, n)| (a.as_str(), n.as_str()))
+                        .unwrap_or(("", ""));
+                    let da = if id.da == GLOBAL_ADDRESS {
+                        "全部".to_string()
+                    } else {
+                        format!("{:02X}", id.da)
+                    };
+                    let mut header = format!(
+                        "CH{} PGN {:5} (0x{:05X}) {:<8} P{} {:02X}→{} {} bytes",
+                        message.channel,
+                        id.pgn,
+                        id.pgn,
+                        acronym,
+                        id.priority,
+                        id.sa,
+                        da,
+                        message.data.len()
+                    );
+                    if let Some(kind) = message.transport {
+                        header.push_str(&format!(" [{}]", kind));
+                    }
+                    egui::CollapsingHeader::new(egui::RichText::new(header).monospace())
+                        .id_salt(("j1939_message", i, message.timestamp_us))
+                        .show(ui, |ui| {
+                            if !name.is_empty() {
+                                ui.label(name);
+                            }
+                            for chunk in message.data.chunks(16) {
+                                ui.monospace(
+                                    chunk
+                                        .iter()
+                                        .map(|b| format!("{:02X}", b))
+                                        .collect::<Vec<_>>()
+                                        .join(" "),
+                                );
+                            }
+                            if is_dm1 {
+                                self.dm1_ui(ui, i, &message.data);
+                            }
+                        });
+                }
+                if shown == 0 {
+                    ui.label("尚無 J1939 訊息");
+                }
+            });
+    }
+
+    fn dm1_ui(&self, ui: &mut egui::Ui, row: usize, data: &[u8]) {
+        let Some(dm1) = decode_dm1(data) else {
+            return;
+        };
+        ui.label(format!(
+            "MIL {}  紅燈 {}  琥珀燈 {}  保護燈 {}",
+            lamp_text(dm1.lamps[0]),
+            lamp_text(dm1.lamps[1]),
+            lamp_text(dm1.lamps[2]),
+            lamp_text(dm1.lamps[3])
+        ));
+        if dm1.dtcs.is_empty() {
+            ui.label("沒有有效的故障碼");
+            return;
+        }
+        egui::Grid::new(("dm1_grid", row))
+            .striped(true)
+            .show(ui, |ui| {
+                for header in ["SPN", "名稱", "FMI", "OC"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for dtc in &dm1.dtcs {
+                    ui.monospace(dtc.spn.to_string());
+                    ui.label(
+                        self.definitions
+                            .spns
+                            .get(&dtc.spn)
+                            .map(String::as_str)
+                            .unwrap_or(""),
+                    );
+                    ui.monospace(dtc.fmi.to_string());
+                    ui.monospace(dtc.oc.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp_us: u64, id: J1939Id, data: &[u8]) -> CanFrame {
+        let mut frame = CanFrame {
+            timestamp_us,
+            id: id.to_raw(),
+            extended: true,
+            len: data.len() as u8,
+            ..Default::default()
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        frame
+    }
+
+    fn tp(pgn: u32, sa: u8, da: u8) -> J1939Id {
+        J1939Id {
+            priority: 7,
+            pgn,
+            sa,
+            da,
+        }
+    }
+
+    // 17 bytes 的 PGN 0xFECA，分成 3 個 TP.DT 封包
+    const PAYLOAD: [u8; 17] = [
+        0x40, 0xFF, 0x6E, 0x00, 0x04, 0x01, 0xBE, 0x00, 0x03, 0x02, 0x00, 0x00, 0x20, 0x00, 0xFF,
+        0xFF, 0xFF,
+    ];
+
+    fn dt_frames(timestamp_us: u64, id: J1939Id) -> Vec<CanFrame> {
+        PAYLOAD
+            .chunks(7)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut data = [0xFF; 8];
+                data[0] = i as u8 + 1;
+                data[1..1 + chunk.len()].copy_from_slice(chunk);
+                frame(timestamp_us + i as u64 * 50_000, id, &data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pdu1_and_pdu2_ids() {
+        // PDU1：PS 是目的位址，不屬於 PGN
+        let id = J1939Id::from_raw(0x18EA_0017);
+        assert_eq!((id.priority, id.pgn, id.sa, id.da), (6, 0xEA00, 0x17, 0x00));
+        assert_eq!(id.to_raw(), 0x18EA_0017);
+        // PDU2：PS 是群組擴充，目的一律是廣播
+        let id = J1939Id::from_raw(0x0CF0_0400);
+        assert_eq!((id.priority, id.pgn, id.sa, id.da), (3, 0xF004, 0x00, 0xFF));
+        assert_eq!(id.to_raw(), 0x0CF0_0400);
+        // Data page 位元
+        let id = J1939Id::from_raw(0x19FE_CA21);
+        assert_eq!(id.pgn, 0x1_FECA);
+        assert_eq!(id.to_raw(), 0x19FE_CA21);
+    }
+
+    #[test]
+    fn bam_reassembly() {
+        let mut r = Reassembler::default();
+        let cm = [TP_CM_BAM, 17, 0, 3, 0xFF, 0xCA, 0xFE, 0x00];
+        assert!(r
+            .feed(&frame(0, tp(PGN_TP_CM, 0x21, GLOBAL_ADDRESS), &cm))
+            .is_none());
+        let mut out = dt_frames(50_000, tp(PGN_TP_DT, 0x21, GLOBAL_ADDRESS))
+            .iter()
+            .filter_map(|f| r.feed(f))
+            .collect::<Vec<_>>();
+        assert_eq!(out.len(), 1);
+        let msg = out.remove(0);
+        assert_eq!(msg.id.pgn, PGN_DM1);
+        assert_eq!((msg.id.sa, msg.id.da), (0x21, GLOBAL_ADDRESS));
+        assert_eq!(msg.data, PAYLOAD);
+        assert_eq!(msg.transport, Some("BAM"));
+        assert_eq!(msg.timestamp_us, 150_000);
+        assert_eq!(r.aborted, 0);
+    }
+
+    #[test]
+    fn rts_cts_reassembly() {
+        let mut r = Reassembler::default();
+        let rts = [TP_CM_RTS, 17, 0, 3, 3, 0xCA, 0xFE, 0x00];
+        assert!(r.feed(&frame(0, tp(PGN_TP_CM, 0x21, 0x80), &rts)).is_none());
+        // 接收端的 CTS 與其他節點的流量不影響重組
+        let cts = [TP_CM_CTS, 3, 1, 0xFF, 0xFF, 0xCA, 0xFE, 0x00];
+        assert!(r
+            .feed(&frame(10_000, tp(PGN_TP_CM, 0x80, 0x21), &cts))
+            .is_none());
+        let other = r.feed(&frame(20_000, J1939Id::from_raw(0x0CF0_0400), &[1; 8]));
+        assert_eq!(other.unwrap().transport, None);
+
+        let frames = dt_frames(50_000, tp(PGN_TP_DT, 0x21, 0x80));
+        assert!(r.feed(&frames[0]).is_none());
+        assert!(r.feed(&frames[1]).is_none());
+        let msg = r.feed(&frames[2]).unwrap();
+        assert_eq!(msg.id.pgn, PGN_DM1);
+        assert_eq!((msg.id.sa, msg.id.da), (0x21, 0x80));
+        assert_eq!(msg.data, PAYLOAD);
+        assert_eq!(msg.transport, Some("RTS/CTS"));
+
+        // 序號跳號會中止 session
+        assert!(r.feed(&frame(0, tp(PGN_TP_CM, 0x21, 0x80), &rts)).is_none());
+        assert!(r.feed(&frames[1]).is_none());
+        assert_eq!(r.aborted, 1);
+        assert!(r.feed(&frames[2]).is_none());
+    }
+
+    #[test]
+    fn t1_timeout_drops_session() {
+        let mut r = Reassembler::default();
+        let cm = [TP_CM_BAM, 17, 0, 3, 0xFF, 0xCA, 0xFE, 0x00];
+        r.feed(&frame(0, tp(PGN_TP_CM, 0x21, GLOBAL_ADDRESS), &cm));
+        let frames = dt_frames(0, tp(PGN_TP_DT, 0x21, GLOBAL_ADDRESS));
+        let late = |f: &CanFrame, t: u64| CanFrame {
+            timestamp_us: t,
+            ..*f
+        };
+        assert!(r.feed(&late(&frames[0], 100_000)).is_none());
+        // 剛好 750 ms 還在時限內
+        assert!(r.feed(&late(&frames[1], 850_000)).is_none());
+        assert_eq!(r.aborted, 0);
+        // 超過 750 ms 後最後一包已經沒有 session 可以接
+        assert!(r.feed(&late(&frames[2], 1_600_001)).is_none());
+        assert_eq!(r.aborted, 1);
+    }
+
+    #[test]
+    fn dm1_dtcs() {
+        let dm1 = decode_dm1(&PAYLOAD).unwrap();
+        assert_eq!(dm1.lamps, [1, 0, 0, 0]);
+        assert_eq!(
+            dm1.dtcs,
+            [
+                Dtc {
+                    spn: 110,
+                    fmi: 4,
+                    oc: 1
+                },
+                Dtc {
+                    spn: 190,
+                    fmi: 3,
+                    oc: 2
+                },
+                // 轉換方式 4：第三個位元組的高 3 位元是 SPN 的 bit 16-18
+                Dtc {
+                    spn: 0x1_0000,
+                    fmi: 0,
+                    oc: 0
+                },
+            ]
+        );
+        let spn_max = decode_dm1(&[0, 0xFF, 0xFE, 0xFF, 0xE5, 0x85]).unwrap();
+        assert_eq!(
+            spn_max.dtcs,
+            [Dtc {
+                spn: 0x7FFFE,
+                fmi: 5,
+                oc: 5
+            }]
+        );
+        // 沒有故障時以全 0 填充
+        let none = decode_dm1(&[0, 0xFF, 0, 0, 0, 0]).unwrap();
+        assert!(none.dtcs.is_empty());
+        assert!(decode_dm1(&[0]).is_none());
+    }
+}
@@ -0,0 +1,849 @@
+use crate::canbus::{parse_hex_bytes, CanFrame};
+use crate::j1939::{
+    J1939Id, GLOBAL_ADDRESS, NULL_ADDRESS, PGN_ACKNOWLEDGMENT, PGN_ADDRESS_CLAIMED, PGN_REQUEST,
+    PGN_TP_CM, PGN_TP_DT, TP_CM_ABORT, TP_CM_BAM, TP_CM_CTS, TP_CM_EOM_ACK, TP_CM_RTS,
+};
+use eframe::egui;
+use std::collections::{BTreeMap, VecDeque};
+
+// 宣告位址後等待 250 ms 沒有衝突才算取得
+const CLAIM_WAIT_US: u64 = 250_000;
+// BAM 封包間隔 (規範 50~200 ms)
+const BAM_INTERVAL_US: u64 = 50_000;
+// T3：送出後等 CTS / EOM ACK；T4：CTS 要求暫停後等下一個 CTS
+const T3_US: u64 = 1_250_000;
+const T4_US: u64 = 1_050_000;
+const MAX_TP_SIZE: usize = 1785;
+// 可任意取得位址時的搜尋範圍
+const DYNAMIC_ADDRESSES: std::ops::RangeInclusive<u8> = 128..=247;
+
+/// J1939 NAME 的各欄位 (SAE J1939-81)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct J1939Name {
+    pub arbitrary_address: bool,
+    pub industry_group: u8,
+    pub vehicle_system_instance: u8,
+    pub vehicle_system: u8,
+    pub function: u8,
+    pub function_instance: u8,
+    pub ecu_instance: u8,
+    pub manufacturer_code: u16,
+    pub identity_number: u32,
+}
+
+impl Default for J1939Name {
+    fn default() -> Self {
+        Self {
+            arbitrary_address: true,
+            industry_group: 0,
+            vehicle_system_instance: 0,
+            vehicle_system: 0,
+            function: 0xFE,
+            function_instance: 0,
+            ecu_instance: 0,
+            manufacturer_code: 0x7FF,
+            identity_number: 1,
+        }
+    }
+}
+
+impl J1939Name {
+    pub fn to_u64(self) -> u64 {
+        (self.arbitrary_address as u64) << 63
+            | (self.industry_group as u64 & 0x7) << 60
+            | (self.vehicle_system_instance as u64 & 0xF) << 56
+            | (self.vehicle_system as u64 & 0x7F) << 49
+            | (self.function as u64) << 40
+            | (self.function_instance as u64 & 0x1F) << 35
+            | (self.ecu_instance as u64 & 0x7) << 32
+            | (self.manufacturer_code as u64 & 0x7FF) << 21
+            | self.identity_number as u64 & 0x1F_FFFF
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimState {
+    Offline,
+    Claiming { address: u8, since_us: u64 },
+    Claimed(u8),
+    CannotClaim,
+}
+
+enum TpStage {
+    // BAM：下一個 DT 的時間
+    Bam { next_us: u64 },
+    WaitCts { deadline_us: u64 },
+    WaitAck { deadline_us: u64 },
+}
+
+struct TpSession {
+    pgn: u32,
+    da: u8,
+    data: Vec<u8>,
+    next_seq: u8,
+    stage: TpStage,
+}
+
+impl TpSession {
+    fn packets(&self) -> u8 {
+        self.data.len().div_ceil(7) as u8
+    }
+
+    fn dt(&self, sa: u8, seq: u8) -> CanFrame {
+        let mut data = [0xFF; 8];
+        data[0] = seq;
+        let start = (seq as usize - 1) * 7;
+        let chunk = &self.data[start..(start + 7).min(self.data.len())];
+        data[1..1 + chunk.len()].copy_from_slice(chunk);
+        frame(7, PGN_TP_DT, sa, self.da, &data)
+    }
+
+    fn cm(&self, sa: u8, control: u8) -> CanFrame {
+        let size = (self.data.len() as u16).to_le_bytes();
+        let max_packets = if control == TP_CM_ABORT {
+            0xFF
+        } else {
+            self.packets()
+        };
+        let mut data = [control, size[0], size[1], max_packets, 0xFF, 0, 0, 0];
+        if control == TP_CM_ABORT {
+            // Abort：位元組 1 是原因 (3 = 逾時)，其餘保留
+            data[1..5].copy_from_slice(&[3, 0xFF, 0xFF, 0xFF]);
+        }
+        data[5..8].copy_from_slice(&self.pgn.to_le_bytes()[..3]);
+        frame(7, PGN_TP_CM, sa, self.da, &data)
+    }
+}
+
+fn frame(priority: u8, pgn: u32, sa: u8, da: u8, data: &[u8]) -> CanFrame {
+    let id = J1939Id {
+        priority,
+        pgn,
+        sa,
+        da,
+    };
+    let mut bytes = [0u8; 8];
+    let len = data.len().min(8);
+    bytes[..len].copy_from_slice(&data[..len]);
+    CanFrame {
+        id: id.to_raw(),
+        extended: true,
+        len: len as u8,
+        data: bytes,
+        ..Default::default()
+    }
+}
+
+/// 模擬一個 J1939 節點：位址宣告、回應 Request、以 BAM 或 RTS/CTS 送出多封包訊息
+///
+/// 由 UI 每個畫面呼叫 handle/poll，要送出的幀放在 outbox 交給傳送路徑
+pub struct J1939Node {
+    pub name: J1939Name,
+    pub preferred_address: u8,
+    pub channel: u32,
+    pub state: ClaimState,
+    // 匯流排上其他節點宣告的位址與 NAME
+    pub others: BTreeMap<u8, u64>,
+    // Request 的回應資料
+    pub responses: BTreeMap<u32, Vec<u8>>,
+    pub outbox: Vec<CanFrame>,
+    pub events: Vec<String>,
+    session: Option<TpSession>,
+    // 等待中的多封包訊息 (pgn, 目的, 資料)
+    queue: VecDeque<(u32, u8, Vec<u8>)>,
+    // UI 輸入
+    response_pgn: String,
+    response_data: String,
+    send_pgn: String,
+    send_da: u8,
+    send_priority: u8,
+    send_data: String,
+}
+
+impl Default for J1939Node {
+    fn default() -> Self {
+        Self {
+            name: J1939Name::default(),
+            preferred_address: 0x80,
+            channel: 0,
+            state: ClaimState::Offline,
+            others: BTreeMap::new(),
+            responses: BTreeMap::new(),
+            outbox: Vec::new(),
+            events: Vec::new(),
+            session: None,
+            queue: VecDeque::new(),
+            response_pgn: "FEEC".to_string(),
+            response_data: String::new(),
+            send_pgn: "FECA".to_string(),
+            send_da: GLOBAL_ADDRESS,
+            send_priority: 6,
+            send_data: String::new(),
+        }
+    }
+}
+
+impl J1939Node {
+    pub fn address(&self) -> Option<u8> {
+        match self.state {
+            ClaimState::Claiming { address, .. } | ClaimState::Claimed(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    pub fn start(&mut self, now_us: u64) {
+        self.others.clear();
+        self.claim(self.preferred_address, now_us);
+    }
+
+    pub fn stop(&mut self) {
+        self.state = ClaimState::Offline;
+        self.session = None;
+        self.queue.clear();
+    }
+
+    fn claim(&mut self, address: u8, now_us: u64) {
+        self.state = ClaimState::Claiming {
+            address,
+            since_us: now_us,
+        };
+        self.send_claim(address);
+    }
+
+    fn send_claim(&mut self, address: u8) {
+        let name = self.name.to_u64().to_le_bytes();
+        self.outbox.push(frame(
+            6,
+            PGN_ADDRESS_CLAIMED,
+            address,
+            GLOBAL_ADDRESS,
+            &name,
+        ));
+    }
+
+    fn cannot_claim(&mut self) {
+        self.state = ClaimState::CannotClaim;
+        self.send_claim(NULL_ADDRESS);
+        self.session = None;
+        self.queue.clear();
+        self.events
+            .push("J1939: 無法取得位址，已送出 Cannot Claim".to_string());
+    }
+
+    /// 餵匯流排上收到的幀
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        if self.state == ClaimState::Offline
+            || frame.channel != self.channel
+            || !frame.extended
+            || frame.remote
+            || frame.error
+        {
+            return;
+        }
+        let id = J1939Id::from_raw(frame.id);
+        let data = frame.payload();
+        let ours = self.address();
+        let to_us = id.da == GLOBAL_ADDRESS || Some(id.da) == ours;
+
+        match id.pgn {
+            PGN_ADDRESS_CLAIMED if data.len() == 8 => {
+                let their_name = u64::from_le_bytes(data.try_into().unwrap_or([0; 8]));
+                // 自己送出的宣告 (虛擬匯流排會回送)
+                if their_name == self.name.to_u64() {
+                    return;
+                }
+                self.others.retain(|_, n| *n != their_name);
+                if id.sa != NULL_ADDRESS {
+                    self.others.insert(id.sa, their_name);
+                }
+                if Some(id.sa) != ours {
+                    return;
+                }
+                // 1. **位址衝突**：NAME 數值小的優先
+                let address = id.sa;
+                if self.name.to_u64() < their_name {
+                    self.events.push(format!(
+                        "J1939: 位址 0x{:02X} 衝突，我方 NAME 優先，重新宣告",
+                        address
+                    ));
+                    self.send_claim(address);
+                } else if self.name.arbitrary_address {
+                    // 2. **讓出位址**，從動態範圍找一個沒人用的
+                    let free = DYNAMIC_ADDRESSES
+                        .chain(0..=127)
+                        .find(|a| *a != address && !self.others.contains_key(a));
+                    match free {
+                        Some(next) => {
+                            self.events.push(format!(
+                                "J1939: 位址 0x{:02X} 被搶走，改宣告 0x{:02X}",
+                                address, next
+                            ));
+                            self.claim(next, now_us);
+                        }
+                        None => self.cannot_claim(),
+                    }
+                } else {
+                    self.cannot_claim();
+                }
+            }
+            PGN_REQUEST if to_us && data.len() >= 3 => {
+                let pgn = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+                self.answer_request(pgn, id.sa, id.da == GLOBAL_ADDRESS);
+            }
+            PGN_TP_CM if Some(id.da) == ours && data.len() >= 8 => {
+                self.handle_tp_cm(id.sa, data, now_us);
+            }
+            _ => {}
+        }
+    }
+
+    fn answer_request(&mut self, pgn: u32, requester: u8, global: bool) {
+        if pgn == PGN_ADDRESS_CLAIMED {
+            match self.state {
+                ClaimState::CannotClaim => self.send_claim(NULL_ADDRESS),
+                _ => {
+                    if let Some(address) = self.address() {
+                        self.send_claim(address);
+                    }
+                }
+            }
+            return;
+        }
+        let ClaimState::Claimed(sa) = self.state else {
+            return;
+        };
+        match self.responses.get(&pgn).cloned() {
+            Some(data) => {
+                let da = if global { GLOBAL_ADDRESS } else { requester };
+                self.send(pgn, da, 6, data);
+            }
+            // 指名給我們但沒有資料：回 NACK；廣播的 Request 不回
+            None if !global => {
+                let p = pgn.to_le_bytes();
+                let nack = [1, 0xFF, 0xFF, 0xFF, requester, p[0], p[1], p[2]];
+                self.outbox
+                    .push(frame(6, PGN_ACKNOWLEDGMENT, sa, GLOBAL_ADDRESS, &nack));
+            }
+            None => {}
+        }
+    }
+
+    fn handle_tp_cm(&mut self, from: u8, data: &[u8], now_us: u64) {
+        let Some(sa) = self.address() else {
+            return;
+        };
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        if session.da != from || matches!(session.stage, TpStage::Bam { .. }) {
+            return;
+        }
+        match data[0] {
+            TP_CM_CTS => {
+                let count = data[1];
+                if count == 0 {
+                    session.stage = TpStage::WaitCts {
+                        deadline_us: now_us + T4_US,
+                    };
+                    return;
+                }
+                // CTS 指定的區段一次送完
+                let first = data[2].max(1);
+                let last = first.saturating_add(count - 1).min(session.packets());
+                for seq in first..=last {
+                    let dt = session.dt(sa, seq);
+                    self.outbox.push(dt);
+                }
+                session.next_seq = last + 1;
+                session.stage = if last >= session.packets() {
+                    TpStage::WaitAck {
+                        deadline_us: now_us + T3_US,
+                    }
+                } else {
+                    TpStage::WaitCts {
+                        deadline_us: now_us + T3_US,
+                    }
+                };
+            }
+            TP_CM_EOM_ACK => {
+                self.events.push(format!(
+                    "J1939: PGN 0x{:05X} 以 RTS/CTS 送給 0x{:02X} 完成 ({} bytes)",
+                    session.pgn,
+                    from,
+                    session.data.len()
+                ));
+                self.session = None;
+            }
+            TP_CM_ABORT => {
+                self.events.push(format!(
+                    "J1939: 0x{:02X} 中止了 PGN 0x{:05X} 的傳輸 (原因 {})",
+                    from, session.pgn, data[1]
+                ));
+                self.session = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// 送出訊息；超過 8 bytes 時廣播用 BAM，指定目的用 RTS/CTS
+    pub fn send(&mut self, pgn: u32, da: u8, priority: u8, data: Vec<u8>) {
+        let Some(sa) = self.address() else {
+            return;
+        };
+        if data.len() <= 8 {
+            self.outbox.push(frame(priority, pgn, sa, da, &data));
+        } else {
+            self.queue.push_back((pgn, da, data));
+        }
+    }
+
+    /// 每個畫面呼叫一次：推進位址宣告與多封包傳輸
+    pub fn poll(&mut self, now_us: u64) {
+        // 1. **宣告滿 250 ms 沒有衝突就取得位址**
+        if let ClaimState::Claiming { address, since_us } = self.state {
+            if now_us.saturating_sub(since_us) >= CLAIM_WAIT_US {
+                self.state = ClaimState::Claimed(address);
+                self.events
+                    .push(format!("J1939: 已取得位址 0x{:02X}", address));
+            }
+        }
+        let ClaimState::Claimed(sa) = self.state else {
+            return;
+        };
+
+        // 2. **開始下一個多封包傳輸**
+        if self.session.is_none() {
+            if let Some((pgn, da, data)) = self.queue.pop_front() {
+                let session = TpSession {
+                    pgn,
+                    da,
+                    data,
+                    next_seq: 1,
+                    stage: if da == GLOBAL_ADDRESS {
+                        TpStage::Bam {
+                            next_us: now_us + BAM_INTERVAL_US,
+                        }
+                    } else {
+                        TpStage::WaitCts {
+                            deadline_us: now_us + T3_US,
+                        }
+                    },
+                };
+                let control = if da == GLOBAL_ADDRESS {
+                    TP_CM_BAM
+                } else {
+                    TP_CM_RTS
+                };
+                self.outbox.push(session.cm(sa, control));
+                self.session = Some(session);
+            }
+        }
+
+        // 3. **推進目前的傳輸**
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        match session.stage {
+            TpStage::Bam { next_us } if now_us >= next_us => {
+                let dt = session.dt(sa, session.next_seq);
+                self.outbox.push(dt);
+                if session.next_seq >= session.packets() {
+                    self.events.push(format!(
+                        "J1939: PGN 0x{:05X} 以 BAM 廣播完成 ({} bytes)",
+                        session.pgn,
+                        session.data.len()
+                    ));
+                    self.session = None;
+                } else {
+                    session.next_seq += 1;
+                    session.stage = TpStage::Bam {
+                        next_us: now_us + BAM_INTERVAL_US,
+                    };
+                }
+            }
+            TpStage::WaitCts { deadline_us } | TpStage::WaitAck { deadline_us }
+                if now_us >= deadline_us =>
+            {
+                let abort = session.cm(sa, TP_CM_ABORT);
+                self.outbox.push(abort);
+                self.events.push(format!(
+                    "J1939: PGN 0x{:05X} 等待 0x{:02X} 回應逾時，已中止",
+                    session.pgn, session.da
+                ));
+                self.session = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool, now_us: u64) {
+        let online = self.state != ClaimState::Offline;
+        ui.add_enabled_ui(!online, |ui| {
+            egui::Grid::new("j1939_name").num_columns(4).show(ui, |ui| {
+                ui.label("Identity:");
+                ui.add(egui::DragValue::new(&mut self.name.identity_number).range(0..=0x1F_FFFF));
+                ui.label("製造商代碼:");
+                ui.add(egui::DragValue::new(&mut self.name.manufacturer_code).range(0..=0x7FF));
+                ui.end_row();
+                ui.label("Function:");
+                ui.add(egui::DragValue::new(&mut self.name.function));
+                ui.label("Function Instance:");
+                ui.add(egui::DragValue::new(&mut self.name.function_instance).range(0..=31));
+                ui.end_row();
+                ui.label("ECU Instance:");
+                ui.add(egui::DragValue::new(&mut self.name.ecu_instance).range(0..=7));
+                ui.label("Vehicle System:");
+                ui.add(egui::DragValue::new(&mut self.name.vehicle_system).range(0..=127));
+                ui.end_row();
+                ui.label("VS Instance:");
+                ui.add(egui::DragValue::new(&mut self.name.vehicle_system_instance).range(0..=15));
+                ui.label("Industry Group:");
+                ui.add(egui::DragValue::new(&mut self.name.industry_group).range(0..=7));
+                ui.end_row();
+                ui.label("偏好位址:");
+                ui.add(
+                    egui::DragValue::new(&mut self.preferred_address).hexadecimal(2, false, true),
+                );
+                ui.checkbox(&mut self.name.arbitrary_address, "可任意取得位址");
+                ui.end_row();
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.monospace(format!("NAME = {:016X}", self.name.to_u64()));
+            if online {
+                if ui.button("離線").clicked() {
+                    self.stop();
+                }
+            } else if ui
+                .add_enabled(can_send, egui::Button::new("上線"))
+                .on_disabled_hover_text("需要先打開裝置")
+                .clicked()
+            {
+                self.start(now_us);
+            }
+            ui.label(match self.state {
+                ClaimState::Offline => "離線".to_string(),
+                ClaimState::Claiming { address, .. } => format!("宣告 0x{:02X} 中", address),
+                ClaimState::Claimed(address) => format!("位址 0x{:02X}", address),
+                ClaimState::CannotClaim => "無法取得位址".to_string(),
+            });
+        });
+
+        ui.separator();
+        ui.label("Request 回應 (PGN → 資料):");
+        let mut remove = None;
+        for (pgn, data) in &self.responses {
+            ui.horizontal(|ui| {
+                ui.monospace(format!(
+                    "0x{:05X}: {}",
+                    pgn,
+                    data.iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ));
+                if ui.small_button("移除").clicked() {
+                    remove = Some(*pgn);
+                }
+            });
+        }
+        if let Some(pgn) = remove {
+            self.responses.remove(&pgn);
+        }
+        ui.horizontal(|ui| {
+            ui.label("PGN (hex):");
+            ui.add(egui::TextEdit::singleline(&mut self.response_pgn).desired_width(60.0));
+            ui.label("資料:");
+            ui.text_edit_singleline(&mut self.response_data);
+            if ui.button("設定").clicked() {
+                match (
+                    u32::from_str_radix(self.response_pgn.trim().trim_start_matches("0x"), 16),
+                    parse_hex_bytes(&self.response_data),
+                ) {
+                    (Ok(pgn), Ok(data)) if !data.is_empty() && data.len() <= MAX_TP_SIZE => {
+                        self.responses.insert(pgn, data);
+                    }
+                    (Err(_), _) => self.events.push("J1939: 無效的 PGN".to_string()),
+                    (_, Err(e)) => self.events.push(e),
+                    _ => self
+                        .events
+                        .push(format!("J1939: 資料長度須為 1~{} bytes", MAX_TP_SIZE)),
+                }
+            }
+        });
+
+        ui.separator();
+        ui.label("送出訊息 (超過 8 bytes 時廣播用 BAM，指定目的用 RTS/CTS):");
+        ui.horizontal(|ui| {
+            ui.label("PGN (hex):");
+            ui.add(egui::TextEdit::singleline(&mut self.send_pgn).desired_width(60.0));
+            ui.label("目的:");
+            ui.add(egui::DragValue::new(&mut self.send_da).hexadecimal(2, false, true));
+            ui.label("優先權:");
+            ui.add(egui::DragValue::new(&mut self.send_priority).range(0..=7));
+        });
+        ui.horizontal(|ui| {
+            ui.label("資料:");
+            ui.text_edit_singleline(&mut self.send_data);
+            let claimed = matches!(self.state, ClaimState::Claimed(_));
+            if ui.add_enabled(claimed, egui::Button::new("送出")).clicked() {
+                match (
+                    u32::from_str_radix(self.send_pgn.trim().trim_start_matches("0x"), 16),
+                    parse_hex_bytes(&self.send_data),
+                ) {
+                    (Ok(pgn), Ok(data)) if data.len() <= MAX_TP_SIZE => {
+                        self.send(pgn, self.send_da, self.send_priority, data);
+                    }
+                    (Err(_), _) => self.events.push("J1939: 無效的 PGN".to_string()),
+                    (_, Err(e)) => self.events.push(e),
+                    _ => self
+                        .events
+                        .push(format!("J1939: 資料最多 {} bytes", MAX_TP_SIZE)),
+                }
+            }
+            if self.session.is_some() || !self.queue.is_empty() {
+                ui.spinner();
+                ui.label(format!("傳送中，佇列 {} 筆", self.queue.len()));
+            }
+        });
+
+        if !self.others.is_empty() {
+            ui.separator();
+            ui.label("匯流排上的節點:");
+            for (address, name) in &self.others {
+                ui.monospace(format!("0x{:02X}  NAME {:016X}", address, name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::j1939::{J1939Message, Reassembler, PGN_DM1};
+    use crate::virtual_bus::VirtualBus;
+    use flume::{Receiver, Sender};
+
+    struct Bench {
+        bus: VirtualBus,
+        tx: Sender<CanFrame>,
+        data_tx: Sender<CanFrame>,
+        data_rx: Receiver<CanFrame>,
+        now: u64,
+        reassembler: Reassembler,
+        received: Vec<J1939Message>,
+    }
+
+    impl Bench {
+        fn new() -> Self {
+            let (bus, tx) = VirtualBus::start();
+            let (data_tx, data_rx) = flume::unbounded();
+            Self {
+                bus,
+                tx,
+                data_tx,
+                data_rx,
+                now: 0,
+                reassembler: Reassembler::default(),
+                received: Vec::new(),
+            }
+        }
+
+        /// 每 1 ms 推進節點與虛擬匯流排；peer 看到每一幀，回傳要送上匯流排的幀
+        fn run(
+            &mut self,
+            nodes: &mut [&mut J1939Node],
+            ms: usize,
+            mut peer: impl FnMut(&J1939Id, &[u8]) -> Vec<CanFrame>,
+        ) {
+            for _ in 0..ms {
+                self.now += 1_000;
+                for node in nodes.iter_mut() {
+                    node.poll(self.now);
+                    for frame in node.outbox.drain(..) {
+                        self.tx.send(frame).unwrap();
+                    }
+                }
+                self.bus.poll(self.now, 0, &self.data_tx);
+                while let Ok(frame) = self.data_rx.try_recv() {
+                    for node in nodes.iter_mut() {
+                        node.handle(&frame, self.now);
+                    }
+                    for reply in peer(&J1939Id::from_raw(frame.id), frame.payload()) {
+                        self.tx.send(reply).unwrap();
+                    }
+                    if let Some(msg) = self.reassembler.feed(&frame) {
+                        if msg.transport.is_some() {
+                            self.received.push(msg);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn node(identity_number: u32, arbitrary_address: bool) -> J1939Node {
+        J1939Node {
+            name: J1939Name {
+                identity_number,
+                arbitrary_address,
+                ..J1939Name::default()
+            },
+            ..J1939Node::default()
+        }
+    }
+
+    fn no_peer(_: &J1939Id, _: &[u8]) -> Vec<CanFrame> {
+        Vec::new()
+    }
+
+    #[test]
+    fn lower_name_wins_address_claim() {
+        let mut bench = Bench::new();
+        let (mut high, mut low) = (node(2, true), node(1, true));
+        high.start(0);
+        low.start(0);
+        bench.run(&mut [&mut high, &mut low], 300, no_peer);
+        assert_eq!(low.state, ClaimState::Claimed(0x80));
+        assert_eq!(high.state, ClaimState::Claimed(0x81));
+        assert_eq!(high.others.get(&0x80), Some(&low.name.to_u64()));
+        assert_eq!(low.others.get(&0x81), Some(&high.name.to_u64()));
+
+        // 不能任意取得位址的節點輸了只能送 Cannot Claim
+        let mut bench = Bench::new();
+        let (mut fixed, mut low) = (node(2, false), node(1, false));
+        fixed.start(0);
+        low.start(0);
+        bench.run(&mut [&mut fixed, &mut low], 300, no_peer);
+        assert_eq!(low.state, ClaimState::Claimed(0x80));
+        assert_eq!(fixed.state, ClaimState::CannotClaim);
+        assert_eq!(fixed.address(), None);
+    }
+
+    fn cts(sa: u8, da: u8, count: u8, next: u8) -> CanFrame {
+        let data = [TP_CM_CTS, count, next, 0xFF, 0xFF, 0xCA, 0xFE, 0];
+        frame(7, PGN_TP_CM, sa, da, &data)
+    }
+
+    #[test]
+    fn rts_cts_transfer_completes() {
+        const PEER: u8 = 0x90;
+        let mut bench = Bench::new();
+        let mut sender = node(1, true);
+        sender.start(0);
+        bench.run(&mut [&mut sender], 300, no_peer);
+        let sa = sender.address().unwrap();
+
+        // 接收端先要 2 包，收到後要求暫停
+        let payload: Vec<u8> = (0..20).collect();
+        sender.send(PGN_DM1, PEER, 6, payload.clone());
+        let mut dt_seen = 0;
+        bench.run(&mut [&mut sender], 50, |id, data| match id.pgn {
+            PGN_TP_CM if id.sa == sa && data[0] == TP_CM_RTS => {
+                assert_eq!(&data[1..4], &[20, 0, 3]);
+                vec![cts(PEER, sa, 2, 1)]
+            }
+            PGN_TP_DT if id.sa == sa => {
+                dt_seen += 1;
+                if dt_seen == 2 {
+                    vec![cts(PEER, sa, 0, 0xFF)]
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        });
+        assert_eq!(dt_seen, 2);
+
+        // 暫停期間 (T4 內) 不會中止
+        bench.run(&mut [&mut sender], 500, no_peer);
+        assert!(sender.session.is_some());
+
+        // 再要剩下的 1 包，收到後回 EOM ACK
+        bench.tx.send(cts(PEER, sa, 1, 3)).unwrap();
+        bench.run(&mut [&mut sender], 10, |id, data| {
+            if id.pgn == PGN_TP_DT && id.sa == sa {
+                assert_eq!(data[0], 3);
+                let ack = [TP_CM_EOM_ACK, 20, 0, 3, 0xFF, 0xCA, 0xFE, 0];
+                return vec![frame(7, PGN_TP_CM, PEER, sa, &ack)];
+            }
+            Vec::new()
+        });
+        assert!(sender.session.is_none());
+        let event = sender.events.last().unwrap();
+        assert!(
+            event.contains("RTS/CTS") && event.contains("完成"),
+            "{}",
+            event
+        );
+        // 旁聽的重組器拿到完整資料
+        assert_eq!(bench.received.len(), 1);
+        assert_eq!(bench.received[0].data, payload);
+        assert_eq!(bench.received[0].id.da, PEER);
+    }
+
+    #[test]
+    fn t3_and_t4_timeouts_abort() {
+        const PEER: u8 = 0x90;
+        let mut bench = Bench::new();
+        let mut sender = node(1, true);
+        sender.start(0);
+        bench.run(&mut [&mut sender], 300, no_peer);
+        let sa = sender.address().unwrap();
+
+        // 沒有 CTS：T3 後送 Abort
+        sender.send(PGN_DM1, PEER, 6, vec![0; 20]);
+        let mut aborts = 0;
+        let mut count_aborts = |id: &J1939Id, data: &[u8]| {
+            if id.pgn == PGN_TP_CM && id.sa == sa && data[0] == TP_CM_ABORT {
+                aborts += 1;
+            }
+            Vec::new()
+        };
+        bench.run(&mut [&mut sender], 1_200, &mut count_aborts);
+        assert!(sender.session.is_some());
+        bench.run(&mut [&mut sender], 100, &mut count_aborts);
+        assert!(sender.session.is_none());
+        assert_eq!(aborts, 1);
+
+        // CTS 要求暫停後沒有下文：T4 後送 Abort
+        sender.send(PGN_DM1, PEER, 6, vec![0; 20]);
+        bench.run(&mut [&mut sender], 5, |id, data| {
+            if id.pgn == PGN_TP_CM && id.sa == sa && data[0] == TP_CM_RTS {
+                return vec![cts(PEER, sa, 0, 0xFF)];
+            }
+            Vec::new()
+        });
+        bench.run(&mut [&mut sender], 1_000, no_peer);
+        assert!(sender.session.is_some());
+        aborts = 0;
+        bench.run(&mut [&mut sender], 100, |id, data| {
+            if id.pgn == PGN_TP_CM && id.sa == sa && data[0] == TP_CM_ABORT {
+                aborts += 1;
+            }
+            Vec::new()
+        });
+        assert!(sender.session.is_none());
+        assert_eq!(aborts, 1);
+        assert!(sender.events.last().unwrap().contains("逾時"));
+    }
+
+    #[test]
+    fn bam_broadcast() {
+        let mut bench = Bench::new();
+        let mut sender = node(1, true);
+        sender.start(0);
+        bench.run(&mut [&mut sender], 300, no_peer);
+
+        let payload: Vec<u8> = (0..30).collect();
+        sender.send(PGN_DM1, GLOBAL_ADDRESS, 6, payload.clone());
+        // 5 包，每 50 ms 一包
+        bench.run(&mut [&mut sender], 200, no_peer);
+        assert!(sender.session.is_some());
+        bench.run(&mut [&mut sender], 60, no_peer);
+        assert!(sender.session.is_none());
+        assert_eq!(bench.received.len(), 1);
+        assert_eq!(bench.received[0].transport, Some("BAM"));
+        assert_eq!(bench.received[0].data, payload);
+    }
+}
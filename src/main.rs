@@ -1,7 +1,39 @@
 #![windows_subsystem = "windows"]
 
+mod a2l;
+mod bus_stats;
 mod canbus;
+mod canopen;
+mod ccp;
+mod dbc;
+mod ecu_scan;
+mod eds;
+mod filter;
+mod firmware;
+mod flash;
+mod frame_store;
+mod isotp;
+mod j1939;
+mod j1939_node;
+mod mdf4;
+mod measurement;
+mod nmea2000;
+mod obd;
+mod pcapng;
+mod plot;
+mod replay;
+mod sdo;
+mod security;
+mod sim_ccp;
+mod sim_ecu;
+mod sim_xcp;
+mod trace_overview;
+mod trace_table;
+mod trc;
+mod uds;
 mod ui_components;
+mod virtual_bus;
+mod xcp;
 
 use ui_components::MyApp;
 
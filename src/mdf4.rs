@@ -0,0 +1,794 @@
+use crate::canbus::CanFrame;
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+// 依 ASAM MDF 4.1 bus logging 慣例：一個 CAN_DataFrame channel group，
+// 紀錄格式為 Timestamp(f64) + CAN_DataFrame(15 bytes)
+const RECORD_LEN: usize = 23;
+const FRAME_OFFSET: u32 = 8;
+const FRAME_BYTES: u32 = 15;
+
+const ID_LEN: u64 = 64;
+const HEADER_LEN: u64 = 24;
+const HD_LEN: u64 = HEADER_LEN + 6 * 8 + 32;
+const FH_LEN: u64 = HEADER_LEN + 2 * 8 + 16;
+const DG_LEN: u64 = HEADER_LEN + 4 * 8 + 8;
+const CG_LEN: u64 = HEADER_LEN + 6 * 8 + 32;
+const SI_LEN: u64 = HEADER_LEN + 3 * 8 + 8;
+const CN_LEN: u64 = HEADER_LEN + 8 * 8 + 72;
+
+// 位址 / 欄位偏移，用於 finish() 回填
+const ID_FILE_ID: u64 = 0;
+const ID_UNFIN_FLAGS: u64 = 60;
+const CG_CYCLE_COUNT: u64 = HEADER_LEN + 6 * 8 + 8;
+
+const CN_TYPE_FIXED: u8 = 0;
+const CN_TYPE_MASTER: u8 = 2;
+const CN_SYNC_NONE: u8 = 0;
+const CN_SYNC_TIME: u8 = 1;
+const DT_UINT_LE: u8 = 0;
+const DT_FLOAT_LE: u8 = 4;
+const DT_BYTE_ARRAY: u8 = 10;
+const CN_FLAG_BUS_EVENT: u32 = 0x400;
+const CG_FLAG_BUS_EVENT: u16 = 0x02 | 0x04;
+const SI_TYPE_BUS: u8 = 2;
+const SI_BUS_CAN: u8 = 2;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn block_header(id: &[u8; 4], len: u64, links: &[u64]) -> Vec<u8> {
+    let mut b = Vec::with_capacity(len as usize);
+    b.extend_from_slice(id);
+    b.extend_from_slice(&[0; 4]);
+    b.extend_from_slice(&len.to_le_bytes());
+    b.extend_from_slice(&(links.len() as u64).to_le_bytes());
+    for link in links {
+        b.extend_from_slice(&link.to_le_bytes());
+    }
+    b
+}
+
+fn text_len(text: &str) -> u64 {
+    HEADER_LEN + ((text.len() as u64 + 1 + 7) & !7)
+}
+
+fn text_block(id: &[u8; 4], text: &str) -> Vec<u8> {
+    let len = text_len(text);
+    let mut b = block_header(id, len, &[]);
+    b.extend_from_slice(text.as_bytes());
+    b.resize(len as usize, 0);
+    b
+}
+
+struct ChannelDef {
+    name: &'static str,
+    cn_type: u8,
+    sync_type: u8,
+    data_type: u8,
+    byte_offset: u32,
+    bit_offset: u8,
+    bit_count: u32,
+    flags: u32,
+}
+
+const TIME_CHANNEL: ChannelDef = ChannelDef {
+    name: "Timestamp",
+    cn_type: CN_TYPE_MASTER,
+    sync_type: CN_SYNC_TIME,
+    data_type: DT_FLOAT_LE,
+    byte_offset: 0,
+    bit_offset: 0,
+    bit_count: 64,
+    flags: 0,
+};
+
+const FRAME_CHANNEL: ChannelDef = ChannelDef {
+    name: "CAN_DataFrame",
+    cn_type: CN_TYPE_FIXED,
+    sync_type: CN_SYNC_NONE,
+    data_type: DT_BYTE_ARRAY,
+    byte_offset: FRAME_OFFSET,
+    bit_offset: 0,
+    bit_count: FRAME_BYTES * 8,
+    flags: CN_FLAG_BUS_EVENT,
+};
+
+const fn sub_channel(
+    name: &'static str,
+    data_type: u8,
+    byte: u32,
+    bit: u8,
+    bits: u32,
+) -> ChannelDef {
+    ChannelDef {
+        name,
+        cn_type: CN_TYPE_FIXED,
+        sync_type: CN_SYNC_NONE,
+        data_type,
+        byte_offset: byte,
+        bit_offset: bit,
+        bit_count: bits,
+        flags: 0,
+    }
+}
+
+const SUB_CHANNELS: [ChannelDef; 6] = [
+    sub_channel("CAN_DataFrame.BusChannel", DT_UINT_LE, 8, 0, 8),
+    sub_channel("CAN_DataFrame.ID", DT_UINT_LE, 9, 0, 29),
+    sub_channel("CAN_DataFrame.IDE", DT_UINT_LE, 12, 7, 1),
+    sub_channel("CAN_DataFrame.DLC", DT_UINT_LE, 13, 0, 4),
+    sub_channel("CAN_DataFrame.DataLength", DT_UINT_LE, 14, 0, 8),
+    sub_channel("CAN_DataFrame.DataBytes", DT_BYTE_ARRAY, 15, 0, 64),
+];
+
+fn channel_block(
+    def: &ChannelDef,
+    next: u64,
+    composition: u64,
+    name: u64,
+    si: u64,
+    unit: u64,
+) -> Vec<u8> {
+    let mut b = block_header(
+        b"##CN",
+        CN_LEN,
+        &[next, composition, name, si, 0, 0, unit, 0],
+    );
+    b.push(def.cn_type);
+    b.push(def.sync_type);
+    b.push(def.data_type);
+    b.push(def.bit_offset);
+    b.extend_from_slice(&def.byte_offset.to_le_bytes());
+    b.extend_from_slice(&def.bit_count.to_le_bytes());
+    b.extend_from_slice(&def.flags.to_le_bytes());
+    b.extend_from_slice(&0u32.to_le_bytes()); // inval_bit_pos
+    b.push(0); // precision
+    b.push(0);
+    b.extend_from_slice(&0u16.to_le_bytes()); // attachment_count
+    b.extend_from_slice(&[0; 48]); // val_range / limit / limit_ext
+    b
+}
+
+/// 串流寫出 MDF4：metadata 一次寫好，之後每幀直接附加到 DT 區塊，
+/// finish() 時回填 DT 長度與 cycle count
+pub struct Mdf4Writer<W: Write + Seek> {
+    w: W,
+    start_us: u64,
+    cg_addr: u64,
+    dt_addr: u64,
+    count: u64,
+    skipped: u64,
+}
+
+impl<W: Write + Seek> Mdf4Writer<W> {
+    pub fn new(mut w: W, start_us: u64) -> io::Result<Self> {
+        // 1. **配置各區塊位址**
+        let fh_xml = format!(
+            "<FHcomment xmlns='http://www.asam.net/mdf/v4'><TX>CAN bus logging</TX>\
+             <tool_id>{}</tool_id><tool_vendor>-</tool_vendor>\
+             <tool_version>{}</tool_version></FHcomment>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut addr = ID_LEN;
+        let mut alloc = |size: u64| {
+            let a = addr;
+            addr += size;
+            a
+        };
+        alloc(HD_LEN); // HD 固定緊接在 ID 之後
+        let fh = alloc(FH_LEN);
+        let fh_md = alloc(text_len(&fh_xml));
+        let dg = alloc(DG_LEN);
+        let cg = alloc(CG_LEN);
+        let cg_name = alloc(text_len(FRAME_CHANNEL.name));
+        let si = alloc(SI_LEN);
+        let si_name = alloc(text_len("CAN"));
+        let time_cn = alloc(CN_LEN);
+        let time_name = alloc(text_len(TIME_CHANNEL.name));
+        let time_unit = alloc(text_len("s"));
+        let frame_cn = alloc(CN_LEN);
+        let frame_name = alloc(text_len(FRAME_CHANNEL.name));
+        let subs: Vec<(u64, u64)> = SUB_CHANNELS
+            .iter()
+            .map(|def| (alloc(CN_LEN), alloc(text_len(def.name))))
+            .collect();
+        let dt = alloc(HEADER_LEN);
+
+        // 2. **ID 區塊**，先標成未完成，finish() 時再改回 "MDF     "
+        let mut id = Vec::with_capacity(ID_LEN as usize);
+        id.extend_from_slice(b"UnFinMF ");
+        id.extend_from_slice(b"4.10    ");
+        id.extend_from_slice(b"canegui ");
+        id.extend_from_slice(&[0; 4]);
+        id.extend_from_slice(&410u16.to_le_bytes());
+        id.extend_from_slice(&[0; 30]);
+        id.extend_from_slice(&(0x01u16 | 0x04).to_le_bytes());
+        id.extend_from_slice(&0u16.to_le_bytes());
+        w.write_all(&id)?;
+
+        // 3. **HD / FH**
+        let mut b = block_header(b"##HD", HD_LEN, &[dg, fh, 0, 0, 0, 0]);
+        b.extend_from_slice(&(start_us * 1000).to_le_bytes());
+        b.extend_from_slice(&0i16.to_le_bytes());
+        b.extend_from_slice(&0i16.to_le_bytes());
+        b.extend_from_slice(&[0, 0, 0, 0]);
+        b.extend_from_slice(&0f64.to_le_bytes());
+        b.extend_from_slice(&0f64.to_le_bytes());
+        w.write_all(&b)?;
+
+        let mut b = block_header(b"##FH", FH_LEN, &[0, fh_md]);
+        b.extend_from_slice(&(start_us * 1000).to_le_bytes());
+        b.extend_from_slice(&[0; 8]);
+        w.write_all(&b)?;
+        w.write_all(&text_block(b"##MD", &fh_xml))?;
+
+        // 4. **DG / CG / SI**
+        let mut b = block_header(b"##DG", DG_LEN, &[0, cg, dt, 0]);
+        b.extend_from_slice(&[0; 8]);
+        w.write_all(&b)?;
+
+        let mut b = block_header(b"##CG", CG_LEN, &[0, time_cn, cg_name, si, 0, 0]);
+        b.extend_from_slice(&0u64.to_le_bytes()); // record_id
+        b.extend_from_slice(&0u64.to_le_bytes()); // cycle_count
+        b.extend_from_slice(&CG_FLAG_BUS_EVENT.to_le_bytes());
+        b.extend_from_slice(&(b'.' as u16).to_le_bytes());
+        b.extend_from_slice(&[0; 4]);
+        b.extend_from_slice(&(RECORD_LEN as u32).to_le_bytes());
+        b.extend_from_slice(&0u32.to_le_bytes());
+        w.write_all(&b)?;
+        w.write_all(&text_block(b"##TX", FRAME_CHANNEL.name))?;
+
+        let mut b = block_header(b"##SI", SI_LEN, &[si_name, 0, 0]);
+        b.extend_from_slice(&[SI_TYPE_BUS, SI_BUS_CAN, 0, 0, 0, 0, 0, 0]);
+        w.write_all(&b)?;
+        w.write_all(&text_block(b"##TX", "CAN"))?;
+
+        // 5. **CN：Timestamp -> CAN_DataFrame (composition: 各子欄位)**
+        w.write_all(&channel_block(
+            &TIME_CHANNEL,
+            frame_cn,
+            0,
+            time_name,
+            0,
+            time_unit,
+        ))?;
+        w.write_all(&text_block(b"##TX", TIME_CHANNEL.name))?;
+        w.write_all(&text_block(b"##TX", "s"))?;
+        w.write_all(&channel_block(
+            &FRAME_CHANNEL,
+            0,
+            subs[0].0,
+            frame_name,
+            si,
+            0,
+        ))?;
+        w.write_all(&text_block(b"##TX", FRAME_CHANNEL.name))?;
+        for (i, def) in SUB_CHANNELS.iter().enumerate() {
+            let next = subs.get(i + 1).map(|s| s.0).unwrap_or(0);
+            w.write_all(&channel_block(def, next, 0, subs[i].1, 0, 0))?;
+            w.write_all(&text_block(b"##TX", def.name))?;
+        }
+
+        // 6. **DT 區塊頭，長度先填 0**
+        w.write_all(&block_header(b"##DT", 0, &[]))?;
+
+        Ok(Self {
+            w,
+            start_us,
+            cg_addr: cg,
+            dt_addr: dt,
+            count: 0,
+            skipped: 0,
+        })
+    }
+
+    /// 遠端幀與錯誤幀不屬於 CAN_DataFrame，BusChannel 只有一個 byte，這些幀都會被略過
+    pub fn write_frame(&mut self, frame: &CanFrame) -> io::Result<()> {
+        if frame.remote || frame.error || frame.channel >= u8::MAX as u32 {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let mut record = [0u8; RECORD_LEN];
+        let t = frame.timestamp_us.saturating_sub(self.start_us) as f64 / 1e6;
+        record[0..8].copy_from_slice(&t.to_le_bytes());
+        record[8] = (frame.channel + 1) as u8;
+        let mut id = frame.id & 0x1FFF_FFFF;
+        if frame.extended {
+            id |= 0x8000_0000;
+        }
+        record[9..13].copy_from_slice(&id.to_le_bytes());
+        record[13] = frame.len.min(8);
+        record[14] = frame.len.min(8);
+        record[15..23].copy_from_slice(&frame.data);
+        self.w.write_all(&record)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn skipped_count(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let dt_len = HEADER_LEN + self.count * RECORD_LEN as u64;
+        self.w.seek(SeekFrom::Start(self.dt_addr + 8))?;
+        self.w.write_all(&dt_len.to_le_bytes())?;
+        self.w
+            .seek(SeekFrom::Start(self.cg_addr + CG_CYCLE_COUNT))?;
+        self.w.write_all(&self.count.to_le_bytes())?;
+        self.w.seek(SeekFrom::Start(ID_FILE_ID))?;
+        self.w.write_all(b"MDF     ")?;
+        self.w.seek(SeekFrom::Start(ID_UNFIN_FLAGS))?;
+        self.w.write_all(&0u16.to_le_bytes())?;
+        self.w.seek(SeekFrom::End(0))?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+pub fn write_mdf4<'a, W: Write + Seek>(
+    w: W,
+    frames: impl IntoIterator<Item = &'a CanFrame>,
+) -> io::Result<()> {
+    let mut frames = frames.into_iter().peekable();
+    let start_us = frames.peek().map(|f| f.timestamp_us).unwrap_or(0);
+    let mut writer = Mdf4Writer::new(w, start_us)?;
+    for frame in frames {
+        writer.write_frame(frame)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+struct Block {
+    id: [u8; 4],
+    len: u64,
+    links: Vec<u64>,
+    data: Vec<u8>,
+}
+
+fn read_block<R: Read + Seek>(r: &mut R, addr: u64, with_data: bool) -> io::Result<Block> {
+    r.seek(SeekFrom::Start(addr))?;
+    let mut header = [0u8; HEADER_LEN as usize];
+    r.read_exact(&mut header)?;
+    let mut id = [0u8; 4];
+    id.copy_from_slice(&header[0..4]);
+    if &id[0..2] != b"##" {
+        return Err(invalid(format!("MDF4: 位址 0x{:X} 不是有效的區塊", addr)));
+    }
+    let len = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let link_count = u64::from_le_bytes(header[16..24].try_into().unwrap());
+    if link_count > 1 << 20 {
+        return Err(invalid("MDF4: link 數量異常"));
+    }
+    let mut links = vec![0u64; link_count as usize];
+    for link in links.iter_mut() {
+        let mut b = [0u8; 8];
+        r.read_exact(&mut b)?;
+        *link = u64::from_le_bytes(b);
+    }
+    let mut data = Vec::new();
+    if with_data {
+        let data_len = len.saturating_sub(HEADER_LEN + link_count * 8);
+        r.by_ref().take(data_len).read_to_end(&mut data)?;
+    }
+    Ok(Block {
+        id,
+        len,
+        links,
+        data,
+    })
+}
+
+fn read_text<R: Read + Seek>(r: &mut R, addr: u64) -> io::Result<String> {
+    if addr == 0 {
+        return Ok(String::new());
+    }
+    let block = read_block(r, addr, true)?;
+    let end = block
+        .data
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(block.data.len());
+    Ok(String::from_utf8_lossy(&block.data[..end]).into_owned())
+}
+
+#[derive(Clone, Copy, Default)]
+struct Field {
+    byte_offset: usize,
+    bit_offset: u32,
+    bit_count: u32,
+    data_type: u8,
+}
+
+impl Field {
+    fn from_cn(block: &Block) -> io::Result<Self> {
+        let d = &block.data;
+        let field = Self {
+            data_type: d[2],
+            bit_offset: d[3] as u32,
+            byte_offset: u32::from_le_bytes(d[4..8].try_into().unwrap()) as usize,
+            bit_count: u32::from_le_bytes(d[8..12].try_into().unwrap()),
+        };
+        // 位元偏移只會在 0..7，超過代表檔案損壞
+        if field.bit_offset > 7 {
+            return Err(invalid("MDF4: CN 位元偏移超出範圍"));
+        }
+        Ok(field)
+    }
+
+    fn uint(&self, record: &[u8]) -> u64 {
+        let mut b = [0u8; 8];
+        let n = ((self.bit_offset + self.bit_count).div_ceil(8) as usize).min(8);
+        let end = (self.byte_offset + n).min(record.len());
+        if self.byte_offset < end {
+            b[..end - self.byte_offset].copy_from_slice(&record[self.byte_offset..end]);
+        }
+        let v = u64::from_le_bytes(b) >> self.bit_offset;
+        if self.bit_count >= 64 {
+            v
+        } else {
+            v & ((1u64 << self.bit_count) - 1)
+        }
+    }
+
+    fn float(&self, record: &[u8]) -> f64 {
+        match (self.data_type, self.bit_count) {
+            (DT_FLOAT_LE, 64) => f64::from_bits(self.uint(record)),
+            (DT_FLOAT_LE, 32) => f32::from_bits(self.uint(record) as u32) as f64,
+            _ => self.uint(record) as f64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FrameLayout {
+    time: Option<Field>,
+    bus: Option<Field>,
+    id: Option<Field>,
+    ide: Option<Field>,
+    dlc: Option<Field>,
+    data_length: Option<Field>,
+    data_bytes: Option<Field>,
+}
+
+fn read_frame_layout<R: Read + Seek>(r: &mut R, mut cn_addr: u64) -> io::Result<FrameLayout> {
+    let mut layout = FrameLayout::default();
+    while cn_addr != 0 {
+        let cn = read_block(r, cn_addr, true)?;
+        if &cn.id != b"##CN" || cn.links.len() < 8 || cn.data.len() < 12 {
+            return Err(invalid("MDF4: CN 區塊格式錯誤"));
+        }
+        let name = read_text(r, cn.links[2])?;
+        if cn.data[0] == CN_TYPE_MASTER {
+            layout.time = Some(Field::from_cn(&cn)?);
+        } else if name == "CAN_DataFrame" {
+            let mut sub_addr = cn.links[1];
+            while sub_addr != 0 {
+                let sub = read_block(r, sub_addr, true)?;
+                if &sub.id != b"##CN" || sub.links.len() < 8 || sub.data.len() < 12 {
+                    break;
+                }
+                let sub_name = read_text(r, sub.links[2])?;
+                let field = Some(Field::from_cn(&sub)?);
+                match sub_name.rsplit('.').next().unwrap_or("") {
+                    "BusChannel" => layout.bus = field,
+                    "ID" => layout.id = field,
+                    "IDE" => layout.ide = field,
+                    "DLC" => layout.dlc = field,
+                    "DataLength" => layout.data_length = field,
+                    "DataBytes" => layout.data_bytes = field,
+                    _ => {}
+                }
+                sub_addr = sub.links[0];
+            }
+        }
+        cn_addr = cn.links[0];
+    }
+    Ok(layout)
+}
+
+/// 找出 dg_data 指向的資料區段 (DT，或 DL 串起來的多個 DT)
+fn data_sections<R: Read + Seek>(
+    r: &mut R,
+    addr: u64,
+    file_len: u64,
+) -> io::Result<Vec<(u64, u64)>> {
+    collect_sections(r, addr, file_len, &mut HashSet::new())
+}
+
+/// visited 記錄走過的 DL/DT 位址，循環的 link 會回傳錯誤而不是無窮迴圈
+fn collect_sections<R: Read + Seek>(
+    r: &mut R,
+    addr: u64,
+    file_len: u64,
+    visited: &mut HashSet<u64>,
+) -> io::Result<Vec<(u64, u64)>> {
+    let mut sections = Vec::new();
+    if addr == 0 {
+        return Ok(sections);
+    }
+    if !visited.insert(addr) {
+        return Err(invalid(format!("MDF4: 資料區塊 0x{:X} 形成循環", addr)));
+    }
+    let block = read_block(r, addr, false)?;
+    match &block.id {
+        b"##DT" => {
+            let start = addr + HEADER_LEN;
+            // 未完成的檔案 DT 長度可能沒回填，用檔案結尾
+            let len = if block.len <= HEADER_LEN {
+                file_len.saturating_sub(start)
+            } else {
+                block.len - HEADER_LEN
+            };
+            sections.push((start, len));
+        }
+        b"##DL" => {
+            let mut dl = Some(block);
+            while let Some(block) = dl {
+                if block.links.is_empty() {
+                    return Err(invalid("MDF4: DL 區塊格式錯誤"));
+                }
+                for &link in &block.links[1..] {
+                    sections.extend(collect_sections(r, link, file_len, visited)?);
+                }
+                dl = match block.links.first() {
+                    Some(&next) if next != 0 => {
+                        if !visited.insert(next) {
+                            return Err(invalid(format!("MDF4: DL 區塊 0x{:X} 形成循環", next)));
+                        }
+                        Some(read_block(r, next, false)?)
+                    }
+                    _ => None,
+                };
+            }
+        }
+        b"##DZ" => return Err(invalid("MDF4: 不支援壓縮的 DZ 區塊")),
+        id => {
+            return Err(invalid(format!(
+                "MDF4: 不支援的資料區塊 {}",
+                String::from_utf8_lossy(id)
+            )))
+        }
+    }
+    Ok(sections)
+}
+
+/// 讀回 CAN_DataFrame channel group 的所有幀，資料區塊逐筆串流讀取
+pub fn read_mdf4<R: Read + Seek>(r: &mut R) -> io::Result<Vec<CanFrame>> {
+    let file_len = r.seek(SeekFrom::End(0))?;
+    r.seek(SeekFrom::Start(0))?;
+    let mut id = [0u8; ID_LEN as usize];
+    r.read_exact(&mut id)?;
+    if &id[0..8] != b"MDF     " && &id[0..8] != b"UnFinMF " {
+        return Err(invalid("不是 MDF 檔案"));
+    }
+    let version = u16::from_le_bytes([id[28], id[29]]);
+    if version < 400 {
+        return Err(invalid(format!("不支援的 MDF 版本 {}", version)));
+    }
+
+    let hd = read_block(r, ID_LEN, true)?;
+    if &hd.id != b"##HD" || hd.links.is_empty() || hd.data.len() < 8 {
+        return Err(invalid("MDF4: HD 區塊格式錯誤"));
+    }
+    let start_us = u64::from_le_bytes(hd.data[0..8].try_into().unwrap()) / 1000;
+
+    let mut frames = Vec::new();
+    let mut dg_addr = hd.links[0];
+    while dg_addr != 0 {
+        let dg = read_block(r, dg_addr, true)?;
+        if &dg.id != b"##DG" || dg.links.len() < 3 {
+            return Err(invalid("MDF4: DG 區塊格式錯誤"));
+        }
+        let rec_id_size = dg.data.first().copied().unwrap_or(0) as usize;
+        if ![0, 1, 2, 4, 8].contains(&rec_id_size) {
+            return Err(invalid(format!(
+                "MDF4: record id 長度 {} 不正確",
+                rec_id_size
+            )));
+        }
+
+        // 1. **收集此 DG 底下的 channel group：record id -> (紀錄長度, 是否為 CAN_DataFrame)**
+        let mut groups = Vec::new();
+        let mut cg_addr = dg.links[1];
+        while cg_addr != 0 {
+            let cg = read_block(r, cg_addr, true)?;
+            if &cg.id != b"##CG" || cg.links.len() < 6 || cg.data.len() < 32 {
+                return Err(invalid("MDF4: CG 區塊格式錯誤"));
+            }
+            let record_id = u64::from_le_bytes(cg.data[0..8].try_into().unwrap());
+            let flags = u16::from_le_bytes([cg.data[16], cg.data[17]]);
+            let data_bytes = u32::from_le_bytes(cg.data[24..28].try_into().unwrap()) as usize;
+            let inval_bytes = u32::from_le_bytes(cg.data[28..32].try_into().unwrap()) as usize;
+            // 長度 0 的紀錄讀取時不會前進
+            if data_bytes + inval_bytes == 0 {
+                return Err(invalid("MDF4: CG 紀錄長度為 0"));
+            }
+            let name = read_text(r, cg.links[2])?;
+            let layout = if name.starts_with("CAN_DataFrame") || flags & 0x02 != 0 {
+                Some(read_frame_layout(r, cg.links[1])?)
+            } else {
+                None
+            };
+            groups.push((record_id, data_bytes + inval_bytes, layout));
+            cg_addr = cg.links[0];
+        }
+
+        // 2. **逐筆讀取紀錄**
+        let mut record = Vec::new();
+        for (start, len) in data_sections(r, dg.links[2], file_len)? {
+            r.seek(SeekFrom::Start(start))?;
+            let mut remaining = len;
+            while remaining > rec_id_size as u64 {
+                let mut rid = [0u8; 8];
+                r.read_exact(&mut rid[..rec_id_size])?;
+                let rid = u64::from_le_bytes(rid);
+                let group = if rec_id_size == 0 {
+                    groups.first()
+                } else {
+                    groups.iter().find(|g| g.0 == rid)
+                };
+                let Some((_, size, layout)) = group else {
+                    return Err(invalid(format!("MDF4: 未知的 record id {}", rid)));
+                };
+                if remaining < (rec_id_size + size) as u64 {
+                    break;
+                }
+                record.resize(*size, 0);
+                r.read_exact(&mut record)?;
+                remaining -= (rec_id_size + size) as u64;
+
+                if let Some(layout) = layout {
+                    if let Some(frame) = decode_record(layout, &record, start_us) {
+                        frames.push(frame);
+                    }
+                }
+            }
+        }
+
+        dg_addr = dg.links[0];
+    }
+
+    Ok(frames)
+}
+
+fn decode_record(layout: &FrameLayout, record: &[u8], start_us: u64) -> Option<CanFrame> {
+    let id_field = layout.id?;
+    let mut frame = CanFrame {
+        id: id_field.uint(record) as u32,
+        ..CanFrame::default()
+    };
+    if let Some(time) = layout.time {
+        frame.timestamp_us = start_us + (time.float(record) * 1e6).round().max(0.0) as u64;
+    }
+    if let Some(bus) = layout.bus {
+        frame.channel = (bus.uint(record) as u32).saturating_sub(1);
+    }
+    frame.extended = match layout.ide {
+        Some(ide) => ide.uint(record) != 0,
+        None => frame.id > 0x7FF,
+    };
+    let len = layout
+        .data_length
+        .or(layout.dlc)
+        .map(|f| f.uint(record) as u8)
+        .unwrap_or(8)
+        .min(8);
+    frame.len = len;
+    if let Some(bytes) = layout.data_bytes {
+        let start = bytes.byte_offset.min(record.len());
+        let end = (start + len as usize).min(record.len());
+        frame.data[..end - start].copy_from_slice(&record[start..end]);
+    }
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let frames: Vec<CanFrame> = (0..10u32)
+            .map(|i| CanFrame {
+                timestamp_us: 1_700_000_000_000_000 + i as u64 * 2500,
+                channel: i % 3,
+                id: if i % 2 == 0 {
+                    0x100 + i
+                } else {
+                    0x18FE_F100 + i
+                },
+                extended: i % 2 == 1,
+                len: (i % 9) as u8,
+                data: {
+                    let mut data = [0u8; 8];
+                    for (j, b) in data.iter_mut().take((i % 9) as usize).enumerate() {
+                        *b = (i * 16 + j as u32) as u8;
+                    }
+                    data
+                },
+                ..Default::default()
+            })
+            .collect();
+        let mut file = Cursor::new(Vec::new());
+        write_mdf4(&mut file, &frames).unwrap();
+        let read = read_mdf4(&mut file).unwrap();
+        assert_eq!(read, frames);
+    }
+
+    #[test]
+    fn skips_frames_that_do_not_fit() {
+        let frames = [
+            CanFrame {
+                remote: true,
+                ..Default::default()
+            },
+            CanFrame {
+                channel: 300,
+                ..Default::default()
+            },
+            CanFrame {
+                id: 0x7FF,
+                len: 1,
+                ..Default::default()
+            },
+        ];
+        let mut writer = Mdf4Writer::new(Cursor::new(Vec::new()), 0).unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        assert_eq!((writer.frame_count(), writer.skipped_count()), (1, 2));
+        let mut file = writer.finish().unwrap();
+        assert_eq!(read_mdf4(&mut file).unwrap(), &frames[2..]);
+    }
+
+    fn sample_file() -> Vec<u8> {
+        let frames = [CanFrame {
+            id: 0x123,
+            len: 2,
+            ..Default::default()
+        }];
+        let mut file = Cursor::new(Vec::new());
+        write_mdf4(&mut file, &frames).unwrap();
+        file.into_inner()
+    }
+
+    fn find_block(bytes: &[u8], id: &[u8; 4]) -> usize {
+        bytes.windows(4).position(|w| w == id).unwrap()
+    }
+
+    #[test]
+    fn rejects_zero_length_records() {
+        let mut bytes = sample_file();
+        let cg = find_block(&bytes, b"##CG");
+        let data_bytes = cg + (HEADER_LEN + 6 * 8 + 24) as usize;
+        bytes[data_bytes..data_bytes + 4].copy_from_slice(&0u32.to_le_bytes());
+        let err = read_mdf4(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_cyclic_data_list() {
+        let mut bytes = sample_file();
+        let dg = find_block(&bytes, b"##DG");
+        let dt = find_block(&bytes, b"##DT") as u64;
+        // 在檔尾加一個 dl_dl_next 指向自己的 DL，並讓 DG 指向它
+        let dl = bytes.len() as u64;
+        let mut block = block_header(b"##DL", HEADER_LEN + 2 * 8 + 16, &[dl, dt]);
+        block.resize((HEADER_LEN + 2 * 8 + 16) as usize, 0);
+        bytes.extend_from_slice(&block);
+        let dg_data = dg + (HEADER_LEN + 2 * 8) as usize;
+        bytes[dg_data..dg_data + 8].copy_from_slice(&dl.to_le_bytes());
+        let err = read_mdf4(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
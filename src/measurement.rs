@@ -0,0 +1,654 @@
+use crate::a2l::CompuMethod;
+use eframe::egui;
+use std::collections::VecDeque;
+
+const HISTORY_LEN: usize = 200;
+
+/// ECU 變數的資料型別
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValueType {
+    pub const ALL: [ValueType; 10] = [
+        ValueType::U8,
+        ValueType::I8,
+        ValueType::U16,
+        ValueType::I16,
+        ValueType::U32,
+        ValueType::I32,
+        ValueType::U64,
+        ValueType::I64,
+        ValueType::F32,
+        ValueType::F64,
+    ];
+
+    pub fn size(self) -> usize {
+        match self {
+            ValueType::U8 | ValueType::I8 => 1,
+            ValueType::U16 | ValueType::I16 => 2,
+            ValueType::U32 | ValueType::I32 | ValueType::F32 => 4,
+            ValueType::U64 | ValueType::I64 | ValueType::F64 => 8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::U8 => "u8",
+            ValueType::I8 => "i8",
+            ValueType::U16 => "u16",
+            ValueType::I16 => "i16",
+            ValueType::U32 => "u32",
+            ValueType::I32 => "i32",
+            ValueType::U64 => "u64",
+            ValueType::I64 => "i64",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8], big_endian: bool) -> f64 {
+        let mut buffer = [0u8; 8];
+        let n = self.size().min(bytes.len());
+        buffer[..n].copy_from_slice(&bytes[..n]);
+        if big_endian {
+            buffer[..n].reverse();
+        }
+        let raw = u64::from_le_bytes(buffer);
+        match self {
+            ValueType::U8 | ValueType::U16 | ValueType::U32 | ValueType::U64 => raw as f64,
+            ValueType::I8 => raw as u8 as i8 as f64,
+            ValueType::I16 => raw as u16 as i16 as f64,
+            ValueType::I32 => raw as u32 as i32 as f64,
+            ValueType::I64 => raw as i64 as f64,
+            ValueType::F32 => f32::from_bits(raw as u32) as f64,
+            ValueType::F64 => f64::from_bits(raw),
+        }
+    }
+
+    /// 整數型別四捨五入並限制在範圍內
+    pub fn encode(self, value: f64, big_endian: bool) -> Vec<u8> {
+        let raw: u64 = match self {
+            ValueType::U8 => value.round().clamp(0.0, u8::MAX as f64) as u64,
+            ValueType::U16 => value.round().clamp(0.0, u16::MAX as f64) as u64,
+            ValueType::U32 => value.round().clamp(0.0, u32::MAX as f64) as u64,
+            ValueType::U64 => value.round().max(0.0) as u64,
+            ValueType::I8 => value.round().clamp(i8::MIN as f64, i8::MAX as f64) as i64 as u64,
+            ValueType::I16 => value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i64 as u64,
+            ValueType::I32 => value.round().clamp(i32::MIN as f64, i32::MAX as f64) as i64 as u64,
+            ValueType::I64 => value.round() as i64 as u64,
+            ValueType::F32 => (value as f32).to_bits() as u64,
+            ValueType::F64 => value.to_bits(),
+        };
+        let mut bytes = raw.to_le_bytes()[..self.size()].to_vec();
+        if big_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+}
+
+/// 要放進 DAQ 的變數 (呼叫端的鍵, 位址, 位址延伸, 長度)
+#[derive(Debug, Clone, Copy)]
+pub struct DaqVariable {
+    pub key: usize,
+    pub address: u32,
+    pub extension: u8,
+    pub size: usize,
+}
+
+/// DAQ 收到的一個變數值
+#[derive(Debug, Clone)]
+pub struct DaqSample {
+    pub key: usize,
+    pub raw: Vec<u8>,
+    // 從站時間戳記 (沒有時為 None) 與主機收到的時間
+    pub slave_time_us: Option<u64>,
+    pub host_time_us: u64,
+}
+
+/// 依位址量測或標定的變數
+pub struct Variable {
+    // 加入變數表時配發的固定編號，移除其他列也不會變，作為讀取與 DAQ 的鍵
+    pub key: usize,
+    pub name: String,
+    pub address: u32,
+    pub extension: u8,
+    pub value_type: ValueType,
+    pub daq: bool,
+    pub value: Option<f64>,
+    pub updated_us: u64,
+    pub sample_count: u64,
+    // A2L 的 COMPU_METHOD；沒有時直接顯示原始值
+    pub conversion: Option<CompuMethod>,
+    pub edit_text: String,
+}
+
+impl Variable {
+    pub fn new(name: String, address: u32, extension: u8, value_type: ValueType) -> Self {
+        Self {
+            key: 0,
+            name,
+            address,
+            extension,
+            value_type,
+            daq: true,
+            value: None,
+            updated_us: 0,
+            sample_count: 0,
+            conversion: None,
+            edit_text: String::new(),
+        }
+    }
+
+    pub fn format(&self, raw: f64) -> String {
+        match &self.conversion {
+            Some(conversion) => conversion.format(raw),
+            None => raw.to_string(),
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        match &self.conversion {
+            Some(conversion) => conversion.parse(text),
+            None => text.trim().parse().ok(),
+        }
+    }
+}
+
+/// 一組命令的結果；讀取成功時是讀到的資料，其餘是最後一個回應
+#[derive(Debug, Clone)]
+pub struct CommandResult<T> {
+    pub tag: T,
+    pub result: Result<Vec<u8>, String>,
+}
+
+pub struct Queued<T> {
+    pub command: Vec<u8>,
+    pub tag: T,
+    group: u64,
+    // 一組命令的最後一個，完成時才回報
+    pub last: bool,
+}
+
+struct Pending<T> {
+    queued: Queued<T>,
+    counter: u8,
+    deadline_us: u64,
+}
+
+/// 主站的命令佇列：一次只送一個命令，其餘排隊
+///
+/// 命令成組排入，同一組的任何一個失敗或逾時，剩下的就不送了。
+pub struct CommandQueue<T> {
+    queue: VecDeque<Queued<T>>,
+    pending: Option<Pending<T>>,
+    counter: u8,
+    next_group: u64,
+    // 分段讀取累積的資料
+    pub accumulated: Vec<u8>,
+}
+
+impl<T> Default for CommandQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            pending: None,
+            counter: 0,
+            next_group: 0,
+            accumulated: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy> CommandQueue<T> {
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.queue.is_empty()
+    }
+
+    /// 排入一組命令，只有最後一個完成時回報
+    pub fn enqueue(&mut self, commands: Vec<Vec<u8>>, tag: T) {
+        let group = self.next_group;
+        self.next_group += 1;
+        let count = commands.len();
+        for (i, command) in commands.into_iter().enumerate() {
+            self.queue.push_back(Queued {
+                command,
+                tag,
+                group,
+                last: i + 1 == count,
+            });
+        }
+    }
+
+    /// 等待回應中的命令計數器
+    pub fn pending_counter(&self) -> Option<u8> {
+        self.pending.as_ref().map(|p| p.counter)
+    }
+
+    /// 收到回應時取出等待中的命令
+    pub fn complete(&mut self) -> Option<Queued<T>> {
+        self.pending.take().map(|p| p.queued)
+    }
+
+    /// 命令失敗：同一組剩下的命令不送了
+    pub fn abort(&mut self, queued: &Queued<T>) {
+        self.queue.retain(|q| q.group != queued.group);
+        self.accumulated.clear();
+    }
+
+    /// 每個畫面呼叫一次：逾時的命令回報錯誤，閒置時取出下一個命令與它的計數器
+    pub fn poll(
+        &mut self,
+        now_us: u64,
+        timeout_ms: u64,
+        completed: &mut VecDeque<CommandResult<T>>,
+    ) -> Option<(&[u8], u8)> {
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|p| now_us > p.deadline_us)
+        {
+            let queued = self.complete().unwrap();
+            self.abort(&queued);
+            completed.push_back(CommandResult {
+                tag: queued.tag,
+                result: Err(format!("命令 0x{:02X} 逾時", queued.command[0])),
+            });
+        }
+        if self.pending.is_some() {
+            return None;
+        }
+        let queued = self.queue.pop_front()?;
+        self.counter = self.counter.wrapping_add(1);
+        let pending = self.pending.insert(Pending {
+            queued,
+            counter: self.counter,
+            deadline_us: now_us + timeout_ms * 1000,
+        });
+        Some((&pending.queued.command, pending.counter))
+    }
+}
+
+/// ODT 中的一個元素：變數的一段
+#[derive(Debug, Clone, Copy)]
+pub struct OdtEntry {
+    pub variable: usize,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// DAQ 清單的 ODT 配置，把收到的 ODT 重組回變數
+pub struct OdtLayout {
+    variables: Vec<DaqVariable>,
+    odts: Vec<Vec<OdtEntry>>,
+    // 目前週期的緩衝區與下一個期待的 ODT
+    buffers: Vec<Vec<u8>>,
+    next_odt: usize,
+}
+
+impl OdtLayout {
+    pub fn new(variables: Vec<DaqVariable>, odts: Vec<Vec<OdtEntry>>) -> Self {
+        Self {
+            buffers: variables.iter().map(|v| vec![0; v.size]).collect(),
+            variables,
+            odts,
+            next_odt: 0,
+        }
+    }
+
+    pub fn odt_count(&self) -> usize {
+        self.odts.len()
+    }
+
+    /// 放入一個 ODT 的資料 (不含 PID 與時間戳記)；最後一個 ODT 到齊時每個變數產生一個樣本
+    pub fn feed(
+        &mut self,
+        odt: usize,
+        mut payload: &[u8],
+        slave_time_us: Option<u64>,
+        host_time_us: u64,
+        samples: &mut VecDeque<DaqSample>,
+    ) {
+        let Some(entries) = self.odts.get(odt) else {
+            return;
+        };
+        if odt == 0 {
+            self.next_odt = 0;
+        }
+        // 漏掉中間的 ODT 就丟掉這個週期
+        if odt != self.next_odt {
+            return;
+        }
+        for entry in entries {
+            let Some(bytes) = payload.get(..entry.size) else {
+                return;
+            };
+            self.buffers[entry.variable][entry.offset..entry.offset + entry.size]
+                .copy_from_slice(bytes);
+            payload = &payload[entry.size..];
+        }
+        self.next_odt = odt + 1;
+        if self.next_odt == self.odts.len() {
+            for (variable, buffer) in self.variables.iter().zip(&self.buffers) {
+                samples.push_back(DaqSample {
+                    key: variable.key,
+                    raw: buffer.clone(),
+                    slave_time_us,
+                    host_time_us,
+                });
+            }
+        }
+    }
+}
+
+/// 變數表要主站做的存取，鍵是變數的固定編號
+pub enum Access {
+    Read {
+        key: usize,
+        address: u32,
+        extension: u8,
+        size: usize,
+    },
+    Write {
+        key: usize,
+        address: u32,
+        extension: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Access {
+    fn read(variable: &Variable) -> Self {
+        Access::Read {
+            key: variable.key,
+            address: variable.address,
+            extension: variable.extension,
+            size: variable.value_type.size(),
+        }
+    }
+}
+
+/// XCP 與 CCP 面板共用的變數表、輪詢與命令紀錄
+pub struct VariableTable {
+    pub variables: Vec<Variable>,
+    pub polling: bool,
+    next_key: usize,
+    poll_interval_ms: u64,
+    next_poll_us: u64,
+    new_name: String,
+    new_address: u32,
+    new_extension: u8,
+    new_type: ValueType,
+    history: VecDeque<String>,
+}
+
+impl Default for VariableTable {
+    fn default() -> Self {
+        Self {
+            variables: Vec::new(),
+            polling: false,
+            next_key: 0,
+            poll_interval_ms: 200,
+            next_poll_us: 0,
+            new_name: String::new(),
+            new_address: 0,
+            new_extension: 0,
+            new_type: ValueType::U16,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl VariableTable {
+    pub fn report(&mut self, line: String) {
+        self.history.push_front(line);
+        self.history.truncate(HISTORY_LEN);
+    }
+
+    /// 加入變數並配發編號
+    pub fn add(&mut self, mut variable: Variable) {
+        variable.key = self.next_key;
+        self.next_key += 1;
+        self.variables.push(variable);
+    }
+
+    fn get_mut(&mut self, key: usize) -> Option<&mut Variable> {
+        self.variables.iter_mut().find(|v| v.key == key)
+    }
+
+    /// 讀取完成；變數已被移除時忽略
+    pub fn set_value(&mut self, key: usize, data: &[u8], big_endian: bool, now_us: u64) {
+        if let Some(variable) = self.get_mut(key) {
+            variable.value = Some(variable.value_type.decode(data, big_endian));
+            variable.updated_us = now_us;
+        }
+    }
+
+    /// DAQ 樣本：有從站時間戳記就用它
+    pub fn apply_samples(&mut self, samples: &mut VecDeque<DaqSample>, big_endian: bool) {
+        while let Some(sample) = samples.pop_front() {
+            if let Some(variable) = self.get_mut(sample.key) {
+                variable.value = Some(variable.value_type.decode(&sample.raw, big_endian));
+                variable.updated_us = sample.slave_time_us.unwrap_or(sample.host_time_us);
+                variable.sample_count += 1;
+            }
+        }
+    }
+
+    /// 輪詢時間到且主站閒置時讀取所有變數
+    pub fn poll(&mut self, now_us: u64, idle: bool) -> Vec<Access> {
+        if !self.polling || !idle || now_us < self.next_poll_us {
+            return Vec::new();
+        }
+        self.next_poll_us = now_us + self.poll_interval_ms * 1000;
+        self.variables.iter().map(Access::read).collect()
+    }
+
+    /// 勾選 DAQ 的變數；有變數時停止輪詢並把樣本數歸零
+    pub fn daq_variables(&mut self) -> Vec<DaqVariable> {
+        let variables: Vec<DaqVariable> = self
+            .variables
+            .iter()
+            .filter(|v| v.daq)
+            .map(|v| DaqVariable {
+                key: v.key,
+                address: v.address,
+                extension: v.extension,
+                size: v.value_type.size(),
+            })
+            .collect();
+        if !variables.is_empty() {
+            self.polling = false;
+            for variable in &mut self.variables {
+                variable.sample_count = 0;
+            }
+        }
+        variables
+    }
+
+    /// 新增列與變數表；寫入後會接著讀回確認
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        id: &str,
+        daq_running: bool,
+        can_access: bool,
+        big_endian: bool,
+    ) -> Vec<Access> {
+        // 1. **新增變數**
+        ui.horizontal(|ui| {
+            ui.label("名稱:");
+            ui.add(egui::TextEdit::singleline(&mut self.new_name).desired_width(100.0));
+            ui.label("位址:");
+            ui.add(egui::DragValue::new(&mut self.new_address).hexadecimal(8, false, true));
+            ui.label("延伸:");
+            ui.add(egui::DragValue::new(&mut self.new_extension));
+            egui::ComboBox::from_id_salt(format!("{}_new_type", id))
+                .selected_text(self.new_type.name())
+                .show_ui(ui, |ui| {
+                    for value_type in ValueType::ALL {
+                        ui.selectable_value(&mut self.new_type, value_type, value_type.name());
+                    }
+                });
+            if ui
+                .add_enabled(!daq_running, egui::Button::new("新增"))
+                .clicked()
+            {
+                let name = if self.new_name.trim().is_empty() {
+                    format!("0x{:08X}", self.new_address)
+                } else {
+                    self.new_name.trim().to_string()
+                };
+                self.add(Variable::new(
+                    name,
+                    self.new_address,
+                    self.new_extension,
+                    self.new_type,
+                ));
+            }
+        });
+
+        // 2. **變數表**
+        let mut accesses = Vec::new();
+        let mut remove = None;
+        egui::Grid::new(format!("{}_variables", id))
+            .striped(true)
+            .num_columns(7)
+            .show(ui, |ui| {
+                for header in ["DAQ", "名稱", "位址", "型別", "數值", "樣本", "標定"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (index, variable) in self.variables.iter_mut().enumerate() {
+                    ui.add_enabled(
+                        !daq_running,
+                        egui::Checkbox::without_text(&mut variable.daq),
+                    );
+                    ui.label(&variable.name);
+                    ui.monospace(format!("{:X}:{:08X}", variable.extension, variable.address));
+                    ui.label(variable.value_type.name());
+                    ui.label(
+                        variable
+                            .value
+                            .map_or("-".to_string(), |v| variable.format(v)),
+                    );
+                    ui.label(variable.sample_count.to_string());
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut variable.edit_text).desired_width(80.0),
+                        );
+                        if ui
+                            .add_enabled(can_access, egui::Button::new("寫入"))
+                            .clicked()
+                        {
+                            match variable.parse(&variable.edit_text) {
+                                Some(value) => {
+                                    accesses.push(Access::Write {
+                                        key: variable.key,
+                                        address: variable.address,
+                                        extension: variable.extension,
+                                        data: variable.value_type.encode(value, big_endian),
+                                    });
+                                    // 寫完讀回確認
+                                    accesses.push(Access::read(variable));
+                                }
+                                None => self.history.push_front(format!(
+                                    "{}: 無效的數值 {}",
+                                    variable.name, variable.edit_text
+                                )),
+                            }
+                        }
+                        if ui
+                            .add_enabled(can_access, egui::Button::new("讀取"))
+                            .clicked()
+                        {
+                            accesses.push(Access::read(variable));
+                        }
+                        if ui
+                            .add_enabled(!daq_running, egui::Button::new("移除"))
+                            .clicked()
+                        {
+                            remove = Some(index);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(index) = remove {
+            self.variables.remove(index);
+        }
+        accesses
+    }
+
+    /// 輪詢開關與間隔
+    pub fn polling_ui(&mut self, ui: &mut egui::Ui, label: &str) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.polling, label);
+            ui.add(
+                egui::DragValue::new(&mut self.poll_interval_ms)
+                    .range(10..=10_000)
+                    .suffix(" ms"),
+            );
+        });
+    }
+
+    pub fn history_ui(&self, ui: &mut egui::Ui, id: &str) {
+        egui::ScrollArea::vertical()
+            .id_salt(format!("{}_history", id))
+            .max_height(120.0)
+            .show(ui, |ui| {
+                for line in &self.history {
+                    ui.monospace(line);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_survive_removing_rows() {
+        let mut table = VariableTable::default();
+        for (name, address) in [("a", 0x1000), ("b", 0x1002), ("c", 0x1004)] {
+            table.add(Variable::new(name.to_string(), address, 0, ValueType::U16));
+        }
+        table.polling = true;
+        let reads: Vec<usize> = table
+            .poll(0, true)
+            .iter()
+            .map(|access| match access {
+                Access::Read { key, .. } => *key,
+                Access::Write { key, .. } => *key,
+            })
+            .collect();
+        assert_eq!(reads, [0, 1, 2]);
+
+        // 讀取還在路上時移除第一列，結果仍要落在原本的變數
+        table.variables.remove(0);
+        table.set_value(2, &[0x12, 0x34], true, 5);
+        table.set_value(0, &[0xFF, 0xFF], true, 5);
+        let values: Vec<_> = table
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.value))
+            .collect();
+        assert_eq!(values, [("b", None), ("c", Some(0x1234 as f64))]);
+
+        table.add(Variable::new("d".to_string(), 0x1006, 0, ValueType::U8));
+        let keys: Vec<_> = table.daq_variables().iter().map(|v| v.key).collect();
+        assert_eq!(keys, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,1030 @@
+use crate::canbus::CanFrame;
+use crate::j1939::{J1939Id, Reassembler};
+use eframe::egui;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// fast-packet 兩幀之間超過 750 ms 視為中斷
+const FAST_PACKET_TIMEOUT_US: u64 = 750_000;
+// 第一幀 6 bytes，之後每幀 7 bytes，框架計數 5 位元
+const MAX_FAST_PACKET_SIZE: usize = 6 + 31 * 7;
+
+/// 內建的 PGN 定義 (canboat JSON 格式的子集)：位置、航向、引擎與 AIS
+const BUILTIN_DEFINITIONS: &str = r#"{
+"LookupEnumerations": [
+ {"Name": "DIRECTION_REFERENCE", "EnumValues": [{"Name": "True", "Value": 0}, {"Name": "Magnetic", "Value": 1}, {"Name": "Error", "Value": 2}]},
+ {"Name": "ENGINE_INSTANCE", "EnumValues": [{"Name": "Single Engine or Dual Engine Port", "Value": 0}, {"Name": "Dual Engine Starboard", "Value": 1}]},
+ {"Name": "GNS", "EnumValues": [{"Name": "GPS", "Value": 0}, {"Name": "GLONASS", "Value": 1}, {"Name": "GPS+GLONASS", "Value": 2}, {"Name": "GPS+SBAS/WAAS", "Value": 3}, {"Name": "GPS+SBAS/WAAS+GLONASS", "Value": 4}, {"Name": "Chayka", "Value": 5}, {"Name": "integrated", "Value": 6}, {"Name": "surveyed", "Value": 7}, {"Name": "Galileo", "Value": 8}]},
+ {"Name": "GNS_METHOD", "EnumValues": [{"Name": "no GNSS", "Value": 0}, {"Name": "GNSS fix", "Value": 1}, {"Name": "DGNSS fix", "Value": 2}, {"Name": "Precise GNSS", "Value": 3}, {"Name": "RTK Fixed Integer", "Value": 4}, {"Name": "RTK float", "Value": 5}, {"Name": "Estimated (DR) mode", "Value": 6}, {"Name": "Manual Input", "Value": 7}, {"Name": "Simulate mode", "Value": 8}]},
+ {"Name": "GNS_INTEGRITY", "EnumValues": [{"Name": "No integrity checking", "Value": 0}, {"Name": "Safe", "Value": 1}, {"Name": "Caution", "Value": 2}]},
+ {"Name": "REPEAT_INDICATOR", "EnumValues": [{"Name": "Initial", "Value": 0}, {"Name": "First retransmission", "Value": 1}, {"Name": "Second retransmission", "Value": 2}, {"Name": "Final retransmission", "Value": 3}]},
+ {"Name": "POSITION_ACCURACY", "EnumValues": [{"Name": "Low", "Value": 0}, {"Name": "High", "Value": 1}]},
+ {"Name": "RAIM_FLAG", "EnumValues": [{"Name": "not in use", "Value": 0}, {"Name": "in use", "Value": 1}]},
+ {"Name": "AIS_TRANSCEIVER", "EnumValues": [{"Name": "Channel A VDL reception", "Value": 0}, {"Name": "Channel B VDL reception", "Value": 1}, {"Name": "Channel A VDL transmission", "Value": 2}, {"Name": "Channel B VDL transmission", "Value": 3}, {"Name": "Own information not broadcast", "Value": 4}]},
+ {"Name": "NAV_STATUS", "EnumValues": [{"Name": "Under way using engine", "Value": 0}, {"Name": "At anchor", "Value": 1}, {"Name": "Not under command", "Value": 2}, {"Name": "Restricted manoeuverability", "Value": 3}, {"Name": "Constrained by her draught", "Value": 4}, {"Name": "Moored", "Value": 5}, {"Name": "Aground", "Value": 6}, {"Name": "Engaged in Fishing", "Value": 7}, {"Name": "Under way sailing", "Value": 8}, {"Name": "AIS-SART", "Value": 14}, {"Name": "Undefined", "Value": 15}]},
+ {"Name": "SHIP_TYPE", "EnumValues": [{"Name": "Fishing", "Value": 30}, {"Name": "Towing", "Value": 31}, {"Name": "Sailing", "Value": 36}, {"Name": "Pleasure", "Value": 37}, {"Name": "Pilot vessel", "Value": 50}, {"Name": "SAR", "Value": 51}, {"Name": "Tug", "Value": 52}, {"Name": "Passenger ship", "Value": 60}, {"Name": "Cargo ship", "Value": 70}, {"Name": "Tanker", "Value": 80}]},
+ {"Name": "AIS_VERSION", "EnumValues": [{"Name": "ITU-R M.1371-1", "Value": 0}, {"Name": "ITU-R M.1371-3", "Value": 1}]}
+],
+"PGNs": [
+ {"PGN": 127250, "Id": "vesselHeading", "Description": "Vessel Heading", "Type": "Single", "Fields": [
+  {"Id": "sid", "Name": "SID", "BitLength": 8, "FieldType": "NUMBER"},
+  {"Id": "heading", "Name": "Heading", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "deviation", "Name": "Deviation", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Signed": true, "Unit": "rad"},
+  {"Id": "variation", "Name": "Variation", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Signed": true, "Unit": "rad"},
+  {"Id": "reference", "Name": "Reference", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "DIRECTION_REFERENCE"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 6, "FieldType": "RESERVED"}]},
+ {"PGN": 127488, "Id": "engineParametersRapidUpdate", "Description": "Engine Parameters, Rapid Update", "Type": "Single", "Fields": [
+  {"Id": "instance", "Name": "Instance", "BitLength": 8, "FieldType": "LOOKUP", "LookupEnumeration": "ENGINE_INSTANCE"},
+  {"Id": "speed", "Name": "Speed", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.25, "Unit": "rpm"},
+  {"Id": "boostPressure", "Name": "Boost Pressure", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 100, "Unit": "Pa"},
+  {"Id": "tiltTrim", "Name": "Tilt/Trim", "BitLength": 8, "FieldType": "NUMBER", "Signed": true, "Unit": "%"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 16, "FieldType": "RESERVED"}]},
+ {"PGN": 127489, "Id": "engineParametersDynamic", "Description": "Engine Parameters, Dynamic", "Type": "Fast", "Fields": [
+  {"Id": "instance", "Name": "Instance", "BitLength": 8, "FieldType": "LOOKUP", "LookupEnumeration": "ENGINE_INSTANCE"},
+  {"Id": "oilPressure", "Name": "Oil pressure", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 100, "Unit": "Pa"},
+  {"Id": "oilTemperature", "Name": "Oil temperature", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Unit": "K"},
+  {"Id": "temperature", "Name": "Temperature", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Unit": "K"},
+  {"Id": "alternatorPotential", "Name": "Alternator Potential", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Signed": true, "Unit": "V"},
+  {"Id": "fuelRate", "Name": "Fuel Rate", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Signed": true, "Unit": "L/h"},
+  {"Id": "totalEngineHours", "Name": "Total Engine hours", "BitLength": 32, "FieldType": "DURATION", "Unit": "s"},
+  {"Id": "coolantPressure", "Name": "Coolant Pressure", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 100, "Unit": "Pa"},
+  {"Id": "fuelPressure", "Name": "Fuel Pressure", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 1000, "Unit": "Pa"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 8, "FieldType": "RESERVED"},
+  {"Id": "discreteStatus1", "Name": "Discrete Status 1", "BitLength": 16, "FieldType": "BITLOOKUP"},
+  {"Id": "discreteStatus2", "Name": "Discrete Status 2", "BitLength": 16, "FieldType": "BITLOOKUP"},
+  {"Id": "engineLoad", "Name": "Engine Load", "BitLength": 8, "FieldType": "NUMBER", "Signed": true, "Unit": "%"},
+  {"Id": "engineTorque", "Name": "Engine Torque", "BitLength": 8, "FieldType": "NUMBER", "Signed": true, "Unit": "%"}]},
+ {"PGN": 129025, "Id": "positionRapidUpdate", "Description": "Position, Rapid Update", "Type": "Single", "Fields": [
+  {"Id": "latitude", "Name": "Latitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"},
+  {"Id": "longitude", "Name": "Longitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"}]},
+ {"PGN": 129026, "Id": "cogSogRapidUpdate", "Description": "COG & SOG, Rapid Update", "Type": "Single", "Fields": [
+  {"Id": "sid", "Name": "SID", "BitLength": 8, "FieldType": "NUMBER"},
+  {"Id": "cogReference", "Name": "COG Reference", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "DIRECTION_REFERENCE"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 6, "FieldType": "RESERVED"},
+  {"Id": "cog", "Name": "COG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "sog", "Name": "SOG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Unit": "m/s"},
+  {"Id": "reserved6", "Name": "Reserved", "BitLength": 16, "FieldType": "RESERVED"}]},
+ {"PGN": 129029, "Id": "gnssPositionData", "Description": "GNSS Position Data", "Type": "Fast",
+  "RepeatingFieldSet1Size": 3, "RepeatingFieldSet1StartField": 16, "RepeatingFieldSet1CountField": 15, "Fields": [
+  {"Order": 1, "Id": "sid", "Name": "SID", "BitLength": 8, "FieldType": "NUMBER"},
+  {"Order": 2, "Id": "date", "Name": "Date", "BitLength": 16, "FieldType": "DATE", "Unit": "d"},
+  {"Order": 3, "Id": "time", "Name": "Time", "BitLength": 32, "FieldType": "TIME", "Resolution": 0.0001, "Unit": "s"},
+  {"Order": 4, "Id": "latitude", "Name": "Latitude", "BitLength": 64, "FieldType": "NUMBER", "Resolution": 1e-16, "Signed": true, "Unit": "deg"},
+  {"Order": 5, "Id": "longitude", "Name": "Longitude", "BitLength": 64, "FieldType": "NUMBER", "Resolution": 1e-16, "Signed": true, "Unit": "deg"},
+  {"Order": 6, "Id": "altitude", "Name": "Altitude", "BitLength": 64, "FieldType": "NUMBER", "Resolution": 1e-6, "Signed": true, "Unit": "m"},
+  {"Order": 7, "Id": "gnssType", "Name": "GNSS type", "BitLength": 4, "FieldType": "LOOKUP", "LookupEnumeration": "GNS"},
+  {"Order": 8, "Id": "method", "Name": "Method", "BitLength": 4, "FieldType": "LOOKUP", "LookupEnumeration": "GNS_METHOD"},
+  {"Order": 9, "Id": "integrity", "Name": "Integrity", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "GNS_INTEGRITY"},
+  {"Order": 10, "Id": "reserved", "Name": "Reserved", "BitLength": 6, "FieldType": "RESERVED"},
+  {"Order": 11, "Id": "numberOfSvs", "Name": "Number of SVs", "BitLength": 8, "FieldType": "NUMBER"},
+  {"Order": 12, "Id": "hdop", "Name": "HDOP", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Signed": true},
+  {"Order": 13, "Id": "pdop", "Name": "PDOP", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Signed": true},
+  {"Order": 14, "Id": "geoidalSeparation", "Name": "Geoidal Separation", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 0.01, "Signed": true, "Unit": "m"},
+  {"Order": 15, "Id": "referenceStations", "Name": "Reference Stations", "BitLength": 8, "FieldType": "NUMBER"},
+  {"Order": 16, "Id": "referenceStationType", "Name": "Reference Station Type", "BitLength": 4, "FieldType": "LOOKUP", "LookupEnumeration": "GNS"},
+  {"Order": 17, "Id": "referenceStationId", "Name": "Reference Station ID", "BitLength": 12, "FieldType": "NUMBER"},
+  {"Order": 18, "Id": "ageOfDgnssCorrections", "Name": "Age of DGNSS Corrections", "BitLength": 16, "FieldType": "DURATION", "Resolution": 0.01, "Unit": "s"}]},
+ {"PGN": 129038, "Id": "aisClassAPositionReport", "Description": "AIS Class A Position Report", "Type": "Fast", "Fields": [
+  {"Id": "messageId", "Name": "Message ID", "BitLength": 6, "FieldType": "NUMBER"},
+  {"Id": "repeatIndicator", "Name": "Repeat Indicator", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "REPEAT_INDICATOR"},
+  {"Id": "userId", "Name": "User ID", "BitLength": 32, "FieldType": "MMSI"},
+  {"Id": "longitude", "Name": "Longitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"},
+  {"Id": "latitude", "Name": "Latitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"},
+  {"Id": "positionAccuracy", "Name": "Position Accuracy", "BitLength": 1, "FieldType": "LOOKUP", "LookupEnumeration": "POSITION_ACCURACY"},
+  {"Id": "raim", "Name": "RAIM", "BitLength": 1, "FieldType": "LOOKUP", "LookupEnumeration": "RAIM_FLAG"},
+  {"Id": "timeStamp", "Name": "Time Stamp", "BitLength": 6, "FieldType": "NUMBER"},
+  {"Id": "cog", "Name": "COG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "sog", "Name": "SOG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Unit": "m/s"},
+  {"Id": "communicationState", "Name": "Communication State", "BitLength": 19, "FieldType": "BINARY"},
+  {"Id": "aisTransceiverInformation", "Name": "AIS Transceiver information", "BitLength": 5, "FieldType": "LOOKUP", "LookupEnumeration": "AIS_TRANSCEIVER"},
+  {"Id": "heading", "Name": "Heading", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "rateOfTurn", "Name": "Rate of Turn", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 3.125e-05, "Signed": true, "Unit": "rad/s"},
+  {"Id": "navStatus", "Name": "Nav Status", "BitLength": 4, "FieldType": "LOOKUP", "LookupEnumeration": "NAV_STATUS"},
+  {"Id": "specialManeuverIndicator", "Name": "Special Maneuver Indicator", "BitLength": 2, "FieldType": "NUMBER"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 2, "FieldType": "RESERVED"},
+  {"Id": "aisSpare", "Name": "AIS Spare", "BitLength": 3, "FieldType": "SPARE"},
+  {"Id": "reserved19", "Name": "Reserved", "BitLength": 5, "FieldType": "RESERVED"},
+  {"Id": "sequenceId", "Name": "Sequence ID", "BitLength": 8, "FieldType": "NUMBER"}]},
+ {"PGN": 129039, "Id": "aisClassBPositionReport", "Description": "AIS Class B Position Report", "Type": "Fast", "Fields": [
+  {"Id": "messageId", "Name": "Message ID", "BitLength": 6, "FieldType": "NUMBER"},
+  {"Id": "repeatIndicator", "Name": "Repeat Indicator", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "REPEAT_INDICATOR"},
+  {"Id": "userId", "Name": "User ID", "BitLength": 32, "FieldType": "MMSI"},
+  {"Id": "longitude", "Name": "Longitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"},
+  {"Id": "latitude", "Name": "Latitude", "BitLength": 32, "FieldType": "NUMBER", "Resolution": 1e-7, "Signed": true, "Unit": "deg"},
+  {"Id": "positionAccuracy", "Name": "Position Accuracy", "BitLength": 1, "FieldType": "LOOKUP", "LookupEnumeration": "POSITION_ACCURACY"},
+  {"Id": "raim", "Name": "RAIM", "BitLength": 1, "FieldType": "LOOKUP", "LookupEnumeration": "RAIM_FLAG"},
+  {"Id": "timeStamp", "Name": "Time Stamp", "BitLength": 6, "FieldType": "NUMBER"},
+  {"Id": "cog", "Name": "COG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "sog", "Name": "SOG", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Unit": "m/s"},
+  {"Id": "communicationState", "Name": "Communication State", "BitLength": 19, "FieldType": "BINARY"},
+  {"Id": "aisTransceiverInformation", "Name": "AIS Transceiver information", "BitLength": 5, "FieldType": "LOOKUP", "LookupEnumeration": "AIS_TRANSCEIVER"},
+  {"Id": "heading", "Name": "Heading", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.0001, "Unit": "rad"},
+  {"Id": "regionalApplication", "Name": "Regional Application", "BitLength": 8, "FieldType": "SPARE"},
+  {"Id": "regionalApplicationB", "Name": "Regional Application B", "BitLength": 2, "FieldType": "SPARE"},
+  {"Id": "unitType", "Name": "Unit type", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "integratedDisplay", "Name": "Integrated Display", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "dsc", "Name": "DSC", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "band", "Name": "Band", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "canHandleMsg22", "Name": "Can handle Msg 22", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "aisMode", "Name": "AIS mode", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "aisCommunicationState", "Name": "AIS communication state", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 15, "FieldType": "RESERVED"}]},
+ {"PGN": 129794, "Id": "aisClassAStaticAndVoyageRelatedData", "Description": "AIS Class A Static and Voyage Related Data", "Type": "Fast", "Fields": [
+  {"Id": "messageId", "Name": "Message ID", "BitLength": 6, "FieldType": "NUMBER"},
+  {"Id": "repeatIndicator", "Name": "Repeat Indicator", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "REPEAT_INDICATOR"},
+  {"Id": "userId", "Name": "User ID", "BitLength": 32, "FieldType": "MMSI"},
+  {"Id": "imoNumber", "Name": "IMO number", "BitLength": 32, "FieldType": "NUMBER"},
+  {"Id": "callsign", "Name": "Callsign", "BitLength": 56, "FieldType": "STRING_FIX"},
+  {"Id": "name", "Name": "Name", "BitLength": 160, "FieldType": "STRING_FIX"},
+  {"Id": "typeOfShip", "Name": "Type of ship", "BitLength": 8, "FieldType": "LOOKUP", "LookupEnumeration": "SHIP_TYPE"},
+  {"Id": "length", "Name": "Length", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Unit": "m"},
+  {"Id": "beam", "Name": "Beam", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Unit": "m"},
+  {"Id": "positionReferenceFromStarboard", "Name": "Position reference from Starboard", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Unit": "m"},
+  {"Id": "positionReferenceFromBow", "Name": "Position reference from Bow", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1, "Unit": "m"},
+  {"Id": "etaDate", "Name": "ETA Date", "BitLength": 16, "FieldType": "DATE", "Unit": "d"},
+  {"Id": "etaTime", "Name": "ETA Time", "BitLength": 32, "FieldType": "TIME", "Resolution": 0.0001, "Unit": "s"},
+  {"Id": "draft", "Name": "Draft", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.01, "Unit": "m"},
+  {"Id": "destination", "Name": "Destination", "BitLength": 160, "FieldType": "STRING_FIX"},
+  {"Id": "aisVersionIndicator", "Name": "AIS version indicator", "BitLength": 2, "FieldType": "LOOKUP", "LookupEnumeration": "AIS_VERSION"},
+  {"Id": "gnssType", "Name": "GNSS type", "BitLength": 4, "FieldType": "LOOKUP", "LookupEnumeration": "GNS"},
+  {"Id": "dte", "Name": "DTE", "BitLength": 1, "FieldType": "NUMBER"},
+  {"Id": "reserved", "Name": "Reserved", "BitLength": 1, "FieldType": "RESERVED"},
+  {"Id": "aisTransceiverInformation", "Name": "AIS Transceiver information", "BitLength": 5, "FieldType": "LOOKUP", "LookupEnumeration": "AIS_TRANSCEIVER"},
+  {"Id": "reserved21", "Name": "Reserved", "BitLength": 3, "FieldType": "RESERVED"},
+  {"Id": "sequenceId", "Name": "Sequence ID", "BitLength": 8, "FieldType": "NUMBER"}]}
+]
+}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Number,
+    Lookup,
+    Float,
+    Date,
+    Time,
+    Duration,
+    Mmsi,
+    Binary,
+    // 固定長度、長度在前 (STRING_LZ)、長度加控制碼在前 (STRING_LAU)
+    StringFix,
+    StringLz,
+    StringLau,
+    Reserved,
+    // 超過 64 位元或長度不固定的欄位，原樣顯示成十六進位
+    Bytes,
+}
+
+#[derive(Debug, Clone)]
+struct FieldDef {
+    name: String,
+    kind: FieldKind,
+    bits: usize,
+    resolution: f64,
+    offset: f64,
+    signed: bool,
+    unit: String,
+    lookup: Option<HashMap<u64, String>>,
+    // 同一 PGN 有多個定義時用來挑選 (專有 PGN 的廠商碼等)
+    matches: Option<u64>,
+}
+
+/// 一個 PGN 的欄位定義
+#[derive(Debug, Clone)]
+pub struct PgnDef {
+    pub description: String,
+    pub fast: bool,
+    fields: Vec<FieldDef>,
+    // 重複欄位組：(起始欄位索引, 欄位數, 計數欄位索引)
+    repeating: Option<(usize, usize, Option<usize>)>,
+}
+
+/// 解碼出的欄位；數值已換成顯示單位 (角度用度、溫度用 °C、速度用節)
+#[derive(Debug, Clone)]
+pub struct DecodedField {
+    pub name: String,
+    pub value: Option<f64>,
+    pub text: String,
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.position)
+    }
+
+    /// 小端位元序取出最多 64 位元
+    fn read(&mut self, bits: usize) -> Option<u64> {
+        if bits > 64 || bits > self.remaining() {
+            return None;
+        }
+        let mut value = 0u64;
+        for i in 0..bits {
+            let bit = self.position + i;
+            if self.data[bit / 8] >> (bit % 8) & 1 != 0 {
+                value |= 1 << i;
+            }
+        }
+        self.position += bits;
+        Some(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        // 字串一定從位元組邊界開始
+        let start = self.position.div_ceil(8);
+        let bytes = self.data.get(start..start + len)?;
+        self.position = (start + len) * 8;
+        Some(bytes)
+    }
+}
+
+fn text_from(bytes: &[u8]) -> String {
+    let text: String = bytes
+        .iter()
+        .take_while(|&&b| b != 0x00 && b != 0xFF)
+        .map(|&b| b as char)
+        .collect();
+    // AIS 用 '@' 填充
+    text.trim_end_matches(['@', ' ']).to_string()
+}
+
+/// 1970-01-01 起的天數轉成年月日 (Howard Hinnant 的 civil_from_days)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl FieldDef {
+    fn decode(&self, reader: &mut BitReader) -> Option<(DecodedField, u64)> {
+        let text = |text: String| DecodedField {
+            name: self.name.clone(),
+            value: None,
+            text,
+        };
+        match self.kind {
+            FieldKind::StringFix => {
+                let bytes = reader.bytes(self.bits / 8)?;
+                return Some((text(text_from(bytes)), 0));
+            }
+            FieldKind::StringLz => {
+                let len = reader.bytes(1)?[0] as usize;
+                let bytes = reader.bytes(len)?;
+                return Some((text(text_from(bytes)), 0));
+            }
+            FieldKind::StringLau => {
+                let header = reader.bytes(2)?;
+                let (len, encoding) = (header[0] as usize, header[1]);
+                let bytes = reader.bytes(len.saturating_sub(2))?;
+                let decoded = if encoding == 0 {
+                    // UTF-16LE
+                    let units: Vec<u16> = bytes
+                        .chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                } else {
+                    text_from(bytes)
+                };
+                return Some((text(decoded), 0));
+            }
+            FieldKind::Bytes => {
+                let bits = match self.bits {
+                    0 => reader.remaining() / 8 * 8,
+                    bits => bits,
+                };
+                if bits == 0 || bits > reader.remaining() {
+                    return None;
+                }
+                let mut bytes = Vec::new();
+                for start in (0..bits).step_by(8) {
+                    bytes.push(reader.read((bits - start).min(8))? as u8);
+                }
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                return Some((text(hex.join(" ")), 0));
+            }
+            _ => {}
+        }
+
+        let raw = reader.read(self.bits)?;
+        let max = if self.bits == 64 {
+            u64::MAX
+        } else {
+            (1u64 << self.bits) - 1
+        };
+        let signed_value = if self.signed && self.bits < 64 && raw >> (self.bits - 1) & 1 != 0 {
+            raw as i64 - (1i64 << self.bits)
+        } else {
+            raw as i64
+        };
+        // 全 1 (有號數為最大正數) 代表沒有資料
+        let missing = self.bits > 1
+            && match self.kind {
+                FieldKind::Number | FieldKind::Duration | FieldKind::Mmsi => {
+                    if self.signed {
+                        signed_value == (max >> 1) as i64
+                    } else {
+                        raw == max
+                    }
+                }
+                FieldKind::Date | FieldKind::Time => raw == max,
+                _ => false,
+            };
+        if missing {
+            return Some((text("-".to_string()), raw));
+        }
+
+        let field = match self.kind {
+            FieldKind::Reserved => text(String::new()),
+            FieldKind::Binary => text(format!("0x{:X}", raw)),
+            FieldKind::Mmsi => DecodedField {
+                name: self.name.clone(),
+                value: Some(raw as f64),
+                text: format!("{:09}", raw),
+            },
+            FieldKind::Lookup => DecodedField {
+                name: self.name.clone(),
+                value: Some(raw as f64),
+                text: match self.lookup.as_ref().and_then(|l| l.get(&raw)) {
+                    Some(name) => name.clone(),
+                    None => raw.to_string(),
+                },
+            },
+            FieldKind::Date => {
+                let (year, month, day) = civil_from_days(raw as i64);
+                DecodedField {
+                    name: self.name.clone(),
+                    value: Some(raw as f64),
+                    text: format!("{:04}-{:02}-{:02}", year, month, day),
+                }
+            }
+            FieldKind::Time => {
+                let seconds = raw as f64 * self.resolution;
+                let whole = seconds as u64;
+                DecodedField {
+                    name: self.name.clone(),
+                    value: Some(seconds),
+                    text: format!(
+                        "{:02}:{:02}:{:06.3}",
+                        whole / 3600,
+                        whole / 60 % 60,
+                        seconds % 60.0
+                    ),
+                }
+            }
+            FieldKind::Float => {
+                let value = f32::from_bits(raw as u32) as f64;
+                self.number(value)
+            }
+            FieldKind::Number | FieldKind::Duration => {
+                self.number(signed_value as f64 * self.resolution + self.offset)
+            }
+            FieldKind::StringFix
+            | FieldKind::StringLz
+            | FieldKind::StringLau
+            | FieldKind::Bytes => unreachable!(),
+        };
+        Some((field, raw))
+    }
+
+    fn number(&self, value: f64) -> DecodedField {
+        let (value, unit) = match self.unit.as_str() {
+            "rad" => (value.to_degrees(), "°"),
+            "rad/s" => (value.to_degrees(), "°/s"),
+            "K" => (value - 273.15, "°C"),
+            "m/s" => (value * 3600.0 / 1852.0, "kn"),
+            "deg" => (value, "°"),
+            unit => (value, unit),
+        };
+        // 依解析度決定顯示的小數位數
+        let decimals = match self.unit.as_str() {
+            "rad" | "rad/s" | "K" | "m/s" => 2,
+            _ if self.resolution >= 1.0 => 0,
+            _ => (-self.resolution.log10()).ceil().clamp(0.0, 7.0) as usize,
+        };
+        let mut text = format!("{:.*}", decimals, value);
+        if !unit.is_empty() {
+            text.push(' ');
+            text.push_str(unit);
+        }
+        DecodedField {
+            name: self.name.clone(),
+            value: Some(value),
+            text,
+        }
+    }
+}
+
+impl PgnDef {
+    /// 解出所有欄位；資料不夠時解到哪裡算哪裡。回傳 None 表示 Match 欄位不符
+    fn decode(&self, data: &[u8]) -> Option<Vec<DecodedField>> {
+        let mut reader = BitReader { data, position: 0 };
+        let mut fields = Vec::new();
+        let mut raws = Vec::new();
+        let fixed = self
+            .repeating
+            .map_or(self.fields.len(), |(start, _, _)| start);
+        for field in &self.fields[..fixed] {
+            let Some((decoded, raw)) = field.decode(&mut reader) else {
+                break;
+            };
+            if field.matches.is_some_and(|m| m != raw) {
+                return None;
+            }
+            raws.push(raw);
+            if field.kind != FieldKind::Reserved {
+                fields.push(decoded);
+            }
+        }
+        if let Some((start, size, count)) = self.repeating {
+            let set = &self.fields[start..(start + size).min(self.fields.len())];
+            // 沒有計數欄位就一直重複到資料用完
+            let count = match count {
+                Some(index) => raws.get(index).map_or(0, |&c| c as usize),
+                None => usize::MAX,
+            };
+            'sets: for i in 0..count {
+                if reader.remaining() == 0 {
+                    break;
+                }
+                for field in set {
+                    let Some((mut decoded, _)) = field.decode(&mut reader) else {
+                        break 'sets;
+                    };
+                    if field.kind != FieldKind::Reserved {
+                        decoded.name = format!("{} {}", decoded.name, i + 1);
+                        fields.push(decoded);
+                    }
+                }
+            }
+        }
+        Some(fields)
+    }
+}
+
+fn get<'a>(object: &'a Value, key: &str) -> Option<&'a Value> {
+    // 舊版 canboat 的鍵是小寫開頭
+    let lower = key[..1].to_lowercase() + &key[1..];
+    object.get(key).or_else(|| object.get(&lower))
+}
+
+fn get_str<'a>(object: &'a Value, key: &str) -> Option<&'a str> {
+    get(object, key)?.as_str()
+}
+
+fn get_f64(object: &Value, key: &str) -> Option<f64> {
+    let value = get(object, key)?;
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn enum_values(values: &Value) -> HashMap<u64, String> {
+    values
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let value = get_f64(item, "Value")? as u64;
+            Some((value, get_str(item, "Name")?.to_string()))
+        })
+        .collect()
+}
+
+/// PGN 定義表 (canboat JSON)
+pub struct Definitions {
+    pub pgns: HashMap<u32, Vec<PgnDef>>,
+}
+
+impl Default for Definitions {
+    fn default() -> Self {
+        Self::parse(BUILTIN_DEFINITIONS).expect("內建 NMEA 2000 定義")
+    }
+}
+
+impl Definitions {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("無法讀取 NMEA 2000 定義: {}", e))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let root: Value = serde_json::from_str(text)
+            .map_err(|e| format!("NMEA 2000 定義第 {} 行: {}", e.line(), e))?;
+        let lookups: HashMap<&str, HashMap<u64, String>> = get(&root, "LookupEnumerations")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|l| Some((get_str(l, "Name")?, enum_values(get(l, "EnumValues")?))))
+            .collect();
+        let list = get(&root, "PGNs")
+            .and_then(Value::as_array)
+            .ok_or("NMEA 2000 定義缺少 PGNs 陣列")?;
+
+        let mut pgns: HashMap<u32, Vec<PgnDef>> = HashMap::new();
+        for (index, item) in list.iter().enumerate() {
+            let err = |msg: &str| format!("NMEA 2000 定義第 {} 個 PGN: {}", index + 1, msg);
+            let pgn = get_f64(item, "PGN").ok_or_else(|| err("缺少 PGN"))? as u32;
+            let mut fields = Vec::new();
+            for field in get(item, "Fields")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let kind_name = get_str(field, "FieldType")
+                    .or_else(|| get_str(field, "Type"))
+                    .unwrap_or("NUMBER");
+                let kind = match kind_name {
+                    "LOOKUP" | "INDIRECT_LOOKUP" | "Lookup table" => FieldKind::Lookup,
+                    "FLOAT" | "IEEE Float" => FieldKind::Float,
+                    "DATE" | "Date" => FieldKind::Date,
+                    "TIME" | "Time" => FieldKind::Time,
+                    "DURATION" => FieldKind::Duration,
+                    "MMSI" => FieldKind::Mmsi,
+                    "BINARY" | "BITLOOKUP" | "Binary data" | "Bitfield" => FieldKind::Binary,
+                    "STRING_FIX" | "ASCII text" => FieldKind::StringFix,
+                    "STRING_LZ" | "ASCII string starting with length byte" => FieldKind::StringLz,
+                    "STRING_LAU"
+                    | "ASCII or UNICODE string starting with length and control byte" => {
+                        FieldKind::StringLau
+                    }
+                    "RESERVED" | "SPARE" => FieldKind::Reserved,
+                    _ => FieldKind::Number,
+                };
+                // 沒有 BitLength 的是長度不固定的欄位，解到資料結尾
+                let bits = get_f64(field, "BitLength").unwrap_or(0.0) as usize;
+                let string = matches!(
+                    kind,
+                    FieldKind::StringFix | FieldKind::StringLz | FieldKind::StringLau
+                );
+                let kind = if string || (1..=64).contains(&bits) {
+                    kind
+                } else {
+                    FieldKind::Bytes
+                };
+                let lookup = get_str(field, "LookupEnumeration")
+                    .and_then(|name| lookups.get(name).cloned())
+                    .or_else(|| get(field, "EnumValues").map(enum_values));
+                fields.push(FieldDef {
+                    name: get_str(field, "Name")
+                        .or_else(|| get_str(field, "Id"))
+                        .unwrap_or("?")
+                        .to_string(),
+                    kind,
+                    bits,
+                    resolution: get_f64(field, "Resolution").unwrap_or(1.0),
+                    offset: get_f64(field, "Offset").unwrap_or(0.0),
+                    signed: get(field, "Signed")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false),
+                    unit: get_str(field, "Unit")
+                        .or_else(|| get_str(field, "Units"))
+                        .unwrap_or("")
+                        .to_string(),
+                    lookup,
+                    matches: get_f64(field, "Match").map(|m| m as u64),
+                });
+            }
+            // 欄位順序從 1 開始
+            let repeating = get_f64(item, "RepeatingFieldSet1Size")
+                .zip(get_f64(item, "RepeatingFieldSet1StartField"))
+                .filter(|(size, start)| *size >= 1.0 && *start >= 1.0)
+                .map(|(size, start)| {
+                    let count = get_f64(item, "RepeatingFieldSet1CountField")
+                        .filter(|&c| c >= 1.0)
+                        .map(|c| c as usize - 1);
+                    (start as usize - 1, size as usize, count)
+                })
+                .filter(|(start, _, _)| *start < fields.len());
+            pgns.entry(pgn).or_default().push(PgnDef {
+                description: get_str(item, "Description")
+                    .or_else(|| get_str(item, "Id"))
+                    .unwrap_or("")
+                    .to_string(),
+                fast: get_str(item, "Type") == Some("Fast"),
+                fields,
+                repeating,
+            });
+        }
+        Ok(Self { pgns })
+    }
+
+    /// 沒有定義的 PGN 依範圍判斷：126720 與 130816 以上的專有 PGN 是 fast-packet
+    pub fn is_fast(&self, pgn: u32) -> bool {
+        match self.pgns.get(&pgn) {
+            Some(defs) => defs.iter().any(|d| d.fast),
+            None => pgn == 126720 || (0x1FF00..=0x1FFFF).contains(&pgn),
+        }
+    }
+
+    /// 挑第一個 Match 欄位相符的定義解碼
+    pub fn decode(&self, pgn: u32, data: &[u8]) -> Option<(&PgnDef, Vec<DecodedField>)> {
+        self.pgns
+            .get(&pgn)?
+            .iter()
+            .find_map(|def| Some((def, def.decode(data)?)))
+    }
+}
+
+struct FastPacket {
+    sequence: u8,
+    size: usize,
+    next_frame: u8,
+    data: Vec<u8>,
+    last_us: u64,
+}
+
+/// fast-packet 重組：第一幀是序號/框架 0 + 總長度 + 6 bytes，之後每幀 7 bytes
+#[derive(Default)]
+pub struct FastPacketReassembler {
+    // (通道, 來源, PGN)
+    sessions: HashMap<(u32, u8, u32), FastPacket>,
+    pub errors: u64,
+}
+
+impl FastPacketReassembler {
+    pub fn clear(&mut self) {
+        self.sessions.clear();
+        self.errors = 0;
+    }
+
+    pub fn feed(
+        &mut self,
+        channel: u32,
+        id: J1939Id,
+        data: &[u8],
+        timestamp_us: u64,
+    ) -> Option<Vec<u8>> {
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, s| timestamp_us.saturating_sub(s.last_us) <= FAST_PACKET_TIMEOUT_US);
+        self.errors += (before - self.sessions.len()) as u64;
+
+        let key = (channel, id.sa, id.pgn);
+        let (&header, payload) = data.split_first()?;
+        let (sequence, frame) = (header >> 5, header & 0x1F);
+        if frame == 0 {
+            let (&size, payload) = payload.split_first()?;
+            let size = size as usize;
+            if size > MAX_FAST_PACKET_SIZE {
+                self.errors += 1;
+                return None;
+            }
+            let packet = FastPacket {
+                sequence,
+                size,
+                next_frame: 1,
+                data: payload.to_vec(),
+                last_us: timestamp_us,
+            };
+            if self.sessions.insert(key, packet).is_some() {
+                self.errors += 1;
+            }
+        } else {
+            let Some(packet) = self.sessions.get_mut(&key) else {
+                // 沒看到第一幀，整個訊息丟掉
+                return None;
+            };
+            if packet.sequence != sequence || packet.next_frame != frame {
+                self.sessions.remove(&key);
+                self.errors += 1;
+                return None;
+            }
+            packet.next_frame += 1;
+            packet.last_us = timestamp_us;
+            packet.data.extend_from_slice(payload);
+        }
+        if self.sessions[&key].data.len() < self.sessions[&key].size {
+            return None;
+        }
+        let mut packet = self.sessions.remove(&key)?;
+        packet.data.truncate(packet.size);
+        Some(packet.data)
+    }
+}
+
+/// 每個 (PGN, 來源, 實例) 最新一筆解碼結果
+struct Latest {
+    description: String,
+    fields: Vec<DecodedField>,
+    count: u64,
+    timestamp_us: u64,
+}
+
+/// NMEA 2000 解碼面板：fast-packet 與 ISO 傳輸重組後依定義檔解出欄位
+#[derive(Default)]
+pub struct Nmea2000Panel {
+    pub enabled: bool,
+    pub definitions: Definitions,
+    definitions_path: String,
+    reassembler: Reassembler,
+    fast: FastPacketReassembler,
+    // (PGN, 來源, MMSI 或實例)
+    latest: BTreeMap<(u32, u8, Option<u64>), Latest>,
+    undefined: u64,
+    filter: String,
+}
+
+impl Nmea2000Panel {
+    pub fn clear(&mut self) {
+        self.reassembler.clear();
+        self.fast.clear();
+        self.latest.clear();
+        self.undefined = 0;
+    }
+
+    pub fn feed(&mut self, frame: &CanFrame) {
+        if !self.enabled {
+            return;
+        }
+        // 1. **ISO 傳輸 (BAM/RTS) 與單幀**
+        let Some(message) = self.reassembler.feed(frame) else {
+            return;
+        };
+        let pgn = message.id.pgn;
+
+        // 2. **fast-packet**
+        let data = if message.transport.is_none() && self.definitions.is_fast(pgn) {
+            match self.fast.feed(
+                message.channel,
+                message.id,
+                &message.data,
+                message.timestamp_us,
+            ) {
+                Some(data) => data,
+                None => return,
+            }
+        } else {
+            message.data
+        };
+
+        // 3. **解碼**
+        let Some((def, fields)) = self.definitions.decode(pgn, &data) else {
+            self.undefined += 1;
+            return;
+        };
+        // AIS 依 MMSI 分開，引擎等依實例分開
+        let instance = fields
+            .iter()
+            .find(|f| f.name == "User ID" || f.name.ends_with("Instance"))
+            .and_then(|f| f.value)
+            .map(|v| v as u64);
+        let entry = self
+            .latest
+            .entry((pgn, message.id.sa, instance))
+            .or_insert_with(|| Latest {
+                description: def.description.clone(),
+                fields: Vec::new(),
+                count: 0,
+                timestamp_us: 0,
+            });
+        entry.fields = fields;
+        entry.count += 1;
+        entry.timestamp_us = message.timestamp_us;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, log: &mut Vec<String>) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "啟用 NMEA 2000 解碼");
+            if ui.button("清除").clicked() {
+                self.clear();
+            }
+            ui.label(format!(
+                "中斷的 fast-packet: {}，未定義的訊息: {}",
+                self.fast.errors, self.undefined
+            ));
+        });
+        ui.horizontal(|ui| {
+            ui.label("PGN 定義 (canboat JSON):");
+            ui.text_edit_singleline(&mut self.definitions_path);
+            if ui.button("載入").clicked() {
+                match Definitions::load(&self.definitions_path) {
+                    Ok(definitions) => {
+                        log.push(format!(
+                            "NMEA 2000 定義已載入: {} 個 PGN",
+                            definitions.pgns.len()
+                        ));
+                        self.definitions = definitions;
+                        self.clear();
+                    }
+                    Err(e) => log.push(e),
+                }
+            }
+            if ui.button("內建定義").clicked() {
+                self.definitions = Definitions::default();
+                self.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("篩選:");
+            ui.text_edit_singleline(&mut self.filter);
+        });
+        ui.separator();
+
+        let filter = self.filter.to_lowercase();
+        egui::ScrollArea::vertical()
+            .id_salt("nmea2000_latest")
+            .max_height(420.0)
+            .show(ui, |ui| {
+                let mut shown = 0;
+                for ((pgn, sa, instance), latest) in &self.latest {
+                    if !filter.is_empty()
+                        && !latest.description.to_lowercase().contains(&filter)
+                        && !pgn.to_string().contains(&filter)
+                    {
+                        continue;
+                    }
+                    shown += 1;
+                    let mut header = format!(
+                        "PGN {:6} {:<40} 來源 {:02X} ×{}",
+                        pgn, latest.description, sa, latest.count
+                    );
+                    if let Some(instance) = instance {
+                        header.push_str(&format!(" [{}]", instance));
+                    }
+                    egui::CollapsingHeader::new(egui::RichText::new(header).monospace())
+                        .id_salt(("nmea2000", pgn, sa, instance))
+                        .show(ui, |ui| {
+                            ui.label(format!(
+                                "最後收到: {:.3} s",
+                                latest.timestamp_us as f64 / 1e6
+                            ));
+                            egui::Grid::new(("nmea2000_fields", pgn, sa, instance))
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for field in &latest.fields {
+                                        ui.label(&field.name);
+                                        ui.monospace(&field.text);
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+                if shown == 0 {
+                    ui.label("尚無 NMEA 2000 訊息");
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 129029 從 0x23 送出
+    fn gnss_id() -> J1939Id {
+        J1939Id::from_raw(3 << 26 | 0x1F805 << 8 | 0x23)
+    }
+
+    /// 把資料切成 fast-packet 幀
+    fn fast_frames(sequence: u8, payload: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = vec![[&[sequence << 5, payload.len() as u8][..], &payload[..6]].concat()];
+        for (i, chunk) in payload[6..].chunks(7).enumerate() {
+            let mut frame = vec![sequence << 5 | (i as u8 + 1)];
+            frame.extend_from_slice(chunk);
+            frame.resize(8, 0xFF);
+            frames.push(frame);
+        }
+        frames
+    }
+
+    fn texts(fields: &[DecodedField]) -> Vec<String> {
+        fields
+            .iter()
+            .map(|f| format!("{}={}", f.name, f.text))
+            .collect()
+    }
+
+    #[test]
+    fn fast_packet_reassembly() {
+        let payload: Vec<u8> = (0..20).collect();
+        let frames = fast_frames(2, &payload);
+        assert_eq!(frames.len(), 3);
+        let mut fast = FastPacketReassembler::default();
+        assert!(fast.feed(0, gnss_id(), &frames[0], 0).is_none());
+        assert!(fast.feed(0, gnss_id(), &frames[1], 1_000).is_none());
+        assert_eq!(
+            fast.feed(0, gnss_id(), &frames[2], 2_000),
+            Some(payload.clone())
+        );
+        assert_eq!(fast.errors, 0);
+
+        // 順序錯亂：整個訊息丟掉，後面的幀也不會湊出結果
+        fast.feed(0, gnss_id(), &frames[0], 10_000);
+        assert!(fast.feed(0, gnss_id(), &frames[2], 11_000).is_none());
+        assert!(fast.feed(0, gnss_id(), &frames[1], 12_000).is_none());
+        assert_eq!(fast.errors, 1);
+
+        // 中途換了序號
+        let other = fast_frames(3, &payload);
+        fast.feed(0, gnss_id(), &frames[0], 20_000);
+        assert!(fast.feed(0, gnss_id(), &other[1], 21_000).is_none());
+        assert_eq!(fast.errors, 2);
+
+        // 兩幀間隔超過逾時
+        fast.feed(0, gnss_id(), &frames[0], 30_000);
+        fast.feed(0, gnss_id(), &frames[1], 31_000);
+        let late = 31_000 + FAST_PACKET_TIMEOUT_US + 1;
+        assert!(fast.feed(0, gnss_id(), &frames[2], late).is_none());
+        assert_eq!(fast.errors, 3);
+
+        // 不同來源各自重組
+        let other_source = J1939Id::from_raw(3 << 26 | 0x1F805 << 8 | 0x24);
+        fast.feed(0, gnss_id(), &frames[0], 40_000);
+        fast.feed(0, other_source, &frames[0], 40_000);
+        fast.feed(0, gnss_id(), &frames[1], 41_000);
+        fast.feed(0, other_source, &frames[1], 41_000);
+        assert_eq!(
+            fast.feed(0, gnss_id(), &frames[2], 42_000),
+            Some(payload.clone())
+        );
+        assert_eq!(
+            fast.feed(0, other_source, &frames[2], 42_000),
+            Some(payload)
+        );
+    }
+
+    #[test]
+    fn decode_builtin_heading() {
+        let mut data = vec![0u8];
+        data.extend_from_slice(&15708u16.to_le_bytes());
+        data.extend_from_slice(&0x7FFFu16.to_le_bytes());
+        data.extend_from_slice(&(-349i16).to_le_bytes());
+        data.push(0xFD);
+        let defs = Definitions::default();
+        let (def, fields) = defs.decode(127250, &data).unwrap();
+        assert_eq!(def.description, "Vessel Heading");
+        assert_eq!(
+            texts(&fields),
+            [
+                "SID=0",
+                "Heading=90.00 °",
+                "Deviation=-",
+                "Variation=-2.00 °",
+                "Reference=Magnetic"
+            ]
+        );
+    }
+
+    #[test]
+    fn repeating_field_set() {
+        let json = r#"{"PGNs": [
+         {"PGN": 130000, "Description": "Counted", "Type": "Fast",
+          "RepeatingFieldSet1Size": 2, "RepeatingFieldSet1StartField": 2, "RepeatingFieldSet1CountField": 1, "Fields": [
+          {"Name": "Count", "BitLength": 8, "FieldType": "NUMBER"},
+          {"Name": "Id", "BitLength": 8, "FieldType": "NUMBER"},
+          {"Name": "Level", "BitLength": 16, "FieldType": "NUMBER", "Resolution": 0.1}]},
+         {"PGN": 130001, "Description": "Until end", "Type": "Fast",
+          "RepeatingFieldSet1Size": 1, "RepeatingFieldSet1StartField": 2, "Fields": [
+          {"Name": "SID", "BitLength": 8, "FieldType": "NUMBER"},
+          {"Name": "Id", "BitLength": 8, "FieldType": "NUMBER"}]}
+        ]}"#;
+        let defs = Definitions::parse(json).unwrap();
+        // 計數欄位是 2，後面多出來的資料不解
+        let (_, fields) = defs
+            .decode(130000, &[2, 7, 0x0A, 0x00, 9, 0x14, 0x00, 11, 0x1E, 0x00])
+            .unwrap();
+        assert_eq!(
+            texts(&fields),
+            ["Count=2", "Id 1=7", "Level 1=1.0", "Id 2=9", "Level 2=2.0"]
+        );
+        let (_, fields) = defs.decode(130001, &[0, 4, 5, 6]).unwrap();
+        assert_eq!(texts(&fields), ["SID=0", "Id 1=4", "Id 2=5", "Id 3=6"]);
+    }
+
+    #[test]
+    fn wide_and_variable_fields_are_kept_as_bytes() {
+        // canboat 裡有 128 位元的 BINARY 與沒有 BitLength 的 VARIABLE 欄位
+        let json = r#"{"PGNs": [
+         {"PGN": 130002, "Description": "Wide", "Type": "Fast", "Fields": [
+          {"Name": "Key", "BitLength": 128, "FieldType": "BINARY"},
+          {"Name": "Flags", "BitLength": 4, "FieldType": "NUMBER"},
+          {"Name": "Reserved", "BitLength": 4, "FieldType": "RESERVED"},
+          {"Name": "Value", "FieldType": "VARIABLE"}]}
+        ]}"#;
+        let defs = Definitions::parse(json).unwrap();
+        let mut data: Vec<u8> = (1..=16).collect();
+        data.extend_from_slice(&[0xF3, 0xAB, 0xCD]);
+        let (_, fields) = defs.decode(130002, &data).unwrap();
+        assert_eq!(
+            texts(&fields),
+            [
+                "Key=01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10",
+                "Flags=3",
+                "Value=AB CD"
+            ]
+        );
+    }
+}
@@ -0,0 +1,822 @@
+use crate::canbus::CanFrame;
+use crate::isotp::{IsoTpConfig, IsoTpEvent, IsoTpLink};
+use eframe::egui;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// 廣播請求 ID 與 ECU 的回應 ID 範圍 (ISO 15765-4 11 位元)
+pub const FUNCTIONAL_ID: u32 = 0x7DF;
+pub const FIRST_RESPONSE_ID: u32 = 0x7E8;
+const ECU_COUNT: u32 = 8;
+// 一次 Mode 01 請求最多 6 個 PID
+const MAX_PIDS_PER_REQUEST: usize = 6;
+// 收集回應的時間窗，NRC 0x78 時延長
+const RESPONSE_WINDOW_MS: u64 = 300;
+const PENDING_WINDOW_MS: u64 = 5000;
+
+/// 標準 Mode 01 PID：資料長度與換算公式
+pub struct Pid {
+    pub pid: u8,
+    pub len: usize,
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub decode: fn(&[u8]) -> f64,
+}
+
+fn word(d: &[u8]) -> f64 {
+    (d[0] as f64) * 256.0 + d[1] as f64
+}
+
+fn percent(d: &[u8]) -> f64 {
+    d[0] as f64 * 100.0 / 255.0
+}
+
+fn temperature(d: &[u8]) -> f64 {
+    d[0] as f64 - 40.0
+}
+
+fn fuel_trim(d: &[u8]) -> f64 {
+    (d[0] as f64 - 128.0) * 100.0 / 128.0
+}
+
+pub const PIDS: &[Pid] = &[
+    Pid {
+        pid: 0x01,
+        len: 4,
+        name: "已存 DTC 數",
+        unit: "",
+        decode: |d| (d[0] & 0x7F) as f64,
+    },
+    Pid {
+        pid: 0x04,
+        len: 1,
+        name: "引擎負載",
+        unit: "%",
+        decode: percent,
+    },
+    Pid {
+        pid: 0x05,
+        len: 1,
+        name: "冷卻液溫度",
+        unit: "°C",
+        decode: temperature,
+    },
+    Pid {
+        pid: 0x06,
+        len: 1,
+        name: "短期燃油修正 B1",
+        unit: "%",
+        decode: fuel_trim,
+    },
+    Pid {
+        pid: 0x07,
+        len: 1,
+        name: "長期燃油修正 B1",
+        unit: "%",
+        decode: fuel_trim,
+    },
+    Pid {
+        pid: 0x08,
+        len: 1,
+        name: "短期燃油修正 B2",
+        unit: "%",
+        decode: fuel_trim,
+    },
+    Pid {
+        pid: 0x09,
+        len: 1,
+        name: "長期燃油修正 B2",
+        unit: "%",
+        decode: fuel_trim,
+    },
+    Pid {
+        pid: 0x0A,
+        len: 1,
+        name: "燃油壓力",
+        unit: "kPa",
+        decode: |d| d[0] as f64 * 3.0,
+    },
+    Pid {
+        pid: 0x0B,
+        len: 1,
+        name: "進氣歧管壓力",
+        unit: "kPa",
+        decode: |d| d[0] as f64,
+    },
+    Pid {
+        pid: 0x0C,
+        len: 2,
+        name: "引擎轉速",
+        unit: "rpm",
+        decode: |d| word(d) / 4.0,
+    },
+    Pid {
+        pid: 0x0D,
+        len: 1,
+        name: "車速",
+        unit: "km/h",
+        decode: |d| d[0] as f64,
+    },
+    Pid {
+        pid: 0x0E,
+        len: 1,
+        name: "點火提前角",
+        unit: "°",
+        decode: |d| d[0] as f64 / 2.0 - 64.0,
+    },
+    Pid {
+        pid: 0x0F,
+        len: 1,
+        name: "進氣溫度",
+        unit: "°C",
+        decode: temperature,
+    },
+    Pid {
+        pid: 0x10,
+        len: 2,
+        name: "空氣流量",
+        unit: "g/s",
+        decode: |d| word(d) / 100.0,
+    },
+    Pid {
+        pid: 0x11,
+        len: 1,
+        name: "節氣門位置",
+        unit: "%",
+        decode: percent,
+    },
+    Pid {
+        pid: 0x1F,
+        len: 2,
+        name: "引擎運轉時間",
+        unit: "s",
+        decode: word,
+    },
+    Pid {
+        pid: 0x21,
+        len: 2,
+        name: "MIL 亮起後里程",
+        unit: "km",
+        decode: word,
+    },
+    Pid {
+        pid: 0x2F,
+        len: 1,
+        name: "油箱油量",
+        unit: "%",
+        decode: percent,
+    },
+    Pid {
+        pid: 0x31,
+        len: 2,
+        name: "清除 DTC 後里程",
+        unit: "km",
+        decode: word,
+    },
+    Pid {
+        pid: 0x33,
+        len: 1,
+        name: "大氣壓力",
+        unit: "kPa",
+        decode: |d| d[0] as f64,
+    },
+    Pid {
+        pid: 0x42,
+        len: 2,
+        name: "控制模組電壓",
+        unit: "V",
+        decode: |d| word(d) / 1000.0,
+    },
+    Pid {
+        pid: 0x46,
+        len: 1,
+        name: "環境溫度",
+        unit: "°C",
+        decode: temperature,
+    },
+    Pid {
+        pid: 0x5C,
+        len: 1,
+        name: "機油溫度",
+        unit: "°C",
+        decode: temperature,
+    },
+    Pid {
+        pid: 0x5E,
+        len: 2,
+        name: "燃油消耗率",
+        unit: "L/h",
+        decode: |d| word(d) / 20.0,
+    },
+];
+
+pub fn pid_info(pid: u8) -> Option<&'static Pid> {
+    PIDS.iter().find(|p| p.pid == pid)
+}
+
+/// 2 bytes 的 OBD DTC 轉成 P0123 形式
+pub fn format_obd_dtc(hi: u8, lo: u8) -> String {
+    let letter = ['P', 'C', 'B', 'U'][(hi >> 6) as usize];
+    format!("{}{}{:01X}{:02X}", letter, (hi >> 4) & 0x3, hi & 0x0F, lo)
+}
+
+/// 解析 Mode 01 回應 [41, pid, 資料, pid, 資料, ...]；遇到不認得長度的 PID 就停
+pub fn decode_mode01(response: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut values = Vec::new();
+    let mut rest = response.get(1..).unwrap_or(&[]);
+    while let Some((&pid, data)) = rest.split_first() {
+        // 支援 PID 位元遮罩 (00, 20, 40 ...) 是 4 bytes
+        let len = if pid % 0x20 == 0 {
+            4
+        } else {
+            match pid_info(pid) {
+                Some(info) => info.len,
+                None => break,
+            }
+        };
+        if data.len() < len {
+            break;
+        }
+        values.push((pid, data[..len].to_vec()));
+        rest = &data[len..];
+    }
+    values
+}
+
+/// 一次廣播請求收到的所有回應
+pub struct ObdReply {
+    pub request: Vec<u8>,
+    // (回應 ID, 資料)
+    pub responses: Vec<(u32, Vec<u8>)>,
+}
+
+struct Pending {
+    request: Vec<u8>,
+    responses: Vec<(u32, Vec<u8>)>,
+    deadline_us: u64,
+}
+
+/// OBD-II 用戶端：請求一律用廣播 0x7DF 單幀送出，
+/// 每個回應 ID 各一條 ISO-TP 連線，多幀回應 (VIN 等) 的 FC 送到對應的實體 ID
+pub struct ObdClient {
+    request_link: IsoTpLink,
+    ecus: Vec<IsoTpLink>,
+    queue: VecDeque<Vec<u8>>,
+    pending: Option<Pending>,
+    pub replies: VecDeque<ObdReply>,
+    // 送不出去的請求，由面板寫進紀錄
+    pub errors: VecDeque<String>,
+}
+
+impl Default for ObdClient {
+    fn default() -> Self {
+        let request_link = IsoTpLink::new(IsoTpConfig {
+            tx_id: FUNCTIONAL_ID,
+            rx_id: FUNCTIONAL_ID,
+            ..Default::default()
+        });
+        let ecus = (0..ECU_COUNT)
+            .map(|i| {
+                IsoTpLink::new(IsoTpConfig {
+                    tx_id: FIRST_RESPONSE_ID - 8 + i,
+                    rx_id: FIRST_RESPONSE_ID + i,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Self {
+            request_link,
+            ecus,
+            queue: VecDeque::new(),
+            pending: None,
+            replies: VecDeque::new(),
+            errors: VecDeque::new(),
+        }
+    }
+}
+
+impl ObdClient {
+    pub fn request(&mut self, request: Vec<u8>) {
+        self.queue.push_back(request);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.queue.is_empty()
+    }
+
+    pub fn set_channel(&mut self, channel: u32) {
+        self.request_link.channel = channel;
+        for link in &mut self.ecus {
+            link.channel = channel;
+        }
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        // 沒有請求時不接收，免得和 UDS 連線重複送 FC
+        if self.pending.is_none() {
+            return;
+        }
+        for link in &mut self.ecus {
+            link.handle(frame, now_us);
+        }
+    }
+
+    /// 取出要送的幀
+    pub fn drain_outbox(&mut self) -> impl Iterator<Item = CanFrame> + '_ {
+        self.request_link
+            .outbox
+            .drain(..)
+            .chain(self.ecus.iter_mut().flat_map(|link| link.outbox.drain(..)))
+    }
+
+    pub fn poll(&mut self, now_us: u64) {
+        self.request_link.poll(now_us);
+        self.request_link.events.clear();
+        for link in &mut self.ecus {
+            link.poll(now_us);
+            while let Some(event) = link.events.pop_front() {
+                let Some(pending) = self.pending.as_mut() else {
+                    continue;
+                };
+                match event {
+                    IsoTpEvent::Received(data) => {
+                        // 7F <mode> 78：這個請求的回應要晚一點才來
+                        if data.len() >= 3
+                            && data[0] == 0x7F
+                            && data[1] == pending.request[0]
+                            && data[2] == 0x78
+                        {
+                            pending.deadline_us = now_us + PENDING_WINDOW_MS * 1000;
+                        } else {
+                            pending.responses.push((link.config.rx_id, data));
+                        }
+                    }
+                    // 多幀回應進行中，時間窗往後延
+                    IsoTpEvent::FirstFrame(_) => {
+                        pending.deadline_us =
+                            pending.deadline_us.max(now_us + RESPONSE_WINDOW_MS * 1000);
+                    }
+                    IsoTpEvent::Sent | IsoTpEvent::Error(_) => {}
+                }
+            }
+        }
+
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|p| now_us > p.deadline_us)
+            && !self.ecus.iter().any(|link| link.is_busy())
+        {
+            let pending = self.pending.take().unwrap();
+            let mut responses = pending.responses;
+            responses.sort_by_key(|(id, _)| *id);
+            self.replies.push_back(ObdReply {
+                request: pending.request,
+                responses,
+            });
+        }
+
+        if self.pending.is_none() {
+            if let Some(request) = self.queue.pop_front() {
+                match self.request_link.send(&request, now_us) {
+                    Ok(()) => {
+                        self.pending = Some(Pending {
+                            request,
+                            responses: Vec::new(),
+                            deadline_us: now_us + RESPONSE_WINDOW_MS * 1000,
+                        })
+                    }
+                    Err(e) => self.errors.push_back(format!("OBD: 請求送出失敗: {}", e)),
+                }
+            }
+        }
+    }
+}
+
+struct LiveValue {
+    raw: Vec<u8>,
+    value: f64,
+}
+
+/// OBD-II 面板：支援的 PID、即時資料、DTC 與 VIN
+pub struct ObdPanel {
+    pub client: ObdClient,
+    supported: BTreeMap<u32, BTreeSet<u8>>,
+    selected: BTreeSet<u8>,
+    polling: bool,
+    interval_ms: u64,
+    next_poll_us: u64,
+    live: BTreeMap<(u32, u8), LiveValue>,
+    stored_dtcs: BTreeMap<u32, Vec<String>>,
+    pending_dtcs: BTreeMap<u32, Vec<String>>,
+    vins: BTreeMap<u32, String>,
+}
+
+impl Default for ObdPanel {
+    fn default() -> Self {
+        Self {
+            client: ObdClient::default(),
+            supported: BTreeMap::new(),
+            selected: BTreeSet::from([0x05, 0x0C, 0x0D]),
+            polling: false,
+            interval_ms: 500,
+            next_poll_us: 0,
+            live: BTreeMap::new(),
+            stored_dtcs: BTreeMap::new(),
+            pending_dtcs: BTreeMap::new(),
+            vins: BTreeMap::new(),
+        }
+    }
+}
+
+impl ObdPanel {
+    /// 處理完成的請求並排入下一輪即時資料
+    pub fn process(&mut self, now_us: u64, log: &mut Vec<String>) {
+        while let Some(reply) = self.client.replies.pop_front() {
+            self.on_reply(reply, log);
+        }
+        log.extend(self.client.errors.drain(..));
+        if self.polling && self.client.is_idle() && now_us >= self.next_poll_us {
+            self.next_poll_us = now_us + self.interval_ms * 1000;
+            let pids: Vec<u8> = self.selected.iter().copied().collect();
+            for chunk in pids.chunks(MAX_PIDS_PER_REQUEST) {
+                let mut request = vec![0x01];
+                request.extend_from_slice(chunk);
+                self.client.request(request);
+            }
+        }
+    }
+
+    fn on_reply(&mut self, reply: ObdReply, log: &mut Vec<String>) {
+        let mode = reply.request[0];
+        if reply.responses.is_empty() {
+            log.push(format!("OBD: Mode {:02X} 沒有 ECU 回應", mode));
+            return;
+        }
+        for (ecu, data) in reply.responses {
+            if data.first() == Some(&0x7F) {
+                log.push(format!(
+                    "OBD: 0x{:03X} 拒絕 Mode {:02X} (NRC 0x{:02X})",
+                    ecu,
+                    mode,
+                    data.get(2).copied().unwrap_or(0)
+                ));
+                continue;
+            }
+            if data.first() != Some(&(mode + 0x40)) {
+                continue;
+            }
+            match mode {
+                0x01 => {
+                    for (pid, raw) in decode_mode01(&data) {
+                        if pid % 0x20 == 0 {
+                            self.on_supported(ecu, pid, &raw);
+                        } else if let Some(info) = pid_info(pid) {
+                            let value = (info.decode)(&raw);
+                            self.live.insert((ecu, pid), LiveValue { raw, value });
+                        }
+                    }
+                }
+                // CAN 上的 Mode 03/07 回應：[43, 數量, DTC 2 bytes ...]
+                0x03 | 0x07 => {
+                    let dtcs: Vec<String> = data
+                        .get(2..)
+                        .unwrap_or(&[])
+                        .chunks_exact(2)
+                        .filter(|d| d != &[0, 0])
+                        .map(|d| format_obd_dtc(d[0], d[1]))
+                        .collect();
+                    let target = if mode == 0x03 {
+                        &mut self.stored_dtcs
+                    } else {
+                        &mut self.pending_dtcs
+                    };
+                    target.insert(ecu, dtcs);
+                }
+                0x04 => {
+                    log.push(format!("OBD: 0x{:03X} 已清除 DTC", ecu));
+                    self.stored_dtcs.remove(&ecu);
+                    self.pending_dtcs.remove(&ecu);
+                }
+                // 49 02 <筆數> VIN 17 字元
+                0x09 if data.get(1) == Some(&0x02) => {
+                    let vin: String = data
+                        .get(3..)
+                        .unwrap_or(&[])
+                        .iter()
+                        .filter(|b| b.is_ascii_graphic())
+                        .map(|&b| b as char)
+                        .collect();
+                    self.vins.insert(ecu, vin);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 支援 PID 位元遮罩：最高位元是 base+1，最後一個位元表示下一組也有
+    fn on_supported(&mut self, ecu: u32, base: u8, mask: &[u8]) {
+        let bits = u32::from_be_bytes([mask[0], mask[1], mask[2], mask[3]]);
+        let supported = self.supported.entry(ecu).or_default();
+        for i in 0..32u16 {
+            // 0xE0 那一組的最後一個位元會超出 PID 範圍
+            let pid = base as u16 + i + 1;
+            if bits & (1 << (31 - i)) != 0 && pid <= 0xFF {
+                supported.insert(pid as u8);
+            }
+        }
+        let next = base as u32 + 0x20;
+        if bits & 1 != 0 && next <= 0xE0 {
+            let already = self.client.queue.iter().any(|r| r == &[0x01, next as u8]);
+            if !already {
+                self.client.request(vec![0x01, next as u8]);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        ui.add_enabled_ui(can_send, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("查詢支援的 PID").clicked() {
+                    self.supported.clear();
+                    self.client.request(vec![0x01, 0x00]);
+                }
+                if ui.button("讀取 VIN").clicked() {
+                    self.client.request(vec![0x09, 0x02]);
+                }
+                if ui.button("已存 DTC (03)").clicked() {
+                    self.client.request(vec![0x03]);
+                }
+                if ui.button("待定 DTC (07)").clicked() {
+                    self.client.request(vec![0x07]);
+                }
+                if ui.button("清除 DTC (04)").clicked() {
+                    self.client.request(vec![0x04]);
+                }
+                if !self.client.is_idle() {
+                    ui.spinner();
+                }
+            });
+        });
+
+        for (ecu, vin) in &self.vins {
+            ui.label(format!("0x{:03X} VIN: {}", ecu, vin));
+        }
+        for (title, dtcs) in [("已存", &self.stored_dtcs), ("待定", &self.pending_dtcs)] {
+            for (ecu, list) in dtcs {
+                ui.label(format!(
+                    "0x{:03X} {} DTC ({}): {}",
+                    ecu,
+                    title,
+                    list.len(),
+                    list.join(", ")
+                ));
+            }
+        }
+
+        // 1. **要輪詢的 PID**
+        ui.separator();
+        let all: BTreeSet<u8> = self.supported.values().flatten().copied().collect();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.polling, "即時資料");
+            ui.add(
+                egui::DragValue::new(&mut self.interval_ms)
+                    .range(100..=10_000)
+                    .suffix(" ms"),
+            );
+            if all.is_empty() {
+                ui.label("(先查詢支援的 PID，或直接勾選下方)");
+            }
+        });
+        if !can_send {
+            self.polling = false;
+        }
+        ui.horizontal_wrapped(|ui| {
+            for info in PIDS {
+                if !all.is_empty() && !all.contains(&info.pid) {
+                    continue;
+                }
+                let mut on = self.selected.contains(&info.pid);
+                if ui
+                    .checkbox(&mut on, format!("{:02X} {}", info.pid, info.name))
+                    .changed()
+                {
+                    if on {
+                        self.selected.insert(info.pid);
+                    } else {
+                        self.selected.remove(&info.pid);
+                    }
+                }
+            }
+        });
+
+        // 2. **數值**
+        ui.separator();
+        egui::Grid::new("obd_live")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                for header in ["ECU", "PID", "名稱", "數值", "原始"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for ((ecu, pid), value) in &self.live {
+                    let Some(info) = pid_info(*pid) else {
+                        continue;
+                    };
+                    ui.monospace(format!("{:03X}", ecu));
+                    ui.monospace(format!("{:02X}", pid));
+                    ui.label(info.name);
+                    // PID 01 另外顯示 MIL 燈號
+                    if *pid == 0x01 {
+                        let mil = if value.raw[0] & 0x80 != 0 {
+                            "亮"
+                        } else {
+                            "滅"
+                        };
+                        ui.label(format!("{}，MIL {}", value.value, mil));
+                    } else {
+                        ui.label(format!("{:.2} {}", value.value, info.unit));
+                    }
+                    ui.monospace(
+                        value
+                            .raw
+                            .iter()
+                            .map(|b| format!("{:02X}", b))
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    );
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_bus::VirtualBus;
+
+    #[test]
+    fn supported_pids_in_last_range() {
+        let mut panel = ObdPanel::default();
+        panel.on_supported(0x7E8, 0xE0, &[0x80, 0x00, 0x00, 0x01]);
+        let supported: Vec<u8> = panel.supported[&0x7E8].iter().copied().collect();
+        assert_eq!(supported, [0xE1]);
+        assert!(panel.client.queue.is_empty());
+
+        panel.on_supported(0x7E8, 0x00, &[0x00, 0x00, 0x00, 0x01]);
+        assert!(panel.supported[&0x7E8].contains(&0x20));
+        assert_eq!(panel.client.queue.back(), Some(&vec![0x01, 0x20]));
+    }
+
+    fn decode(pid: u8, data: &[u8]) -> f64 {
+        (pid_info(pid).unwrap().decode)(data)
+    }
+
+    #[test]
+    fn pid_formulas() {
+        // 0x0C 轉速 = (256A + B) / 4
+        assert_eq!(decode(0x0C, &[0x1A, 0xF8]), 1726.0);
+        // 0x05 冷卻液溫度 = A - 40
+        assert_eq!(decode(0x05, &[0x7B]), 83.0);
+        assert_eq!(decode(0x05, &[0x00]), -40.0);
+        // 0x0D 車速 = A
+        assert_eq!(decode(0x0D, &[0x3C]), 60.0);
+        assert_eq!(decode(0x04, &[0xFF]), 100.0);
+        assert_eq!(decode(0x06, &[0x80]), 0.0);
+    }
+
+    #[test]
+    fn decode_mode01_multiple_pids() {
+        let response = [0x41, 0x0C, 0x1A, 0xF8, 0x05, 0x7B, 0x0D, 0x3C];
+        assert_eq!(
+            decode_mode01(&response),
+            [
+                (0x0C, vec![0x1A, 0xF8]),
+                (0x05, vec![0x7B]),
+                (0x0D, vec![0x3C])
+            ]
+        );
+        // 位元遮罩固定 4 bytes；資料不足或不認得的 PID 就停
+        assert_eq!(
+            decode_mode01(&[0x41, 0x00, 0xBE, 0x1F, 0xA8, 0x13, 0x0C, 0x1A]),
+            [(0x00, vec![0xBE, 0x1F, 0xA8, 0x13])]
+        );
+        assert!(decode_mode01(&[0x41, 0xFF, 0x01]).is_empty());
+
+        let mut panel = ObdPanel::default();
+        let mut log = Vec::new();
+        panel.on_reply(
+            ObdReply {
+                request: vec![0x01, 0x0C, 0x05, 0x0D],
+                responses: vec![(0x7E8, response.to_vec())],
+            },
+            &mut log,
+        );
+        assert_eq!(panel.live[&(0x7E8, 0x0C)].value, 1726.0);
+        assert_eq!(panel.live[&(0x7E8, 0x05)].value, 83.0);
+        assert_eq!(panel.live[&(0x7E8, 0x0D)].value, 60.0);
+    }
+
+    #[test]
+    fn failed_request_is_logged() {
+        let mut panel = ObdPanel::default();
+        panel.client.request(Vec::new());
+        panel.client.poll(0);
+        assert!(panel.client.is_idle());
+        let mut log = Vec::new();
+        panel.process(0, &mut log);
+        assert_eq!(log.len(), 1);
+        assert!(log[0].starts_with("OBD: 請求送出失敗"));
+    }
+
+    /// 面板接在虛擬匯流排上，每 1 ms 推進一次
+    fn run(
+        panel: &mut ObdPanel,
+        bus: &mut VirtualBus,
+        tx: &flume::Sender<CanFrame>,
+        now: &mut u64,
+        log: &mut Vec<String>,
+    ) {
+        let (data_tx, data_rx) = flume::unbounded();
+        for _ in 0..2_000 {
+            *now += 1_000;
+            panel.client.poll(*now);
+            for frame in panel.client.drain_outbox() {
+                tx.send(frame).unwrap();
+            }
+            bus.poll(*now, 0, &data_tx);
+            while let Ok(frame) = data_rx.try_recv() {
+                panel.client.handle(&frame, *now);
+            }
+            panel.process(*now, log);
+            if panel.client.is_idle() {
+                return;
+            }
+        }
+        panic!("OBD 請求沒有完成");
+    }
+
+    #[test]
+    fn dtcs_clear_and_vin_against_simulated_ecu() {
+        let (mut bus, tx) = VirtualBus::start();
+        let mut panel = ObdPanel::default();
+        let (mut now, mut log) = (0, Vec::new());
+
+        panel.client.request(vec![0x01, 0x05]);
+        panel.client.request(vec![0x03]);
+        panel.client.request(vec![0x07]);
+        // VIN 回應 20 bytes，要走多幀
+        panel.client.request(vec![0x09, 0x02]);
+        run(&mut panel, &mut bus, &tx, &mut now, &mut log);
+        assert!(log.is_empty(), "{:?}", log);
+        assert_eq!(panel.live[&(0x7E8, 0x05)].value, 90.0);
+        assert_eq!(panel.stored_dtcs[&0x7E8], ["P0123", "U0155"]);
+        assert_eq!(panel.pending_dtcs[&0x7E8], ["P0123"]);
+        assert_eq!(panel.vins[&0x7E8], "SIMULATEDECU00001");
+
+        panel.client.request(vec![0x04]);
+        run(&mut panel, &mut bus, &tx, &mut now, &mut log);
+        assert_eq!(log, ["OBD: 0x7E8 已清除 DTC"]);
+        assert!(!panel.stored_dtcs.contains_key(&0x7E8));
+        panel.client.request(vec![0x03]);
+        run(&mut panel, &mut bus, &tx, &mut now, &mut log);
+        assert!(panel.stored_dtcs[&0x7E8].is_empty());
+    }
+
+    #[test]
+    fn response_pending_only_for_requested_mode() {
+        let respond = |payload: &[u8]| {
+            let mut data = [0xCC; 8];
+            data[0] = payload.len() as u8;
+            data[1..1 + payload.len()].copy_from_slice(payload);
+            CanFrame {
+                id: 0x7E8,
+                len: 8,
+                data,
+                ..Default::default()
+            }
+        };
+        let mut client = ObdClient::default();
+        client.request(vec![0x01, 0x0C]);
+        client.poll(0);
+        // 別的 mode 的 0x78 不延長時間窗
+        client.handle(&respond(&[0x7F, 0x09, 0x78]), 1_000);
+        client.poll(1_000);
+        client.poll(301_000);
+        let reply = client.replies.pop_front().unwrap();
+        assert_eq!(reply.responses, [(0x7E8, vec![0x7F, 0x09, 0x78])]);
+
+        client.request(vec![0x01, 0x0C]);
+        client.poll(302_000);
+        client.handle(&respond(&[0x7F, 0x01, 0x78]), 303_000);
+        client.poll(303_000);
+        client.poll(700_000);
+        assert!(client.replies.is_empty());
+        client.handle(&respond(&[0x41, 0x0C, 0x1A, 0xF8]), 800_000);
+        client.poll(800_000);
+        client.poll(5_303_001);
+        let reply = client.replies.pop_front().unwrap();
+        assert_eq!(reply.responses, [(0x7E8, vec![0x41, 0x0C, 0x1A, 0xF8])]);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::canbus::CanFrame;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Read, Write};
+
+// pcapng 區塊類型
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+const SOCKETCAN_FRAME_LEN: usize = 16;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    while !body.len().is_multiple_of(4) {
+        body.push(0);
+    }
+}
+
+fn write_block<W: Write>(w: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let total_len = (12 + body.len()) as u32;
+    w.write_all(&block_type.to_le_bytes())?;
+    w.write_all(&total_len.to_le_bytes())?;
+    w.write_all(body)?;
+    w.write_all(&total_len.to_le_bytes())
+}
+
+/// 編碼為 LINKTYPE_CAN_SOCKETCAN 的 16 bytes 封包 (can_id 為 network byte order)
+fn encode_socketcan(frame: &CanFrame) -> [u8; SOCKETCAN_FRAME_LEN] {
+    let mut can_id = if frame.extended {
+        (frame.id & CAN_EFF_MASK) | CAN_EFF_FLAG
+    } else {
+        frame.id & 0x7FF
+    };
+    if frame.remote {
+        can_id |= CAN_RTR_FLAG;
+    }
+    if frame.error {
+        can_id |= CAN_ERR_FLAG;
+    }
+
+    let mut buf = [0u8; SOCKETCAN_FRAME_LEN];
+    buf[0..4].copy_from_slice(&can_id.to_be_bytes());
+    buf[4] = frame.len.min(8);
+    buf[8..16].copy_from_slice(&frame.data);
+    buf
+}
+
+fn decode_socketcan(packet: &[u8], channel: u32, timestamp_us: u64) -> Option<CanFrame> {
+    if packet.len() < 8 {
+        return None;
+    }
+    let can_id = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]);
+    let len = packet[4].min(8);
+    let mut data = [0u8; 8];
+    let available = packet.len().saturating_sub(8).min(len as usize);
+    data[..available].copy_from_slice(&packet[8..8 + available]);
+
+    let extended = can_id & CAN_EFF_FLAG != 0;
+    Some(CanFrame {
+        timestamp_us,
+        channel,
+        id: if extended {
+            can_id & CAN_EFF_MASK
+        } else {
+            can_id & 0x7FF
+        },
+        extended,
+        remote: can_id & CAN_RTR_FLAG != 0,
+        error: can_id & CAN_ERR_FLAG != 0,
+        len,
+        data,
+    })
+}
+
+/// 匯出為 pcapng，每個通道一個 interface (if_name = "canN")，時間解析度為微秒
+pub fn write_pcapng<'a, W: Write>(
+    w: &mut W,
+    frames: impl Iterator<Item = &'a CanFrame> + Clone,
+) -> io::Result<()> {
+    // 1. **Section Header Block**
+    let mut shb = Vec::new();
+    shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    shb.extend_from_slice(&1u16.to_le_bytes());
+    shb.extend_from_slice(&0u16.to_le_bytes());
+    shb.extend_from_slice(&(-1i64).to_le_bytes());
+    push_option(
+        &mut shb,
+        OPT_SHB_USERAPPL,
+        env!("CARGO_PKG_NAME").as_bytes(),
+    );
+    push_option(&mut shb, OPT_ENDOFOPT, &[]);
+    write_block(w, BLOCK_SHB, &shb)?;
+
+    // 2. **每個通道一個 Interface Description Block**
+    // 先排序通道再編號，IDB 的順序才會和 EPB 的 interface ID 一致
+    let channels: BTreeSet<u32> = frames.clone().map(|f| f.channel).collect();
+    let interfaces: HashMap<u32, u32> = channels
+        .iter()
+        .enumerate()
+        .map(|(id, &channel)| (channel, id as u32))
+        .collect();
+    for channel in &channels {
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&(SOCKETCAN_FRAME_LEN as u32).to_le_bytes());
+        push_option(&mut idb, OPT_IF_NAME, format!("can{}", channel).as_bytes());
+        push_option(&mut idb, OPT_IF_TSRESOL, &[6]);
+        push_option(&mut idb, OPT_ENDOFOPT, &[]);
+        write_block(w, BLOCK_IDB, &idb)?;
+    }
+
+    // 3. **Enhanced Packet Blocks**
+    let mut epb = Vec::with_capacity(20 + SOCKETCAN_FRAME_LEN);
+    for frame in frames {
+        epb.clear();
+        epb.extend_from_slice(&interfaces[&frame.channel].to_le_bytes());
+        epb.extend_from_slice(&((frame.timestamp_us >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(frame.timestamp_us as u32).to_le_bytes());
+        epb.extend_from_slice(&(SOCKETCAN_FRAME_LEN as u32).to_le_bytes());
+        epb.extend_from_slice(&(SOCKETCAN_FRAME_LEN as u32).to_le_bytes());
+        epb.extend_from_slice(&encode_socketcan(frame));
+        write_block(w, BLOCK_EPB, &epb)?;
+    }
+
+    w.flush()
+}
+
+struct Interface {
+    link_type: u16,
+    channel: u32,
+    // 每秒的 tick 數
+    ticks_per_sec: u64,
+}
+
+struct Section {
+    big_endian: bool,
+}
+
+impl Section {
+    fn u16(&self, b: &[u8]) -> u16 {
+        let v = [b[0], b[1]];
+        if self.big_endian {
+            u16::from_be_bytes(v)
+        } else {
+            u16::from_le_bytes(v)
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let v = [b[0], b[1], b[2], b[3]];
+        if self.big_endian {
+            u32::from_be_bytes(v)
+        } else {
+            u32::from_le_bytes(v)
+        }
+    }
+}
+
+fn parse_options<'a>(section: &Section, mut body: &'a [u8]) -> Vec<(u16, &'a [u8])> {
+    let mut options = Vec::new();
+    while body.len() >= 4 {
+        let code = section.u16(&body[0..2]);
+        let len = section.u16(&body[2..4]) as usize;
+        if code == OPT_ENDOFOPT || body.len() < 4 + len {
+            break;
+        }
+        options.push((code, &body[4..4 + len]));
+        let padded = (len + 3) & !3;
+        body = &body[(4 + padded).min(body.len())..];
+    }
+    options
+}
+
+fn parse_tsresol(value: u8) -> u64 {
+    let exp = (value & 0x7F) as u32;
+    if value & 0x80 != 0 {
+        1u64.checked_shl(exp).unwrap_or(u64::MAX)
+    } else {
+        10u64.checked_pow(exp).unwrap_or(u64::MAX)
+    }
+}
+
+/// 讀回 pcapng，只保留 LINKTYPE_CAN_SOCKETCAN interface 的封包
+pub fn read_pcapng<R: Read>(r: &mut R) -> io::Result<Vec<CanFrame>> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+
+    let mut frames = Vec::new();
+    let mut section: Option<Section> = None;
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut pos = 0;
+
+    while pos + 12 <= data.len() {
+        let raw_type = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+
+        if raw_type == BLOCK_SHB {
+            let magic = &data[pos + 8..pos + 12];
+            let big_endian = match u32::from_le_bytes(magic.try_into().unwrap()) {
+                BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("pcapng: 無效的 byte-order magic")),
+            };
+            section = Some(Section { big_endian });
+            interfaces.clear();
+        }
+
+        let sec = section
+            .as_ref()
+            .ok_or_else(|| invalid("不是 pcapng 檔案 (缺少 Section Header Block)"))?;
+        let block_type = sec.u32(&data[pos..pos + 4]);
+        let total_len = sec.u32(&data[pos + 4..pos + 8]) as usize;
+        if total_len < 12 || !total_len.is_multiple_of(4) || pos + total_len > data.len() {
+            return Err(invalid(format!("pcapng: 區塊長度錯誤 (offset {})", pos)));
+        }
+        let body = &data[pos + 8..pos + total_len - 4];
+
+        match block_type {
+            BLOCK_IDB if body.len() >= 8 => {
+                let index = interfaces.len() as u32;
+                let mut iface = Interface {
+                    link_type: sec.u16(&body[0..2]),
+                    channel: index,
+                    ticks_per_sec: 1_000_000,
+                };
+                for (code, value) in parse_options(sec, &body[8..]) {
+                    match code {
+                        OPT_IF_NAME => {
+                            let name = String::from_utf8_lossy(value);
+                            let name = name.trim_end_matches('\0');
+                            let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+                            if let Ok(n) = digits.parse() {
+                                iface.channel = n;
+                            }
+                        }
+                        OPT_IF_TSRESOL if !value.is_empty() => {
+                            iface.ticks_per_sec = parse_tsresol(value[0]);
+                        }
+                        _ => {}
+                    }
+                }
+                interfaces.push(iface);
+            }
+            BLOCK_EPB if body.len() >= 20 => {
+                let if_id = sec.u32(&body[0..4]) as usize;
+                let iface = interfaces
+                    .get(if_id)
+                    .ok_or_else(|| invalid(format!("pcapng: 未定義的 interface {}", if_id)))?;
+                if iface.link_type == LINKTYPE_CAN_SOCKETCAN {
+                    let ticks =
+                        ((sec.u32(&body[4..8]) as u64) << 32) | sec.u32(&body[8..12]) as u64;
+                    let timestamp_us =
+                        (ticks as u128 * 1_000_000 / iface.ticks_per_sec as u128) as u64;
+                    let cap_len = (sec.u32(&body[12..16]) as usize).min(body.len() - 20);
+                    let packet = &body[20..20 + cap_len];
+                    if let Some(frame) = decode_socketcan(packet, iface.channel, timestamp_us) {
+                        frames.push(frame);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        pos += total_len;
+    }
+
+    if section.is_none() {
+        return Err(invalid("不是 pcapng 檔案"));
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_keeps_channels() {
+        // 第一幀在通道 1，確認 interface 編號不會和 IDB 順序錯開
+        let frames: Vec<CanFrame> = (0..6u32)
+            .map(|i| CanFrame {
+                timestamp_us: 1_700_000_000_000_000 + i as u64 * 1500,
+                channel: 1 - i % 2,
+                id: 0x100 + i,
+                extended: i == 3,
+                len: 8,
+                data: [i as u8, 1, 2, 3, 4, 5, 6, 7],
+                ..Default::default()
+            })
+            .collect();
+        let mut buf = Vec::new();
+        write_pcapng(&mut buf, frames.iter()).unwrap();
+        assert_eq!(read_pcapng(&mut &buf[..]).unwrap(), frames);
+    }
+}
@@ -0,0 +1,609 @@
+use crate::canbus::CanFrame;
+use crate::dbc::{ByteOrder, Dbc, Multiplex, Signal};
+use eframe::egui;
+use egui::{Align2, Color32, FontId, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use std::collections::{HashMap, VecDeque};
+
+const AXIS_WIDTH: f32 = 56.0;
+const PLOT_HEIGHT: f32 = 320.0;
+// 每條曲線最多保留的樣本數，超過就丟掉最舊的
+const MAX_SAMPLES: usize = 2_000_000;
+// 一條軸最多畫的刻度數
+const MAX_TICKS: usize = 100;
+
+const PALETTE: [Color32; 8] = [
+    Color32::from_rgb(31, 119, 180),
+    Color32::from_rgb(255, 127, 14),
+    Color32::from_rgb(44, 160, 44),
+    Color32::from_rgb(214, 39, 40),
+    Color32::from_rgb(148, 103, 189),
+    Color32::from_rgb(140, 86, 75),
+    Color32::from_rgb(227, 119, 194),
+    Color32::from_rgb(23, 190, 207),
+];
+
+/// 曲線來源：DBC 訊號，或未定義 ID 的原始位元範圍 (以合成的 Signal 表示)
+pub struct PlotSource {
+    pub id: u32,
+    pub extended: bool,
+    pub signal: Signal,
+    pub multiplexor: Option<Signal>,
+}
+
+impl PlotSource {
+    fn key(&self) -> (u32, bool) {
+        (self.id, self.extended)
+    }
+
+    fn sample(&self, frame: &CanFrame) -> Option<f64> {
+        let data = frame.payload();
+        if let (Multiplex::Multiplexed(v), Some(mux)) = (self.signal.multiplex, &self.multiplexor) {
+            if mux.raw_value(data) as u64 != v {
+                return None;
+            }
+        }
+        let bits_needed = match self.signal.byte_order {
+            ByteOrder::Intel => self.signal.start_bit + self.signal.size,
+            ByteOrder::Motorola => self.signal.start_bit / 8 * 8 + 8,
+        };
+        if (data.len() as u32) * 8 < bits_needed.min(64) {
+            return None;
+        }
+        Some(self.signal.raw_value(data) as f64 * self.signal.factor + self.signal.offset)
+    }
+}
+
+pub fn raw_source(
+    id: u32,
+    extended: bool,
+    start_bit: u32,
+    size: u32,
+    order: ByteOrder,
+    signed: bool,
+) -> PlotSource {
+    PlotSource {
+        id,
+        extended,
+        signal: Signal {
+            name: format!("0x{:X}[{}:{}]", id, start_bit, size),
+            start_bit,
+            size,
+            byte_order: order,
+            signed,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+            unit: String::new(),
+            receivers: Vec::new(),
+            multiplex: Multiplex::None,
+            comment: None,
+            value_descriptions: Vec::new(),
+        },
+        multiplexor: None,
+    }
+}
+
+pub struct Series {
+    pub source: PlotSource,
+    pub color: Color32,
+    pub visible: bool,
+    // (秒, 值)，時間遞增
+    pub samples: VecDeque<(f64, f64)>,
+    // None 表示自動縮放
+    pub y_range: Option<(f64, f64)>,
+}
+
+impl Series {
+    fn visible_slice(&self, t_min: f64, t_max: f64) -> (usize, usize) {
+        let start = self
+            .samples
+            .partition_point(|s| s.0 < t_min)
+            .saturating_sub(1);
+        let end = (self.samples.partition_point(|s| s.0 <= t_max) + 1).min(self.samples.len());
+        (start, end.max(start))
+    }
+
+    fn auto_range(&self, t_min: f64, t_max: f64) -> (f64, f64) {
+        let (start, end) = self.visible_slice(t_min, t_max);
+        let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+        for &(_, v) in self.samples.range(start..end) {
+            lo = lo.min(v);
+            hi = hi.max(v);
+        }
+        if !lo.is_finite() {
+            return (0.0, 1.0);
+        }
+        if hi - lo < 1e-9 {
+            return (lo - 1.0, hi + 1.0);
+        }
+        let pad = (hi - lo) * 0.05;
+        (lo - pad, hi + pad)
+    }
+
+    fn value_at(&self, t: f64) -> Option<(f64, f64)> {
+        let i = self.samples.partition_point(|s| s.0 < t);
+        let candidates = [i.checked_sub(1), Some(i)];
+        candidates
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.samples.get(i).copied())
+            .min_by(|a, b| (a.0 - t).abs().total_cmp(&(b.0 - t).abs()))
+    }
+}
+
+pub struct PlotPanel {
+    pub series: Vec<Series>,
+    pub window_s: f64,
+    pub follow: bool,
+    pub view: (f64, f64),
+    t0_us: Option<u64>,
+    latest_t: f64,
+    by_id: HashMap<(u32, bool), Vec<usize>>,
+    // 新增曲線的 UI 狀態
+    pick_message: usize,
+    pick_signal: usize,
+    raw_id: String,
+    raw_extended: bool,
+    raw_start: u32,
+    raw_size: u32,
+    raw_motorola: bool,
+    raw_signed: bool,
+}
+
+impl Default for PlotPanel {
+    fn default() -> Self {
+        Self {
+            series: Vec::new(),
+            window_s: 10.0,
+            follow: true,
+            view: (0.0, 10.0),
+            t0_us: None,
+            latest_t: 0.0,
+            by_id: HashMap::new(),
+            pick_message: 0,
+            pick_signal: 0,
+            raw_id: "100".to_string(),
+            raw_extended: false,
+            raw_start: 0,
+            raw_size: 8,
+            raw_motorola: false,
+            raw_signed: false,
+        }
+    }
+}
+
+fn nice_step(range: f64, target_ticks: f64) -> f64 {
+    let raw = (range / target_ticks).max(1e-12);
+    let mag = 10f64.powf(raw.log10().floor());
+    let norm = raw / mag;
+    let nice = if norm < 1.5 {
+        1.0
+    } else if norm < 3.0 {
+        2.0
+    } else if norm < 7.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * mag
+}
+
+/// lo..=hi 之間的刻度位置；用索引計算，數值大到 step 加不上去時也不會卡住
+fn ticks(lo: f64, hi: f64, step: f64) -> impl Iterator<Item = f64> {
+    let first = (lo / step).ceil() * step;
+    let count = if first + step > first && hi >= first {
+        ((hi - first) / step).floor().min(MAX_TICKS as f64) as usize
+    } else {
+        0
+    };
+    (0..=count)
+        .map(move |i| first + i as f64 * step)
+        .filter(move |&v| v <= hi)
+}
+
+fn format_tick(v: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).clamp(0.0, 6.0) as usize;
+    format!("{:.*}", decimals, v)
+}
+
+impl PlotPanel {
+    pub fn add_source(&mut self, source: PlotSource) {
+        let color = PALETTE[self.series.len() % PALETTE.len()];
+        self.series.push(Series {
+            source,
+            color,
+            visible: true,
+            samples: VecDeque::new(),
+            y_range: None,
+        });
+        self.rebuild_index();
+    }
+
+    fn rebuild_index(&mut self) {
+        self.by_id.clear();
+        for (i, s) in self.series.iter().enumerate() {
+            self.by_id.entry(s.source.key()).or_default().push(i);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for s in &mut self.series {
+            s.samples.clear();
+        }
+        self.t0_us = None;
+        self.latest_t = 0.0;
+    }
+
+    /// 由顯示篩選掃描新幀時呼叫，每幀只查一次 HashMap
+    pub fn feed(&mut self, frame: &CanFrame) {
+        let Some(indices) = self.by_id.get(&(frame.id, frame.extended)) else {
+            return;
+        };
+        let t0 = *self.t0_us.get_or_insert(frame.timestamp_us);
+        let t = frame.timestamp_us.saturating_sub(t0) as f64 / 1e6;
+        for &i in indices {
+            let series = &mut self.series[i];
+            if let Some(v) = series.source.sample(frame) {
+                if series.samples.back().is_some_and(|last| last.0 > t) {
+                    continue;
+                }
+                if series.samples.len() >= MAX_SAMPLES {
+                    series.samples.pop_front();
+                }
+                series.samples.push_back((t, v));
+                self.latest_t = self.latest_t.max(t);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, dbc: Option<&Dbc>) {
+        self.selection_ui(ui, dbc);
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("時間窗 (s):");
+            ui.add(
+                egui::DragValue::new(&mut self.window_s)
+                    .range(0.1..=3600.0)
+                    .speed(0.1),
+            );
+            if ui.checkbox(&mut self.follow, "跟隨最新").changed() && !self.follow {
+                self.view = (self.latest_t - self.window_s, self.latest_t);
+            }
+            if ui.button("清除資料").clicked() {
+                self.clear();
+            }
+            ui.label("滾輪: 縮放時間 / 在軸上縮放 Y, 拖曳: 平移, 雙擊: 重設");
+        });
+
+        self.plot_ui(ui);
+    }
+
+    fn selection_ui(&mut self, ui: &mut egui::Ui, dbc: Option<&Dbc>) {
+        if let Some(dbc) = dbc.filter(|d| !d.messages.is_empty()) {
+            ui.horizontal(|ui| {
+                ui.label("訊號:");
+                self.pick_message = self.pick_message.min(dbc.messages.len() - 1);
+                let message = &dbc.messages[self.pick_message];
+                egui::ComboBox::from_id_salt("plot_message")
+                    .selected_text(format!("0x{:X} {}", message.id, message.name))
+                    .show_ui(ui, |ui| {
+                        for (i, m) in dbc.messages.iter().enumerate() {
+                            ui.selectable_value(
+                                &mut self.pick_message,
+                                i,
+                                format!("0x{:X} {}", m.id, m.name),
+                            );
+                        }
+                    });
+                let message = &dbc.messages[self.pick_message];
+                if message.signals.is_empty() {
+                    return;
+                }
+                self.pick_signal = self.pick_signal.min(message.signals.len() - 1);
+                egui::ComboBox::from_id_salt("plot_signal")
+                    .selected_text(&message.signals[self.pick_signal].name)
+                    .show_ui(ui, |ui| {
+                        for (i, s) in message.signals.iter().enumerate() {
+                            ui.selectable_value(&mut self.pick_signal, i, &s.name);
+                        }
+                    });
+                if ui.button("新增訊號").clicked() {
+                    self.add_source(PlotSource {
+                        id: message.id,
+                        extended: message.extended,
+                        signal: message.signals[self.pick_signal].clone(),
+                        multiplexor: message
+                            .signals
+                            .iter()
+                            .find(|s| s.multiplex == Multiplex::Multiplexor)
+                            .cloned(),
+                    });
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("原始 ID (hex):");
+            ui.add(egui::TextEdit::singleline(&mut self.raw_id).desired_width(70.0));
+            ui.checkbox(&mut self.raw_extended, "擴展");
+            ui.label("起始位元:");
+            ui.add(egui::DragValue::new(&mut self.raw_start).range(0..=63));
+            ui.label("長度:");
+            ui.add(egui::DragValue::new(&mut self.raw_size).range(1..=64));
+            ui.checkbox(&mut self.raw_motorola, "Motorola");
+            ui.checkbox(&mut self.raw_signed, "有號");
+            if ui.button("新增原始位元").clicked() {
+                let hex = self.raw_id.trim().trim_start_matches("0x");
+                if let Ok(id) = u32::from_str_radix(hex, 16) {
+                    let order = if self.raw_motorola {
+                        ByteOrder::Motorola
+                    } else {
+                        ByteOrder::Intel
+                    };
+                    self.add_source(raw_source(
+                        id,
+                        self.raw_extended,
+                        self.raw_start,
+                        self.raw_size,
+                        order,
+                        self.raw_signed,
+                    ));
+                }
+            }
+        });
+
+        let mut remove = None;
+        for (i, s) in self.series.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.colored_label(s.color, "■");
+                ui.checkbox(&mut s.visible, &s.source.signal.name);
+                ui.label(format!("{} 樣本", s.samples.len()));
+                if s.y_range.is_some() && ui.small_button("自動 Y").clicked() {
+                    s.y_range = None;
+                }
+                if ui.small_button("移除").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.series.remove(i);
+            self.rebuild_index();
+        }
+    }
+
+    fn plot_ui(&mut self, ui: &mut egui::Ui) {
+        let size = Vec2::new(ui.available_width(), PLOT_HEIGHT);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+        let visible: Vec<usize> = (0..self.series.len())
+            .filter(|&i| self.series[i].visible)
+            .collect();
+        let axes_width = AXIS_WIDTH * visible.len() as f32;
+        let data_rect = Rect::from_min_max(
+            Pos2::new(rect.left() + axes_width, rect.top() + 16.0),
+            Pos2::new(rect.right() - 8.0, rect.bottom() - 18.0),
+        );
+        if data_rect.width() < 20.0 || data_rect.height() < 20.0 {
+            return;
+        }
+
+        // 1. **時間範圍與互動**
+        if self.follow {
+            self.view = (self.latest_t - self.window_s, self.latest_t);
+        }
+        let (mut t_min, mut t_max) = self.view;
+        let span = (t_max - t_min).max(1e-6);
+        let hover = response.hover_pos();
+        let hovered_axis = hover
+            .filter(|p| p.x < data_rect.left())
+            .map(|p| ((p.x - rect.left()) / AXIS_WIDTH) as usize)
+            .and_then(|i| visible.get(i).copied());
+
+        let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+        if let (Some(pos), true) = (hover, scroll != 0.0) {
+            let factor = (-scroll as f64 * 0.002).exp();
+            if let Some(i) = hovered_axis {
+                let (lo, hi) = self.series[i]
+                    .y_range
+                    .unwrap_or_else(|| self.series[i].auto_range(t_min, t_max));
+                let center = (lo + hi) / 2.0;
+                self.series[i].y_range = Some((
+                    center - (center - lo) * factor,
+                    center + (hi - center) * factor,
+                ));
+            } else {
+                let anchor = t_min + ((pos.x - data_rect.left()) / data_rect.width()) as f64 * span;
+                t_min = anchor - (anchor - t_min) * factor;
+                t_max = anchor + (t_max - anchor) * factor;
+                self.follow = false;
+            }
+        }
+        if response.dragged() {
+            let delta = response.drag_delta();
+            if let Some(i) = hovered_axis {
+                let (lo, hi) = self.series[i]
+                    .y_range
+                    .unwrap_or_else(|| self.series[i].auto_range(t_min, t_max));
+                let dv = delta.y as f64 / data_rect.height() as f64 * (hi - lo);
+                self.series[i].y_range = Some((lo + dv, hi + dv));
+            } else {
+                let dt = -delta.x as f64 / data_rect.width() as f64 * (t_max - t_min);
+                t_min += dt;
+                t_max += dt;
+                self.follow = false;
+            }
+        }
+        if response.double_clicked() {
+            match hovered_axis {
+                Some(i) => self.series[i].y_range = None,
+                None => self.follow = true,
+            }
+        }
+        if !self.follow {
+            self.view = (t_min, t_max);
+        }
+        let span = (t_max - t_min).max(1e-6);
+        let to_x = |t: f64| data_rect.left() + ((t - t_min) / span) as f32 * data_rect.width();
+
+        // 2. **時間格線**
+        let grid = ui.visuals().weak_text_color().gamma_multiply(0.3);
+        let text_color = ui.visuals().text_color();
+        let step = nice_step(span, (data_rect.width() / 100.0) as f64);
+        for t in ticks(t_min, t_max, step) {
+            let x = to_x(t);
+            painter.line_segment(
+                [
+                    Pos2::new(x, data_rect.top()),
+                    Pos2::new(x, data_rect.bottom()),
+                ],
+                Stroke::new(1.0, grid),
+            );
+            painter.text(
+                Pos2::new(x, data_rect.bottom() + 2.0),
+                Align2::CENTER_TOP,
+                format_tick(t, step),
+                FontId::monospace(10.0),
+                text_color,
+            );
+        }
+
+        // 3. **每條曲線一個 Y 軸 + 降取樣繪製 (每個像素欄保留 min/max)**
+        for (axis, &i) in visible.iter().enumerate() {
+            let series = &self.series[i];
+            let (lo, hi) = series
+                .y_range
+                .unwrap_or_else(|| series.auto_range(t_min, t_max));
+            let to_y = |v: f64| {
+                data_rect.bottom() - ((v - lo) / (hi - lo).max(1e-12)) as f32 * data_rect.height()
+            };
+
+            let axis_x = rect.left() + AXIS_WIDTH * (axis as f32 + 1.0) - 4.0;
+            painter.line_segment(
+                [
+                    Pos2::new(axis_x, data_rect.top()),
+                    Pos2::new(axis_x, data_rect.bottom()),
+                ],
+                Stroke::new(1.0, series.color),
+            );
+            painter.text(
+                Pos2::new(axis_x, rect.top() + 2.0),
+                Align2::RIGHT_TOP,
+                &series.source.signal.name,
+                FontId::proportional(10.0),
+                series.color,
+            );
+            let ystep = nice_step(hi - lo, (data_rect.height() / 40.0) as f64);
+            for v in ticks(lo, hi, ystep) {
+                let y = to_y(v);
+                painter.line_segment(
+                    [Pos2::new(axis_x - 3.0, y), Pos2::new(axis_x, y)],
+                    Stroke::new(1.0, series.color),
+                );
+                painter.text(
+                    Pos2::new(axis_x - 4.0, y),
+                    Align2::RIGHT_CENTER,
+                    format_tick(v, ystep),
+                    FontId::monospace(9.0),
+                    series.color,
+                );
+            }
+
+            let (start, end) = series.visible_slice(t_min, t_max);
+            let width = data_rect.width().max(1.0) as usize;
+            let mut points = Vec::new();
+            if end - start > width * 4 {
+                let mut column = usize::MAX;
+                let (mut cmin, mut cmax) = (0.0, 0.0);
+                for &(t, v) in series.samples.range(start..end) {
+                    let c = ((to_x(t) - data_rect.left()).max(0.0) as usize).min(width);
+                    if c != column {
+                        if column != usize::MAX {
+                            let x = data_rect.left() + column as f32;
+                            points.push(Pos2::new(x, to_y(cmin)));
+                            points.push(Pos2::new(x, to_y(cmax)));
+                        }
+                        column = c;
+                        cmin = v;
+                        cmax = v;
+                    } else {
+                        cmin = f64::min(cmin, v);
+                        cmax = f64::max(cmax, v);
+                    }
+                }
+                if column != usize::MAX {
+                    let x = data_rect.left() + column as f32;
+                    points.push(Pos2::new(x, to_y(cmin)));
+                    points.push(Pos2::new(x, to_y(cmax)));
+                }
+            } else {
+                points.extend(
+                    series
+                        .samples
+                        .range(start..end)
+                        .map(|&(t, v)| Pos2::new(to_x(t), to_y(v))),
+                );
+            }
+            if points.len() >= 2 {
+                ui.painter()
+                    .with_clip_rect(data_rect)
+                    .add(Shape::line(points, Stroke::new(1.5, series.color)));
+            }
+        }
+
+        // 4. **游標讀值**
+        if let Some(pos) = hover.filter(|p| data_rect.contains(*p)) {
+            let t = t_min + ((pos.x - data_rect.left()) / data_rect.width()) as f64 * span;
+            painter.line_segment(
+                [
+                    Pos2::new(pos.x, data_rect.top()),
+                    Pos2::new(pos.x, data_rect.bottom()),
+                ],
+                Stroke::new(1.0, text_color),
+            );
+            let readings: Vec<(Color32, String)> = visible
+                .iter()
+                .filter_map(|&i| {
+                    let s = &self.series[i];
+                    s.value_at(t).map(|(st, v)| {
+                        (
+                            s.color,
+                            format!(
+                                "{} = {:.4} {} @ {:.4}s",
+                                s.source.signal.name, v, s.source.signal.unit, st
+                            ),
+                        )
+                    })
+                })
+                .collect();
+            response.on_hover_ui_at_pointer(|ui| {
+                ui.label(format!("t = {:.4} s", t));
+                for (color, text) in readings {
+                    ui.colored_label(color, text);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_stop_on_huge_values() {
+        assert_eq!(
+            ticks(0.0, 1.0, 0.25).collect::<Vec<_>>(),
+            [0.0, 0.25, 0.5, 0.75, 1.0]
+        );
+        // 2^60 附近 ±1 的範圍，step 加上去數值不變
+        let v = (1u64 << 60) as f64;
+        let step = nice_step(2.0, 8.0);
+        assert!(ticks(v - 1.0, v + 1.0, step).count() <= 1);
+        assert_eq!(ticks(0.0, 1e9, 1e-3).count(), MAX_TICKS + 1);
+    }
+}
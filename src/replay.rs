@@ -0,0 +1,488 @@
+use crate::canbus::{now_us, CanFrame};
+use crate::frame_store::FrameStore;
+use eframe::egui;
+use flume::Sender;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdFilterMode {
+    All,
+    Include,
+    Exclude,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    pub speed: f64,
+    pub looping: bool,
+    pub step_mode: bool,
+    // 相對於 trace 第一幀的時間窗 (ms)
+    pub start_ms: f64,
+    pub end_ms: Option<f64>,
+    pub filter_mode: IdFilterMode,
+    // (ID, 是否為 29 位元)，同一個數值的 11 與 29 位元 ID 是不同的幀
+    pub filter_ids: HashSet<(u32, bool)>,
+    // None 表示保留 trace 中原本的通道
+    pub channel: Option<u32>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            looping: false,
+            step_mode: false,
+            start_ms: 0.0,
+            end_ms: None,
+            filter_mode: IdFilterMode::All,
+            filter_ids: HashSet::new(),
+            channel: None,
+        }
+    }
+}
+
+/// 回放執行緒與 UI 之間共享的狀態
+#[derive(Default)]
+pub struct ReplayShared {
+    pub running: AtomicBool,
+    pub paused: AtomicBool,
+    pub step_mode: AtomicBool,
+    pub steps: AtomicU32,
+    pub position: AtomicUsize,
+    pub total: AtomicUsize,
+    pub loops: AtomicU32,
+    pub max_late_us: AtomicU64,
+}
+
+impl ReplayShared {
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn progress(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        self.position.load(Ordering::Relaxed) as f32 / total as f32
+    }
+}
+
+/// 依時間窗與 ID 篩選出要回放的幀
+pub fn select_frames(frames: &FrameStore, config: &ReplayConfig) -> Vec<CanFrame> {
+    let Some(first) = frames.first() else {
+        return Vec::new();
+    };
+    let start_us = first.timestamp_us + (config.start_ms.max(0.0) * 1000.0) as u64;
+    let end_us = config
+        .end_ms
+        .map(|ms| first.timestamp_us + (ms.max(0.0) * 1000.0) as u64)
+        .unwrap_or(u64::MAX);
+
+    frames
+        .iter()
+        .filter(|f| f.timestamp_us >= start_us && f.timestamp_us <= end_us)
+        // 錯誤幀是控制器回報的狀態，送出去會變成一般資料幀
+        .filter(|f| !f.error)
+        .filter(|f| match config.filter_mode {
+            IdFilterMode::All => true,
+            IdFilterMode::Include => config.filter_ids.contains(&(f.id, f.extended)),
+            IdFilterMode::Exclude => !config.filter_ids.contains(&(f.id, f.extended)),
+        })
+        .copied()
+        .collect()
+}
+
+// 先 sleep 到目標前 1ms，剩下的用忙等補足，避免 OS 排程誤差累積
+fn wait_until(target: Instant, shared: &ReplayShared) {
+    loop {
+        let now = Instant::now();
+        if now >= target || !shared.running.load(Ordering::Relaxed) {
+            return;
+        }
+        let remaining = target - now;
+        if remaining > Duration::from_millis(2) {
+            thread::sleep((remaining - Duration::from_millis(1)).min(Duration::from_millis(20)));
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+pub fn start_replay(
+    frames: Vec<CanFrame>,
+    config: ReplayConfig,
+    tx: Sender<CanFrame>,
+) -> Arc<ReplayShared> {
+    let shared = Arc::new(ReplayShared::default());
+    shared.running.store(true, Ordering::SeqCst);
+    shared.step_mode.store(config.step_mode, Ordering::SeqCst);
+    shared.total.store(frames.len(), Ordering::SeqCst);
+
+    let worker = Arc::clone(&shared);
+    thread::spawn(move || {
+        let speed = config.speed.max(0.001);
+        let running = || worker.running.load(Ordering::Relaxed);
+
+        'outer: while running() && !frames.is_empty() {
+            let first_ts = frames[0].timestamp_us;
+            let offset = |f: &CanFrame| {
+                Duration::from_secs_f64(
+                    f.timestamp_us.saturating_sub(first_ts) as f64 / 1e6 / speed,
+                )
+            };
+            // 時間基準：t0 時刻對應到 base 這個偏移 (用 Duration 記錄，避免 Instant 往前減到溢位)
+            let mut t0 = Instant::now();
+            let mut base = Duration::ZERO;
+            let target_of =
+                |t0: Instant, base: Duration, f: &CanFrame| t0 + offset(f).saturating_sub(base);
+
+            for (i, frame) in frames.iter().enumerate() {
+                if worker.step_mode.load(Ordering::Relaxed) {
+                    // 1. **逐步模式**：等使用者按下一步
+                    while worker.steps.load(Ordering::SeqCst) == 0
+                        && worker.step_mode.load(Ordering::Relaxed)
+                    {
+                        if !running() {
+                            break 'outer;
+                        }
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                    let _ = worker
+                        .steps
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                    // 重新對齊時間基準，離開逐步模式後從這一幀接續計時
+                    t0 = Instant::now();
+                    base = offset(frame);
+                } else {
+                    // 2. **依原始間隔等待**，暫停期間把時間基準往後推
+                    let mut target = target_of(t0, base, frame);
+                    while worker.paused.load(Ordering::Relaxed) {
+                        let paused_at = Instant::now();
+                        while worker.paused.load(Ordering::Relaxed) {
+                            if !running() {
+                                break 'outer;
+                            }
+                            thread::sleep(Duration::from_millis(5));
+                        }
+                        t0 += paused_at.elapsed();
+                        target = target_of(t0, base, frame);
+                    }
+                    wait_until(target, &worker);
+                    let late = Instant::now().saturating_duration_since(target);
+                    worker
+                        .max_late_us
+                        .fetch_max(late.as_micros() as u64, Ordering::Relaxed);
+                }
+                if !running() {
+                    break 'outer;
+                }
+
+                let mut out = *frame;
+                out.timestamp_us = now_us();
+                if let Some(channel) = config.channel {
+                    out.channel = channel;
+                }
+                if tx.send(out).is_err() {
+                    break 'outer;
+                }
+                worker.position.store(i + 1, Ordering::Relaxed);
+            }
+
+            if !config.looping {
+                break;
+            }
+            worker.loops.fetch_add(1, Ordering::Relaxed);
+        }
+
+        worker.running.store(false, Ordering::SeqCst);
+    });
+
+    shared
+}
+
+/// 十六進位 ID 清單；結尾加 x 或超過 3 位數表示 29 位元 ID
+fn parse_id_list(text: &str) -> Result<HashSet<(u32, bool)>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let invalid = || format!("無效的 ID: {}", s);
+            let hex = s
+                .strip_prefix("0x")
+                .or_else(|| s.strip_prefix("0X"))
+                .unwrap_or(s);
+            let (hex, marked) = match hex.strip_suffix(['x', 'X']) {
+                Some(hex) => (hex, true),
+                None => (hex, false),
+            };
+            let id = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|&id| id <= 0x1FFF_FFFF)
+                .ok_or_else(invalid)?;
+            Ok((id, marked || hex.len() > 3 || id > 0x7FF))
+        })
+        .collect()
+}
+
+pub struct ReplayPanel {
+    pub config: ReplayConfig,
+    pub filter_text: String,
+    pub use_end: bool,
+    pub end_ms: f64,
+    pub keep_channel: bool,
+    pub shared: Option<Arc<ReplayShared>>,
+}
+
+impl Default for ReplayPanel {
+    fn default() -> Self {
+        Self {
+            config: ReplayConfig::default(),
+            filter_text: String::new(),
+            use_end: false,
+            end_ms: 0.0,
+            keep_channel: false,
+            shared: None,
+        }
+    }
+}
+
+impl ReplayPanel {
+    pub fn is_running(&self) -> bool {
+        self.shared
+            .as_ref()
+            .is_some_and(|s| s.running.load(Ordering::Relaxed))
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        frames: &FrameStore,
+        tx: Option<&Sender<CanFrame>>,
+        channel: u32,
+        log: &mut Vec<String>,
+    ) {
+        let running = self.is_running();
+        let duration_ms = match (frames.first(), frames.last()) {
+            (Some(a), Some(b)) => b.timestamp_us.saturating_sub(a.timestamp_us) as f64 / 1000.0,
+            _ => 0.0,
+        };
+        ui.label(format!(
+            "來源: {} 幀, 長度 {:.1} ms",
+            frames.len(),
+            duration_ms
+        ));
+
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("速度:");
+                ui.add(
+                    egui::DragValue::new(&mut self.config.speed)
+                        .range(0.01..=100.0)
+                        .speed(0.05)
+                        .suffix("x"),
+                );
+                ui.checkbox(&mut self.config.looping, "循環");
+                ui.checkbox(&mut self.keep_channel, "保留原通道");
+            });
+            ui.horizontal(|ui| {
+                ui.label("開始 (ms):");
+                ui.add(egui::DragValue::new(&mut self.config.start_ms).range(0.0..=duration_ms));
+                ui.checkbox(&mut self.use_end, "結束 (ms):");
+                ui.add_enabled(
+                    self.use_end,
+                    egui::DragValue::new(&mut self.end_ms).range(0.0..=duration_ms),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("ID 篩選:");
+                ui.selectable_value(&mut self.config.filter_mode, IdFilterMode::All, "全部");
+                ui.selectable_value(
+                    &mut self.config.filter_mode,
+                    IdFilterMode::Include,
+                    "只包含",
+                );
+                ui.selectable_value(&mut self.config.filter_mode, IdFilterMode::Exclude, "排除");
+            });
+            if self.config.filter_mode != IdFilterMode::All {
+                ui.horizontal(|ui| {
+                    ui.label("ID (hex):");
+                    ui.text_edit_singleline(&mut self.filter_text)
+                        .on_hover_text("以逗號或空白分隔；結尾加 x 或超過 3 位數為 29 位元 ID");
+                });
+            }
+        });
+
+        let mut step_mode = self.config.step_mode;
+        if ui.checkbox(&mut step_mode, "逐步模式").changed() {
+            self.config.step_mode = step_mode;
+            if let Some(shared) = &self.shared {
+                shared.step_mode.store(step_mode, Ordering::SeqCst);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if !running {
+                if ui.button("開始回放").clicked() {
+                    self.start(frames, tx, channel, log);
+                }
+            } else if let Some(shared) = self.shared.clone() {
+                if shared.paused.load(Ordering::Relaxed) {
+                    if ui.button("繼續").clicked() {
+                        shared.paused.store(false, Ordering::SeqCst);
+                    }
+                } else if ui.button("暫停").clicked() {
+                    shared.paused.store(true, Ordering::SeqCst);
+                }
+                if self.config.step_mode && ui.button("下一步").clicked() {
+                    shared.steps.fetch_add(1, Ordering::SeqCst);
+                }
+                if ui.button("停止回放").clicked() {
+                    shared.stop();
+                    log.push("回放已停止".to_string());
+                }
+            }
+        });
+
+        if let Some(shared) = &self.shared {
+            let position = shared.position.load(Ordering::Relaxed);
+            let total = shared.total.load(Ordering::Relaxed);
+            ui.add(
+                egui::ProgressBar::new(shared.progress())
+                    .text(format!("{} / {}", position, total))
+                    .show_percentage(),
+            );
+            ui.label(format!(
+                "循環次數: {}, 最大延遲: {} µs",
+                shared.loops.load(Ordering::Relaxed),
+                shared.max_late_us.load(Ordering::Relaxed)
+            ));
+        }
+    }
+
+    fn start(
+        &mut self,
+        frames: &FrameStore,
+        tx: Option<&Sender<CanFrame>>,
+        channel: u32,
+        log: &mut Vec<String>,
+    ) {
+        let Some(tx) = tx else {
+            log.push("錯誤：裝置尚未打開，無法回放".to_string());
+            return;
+        };
+        self.config.filter_ids = match parse_id_list(&self.filter_text) {
+            Ok(ids) => ids,
+            Err(e) => {
+                log.push(e);
+                return;
+            }
+        };
+        self.config.end_ms = self.use_end.then_some(self.end_ms);
+        self.config.channel = (!self.keep_channel).then_some(channel);
+
+        let selected = select_frames(frames, &self.config);
+        if selected.is_empty() {
+            log.push("回放: 沒有符合條件的幀".to_string());
+            return;
+        }
+        log.push(format!("開始回放 {} 幀", selected.len()));
+        self.shared = Some(start_replay(selected, self.config.clone(), tx.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp_us: u64, id: u32) -> CanFrame {
+        CanFrame {
+            timestamp_us,
+            id,
+            len: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn select_skips_error_frames() {
+        let mut store = FrameStore::default();
+        store.extend([
+            frame(1_000, 0x100),
+            CanFrame {
+                error: true,
+                ..frame(2_000, 0)
+            },
+            frame(3_000, 0x200),
+        ]);
+        let ids: Vec<u32> = select_frames(&store, &ReplayConfig::default())
+            .iter()
+            .map(|f| f.id)
+            .collect();
+        assert_eq!(ids, [0x100, 0x200]);
+    }
+
+    #[test]
+    fn step_mode_with_large_offsets() {
+        // 低速回放長 trace 時，換算後的偏移遠大於程式執行時間
+        let frames = vec![frame(0, 0x100), frame(1_000_000_000_000, 0x200)];
+        let config = ReplayConfig {
+            speed: 0.001,
+            step_mode: true,
+            ..Default::default()
+        };
+        let (tx, rx) = flume::unbounded();
+        let shared = start_replay(frames, config, tx);
+        shared.steps.store(2, Ordering::SeqCst);
+        let timeout = Duration::from_secs(2);
+        assert_eq!(rx.recv_timeout(timeout).unwrap().id, 0x100);
+        assert_eq!(rx.recv_timeout(timeout).unwrap().id, 0x200);
+        shared.stop();
+    }
+
+    #[test]
+    fn filter_tells_standard_and_extended_ids_apart() {
+        assert_eq!(
+            parse_id_list("100, 0x100x 0100 18FEF100 7ff").unwrap(),
+            HashSet::from([
+                (0x100, false),
+                (0x100, true),
+                (0x18FE_F100, true),
+                (0x7FF, false),
+            ])
+        );
+        assert!(parse_id_list("12G").is_err());
+        assert!(parse_id_list("x").is_err());
+        assert!(parse_id_list("20000000").is_err());
+
+        let mut store = FrameStore::default();
+        store.extend([
+            frame(1_000, 0x100),
+            CanFrame {
+                extended: true,
+                ..frame(2_000, 0x100)
+            },
+            frame(3_000, 0x200),
+        ]);
+        let select = |mode, text| {
+            let config = ReplayConfig {
+                filter_mode: mode,
+                filter_ids: parse_id_list(text).unwrap(),
+                ..Default::default()
+            };
+            select_frames(&store, &config)
+                .iter()
+                .map(|f| (f.id, f.extended))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(IdFilterMode::Include, "100"), [(0x100, false)]);
+        assert_eq!(select(IdFilterMode::Include, "100x"), [(0x100, true)]);
+        assert_eq!(
+            select(IdFilterMode::Exclude, "00000100"),
+            [(0x100, false), (0x200, false)]
+        );
+    }
+}
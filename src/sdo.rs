@@ -0,0 +1,832 @@
+use crate::canbus::CanFrame;
+use std::collections::VecDeque;
+
+// 用戶端 -> 伺服器 0x600 + 節點，伺服器 -> 用戶端 0x580 + 節點
+pub const SDO_REQUEST_BASE: u32 = 0x600;
+pub const SDO_RESPONSE_BASE: u32 = 0x580;
+const ABORT: u8 = 0x80;
+const ABORT_TIMEOUT: u32 = 0x0504_0000;
+const ABORT_COMMAND: u32 = 0x0504_0001;
+const ABORT_SEQUENCE: u32 = 0x0504_0003;
+const ABORT_CRC: u32 = 0x0504_0004;
+// 區塊傳輸每個子區塊最多 127 段
+pub const MAX_BLOCK_SIZE: u8 = 127;
+
+pub fn abort_name(code: u32) -> &'static str {
+    match code {
+        0x0503_0000 => "切換位元沒有交替",
+        0x0504_0000 => "SDO 逾時",
+        0x0504_0001 => "無效的命令碼",
+        0x0504_0002 => "無效的區塊大小",
+        0x0504_0003 => "無效的序號",
+        0x0504_0004 => "CRC 錯誤",
+        0x0504_0005 => "記憶體不足",
+        0x0601_0000 => "不支援的存取",
+        0x0601_0001 => "物件只能寫入",
+        0x0601_0002 => "物件唯讀",
+        0x0602_0000 => "物件不存在",
+        0x0604_0041 => "物件不能映射到 PDO",
+        0x0604_0042 => "超過 PDO 長度",
+        0x0604_0043 => "參數不相容",
+        0x0604_0047 => "裝置內部不相容",
+        0x0606_0000 => "硬體錯誤",
+        0x0607_0010 => "資料型別或長度不符",
+        0x0607_0012 => "資料太長",
+        0x0607_0013 => "資料太短",
+        0x0609_0011 => "子索引不存在",
+        0x0609_0030 => "數值無效",
+        0x0609_0031 => "數值太大",
+        0x0609_0032 => "數值太小",
+        0x0609_0036 => "最大值小於最小值",
+        0x060A_0023 => "資源無法使用",
+        0x0800_0000 => "一般錯誤",
+        0x0800_0020 => "資料無法傳送或儲存",
+        0x0800_0021 => "本地控制中，無法傳送或儲存",
+        0x0800_0022 => "目前裝置狀態下無法傳送或儲存",
+        0x0800_0023 => "沒有物件字典",
+        0x0800_0024 => "沒有資料",
+        _ => "未知的中止碼",
+    }
+}
+
+/// 區塊傳輸用的 CRC-16 (CCITT，多項式 0x1021，初值 0)
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// 一次 SDO 讀取 (write 為 None) 或寫入
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdoRequest {
+    pub node: u8,
+    pub index: u16,
+    pub sub_index: u8,
+    pub write: Option<Vec<u8>>,
+    pub block: bool,
+}
+
+impl SdoRequest {
+    pub fn describe(&self) -> String {
+        format!(
+            "節點 {} {} {:04X}sub{:02X}",
+            self.node,
+            if self.write.is_some() { "寫" } else { "讀" },
+            self.index,
+            self.sub_index
+        )
+    }
+}
+
+/// 完成的傳輸；讀取成功時是讀到的資料，寫入成功時是空的
+#[derive(Debug, Clone)]
+pub struct SdoResult {
+    pub request: SdoRequest,
+    pub result: Result<Vec<u8>, String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    UploadInit,
+    UploadSegment { toggle: u8 },
+    DownloadInit,
+    DownloadSegment { toggle: u8 },
+    BlockDownloadInit,
+    // 子區塊已送出，等待確認；start 為這個子區塊的起始位移
+    BlockDownloadAck { start: usize },
+    BlockDownloadEnd,
+    BlockUploadInit,
+    // 下一個期待的序號
+    BlockUploadData { crc: bool, expected: u8 },
+    BlockUploadEnd { crc: bool },
+}
+
+struct Active {
+    request: SdoRequest,
+    stage: Stage,
+    started_us: u64,
+    deadline_us: u64,
+    // 讀取時累積收到的資料；寫入時是要送的資料
+    data: Vec<u8>,
+    // 下載時已送出的位元組數
+    offset: usize,
+    block_size: u8,
+}
+
+/// SDO 用戶端：一次一個傳輸，其餘排隊，結果放在 completed
+pub struct SdoClient {
+    pub timeout_ms: u64,
+    pub block_size: u8,
+    pub channel: u32,
+    pub outbox: Vec<CanFrame>,
+    pub completed: VecDeque<SdoResult>,
+    queue: VecDeque<SdoRequest>,
+    active: Option<Active>,
+}
+
+impl Default for SdoClient {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 1000,
+            block_size: MAX_BLOCK_SIZE,
+            channel: 0,
+            outbox: Vec::new(),
+            completed: VecDeque::new(),
+            queue: VecDeque::new(),
+            active: None,
+        }
+    }
+}
+
+fn multiplexer(index: u16, sub_index: u8) -> [u8; 3] {
+    let [lo, hi] = index.to_le_bytes();
+    [lo, hi, sub_index]
+}
+
+impl SdoClient {
+    pub fn request(&mut self, request: SdoRequest) {
+        self.queue.push_back(request);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.active.is_none() && self.queue.is_empty()
+    }
+
+    pub fn cancel(&mut self) {
+        self.queue.clear();
+        if let Some(active) = self.active.take() {
+            self.send_abort(&active.request, ABORT_COMMAND);
+        }
+    }
+
+    fn send(&mut self, node: u8, data: [u8; 8]) {
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: SDO_REQUEST_BASE + node as u32,
+            len: 8,
+            data,
+            ..Default::default()
+        });
+    }
+
+    fn send_command(&mut self, request: &SdoRequest, command: u8, tail: [u8; 4]) {
+        let [lo, hi, sub] = multiplexer(request.index, request.sub_index);
+        self.send(
+            request.node,
+            [command, lo, hi, sub, tail[0], tail[1], tail[2], tail[3]],
+        );
+    }
+
+    fn send_abort(&mut self, request: &SdoRequest, code: u32) {
+        self.send_command(request, ABORT, code.to_le_bytes());
+    }
+
+    fn finish(&mut self, result: Result<Vec<u8>, String>, now_us: u64) {
+        if let Some(active) = self.active.take() {
+            self.completed.push_back(SdoResult {
+                request: active.request,
+                result,
+                elapsed_ms: now_us.saturating_sub(active.started_us) / 1000,
+            });
+        }
+    }
+
+    // 送出錯誤的中止並結束傳輸
+    fn fail(&mut self, code: u32, now_us: u64) {
+        if let Some(active) = self.active.as_ref() {
+            let request = active.request.clone();
+            self.send_abort(&request, code);
+        }
+        self.finish(
+            Err(format!("本端中止 0x{:08X} {}", code, abort_name(code))),
+            now_us,
+        );
+    }
+
+    fn start(&mut self, request: SdoRequest, now_us: u64) {
+        let mut active = Active {
+            stage: Stage::UploadInit,
+            started_us: now_us,
+            deadline_us: now_us + self.timeout_ms * 1000,
+            data: Vec::new(),
+            offset: 0,
+            block_size: self.block_size.clamp(1, MAX_BLOCK_SIZE),
+            request,
+        };
+        let request = active.request.clone();
+        match &request.write {
+            None if request.block => {
+                // 區塊上傳：要求 CRC，協定切換門檻 0 (不切換)
+                active.stage = Stage::BlockUploadInit;
+                self.send_command(&request, 0xA4, [active.block_size, 0, 0, 0]);
+            }
+            None => self.send_command(&request, 0x40, [0; 4]),
+            // 4 bytes 以內用快速傳輸
+            Some(data) if !data.is_empty() && data.len() <= 4 => {
+                let mut tail = [0u8; 4];
+                tail[..data.len()].copy_from_slice(data);
+                let command = 0x23 | ((4 - data.len()) as u8) << 2;
+                active.stage = Stage::DownloadInit;
+                self.send_command(&request, command, tail);
+            }
+            Some(data) => {
+                let size = (data.len() as u32).to_le_bytes();
+                active.data = data.clone();
+                // 區塊下載至少要有一段資料，空資料改用一般下載
+                if request.block && !data.is_empty() {
+                    active.stage = Stage::BlockDownloadInit;
+                    self.send_command(&request, 0xC6, size);
+                } else {
+                    active.stage = Stage::DownloadInit;
+                    self.send_command(&request, 0x21, size);
+                }
+            }
+        }
+        self.active = Some(active);
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        let Some(active) = self.active.as_mut() else {
+            return;
+        };
+        if frame.channel != self.channel
+            || frame.extended
+            || frame.remote
+            || frame.error
+            || frame.id != SDO_RESPONSE_BASE + active.request.node as u32
+            || frame.len < 8
+        {
+            return;
+        }
+        let d = frame.data;
+        active.deadline_us = now_us + self.timeout_ms * 1000;
+        let request = active.request.clone();
+        let same_object = d[1..4] == multiplexer(request.index, request.sub_index);
+
+        // 區塊上傳中 0x80 也可能是資料段，要對上物件才算中止
+        let block_upload = matches!(active.stage, Stage::BlockUploadData { .. });
+        if d[0] == ABORT && (same_object || !block_upload) {
+            let code = u32::from_le_bytes([d[4], d[5], d[6], d[7]]);
+            self.finish(
+                Err(format!("節點中止 0x{:08X} {}", code, abort_name(code))),
+                now_us,
+            );
+            return;
+        }
+
+        // 區塊上傳的資料段第一個 byte 是序號，不是命令碼
+        if let Stage::BlockUploadData { crc, expected } = active.stage {
+            let seq = d[0] & 0x7F;
+            let last = d[0] & 0x80 != 0;
+            let mut next = expected;
+            if seq == expected {
+                active.data.extend_from_slice(&d[1..8]);
+                next += 1;
+            }
+            // 子區塊或整筆的最後一段：確認收到的最後一個連續序號，
+            // 有漏掉的段落時伺服器會從那之後重送
+            if seq == active.block_size || last {
+                let block_size = active.block_size;
+                active.stage = if last && seq == expected {
+                    Stage::BlockUploadEnd { crc }
+                } else {
+                    Stage::BlockUploadData { crc, expected: 1 }
+                };
+                self.send(request.node, [0xA2, next - 1, block_size, 0, 0, 0, 0, 0]);
+            } else {
+                active.stage = Stage::BlockUploadData {
+                    crc,
+                    expected: next,
+                };
+            }
+            return;
+        }
+
+        match active.stage {
+            // 1. **一般上傳**
+            Stage::UploadInit if d[0] & 0xE0 == 0x40 && same_object => {
+                let expedited = d[0] & 0x02 != 0;
+                let size_indicated = d[0] & 0x01 != 0;
+                if expedited {
+                    let len = if size_indicated {
+                        4 - (d[0] >> 2 & 0x03) as usize
+                    } else {
+                        4
+                    };
+                    self.finish(Ok(d[4..4 + len].to_vec()), now_us);
+                } else {
+                    active.stage = Stage::UploadSegment { toggle: 0 };
+                    self.send(request.node, [0x60, 0, 0, 0, 0, 0, 0, 0]);
+                }
+            }
+            Stage::UploadSegment { toggle } if d[0] & 0xE0 == 0x00 => {
+                if d[0] >> 4 & 0x01 != toggle {
+                    self.fail(0x0503_0000, now_us);
+                    return;
+                }
+                let len = 7 - (d[0] >> 1 & 0x07) as usize;
+                active.data.extend_from_slice(&d[1..1 + len]);
+                if d[0] & 0x01 != 0 {
+                    let data = std::mem::take(&mut active.data);
+                    self.finish(Ok(data), now_us);
+                } else {
+                    let toggle = toggle ^ 1;
+                    active.stage = Stage::UploadSegment { toggle };
+                    self.send(request.node, [0x60 | toggle << 4, 0, 0, 0, 0, 0, 0, 0]);
+                }
+            }
+            // 2. **一般下載**
+            Stage::DownloadInit if d[0] == 0x60 && same_object => {
+                if active.data.is_empty() {
+                    self.finish(Ok(Vec::new()), now_us);
+                } else {
+                    self.send_segment(0);
+                }
+            }
+            Stage::DownloadSegment { toggle } if d[0] & 0xE0 == 0x20 => {
+                if d[0] >> 4 & 0x01 != toggle {
+                    self.fail(0x0503_0000, now_us);
+                } else if active.offset >= active.data.len() {
+                    self.finish(Ok(Vec::new()), now_us);
+                } else {
+                    self.send_segment(toggle ^ 1);
+                }
+            }
+            // 3. **區塊下載**
+            Stage::BlockDownloadInit if d[0] & 0xE3 == 0xA0 && same_object => {
+                active.block_size = d[4].clamp(1, MAX_BLOCK_SIZE);
+                self.send_sub_block();
+            }
+            Stage::BlockDownloadAck { start } if d[0] == 0xA2 => {
+                let acknowledged = (start + d[1] as usize * 7).min(active.data.len());
+                if d[1] as usize > (active.offset - start).div_ceil(7) {
+                    self.fail(ABORT_SEQUENCE, now_us);
+                    return;
+                }
+                active.offset = acknowledged;
+                active.block_size = d[2].clamp(1, MAX_BLOCK_SIZE);
+                if acknowledged >= active.data.len() {
+                    // 最後一段沒用到的 byte 數與整筆資料的 CRC
+                    let unused = (7 - (active.data.len() - 1) % 7 - 1) as u8;
+                    let [crc_lo, crc_hi] = crc16(&active.data).to_le_bytes();
+                    active.stage = Stage::BlockDownloadEnd;
+                    self.send(
+                        request.node,
+                        [0xC1 | unused << 2, crc_lo, crc_hi, 0, 0, 0, 0, 0],
+                    );
+                } else {
+                    self.send_sub_block();
+                }
+            }
+            Stage::BlockDownloadEnd if d[0] == 0xA1 => self.finish(Ok(Vec::new()), now_us),
+            // 4. **區塊上傳**
+            Stage::BlockUploadInit if d[0] & 0xE1 == 0xC0 && same_object => {
+                let crc = d[0] & 0x04 != 0;
+                active.stage = Stage::BlockUploadData { crc, expected: 1 };
+                self.send(request.node, [0xA3, 0, 0, 0, 0, 0, 0, 0]);
+            }
+            Stage::BlockUploadEnd { crc } if d[0] & 0xE3 == 0xC1 => {
+                let unused = (d[0] >> 2 & 0x07) as usize;
+                let len = active.data.len().saturating_sub(unused);
+                active.data.truncate(len);
+                if crc && crc16(&active.data) != u16::from_le_bytes([d[1], d[2]]) {
+                    self.fail(ABORT_CRC, now_us);
+                    return;
+                }
+                let data = std::mem::take(&mut active.data);
+                self.send(request.node, [0xA1, 0, 0, 0, 0, 0, 0, 0]);
+                self.finish(Ok(data), now_us);
+            }
+            _ => self.fail(ABORT_COMMAND, now_us),
+        }
+    }
+
+    // 一般下載的下一段：[t n c, 最多 7 bytes]
+    fn send_segment(&mut self, toggle: u8) {
+        let Some(active) = self.active.as_mut() else {
+            return;
+        };
+        let chunk_len = (active.data.len() - active.offset).min(7);
+        let mut data = [0u8; 8];
+        data[1..1 + chunk_len]
+            .copy_from_slice(&active.data[active.offset..active.offset + chunk_len]);
+        active.offset += chunk_len;
+        let last = active.offset >= active.data.len();
+        data[0] = toggle << 4 | ((7 - chunk_len) as u8) << 1 | last as u8;
+        active.stage = Stage::DownloadSegment { toggle };
+        let node = active.request.node;
+        self.send(node, data);
+    }
+
+    // 區塊下載：從已確認的位置連續送出一個子區塊
+    fn send_sub_block(&mut self) {
+        let Some(active) = self.active.as_mut() else {
+            return;
+        };
+        let start = active.offset;
+        let mut frames = Vec::new();
+        for seq in 1..=active.block_size {
+            let chunk_len = (active.data.len() - active.offset).min(7);
+            let mut data = [0u8; 8];
+            data[1..1 + chunk_len]
+                .copy_from_slice(&active.data[active.offset..active.offset + chunk_len]);
+            active.offset += chunk_len;
+            let last = active.offset >= active.data.len();
+            data[0] = (last as u8) << 7 | seq;
+            frames.push(data);
+            if last {
+                break;
+            }
+        }
+        active.stage = Stage::BlockDownloadAck { start };
+        let node = active.request.node;
+        for data in frames {
+            self.send(node, data);
+        }
+    }
+
+    /// 每個畫面呼叫一次：檢查逾時並開始下一個傳輸
+    pub fn poll(&mut self, now_us: u64) {
+        if self.active.as_ref().is_some_and(|a| now_us > a.deadline_us) {
+            self.fail(ABORT_TIMEOUT, now_us);
+        }
+        if self.active.is_none() {
+            if let Some(request) = self.queue.pop_front() {
+                self.start(request, now_us);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(node: u8, data: [u8; 8]) -> CanFrame {
+        CanFrame {
+            id: SDO_RESPONSE_BASE + node as u32,
+            len: 8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_block_download_uses_segmented_transfer() {
+        let mut client = SdoClient::default();
+        client.request(SdoRequest {
+            node: 5,
+            index: 0x2000,
+            sub_index: 1,
+            write: Some(Vec::new()),
+            block: true,
+        });
+        client.poll(0);
+        let sent: Vec<u8> = client.outbox.drain(..).map(|f| f.data[0]).collect();
+        assert_eq!(sent, [0x21]);
+        client.handle(&response(5, [0x60, 0x00, 0x20, 1, 0, 0, 0, 0]), 1_000);
+        assert_eq!(client.completed.pop_front().unwrap().result, Ok(Vec::new()));
+    }
+
+    #[test]
+    fn abort_during_block_upload() {
+        let mut client = SdoClient::default();
+        client.request(SdoRequest {
+            node: 5,
+            index: 0x2000,
+            sub_index: 0,
+            write: None,
+            block: true,
+        });
+        client.poll(0);
+        assert_eq!(client.outbox.drain(..).next().unwrap().data[0], 0xA4);
+        client.handle(&response(5, [0xC6, 0x00, 0x20, 0, 100, 0, 0, 0]), 1_000);
+        assert_eq!(client.outbox.drain(..).next().unwrap().data[0], 0xA3);
+
+        client.handle(
+            &response(5, [ABORT, 0x00, 0x20, 0, 0x00, 0x00, 0x06, 0x08]),
+            2_000,
+        );
+        let result = client.completed.pop_front().unwrap().result;
+        assert!(result.unwrap_err().contains("節點中止 0x08060000"));
+        // 中止不會被當成資料段確認
+        assert!(client.outbox.is_empty());
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Mode {
+        Idle,
+        UploadSegment,
+        DownloadSegment,
+        BlockDownload,
+        BlockDownloadEnd,
+        BlockUpload,
+    }
+
+    /// 只有一個物件的 SDO 伺服器，可以注入切換位元錯誤、遺失的區塊段與 CRC 錯誤
+    struct Server {
+        node: u8,
+        object: Vec<u8>,
+        written: Option<Vec<u8>>,
+        // 收到的所有請求
+        received: Vec<[u8; 8]>,
+        mode: Mode,
+        buffer: Vec<u8>,
+        offset: usize,
+        segments: usize,
+        block_size: u8,
+        expected: u8,
+        // 第幾段 (從 0 起) 回錯的切換位元
+        bad_toggle_at: Option<usize>,
+        // 區塊下載時第一次遺失的序號
+        drop_seq: Option<u8>,
+        bad_crc: bool,
+    }
+
+    impl Server {
+        fn new(object: Vec<u8>) -> Self {
+            Self {
+                node: 5,
+                object,
+                written: None,
+                received: Vec::new(),
+                mode: Mode::Idle,
+                buffer: Vec::new(),
+                offset: 0,
+                segments: 0,
+                block_size: 4,
+                expected: 1,
+                bad_toggle_at: None,
+                drop_seq: None,
+                bad_crc: false,
+            }
+        }
+
+        fn toggle(&mut self, toggle: u8) -> u8 {
+            let bad = self.bad_toggle_at == Some(self.segments);
+            self.segments += 1;
+            toggle ^ bad as u8
+        }
+
+        // 從 offset 起送一個區塊上傳的子區塊
+        fn sub_block(&mut self) -> Vec<[u8; 8]> {
+            let mut frames = Vec::new();
+            let mut offset = self.offset;
+            for seq in 1..=self.block_size {
+                let chunk = &self.object[offset..(offset + 7).min(self.object.len())];
+                let mut data = [0u8; 8];
+                data[1..1 + chunk.len()].copy_from_slice(chunk);
+                offset += chunk.len();
+                let last = offset >= self.object.len();
+                data[0] = (last as u8) << 7 | seq;
+                frames.push(data);
+                if last {
+                    break;
+                }
+            }
+            frames
+        }
+
+        fn handle(&mut self, d: [u8; 8]) -> Vec<[u8; 8]> {
+            self.received.push(d);
+            let [_, lo, hi, sub, ..] = d;
+            if d[0] == ABORT && self.mode != Mode::BlockDownload {
+                self.mode = Mode::Idle;
+                return Vec::new();
+            }
+            match self.mode {
+                Mode::BlockDownload => {
+                    let seq = d[0] & 0x7F;
+                    let last = d[0] & 0x80 != 0;
+                    if self.drop_seq == Some(seq) {
+                        self.drop_seq = None;
+                    } else if seq == self.expected {
+                        self.buffer.extend_from_slice(&d[1..8]);
+                        self.expected += 1;
+                    }
+                    if seq != self.block_size && !last {
+                        return Vec::new();
+                    }
+                    let ack = self.expected - 1;
+                    if last && ack == seq {
+                        self.mode = Mode::BlockDownloadEnd;
+                    }
+                    self.expected = 1;
+                    vec![[0xA2, ack, self.block_size, 0, 0, 0, 0, 0]]
+                }
+                Mode::BlockDownloadEnd if d[0] & 0xE3 == 0xC1 => {
+                    let unused = (d[0] >> 2 & 0x07) as usize;
+                    self.buffer.truncate(self.buffer.len() - unused);
+                    self.mode = Mode::Idle;
+                    if crc16(&self.buffer) != u16::from_le_bytes([d[1], d[2]]) {
+                        return vec![[ABORT, 0, 0, 0, 0x04, 0x00, 0x04, 0x05]];
+                    }
+                    self.written = Some(std::mem::take(&mut self.buffer));
+                    vec![[0xA1, 0, 0, 0, 0, 0, 0, 0]]
+                }
+                Mode::UploadSegment if d[0] & 0xEF == 0x60 => {
+                    let chunk = &self.object[self.offset..(self.offset + 7).min(self.object.len())];
+                    let len = chunk.len();
+                    let mut data = [0u8; 8];
+                    data[1..1 + len].copy_from_slice(chunk);
+                    self.offset += len;
+                    let last = self.offset >= self.object.len();
+                    let toggle = self.toggle(d[0] >> 4 & 0x01);
+                    data[0] = toggle << 4 | ((7 - len) as u8) << 1 | last as u8;
+                    if last {
+                        self.mode = Mode::Idle;
+                    }
+                    vec![data]
+                }
+                Mode::DownloadSegment if d[0] & 0xE0 == 0x00 => {
+                    let len = 7 - (d[0] >> 1 & 0x07) as usize;
+                    self.buffer.extend_from_slice(&d[1..1 + len]);
+                    if d[0] & 0x01 != 0 {
+                        self.written = Some(std::mem::take(&mut self.buffer));
+                        self.mode = Mode::Idle;
+                    }
+                    let toggle = self.toggle(d[0] >> 4 & 0x01);
+                    vec![[0x20 | toggle << 4, 0, 0, 0, 0, 0, 0, 0]]
+                }
+                Mode::BlockUpload if d[0] == 0xA3 => self.sub_block(),
+                Mode::BlockUpload if d[0] == 0xA2 => {
+                    self.offset = (self.offset + d[1] as usize * 7).min(self.object.len());
+                    self.block_size = d[2];
+                    if self.offset < self.object.len() {
+                        return self.sub_block();
+                    }
+                    let unused = ((7 - self.object.len() % 7) % 7) as u8;
+                    let crc = crc16(&self.object) ^ self.bad_crc as u16;
+                    let [crc_lo, crc_hi] = crc.to_le_bytes();
+                    vec![[0xC1 | unused << 2, crc_lo, crc_hi, 0, 0, 0, 0, 0]]
+                }
+                Mode::BlockUpload if d[0] == 0xA1 => {
+                    self.mode = Mode::Idle;
+                    Vec::new()
+                }
+                _ => {
+                    let size = (self.object.len() as u32).to_le_bytes();
+                    self.offset = 0;
+                    self.segments = 0;
+                    self.buffer.clear();
+                    match d[0] {
+                        0x40 => {
+                            self.mode = Mode::UploadSegment;
+                            vec![[0x41, lo, hi, sub, size[0], size[1], size[2], size[3]]]
+                        }
+                        0x21 => {
+                            self.mode = Mode::DownloadSegment;
+                            vec![[0x60, lo, hi, sub, 0, 0, 0, 0]]
+                        }
+                        0xC6 => {
+                            self.mode = Mode::BlockDownload;
+                            self.expected = 1;
+                            vec![[0xA4, lo, hi, sub, self.block_size, 0, 0, 0]]
+                        }
+                        0xA4 => {
+                            self.mode = Mode::BlockUpload;
+                            self.block_size = d[4];
+                            vec![[0xC6, lo, hi, sub, size[0], size[1], size[2], size[3]]]
+                        }
+                        _ => vec![[ABORT, lo, hi, sub, 0x01, 0x00, 0x04, 0x05]],
+                    }
+                }
+            }
+        }
+    }
+
+    /// 用戶端與伺服器每 1 ms 交換一次，直到傳輸完成
+    fn run(client: &mut SdoClient, server: &mut Server, request: SdoRequest) -> SdoResult {
+        client.request(request);
+        let mut now = 0;
+        for _ in 0..100 {
+            now += 1_000;
+            client.poll(now);
+            while !client.outbox.is_empty() {
+                let frames: Vec<CanFrame> = client.outbox.drain(..).collect();
+                for frame in frames {
+                    assert_eq!(frame.id, SDO_REQUEST_BASE + server.node as u32);
+                    for reply in server.handle(frame.data) {
+                        client.handle(&response(server.node, reply), now);
+                    }
+                }
+            }
+            if let Some(result) = client.completed.pop_front() {
+                return result;
+            }
+        }
+        panic!("SDO 傳輸沒有完成");
+    }
+
+    fn read(block: bool) -> SdoRequest {
+        SdoRequest {
+            node: 5,
+            index: 0x2000,
+            sub_index: 1,
+            write: None,
+            block,
+        }
+    }
+
+    fn write(data: &[u8], block: bool) -> SdoRequest {
+        SdoRequest {
+            write: Some(data.to_vec()),
+            ..read(block)
+        }
+    }
+
+    fn object(len: usize) -> Vec<u8> {
+        (0..len as u8).map(|b| b.wrapping_mul(37)).collect()
+    }
+
+    #[test]
+    fn segmented_upload_and_download() {
+        let mut client = SdoClient::default();
+        let mut server = Server::new(object(20));
+        let result = run(&mut client, &mut server, read(false));
+        assert_eq!(result.result, Ok(object(20)));
+
+        let data = object(17);
+        let result = run(&mut client, &mut server, write(&data, false));
+        assert_eq!(result.result, Ok(Vec::new()));
+        assert_eq!(server.written, Some(data));
+        // 3 段，切換位元 0 1 0
+        let toggles: Vec<u8> = server.received[server.received.len() - 3..]
+            .iter()
+            .map(|d| d[0] >> 4 & 0x01)
+            .collect();
+        assert_eq!(toggles, [0, 1, 0]);
+    }
+
+    #[test]
+    fn toggle_errors_abort() {
+        let mut client = SdoClient::default();
+        let mut server = Server::new(object(20));
+        server.bad_toggle_at = Some(1);
+        let result = run(&mut client, &mut server, read(false));
+        assert!(result.result.unwrap_err().contains("0x05030000"));
+        let abort = server.received.last().unwrap();
+        assert_eq!(abort[0], ABORT);
+        assert_eq!(
+            u32::from_le_bytes(abort[4..8].try_into().unwrap()),
+            0x0503_0000
+        );
+
+        let mut server = Server::new(Vec::new());
+        server.bad_toggle_at = Some(0);
+        let result = run(&mut client, &mut server, write(&object(17), false));
+        assert!(result.result.unwrap_err().contains("0x05030000"));
+        assert_eq!(server.received.last().unwrap()[0], ABORT);
+        assert!(client.is_idle());
+    }
+
+    #[test]
+    fn block_download_resends_after_partial_ack() {
+        let mut client = SdoClient::default();
+        let mut server = Server::new(Vec::new());
+        // 子區塊 4 段；第一個子區塊的第 2 段遺失，確認 1 後從第 2 段重送
+        server.drop_seq = Some(2);
+        let data = object(40);
+        let result = run(&mut client, &mut server, write(&data, true));
+        assert_eq!(result.result, Ok(Vec::new()));
+        assert_eq!(server.written, Some(data));
+        // 40 bytes = 6 段，加上重送的 3 段
+        let segments = server
+            .received
+            .iter()
+            .filter(|d| d[0] != 0xC6 && d[0] & 0xE3 != 0xC1)
+            .count();
+        assert_eq!(segments, 9);
+    }
+
+    #[test]
+    fn block_upload_crc_mismatch_aborts() {
+        let mut client = SdoClient {
+            block_size: 3,
+            ..Default::default()
+        };
+        let mut server = Server::new(object(30));
+        let result = run(&mut client, &mut server, read(true));
+        assert_eq!(result.result, Ok(object(30)));
+        assert_eq!(server.received.last().unwrap()[0], 0xA1);
+
+        let mut server = Server::new(object(30));
+        server.bad_crc = true;
+        let result = run(&mut client, &mut server, read(true));
+        assert!(result.result.unwrap_err().contains("本端中止 0x05040004"));
+        let abort = server.received.last().unwrap();
+        assert_eq!(abort[0], ABORT);
+        assert_eq!(
+            u32::from_le_bytes(abort[4..8].try_into().unwrap()),
+            ABORT_CRC
+        );
+        assert!(!server.received.iter().any(|d| d[0] == 0xA1));
+    }
+}
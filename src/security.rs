@@ -0,0 +1,707 @@
+use crate::uds::{nrc_name, Exchange, Outcome, UdsClient, SID_ECU_RESET, SID_SESSION_CONTROL};
+use eframe::egui;
+use libloading::Library;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::Arc;
+
+pub const SID_SECURITY_ACCESS: u8 = 0x27;
+const NRC_SECURITY_ACCESS_DENIED: u8 = 0x33;
+const MAX_KEY_LEN: usize = 64;
+
+// Vector 介面: GenerateKeyEx(seed, seedLen, level, variant, key, maxKeyLen, &keyLen)，回傳 0 表示成功
+type GenerateKeyEx =
+    unsafe extern "C" fn(*const u8, u32, u32, *const c_char, *mut u8, u32, *mut u32) -> u32;
+
+/// 匯出 GenerateKeyEx 的種子/金鑰 DLL
+pub struct KeyLibrary {
+    _lib: Arc<Library>,
+    generate: GenerateKeyEx,
+}
+
+impl KeyLibrary {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let lib = unsafe { Library::new(path) }
+            .map_err(|e| format!("無法載入 {}: {} (DLL 位元數須與程式相同)", path, e))?;
+        let lib = Arc::new(lib);
+        let generate = unsafe { lib.get::<GenerateKeyEx>(b"GenerateKeyEx") }
+            .map(|f| *f)
+            .map_err(|_| format!("{} 沒有匯出 GenerateKeyEx", path))?;
+        Ok(Self {
+            _lib: lib,
+            generate,
+        })
+    }
+
+    fn compute(&self, seed: &[u8], level: u8, variant: &str) -> Result<Vec<u8>, String> {
+        let variant = CString::new(variant).map_err(|_| "Variant 不能含有 NUL".to_string())?;
+        let mut key = [0u8; MAX_KEY_LEN];
+        let mut len = 0u32;
+        let result = unsafe {
+            (self.generate)(
+                seed.as_ptr(),
+                seed.len() as u32,
+                level as u32,
+                variant.as_ptr(),
+                key.as_mut_ptr(),
+                MAX_KEY_LEN as u32,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(format!("GenerateKeyEx 失敗，回傳 {}", result));
+        }
+        // 截斷的金鑰一定錯，送出只會浪費一次嘗試次數
+        if len as usize > MAX_KEY_LEN {
+            return Err(format!(
+                "GenerateKeyEx 回傳的金鑰長度 {} 超過 {} bytes",
+                len, MAX_KEY_LEN
+            ));
+        }
+        Ok(key[..len as usize].to_vec())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Value(u64),
+    Seed,
+    Level,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Xor(Operand),
+    Add(Operand),
+    Sub(Operand),
+    Mul(Operand),
+    And(Operand),
+    Or(Operand),
+    Rol(u32),
+    Ror(u32),
+    Shl(u32),
+    Shr(u32),
+    Not,
+    Swap,
+    // 每次左移一位，移出的位元為 1 時再 XOR 多項式
+    Lfsr(u64, u32),
+}
+
+/// 以簡單步驟描述的種子/金鑰演算法
+///
+/// 一行一個步驟，對以大端序讀入的種子整數依序運算：
+/// `xor/add/sub/mul/and/or <數值|seed|level>`、`rol/ror/shl/shr <位元>`、
+/// `not`、`swap` (反轉位元組)、`lfsr <多項式> <次數>`。
+/// `width <bytes>` 指定金鑰長度 (預設與種子同長)，`#` 之後是註解。
+#[derive(Debug, Clone, Default)]
+pub struct KeyScript {
+    width: Option<usize>,
+    steps: Vec<Step>,
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("無效的數值: {}", text))
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    match text {
+        "seed" => Ok(Operand::Seed),
+        "level" => Ok(Operand::Level),
+        _ => parse_number(text).map(Operand::Value),
+    }
+}
+
+impl KeyScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = KeyScript::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |e: String| format!("第 {} 行: {}", i + 1, e);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let op = words[0].to_ascii_lowercase();
+            let args = &words[1..];
+            let arity = match op.as_str() {
+                "not" | "swap" => 0,
+                "lfsr" => 2,
+                _ => 1,
+            };
+            if args.len() != arity {
+                return Err(err(format!("{} 需要 {} 個參數", op, arity)));
+            }
+            let bits = || -> Result<u32, String> {
+                parse_number(args[0])
+                    .ok()
+                    .filter(|n| *n < 64)
+                    .map(|n| n as u32)
+                    .ok_or_else(|| format!("位移量須為 0~63: {}", args[0]))
+            };
+            let operand = || parse_operand(args[0]);
+            let step = match op.as_str() {
+                "width" => {
+                    let width = parse_number(args[0]).map_err(err)?;
+                    if !(1..=8).contains(&width) {
+                        return Err(err("width 須為 1~8".to_string()));
+                    }
+                    script.width = Some(width as usize);
+                    continue;
+                }
+                "xor" => Step::Xor(operand().map_err(err)?),
+                "add" => Step::Add(operand().map_err(err)?),
+                "sub" => Step::Sub(operand().map_err(err)?),
+                "mul" => Step::Mul(operand().map_err(err)?),
+                "and" => Step::And(operand().map_err(err)?),
+                "or" => Step::Or(operand().map_err(err)?),
+                "rol" => Step::Rol(bits().map_err(err)?),
+                "ror" => Step::Ror(bits().map_err(err)?),
+                "shl" => Step::Shl(bits().map_err(err)?),
+                "shr" => Step::Shr(bits().map_err(err)?),
+                "not" => Step::Not,
+                "swap" => Step::Swap,
+                "lfsr" => Step::Lfsr(
+                    parse_number(args[0]).map_err(err)?,
+                    parse_number(args[1]).map_err(err)?.min(1024) as u32,
+                ),
+                _ => return Err(err(format!("未知的步驟: {}", op))),
+            };
+            script.steps.push(step);
+        }
+        Ok(script)
+    }
+
+    pub fn compute(&self, seed: &[u8], level: u8) -> Result<Vec<u8>, String> {
+        if seed.is_empty() || seed.len() > 8 {
+            return Err(format!("腳本只支援 1~8 bytes 的種子，收到 {}", seed.len()));
+        }
+        let width = self.width.unwrap_or(seed.len());
+        let bits = width as u32 * 8;
+        let mask = if bits == 64 {
+            u64::MAX
+        } else {
+            (1 << bits) - 1
+        };
+        let seed_value = seed.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+        let value_of = |operand: Operand| match operand {
+            Operand::Value(v) => v,
+            Operand::Seed => seed_value,
+            Operand::Level => level as u64,
+        };
+        // 在 width 位元內旋轉
+        let rotate_left = |v: u64, n: u32| {
+            let n = n % bits;
+            if n == 0 {
+                v
+            } else {
+                (v << n | v >> (bits - n)) & mask
+            }
+        };
+
+        let mut key = seed_value & mask;
+        for step in &self.steps {
+            key = match *step {
+                Step::Xor(o) => key ^ value_of(o),
+                Step::Add(o) => key.wrapping_add(value_of(o)),
+                Step::Sub(o) => key.wrapping_sub(value_of(o)),
+                Step::Mul(o) => key.wrapping_mul(value_of(o)),
+                Step::And(o) => key & value_of(o),
+                Step::Or(o) => key | value_of(o),
+                Step::Rol(n) => rotate_left(key, n),
+                Step::Ror(n) => rotate_left(key, bits - n % bits),
+                Step::Shl(n) => key << n,
+                Step::Shr(n) => key >> n,
+                Step::Not => !key,
+                Step::Swap => key.swap_bytes() >> (64 - bits),
+                Step::Lfsr(poly, rounds) => {
+                    let top = 1u64 << (bits - 1);
+                    (0..rounds).fold(key, |k, _| {
+                        if k & top != 0 {
+                            ((k << 1) ^ poly) & mask
+                        } else {
+                            (k << 1) & mask
+                        }
+                    })
+                }
+            } & mask;
+        }
+        Ok(key.to_be_bytes()[8 - width..].to_vec())
+    }
+}
+
+/// 內建的範例腳本
+const PRESETS: [(&str, &str); 3] = [
+    ("XOR 常數", "xor 0x5A5A5A5A\n"),
+    ("旋轉後加常數", "rol 3\nxor seed\nadd 0x1234\n"),
+    ("LFSR", "# 常見的 35 次 CRC 式移位\nlfsr 0x04C11DB7 35\n"),
+];
+
+pub enum KeyAlgorithm {
+    Script(KeyScript),
+    Library { lib: KeyLibrary, variant: String },
+}
+
+impl KeyAlgorithm {
+    pub fn compute(&self, seed: &[u8], level: u8) -> Result<Vec<u8>, String> {
+        match self {
+            KeyAlgorithm::Script(script) => script.compute(seed, level),
+            KeyAlgorithm::Library { lib, variant } => lib.compute(seed, level, variant),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    WaitSeed,
+    WaitKey,
+}
+
+/// 一次 SecurityAccess 解鎖：要種子、算金鑰、送金鑰
+pub struct Unlock {
+    pub level: u8,
+    stage: Stage,
+    // 因 NRC 0x33 觸發的自動解鎖，成功後重送這個請求
+    pub retry: Option<Vec<u8>>,
+}
+
+impl Unlock {
+    pub fn start(level: u8, retry: Option<Vec<u8>>, client: &mut UdsClient) -> Self {
+        client.request(vec![SID_SECURITY_ACCESS, level]);
+        Self {
+            level,
+            stage: Stage::WaitSeed,
+            retry,
+        }
+    }
+
+    /// 處理一筆完成的請求；解鎖結束時回傳結果
+    pub fn on_exchange(
+        &mut self,
+        exchange: &Exchange,
+        algorithm: Option<&KeyAlgorithm>,
+        client: &mut UdsClient,
+    ) -> Option<Result<(), String>> {
+        let expected = match self.stage {
+            Stage::WaitSeed => self.level,
+            Stage::WaitKey => self.level + 1,
+        };
+        if exchange.request.get(..2) != Some(&[SID_SECURITY_ACCESS, expected]) {
+            return None;
+        }
+        let data = match &exchange.outcome {
+            Outcome::Positive(data) => data,
+            Outcome::Negative(nrc) => {
+                return Some(Err(format!(
+                    "SecurityAccess 0x{:02X} 被拒: {}",
+                    expected,
+                    nrc_name(*nrc)
+                )))
+            }
+            other => return Some(Err(other.describe(&exchange.request))),
+        };
+        if self.stage == Stage::WaitKey {
+            return Some(Ok(()));
+        }
+
+        // 全 0 的種子表示已經解鎖
+        let seed = &data[2.min(data.len())..];
+        if seed.iter().all(|b| *b == 0) {
+            return Some(Ok(()));
+        }
+        let Some(algorithm) = algorithm else {
+            return Some(Err("沒有設定種子/金鑰演算法".to_string()));
+        };
+        match algorithm.compute(seed, self.level) {
+            Ok(key) => {
+                let mut request = vec![SID_SECURITY_ACCESS, self.level + 1];
+                request.extend_from_slice(&key);
+                client.request(request);
+                self.stage = Stage::WaitKey;
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Script,
+    Library,
+}
+
+/// UDS 面板裡的 SecurityAccess 設定與自動解鎖
+pub struct SecurityPanel {
+    pub level: u8,
+    pub auto_unlock: bool,
+    // 腳本與 DLL 各自保留，切換來源時不會丟掉另一邊
+    script: Option<KeyAlgorithm>,
+    library: Option<KeyAlgorithm>,
+    pub unlock: Option<Unlock>,
+    pub unlocked: Option<u8>,
+    // 自動解鎖後重送的請求，再被拒就不再解鎖
+    retried: Option<Vec<u8>>,
+    source: Source,
+    script_text: String,
+    script_error: Option<String>,
+    dll_path: String,
+    variant: String,
+}
+
+impl Default for SecurityPanel {
+    fn default() -> Self {
+        let script_text = PRESETS[0].1.to_string();
+        Self {
+            level: 0x01,
+            auto_unlock: false,
+            script: KeyScript::parse(&script_text)
+                .ok()
+                .map(KeyAlgorithm::Script),
+            library: None,
+            unlock: None,
+            unlocked: None,
+            retried: None,
+            source: Source::Script,
+            script_text,
+            script_error: None,
+            dll_path: String::new(),
+            variant: String::new(),
+        }
+    }
+}
+
+impl SecurityPanel {
+    /// 目前選擇的來源對應的演算法
+    pub fn algorithm(&self) -> Option<&KeyAlgorithm> {
+        match self.source {
+            Source::Script => self.script.as_ref(),
+            Source::Library => self.library.as_ref(),
+        }
+    }
+
+    pub fn start(&mut self, client: &mut UdsClient, retry: Option<Vec<u8>>) {
+        self.unlock = Some(Unlock::start(self.level, retry, client));
+    }
+
+    /// 切換工作階段或重置後解鎖狀態失效；刷寫中的請求也要經過這裡
+    pub fn on_session_change(&mut self, exchange: &Exchange) {
+        if matches!(exchange.request[0], SID_SESSION_CONTROL | SID_ECU_RESET)
+            && matches!(exchange.outcome, Outcome::Positive(_))
+        {
+            self.unlocked = None;
+        }
+    }
+
+    /// 處理一筆完成的請求：推進解鎖，或在 NRC 0x33 時自動解鎖再重送
+    pub fn on_exchange(
+        &mut self,
+        exchange: &Exchange,
+        client: &mut UdsClient,
+        log: &mut Vec<String>,
+    ) {
+        self.on_session_change(exchange);
+        let algorithm = match self.source {
+            Source::Script => self.script.as_ref(),
+            Source::Library => self.library.as_ref(),
+        };
+        if let Some(unlock) = self.unlock.as_mut() {
+            if let Some(result) = unlock.on_exchange(exchange, algorithm, client) {
+                let unlock = self.unlock.take().unwrap();
+                match result {
+                    Ok(()) => {
+                        log.push(format!("SecurityAccess 等級 0x{:02X} 已解鎖", unlock.level));
+                        self.unlocked = Some(unlock.level);
+                        if let Some(request) = unlock.retry {
+                            self.retried = Some(request.clone());
+                            client.request(request);
+                        }
+                    }
+                    Err(e) => log.push(e),
+                }
+            }
+            return;
+        }
+        let retried = self.retried.as_ref() == Some(&exchange.request);
+        if retried {
+            self.retried = None;
+        }
+        if self.auto_unlock
+            && exchange.outcome == Outcome::Negative(NRC_SECURITY_ACCESS_DENIED)
+            && exchange.request[0] != SID_SECURITY_ACCESS
+        {
+            if retried {
+                log.push(format!(
+                    "解鎖等級 0x{:02X} 後重送仍被拒: NRC 0x{:02X} {}",
+                    self.level,
+                    NRC_SECURITY_ACCESS_DENIED,
+                    nrc_name(NRC_SECURITY_ACCESS_DENIED)
+                ));
+                return;
+            }
+            log.push(format!(
+                "存取被拒，自動以等級 0x{:02X} 解鎖後重送",
+                self.level
+            ));
+            self.start(client, Some(exchange.request.clone()));
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        client: &mut UdsClient,
+        can_send: bool,
+        log: &mut Vec<String>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("等級:");
+            ui.add(
+                egui::DragValue::new(&mut self.level)
+                    .range(1..=0x7D)
+                    .hexadecimal(2, false, true),
+            );
+            // 要種子用奇數，送金鑰用下一個偶數
+            if self.level.is_multiple_of(2) {
+                self.level -= 1;
+            }
+            if ui
+                .add_enabled(can_send && self.unlock.is_none(), egui::Button::new("解鎖"))
+                .clicked()
+            {
+                self.start(client, None);
+            }
+            ui.checkbox(&mut self.auto_unlock, "遇到 NRC 0x33 自動解鎖");
+            match (self.unlock.as_ref(), self.unlocked) {
+                (Some(_), _) => {
+                    ui.spinner();
+                }
+                (None, Some(level)) => {
+                    ui.label(format!("已解鎖 0x{:02X}", level));
+                }
+                (None, None) => {
+                    ui.label("未解鎖");
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.source, Source::Script, "腳本");
+            ui.radio_value(&mut self.source, Source::Library, "DLL (GenerateKeyEx)");
+        });
+        match self.source {
+            Source::Script => {
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("範例:");
+                    for (name, text) in PRESETS {
+                        if ui.small_button(name).clicked() {
+                            self.script_text = text.to_string();
+                            changed = true;
+                        }
+                    }
+                });
+                changed |= ui
+                    .add(
+                        egui::TextEdit::multiline(&mut self.script_text)
+                            .desired_rows(4)
+                            .desired_width(f32::INFINITY)
+                            .font(egui::TextStyle::Monospace),
+                    )
+                    .changed();
+                // 只在內容改變時重新解析
+                if changed {
+                    match KeyScript::parse(&self.script_text) {
+                        Ok(script) => {
+                            self.script = Some(KeyAlgorithm::Script(script));
+                            self.script_error = None;
+                        }
+                        Err(e) => {
+                            self.script = None;
+                            self.script_error = Some(e);
+                        }
+                    }
+                }
+                if let Some(e) = &self.script_error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+            }
+            Source::Library => {
+                ui.horizontal(|ui| {
+                    ui.label("DLL:");
+                    ui.text_edit_singleline(&mut self.dll_path);
+                    ui.label("Variant:");
+                    ui.add(egui::TextEdit::singleline(&mut self.variant).desired_width(80.0));
+                    if ui.button("載入").clicked() {
+                        match KeyLibrary::load(&self.dll_path) {
+                            Ok(lib) => {
+                                log.push(format!("已載入種子/金鑰 DLL: {}", self.dll_path));
+                                self.library = Some(KeyAlgorithm::Library {
+                                    lib,
+                                    variant: self.variant.clone(),
+                                });
+                            }
+                            Err(e) => log.push(e),
+                        }
+                    }
+                });
+                match &mut self.library {
+                    Some(KeyAlgorithm::Library { variant, .. }) => *variant = self.variant.clone(),
+                    _ => {
+                        ui.label("尚未載入 DLL");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(request: &[u8], outcome: Outcome) -> Exchange {
+        Exchange {
+            timestamp_us: 0,
+            request: request.to_vec(),
+            outcome,
+            elapsed_ms: 0,
+            pending_count: 0,
+        }
+    }
+
+    #[test]
+    fn auto_unlock_retries_once() {
+        let mut panel = SecurityPanel {
+            auto_unlock: true,
+            ..Default::default()
+        };
+        let (mut client, mut log) = (UdsClient::default(), Vec::new());
+        let write = [0x2E, 0xF1, 0x90, 0x01];
+        let denied = Outcome::Negative(NRC_SECURITY_ACCESS_DENIED);
+
+        panel.on_exchange(&exchange(&write, denied.clone()), &mut client, &mut log);
+        assert!(panel.unlock.is_some());
+        // 全 0 種子表示已解鎖，直接重送
+        let seed = Outcome::Positive(vec![0x67, 0x01, 0x00, 0x00]);
+        panel.on_exchange(&exchange(&[0x27, 0x01], seed), &mut client, &mut log);
+        assert_eq!(panel.unlocked, Some(0x01));
+
+        // 重送又被拒：回報 NRC，不再解鎖
+        panel.on_exchange(&exchange(&write, denied.clone()), &mut client, &mut log);
+        assert!(panel.unlock.is_none());
+        assert!(log.last().unwrap().contains("NRC 0x33"), "{:?}", log);
+
+        // 之後再送的請求被拒時照樣自動解鎖
+        panel.on_exchange(&exchange(&write, denied), &mut client, &mut log);
+        assert!(panel.unlock.is_some());
+    }
+
+    fn key(script: &str, seed: &[u8], level: u8) -> Vec<u8> {
+        KeyScript::parse(script)
+            .unwrap()
+            .compute(seed, level)
+            .unwrap()
+    }
+
+    #[test]
+    fn shifts_and_rotates_stay_in_width() {
+        let seed = [0x12, 0x34];
+        assert_eq!(key("rol 4", &seed, 1), [0x23, 0x41]);
+        assert_eq!(key("ror 4", &seed, 1), [0x41, 0x23]);
+        // 旋轉量以金鑰位元數取餘數
+        assert_eq!(key("rol 20", &seed, 1), [0x23, 0x41]);
+        assert_eq!(key("ror 16", &seed, 1), [0x12, 0x34]);
+        // 移出 width 的位元丟掉
+        assert_eq!(key("shl 4", &seed, 1), [0x23, 0x40]);
+        assert_eq!(key("shr 4", &seed, 1), [0x01, 0x23]);
+        // width 比種子長時補 0，比種子短時只取低位元組
+        assert_eq!(key("width 4\nrol 8", &seed, 1), [0x00, 0x12, 0x34, 0x00]);
+        assert_eq!(key("width 4\nror 8", &seed, 1), [0x34, 0x00, 0x00, 0x12]);
+        assert_eq!(key("width 1\nrol 1", &seed, 1), [0x68]);
+        assert_eq!(key("width 1\nnot", &[0x0F], 1), [0xF0]);
+    }
+
+    #[test]
+    fn swap_and_lfsr() {
+        assert_eq!(key("swap", &[0x12, 0x34, 0x56], 1), [0x56, 0x34, 0x12]);
+        assert_eq!(
+            key("width 4\nswap", &[0x12, 0x34], 1),
+            [0x34, 0x12, 0x00, 0x00]
+        );
+        // 0x81 → 移出 1：0x02 ^ 0x07 = 0x05 → 移出 0：0x0A
+        assert_eq!(key("lfsr 0x07 2", &[0x81], 1), [0x0A]);
+        assert_eq!(key("lfsr 0x07 0", &[0x81], 1), [0x81]);
+        let preset = KeyScript::parse(PRESETS[2].1).unwrap();
+        assert_eq!(preset.compute(&[0; 4], 1).unwrap(), [0; 4]);
+    }
+
+    #[test]
+    fn seed_and_level_operands() {
+        let seed = [0x12, 0x34];
+        assert_eq!(key("xor seed", &seed, 1), [0x00, 0x00]);
+        assert_eq!(key("add level\nxor seed", &seed, 3), [0x00, 0x03]);
+        // 0x1234 × 3 - 0x1234 = 0x2468
+        assert_eq!(
+            key("mul 3\nsub seed\nand 0xFF00\nor level", &seed, 5),
+            [0x24, 0x05]
+        );
+        assert_eq!(key("sub 2", &[0x00, 0x01], 1), [0xFF, 0xFF]);
+        // 註解、大小寫與十進位
+        assert_eq!(key("XOR 255 # 低位元組反相", &seed, 1), [0x12, 0xCB]);
+    }
+
+    #[test]
+    fn script_errors() {
+        let error = |text: &str| KeyScript::parse(text).unwrap_err();
+        assert_eq!(error("foo 1"), "第 1 行: 未知的步驟: foo");
+        assert_eq!(error("xor"), "第 1 行: xor 需要 1 個參數");
+        assert_eq!(error("swap 1"), "第 1 行: swap 需要 0 個參數");
+        assert_eq!(error("lfsr 0x07"), "第 1 行: lfsr 需要 2 個參數");
+        assert_eq!(error("# 註解\n\nrol 64"), "第 3 行: 位移量須為 0~63: 64");
+        assert_eq!(error("xor zz"), "第 1 行: 無效的數值: zz");
+        assert_eq!(error("add 0xG1"), "第 1 行: 無效的數值: 0xG1");
+        assert_eq!(error("width 9"), "第 1 行: width 須為 1~8");
+
+        let script = KeyScript::parse("not").unwrap();
+        assert!(script.compute(&[], 1).is_err());
+        assert!(script.compute(&[0; 9], 1).is_err());
+    }
+
+    #[test]
+    fn unlock_sends_computed_key() {
+        let algorithm = KeyAlgorithm::Script(KeyScript::parse("xor 0x5A5A5A5A").unwrap());
+        let mut client = UdsClient::default();
+        let mut unlock = Unlock::start(0x01, None, &mut client);
+        client.poll(0);
+        assert_eq!(client.link.outbox[0].data[..3], [0x02, 0x27, 0x01]);
+
+        let mut client = UdsClient::default();
+        let seed = Outcome::Positive(vec![0x67, 0x01, 0x11, 0x22, 0x33, 0x44]);
+        let result = unlock.on_exchange(
+            &exchange(&[0x27, 0x01], seed),
+            Some(&algorithm),
+            &mut client,
+        );
+        assert!(result.is_none());
+        client.poll(0);
+        assert_eq!(
+            client.link.outbox[0].data[..7],
+            [0x06, 0x27, 0x02, 0x4B, 0x78, 0x69, 0x1E]
+        );
+
+        // 金鑰被拒時回報 NRC
+        let invalid = Outcome::Negative(0x35);
+        let result = unlock.on_exchange(
+            &exchange(&[0x27, 0x02, 0x4B, 0x78, 0x69, 0x1E], invalid),
+            Some(&algorithm),
+            &mut client,
+        );
+        assert_eq!(
+            result,
+            Some(Err("SecurityAccess 0x02 被拒: invalidKey".to_string()))
+        );
+    }
+}
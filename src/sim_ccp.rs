@@ -0,0 +1,500 @@
+use crate::canbus::CanFrame;
+
+const CRO_ID: u32 = 0x6F0;
+const DTO_ID: u32 = 0x6F1;
+// 模擬記憶體從這個位址開始
+const MEMORY_BASE: u32 = 0x2000;
+const MEMORY_SIZE: usize = 0x100;
+// 事件通道 0 的週期
+const EVENT_PERIOD_US: u64 = 10_000;
+// 唯一的 DAQ 清單 0 有 4 個 ODT，PID 從 0 開始
+const ODT_COUNT: usize = 4;
+const ODT_ELEMENTS: usize = 7;
+
+// 量測值 (依 big_endian 的位元組順序)；0x2080 起是標定參數
+const COUNTER_ADDRESS: u32 = 0x2000;
+const SPEED_ADDRESS: u32 = 0x2002;
+const TEMPERATURE_ADDRESS: u32 = 0x2004;
+const VOLTAGE_ADDRESS: u32 = 0x2008;
+const IDLE_SPEED_ADDRESS: u32 = 0x2080;
+
+/// 掛在虛擬匯流排上的 CCP 2.1 從站，CRO 0x6F0、DTO 0x6F1、站位址 0x0001
+///
+/// 0x2000 起是每 10 ms 更新的量測值 (u16 計數器、u16 轉速、i8 溫度、f32 電壓)，
+/// 0x2080 是 u16 怠速設定，轉速在它上下擺動。
+pub struct SimCcp {
+    pub channel: u32,
+    // 多位元組參數與量測值用 Motorola 順序，false 為 Intel
+    big_endian: bool,
+    pub outbox: Vec<CanFrame>,
+    station_address: u16,
+    connected: bool,
+    memory: Vec<u8>,
+    mta: u32,
+    // ODT 元素 (位址, 長度)
+    odts: [[(u32, u8); ODT_ELEMENTS]; ODT_COUNT],
+    daq_pointer: (usize, usize),
+    // 啟動時的 (最後一個 ODT, 事件通道, 預分頻)
+    daq: Option<(usize, u8, u16)>,
+    prepared: Option<(usize, u8, u16)>,
+    daq_counter: u16,
+    next_event_us: u64,
+    ticks: u16,
+}
+
+impl Default for SimCcp {
+    fn default() -> Self {
+        Self::with_byte_order(true)
+    }
+}
+
+impl SimCcp {
+    pub fn with_byte_order(big_endian: bool) -> Self {
+        let mut sim = Self {
+            channel: 0,
+            big_endian,
+            outbox: Vec::new(),
+            station_address: 0x0001,
+            connected: false,
+            memory: vec![0; MEMORY_SIZE],
+            mta: 0,
+            odts: [[(0, 0); ODT_ELEMENTS]; ODT_COUNT],
+            daq_pointer: (0, 0),
+            daq: None,
+            prepared: None,
+            daq_counter: 0,
+            next_event_us: 0,
+            ticks: 0,
+        };
+        sim.store(IDLE_SPEED_ADDRESS, &sim.word(800));
+        sim
+    }
+
+    fn range(&self, address: u32, len: usize) -> Option<std::ops::Range<usize>> {
+        let start = address.checked_sub(MEMORY_BASE)? as usize;
+        (start + len <= MEMORY_SIZE).then_some(start..start + len)
+    }
+
+    fn word(&self, value: u16) -> [u8; 2] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn dword(&self, value: u32) -> [u8; 4] {
+        if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn store(&mut self, address: u32, data: &[u8]) {
+        if let Some(range) = self.range(address, data.len()) {
+            self.memory[range].copy_from_slice(data);
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        let mut bytes = [0u8; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: DTO_ID,
+            len: 8,
+            data: bytes,
+            ..Default::default()
+        });
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame) {
+        if frame.channel != self.channel
+            || frame.id != CRO_ID
+            || frame.extended
+            || frame.remote
+            || frame.len < 2
+        {
+            return;
+        }
+        let request = frame.payload();
+        let counter = request[1];
+        let result = self.respond(request);
+        // 未連線時不回應
+        if let Some(result) = result {
+            let (code, parameters) = match result {
+                Ok(parameters) => (0x00, parameters),
+                Err(code) => (code, Vec::new()),
+            };
+            self.send(&[&[0xFF, code, counter][..], &parameters].concat());
+        }
+    }
+
+    fn respond(&mut self, request: &[u8]) -> Option<Result<Vec<u8>, u8>> {
+        let byte = |i: usize| request.get(i).copied().unwrap_or(0);
+        let big_endian = self.big_endian;
+        let dword = |i: usize| {
+            let bytes = [byte(i), byte(i + 1), byte(i + 2), byte(i + 3)];
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let station = u16::from_le_bytes([byte(2), byte(3)]);
+        match request[0] {
+            // CONNECT：只回應自己的站位址
+            0x01 if station == self.station_address => {
+                self.connected = true;
+                return Some(Ok(Vec::new()));
+            }
+            0x01 => {
+                self.connected = false;
+                return None;
+            }
+            _ if !self.connected => return None,
+            _ => {}
+        }
+        Some(match request[0] {
+            // GET_CCP_VERSION
+            0x1B => Ok(vec![2, 1]),
+            // DISCONNECT
+            0x07 => {
+                if byte(2) == 0x01 {
+                    self.daq = None;
+                    self.prepared = None;
+                }
+                self.connected = false;
+                Ok(Vec::new())
+            }
+            // SET_MTA (只支援 MTA0)
+            0x02 if byte(2) == 0 => {
+                self.mta = dword(4);
+                Ok(Vec::new())
+            }
+            // DNLOAD / DNLOAD_6
+            0x03 | 0x23 => {
+                let (len, start) = if request[0] == 0x23 {
+                    (6, 2)
+                } else {
+                    (byte(2) as usize, 3)
+                };
+                match self.range(self.mta, len) {
+                    Some(range) if start + len <= request.len() => {
+                        self.memory[range].copy_from_slice(&request[start..start + len]);
+                        self.mta += len as u32;
+                        let mut parameters = vec![0];
+                        parameters.extend_from_slice(&self.dword(self.mta));
+                        Ok(parameters)
+                    }
+                    Some(_) => Err(0x31),
+                    None => Err(0x33),
+                }
+            }
+            // UPLOAD
+            0x04 => {
+                let len = byte(2) as usize;
+                let result = self.upload(self.mta, len);
+                if result.is_ok() {
+                    self.mta += len as u32;
+                }
+                result
+            }
+            // SHORT_UP
+            0x0F => self.upload(dword(4), byte(2) as usize),
+            // GET_DAQ_SIZE：清空清單並回傳 ODT 數量與第一個 PID
+            0x14 if byte(2) == 0 => {
+                self.daq = None;
+                self.prepared = None;
+                self.odts = [[(0, 0); ODT_ELEMENTS]; ODT_COUNT];
+                Ok(vec![ODT_COUNT as u8, 0])
+            }
+            0x14 => Ok(vec![0, 0]),
+            // SET_DAQ_PTR
+            0x15 => {
+                if byte(2) != 0 || byte(3) as usize >= ODT_COUNT || byte(4) as usize >= ODT_ELEMENTS
+                {
+                    Err(0x32)
+                } else {
+                    self.daq_pointer = (byte(3) as usize, byte(4) as usize);
+                    Ok(Vec::new())
+                }
+            }
+            // WRITE_DAQ
+            0x16 => {
+                let size = byte(2);
+                if ![1, 2, 4].contains(&size) || self.range(dword(4), size as usize).is_none() {
+                    Err(0x32)
+                } else {
+                    let (odt, element) = self.daq_pointer;
+                    self.odts[odt][element] = (dword(4), size);
+                    Ok(Vec::new())
+                }
+            }
+            // START_STOP
+            0x06 => {
+                let last_odt = byte(4) as usize;
+                let prescaler = [byte(6), byte(7)];
+                let prescaler = if big_endian {
+                    u16::from_be_bytes(prescaler)
+                } else {
+                    u16::from_le_bytes(prescaler)
+                };
+                let settings = (last_odt, byte(5), prescaler.max(1));
+                if byte(3) != 0 || last_odt >= ODT_COUNT {
+                    Err(0x32)
+                } else {
+                    match byte(2) {
+                        0 => self.daq = None,
+                        1 => self.daq = Some(settings),
+                        _ => self.prepared = Some(settings),
+                    }
+                    Ok(Vec::new())
+                }
+            }
+            // START_STOP_ALL
+            0x08 => {
+                self.daq = if byte(2) == 0x01 {
+                    self.prepared.take()
+                } else {
+                    None
+                };
+                Ok(Vec::new())
+            }
+            _ => Err(0x30),
+        })
+    }
+
+    fn upload(&self, address: u32, len: usize) -> Result<Vec<u8>, u8> {
+        if len > 5 {
+            return Err(0x32);
+        }
+        match self.range(address, len) {
+            Some(range) => Ok(self.memory[range].to_vec()),
+            None => Err(0x33),
+        }
+    }
+
+    /// 每 10 ms 更新量測值並觸發事件通道 0
+    pub fn poll(&mut self, now_us: u64) {
+        if now_us < self.next_event_us {
+            return;
+        }
+        self.next_event_us = now_us + EVENT_PERIOD_US;
+        self.ticks = self.ticks.wrapping_add(1);
+
+        // 1. **量測值**
+        let idle = self.range(IDLE_SPEED_ADDRESS, 2).unwrap();
+        let idle = [self.memory[idle.start], self.memory[idle.start + 1]];
+        let idle = if self.big_endian {
+            u16::from_be_bytes(idle)
+        } else {
+            u16::from_le_bytes(idle)
+        };
+        let swing = (self.ticks % 100) as i32 - 50;
+        self.store(COUNTER_ADDRESS, &self.word(self.ticks));
+        self.store(
+            SPEED_ADDRESS,
+            &self.word((idle as i32 + swing).max(0) as u16),
+        );
+        self.store(TEMPERATURE_ADDRESS, &[(85 + swing / 10) as i8 as u8]);
+        self.store(
+            VOLTAGE_ADDRESS,
+            &self.dword((13.8f32 + swing as f32 / 100.0).to_bits()),
+        );
+
+        // 2. **DAQ**
+        let Some((last_odt, event, prescaler)) = self.daq else {
+            return;
+        };
+        if event != 0 {
+            return;
+        }
+        self.daq_counter += 1;
+        if self.daq_counter < prescaler {
+            return;
+        }
+        self.daq_counter = 0;
+        for odt in 0..=last_odt {
+            let mut data = vec![odt as u8];
+            for &(address, size) in &self.odts[odt] {
+                if size == 0 {
+                    continue;
+                }
+                let start = (address - MEMORY_BASE) as usize;
+                data.extend_from_slice(&self.memory[start..start + size as usize]);
+            }
+            data.truncate(8);
+            self.send(&data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ccp::{CcpMaster, CcpResult, Tag};
+    use crate::measurement::{DaqVariable, ValueType};
+
+    /// 主站與模擬從站每 1 ms 交換一次，回傳這段時間完成的結果
+    fn run(master: &mut CcpMaster, sim: &mut SimCcp, now: &mut u64, ms: usize) -> Vec<CcpResult> {
+        for _ in 0..ms {
+            *now += 1_000;
+            master.poll(*now);
+            sim.poll(*now);
+            for frame in std::mem::take(&mut master.outbox) {
+                sim.handle(&frame);
+            }
+            for frame in std::mem::take(&mut sim.outbox) {
+                master.handle(&CanFrame {
+                    timestamp_us: *now,
+                    ..frame
+                });
+            }
+        }
+        master.completed.drain(..).collect()
+    }
+
+    #[test]
+    fn connect_upload_download_and_daq() {
+        let (mut master, mut sim, mut now) = (CcpMaster::default(), SimCcp::default(), 0);
+
+        // 1. **CONNECT、SHORT_UP 與 DNLOAD**
+        master.connect();
+        master.read(0x2080, 0, 2, 0);
+        master.write(0x2090, 0, &[1, 2, 3, 4, 5, 6, 7, 8, 9], 1);
+        master.read(0x2090, 0, 9, 1);
+        master.read(0x3000, 0, 1, 2);
+        let results = run(&mut master, &mut sim, &mut now, 50);
+        assert!(master.connected);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].tag, Tag::Connect);
+        let idle = results[1].result.as_ref().unwrap();
+        assert_eq!(ValueType::U16.decode(idle, true), 800.0);
+        assert!(results[2].result.is_ok());
+        assert_eq!(
+            results[3].result.as_ref().unwrap(),
+            &vec![1, 2, 3, 4, 5, 6, 7, 8, 9]
+        );
+        assert!(results[4].result.as_ref().unwrap_err().contains("拒絕存取"));
+
+        // 2. **DAQ：變數跨 ODT，每個事件週期一組樣本**
+        let variables = [
+            (0x2000, 2),
+            (0x2002, 2),
+            (0x2004, 1),
+            (0x2008, 4),
+            (0x2090, 8),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(key, (address, size))| DaqVariable {
+            key,
+            address,
+            extension: 0,
+            size,
+        })
+        .collect();
+        master.start_daq(variables, 0, 0, 1);
+        let results = run(&mut master, &mut sim, &mut now, 200);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tag, Tag::DaqSetup);
+        assert!(results[0].result.is_ok());
+        assert!(master.daq_running);
+        let samples: Vec<_> = master.samples.drain(..).collect();
+        assert!(samples.len() >= 5 * 10, "{}", samples.len());
+        let counters: Vec<_> = samples
+            .iter()
+            .filter(|s| s.key == 0)
+            .map(|s| ValueType::U16.decode(&s.raw, true))
+            .collect();
+        assert_eq!(counters[1] - counters[0], 1.0);
+        let voltage = samples.iter().find(|s| s.key == 3).unwrap();
+        let voltage = ValueType::F32.decode(&voltage.raw, true);
+        assert!((13.2..=14.4).contains(&voltage), "{}", voltage);
+        let block = samples.iter().find(|s| s.key == 4).unwrap();
+        assert_eq!(block.raw, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // 3. **停止後不再有樣本**
+        master.stop_daq(0);
+        run(&mut master, &mut sim, &mut now, 50);
+        assert!(!master.daq_running);
+        master.samples.clear();
+        run(&mut master, &mut sim, &mut now, 50);
+        assert!(master.samples.is_empty());
+    }
+
+    #[test]
+    fn long_reads_use_set_mta_and_upload() {
+        let (mut master, mut sim, mut now) = (CcpMaster::default(), SimCcp::default(), 0);
+        master.connect();
+        let data: Vec<u8> = (1..=12).collect();
+        master.write(0x20A0, 0, &data, 0);
+        run(&mut master, &mut sim, &mut now, 20);
+
+        master.read(0x20A0, 0, 12, 0);
+        master.read(0x20A0, 0, 4, 1);
+        let mut commands = Vec::new();
+        for _ in 0..20 {
+            now += 1_000;
+            master.poll(now);
+            for frame in std::mem::take(&mut master.outbox) {
+                commands.push(frame.data[0]);
+                sim.handle(&frame);
+            }
+            for frame in std::mem::take(&mut sim.outbox) {
+                master.handle(&frame);
+            }
+        }
+        // SET_MTA、3 個 UPLOAD (5 + 5 + 2 bytes)，短的只要一個 SHORT_UP
+        assert_eq!(commands, [0x02, 0x04, 0x04, 0x04, 0x0F]);
+        let results: Vec<_> = master.completed.drain(..).collect();
+        assert_eq!(results[0].result, Ok(data.clone()));
+        assert_eq!(results[1].result, Ok(data[..4].to_vec()));
+    }
+
+    #[test]
+    fn intel_byte_order_daq_with_prescaler() {
+        let mut master = CcpMaster::default();
+        master.big_endian = false;
+        let mut sim = SimCcp::with_byte_order(false);
+        let mut now = 0;
+        master.connect();
+        master.read(0x2080, 0, 2, 0);
+        let results = run(&mut master, &mut sim, &mut now, 20);
+        let idle = results[1].result.as_ref().unwrap();
+        assert_eq!(ValueType::U16.decode(idle, false), 800.0);
+
+        // 位址與預分頻都是 Intel 順序；預分頻 2 表示每兩個事件一組樣本
+        let variables = [(0x2000, 2), (0x2008, 4)]
+            .into_iter()
+            .enumerate()
+            .map(|(key, (address, size))| DaqVariable {
+                key,
+                address,
+                extension: 0,
+                size,
+            })
+            .collect();
+        master.start_daq(variables, 0, 0, 2);
+        let results = run(&mut master, &mut sim, &mut now, 200);
+        assert!(results[0].result.is_ok());
+        assert_eq!(sim.daq.map(|(_, _, prescaler)| prescaler), Some(2));
+        let counters: Vec<_> = master
+            .samples
+            .iter()
+            .filter(|s| s.key == 0)
+            .map(|s| ValueType::U16.decode(&s.raw, false))
+            .collect();
+        assert!(counters.len() >= 5, "{:?}", counters);
+        assert!(
+            counters.windows(2).all(|w| w[1] - w[0] == 2.0),
+            "{:?}",
+            counters
+        );
+        let voltage = master.samples.iter().find(|s| s.key == 1).unwrap();
+        let voltage = ValueType::F32.decode(&voltage.raw, false);
+        assert!((13.2..=14.4).contains(&voltage), "{}", voltage);
+    }
+}
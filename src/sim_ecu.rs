@@ -0,0 +1,552 @@
+use crate::canbus::CanFrame;
+use crate::firmware::Segment;
+use crate::isotp::{IsoTpConfig, IsoTpEvent, IsoTpLink};
+use eframe::egui;
+use std::collections::BTreeMap;
+
+// 模擬 ECU 的金鑰 = 種子 XOR 這個常數 (與 SecurityAccess 的「XOR 常數」範例相同)
+const KEY_MASK: u32 = 0x5A5A_5A5A;
+// RequestDownload 回覆的 maxNumberOfBlockLength (含 SID 與序號)
+const MAX_BLOCK_LENGTH: u16 = 0x0402;
+const ERASE_ROUTINE: u16 = 0xFF00;
+const CHECK_ROUTINE: u16 = 0xFF01;
+// 抹除要花一點時間，先回 NRC 0x78
+const ERASE_TIME_US: u64 = 500_000;
+// 模擬 ECU 支援的 OBD-II Mode 01 PID
+const OBD_PIDS: &[u8] = &[0x01, 0x04, 0x05, 0x0C, 0x0D, 0x0F, 0x11, 0x1F, 0x2F, 0x42];
+
+struct Download {
+    address: u32,
+    size: usize,
+    data: Vec<u8>,
+    // 上一個接受的區塊序號
+    bsc: u8,
+}
+
+/// 掛在虛擬匯流排上的 UDS ECU，請求 0x7E0、回應 0x7E8，用來測試診斷與刷寫流程
+pub struct SimEcu {
+    pub link: IsoTpLink,
+    session: u8,
+    unlocked: bool,
+    seed: Option<u32>,
+    seed_counter: u32,
+    dids: BTreeMap<u16, Vec<u8>>,
+    dtcs: Vec<(u32, u8)>,
+    download: Option<Download>,
+    memory: Vec<Segment>,
+    // 延後送出的回應 (時間, 資料)
+    delayed: Option<(u64, Vec<u8>)>,
+}
+
+impl Default for SimEcu {
+    fn default() -> Self {
+        let config = IsoTpConfig {
+            tx_id: 0x7E8,
+            rx_id: 0x7E0,
+            ..Default::default()
+        };
+        let dids = BTreeMap::from([
+            (0xF190, b"SIMULATEDECU00001".to_vec()),
+            (0xF18C, b"SN-000042".to_vec()),
+            (0xF195, b"SW 1.0.0".to_vec()),
+        ]);
+        Self {
+            link: IsoTpLink::new(config),
+            session: 0x01,
+            unlocked: false,
+            seed: None,
+            seed_counter: 0x1234_5678,
+            dids,
+            dtcs: vec![(0x012345, 0x2F), (0xC15500, 0x09)],
+            download: None,
+            memory: Vec::new(),
+            delayed: None,
+        }
+    }
+}
+
+fn negative(sid: u8, nrc: u8) -> Option<Vec<u8>> {
+    Some(vec![0x7F, sid, nrc])
+}
+
+impl SimEcu {
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        // OBD-II 廣播請求都是單幀，當成送到自己的實體 ID 處理
+        if frame.id == crate::obd::FUNCTIONAL_ID && !frame.extended {
+            let frame = CanFrame {
+                id: self.link.config.rx_id,
+                ..*frame
+            };
+            self.link.handle(&frame, now_us);
+        } else {
+            self.link.handle(frame, now_us);
+        }
+    }
+
+    pub fn poll(&mut self, now_us: u64) {
+        self.link.poll(now_us);
+        while let Some(event) = self.link.events.pop_front() {
+            if let IsoTpEvent::Received(request) = event {
+                if let Some(response) = self.respond(&request, now_us) {
+                    let _ = self.link.send(&response, now_us);
+                }
+            }
+        }
+        if self.delayed.as_ref().is_some_and(|(due, _)| now_us >= *due) {
+            let (_, response) = self.delayed.take().unwrap();
+            let _ = self.link.send(&response, now_us);
+        }
+    }
+
+    fn respond(&mut self, request: &[u8], now_us: u64) -> Option<Vec<u8>> {
+        let sid = request[0];
+        let sub = request.get(1).copied().unwrap_or(0);
+        // 子功能最高位元：抑制正回應
+        let suppress = sub & 0x80 != 0;
+        match sid {
+            0x10 => {
+                let session = sub & 0x7F;
+                if !matches!(session, 0x01..=0x03) {
+                    return negative(sid, 0x12);
+                }
+                self.session = session;
+                self.unlocked = false;
+                self.seed = None;
+                (!suppress).then(|| vec![0x50, session, 0x00, 0x32, 0x01, 0xF4])
+            }
+            0x11 => {
+                self.session = 0x01;
+                self.unlocked = false;
+                self.download = None;
+                (!suppress).then(|| vec![0x51, sub & 0x7F])
+            }
+            0x3E => (!suppress).then(|| vec![0x7E, 0x00]),
+            0x27 if sub % 2 == 1 => {
+                if self.session == 0x01 {
+                    return negative(sid, 0x7F);
+                }
+                let seed = if self.unlocked {
+                    0
+                } else {
+                    // 簡單的線性同餘，每次要的種子都不同
+                    self.seed_counter = self
+                        .seed_counter
+                        .wrapping_mul(1_103_515_245)
+                        .wrapping_add(12345);
+                    self.seed_counter | 1
+                };
+                self.seed = Some(seed);
+                let mut response = vec![0x67, sub];
+                response.extend_from_slice(&seed.to_be_bytes());
+                Some(response)
+            }
+            0x27 => {
+                let Some(seed) = self.seed.take() else {
+                    return negative(sid, 0x24);
+                };
+                if request.len() != 6 {
+                    return negative(sid, 0x13);
+                }
+                let key = u32::from_be_bytes([request[2], request[3], request[4], request[5]]);
+                if key != seed ^ KEY_MASK {
+                    return negative(sid, 0x35);
+                }
+                self.unlocked = true;
+                Some(vec![0x67, sub])
+            }
+            0x22 if request.len() == 3 => {
+                let did = u16::from_be_bytes([request[1], request[2]]);
+                let Some(value) = self.dids.get(&did) else {
+                    return negative(sid, 0x31);
+                };
+                let mut response = vec![0x62, request[1], request[2]];
+                response.extend_from_slice(value);
+                Some(response)
+            }
+            0x22 => negative(sid, 0x13),
+            0x2E if request.len() > 3 => {
+                if !self.unlocked {
+                    return negative(sid, 0x33);
+                }
+                let did = u16::from_be_bytes([request[1], request[2]]);
+                self.dids.insert(did, request[3..].to_vec());
+                Some(vec![0x6E, request[1], request[2]])
+            }
+            0x19 if sub == 0x02 && request.len() == 3 => {
+                let mut response = vec![0x59, 0x02, 0xFF];
+                for (dtc, status) in self.dtcs.iter().filter(|(_, s)| s & request[2] != 0) {
+                    response.extend_from_slice(&dtc.to_be_bytes()[1..]);
+                    response.push(*status);
+                }
+                Some(response)
+            }
+            0x14 => {
+                self.dtcs.clear();
+                Some(vec![0x54])
+            }
+            0x01 => self.obd_current_data(request, now_us),
+            // OBD DTC 只取 UDS DTC 的前兩個 byte：已確認 (03) 與待定 (07)
+            0x03 | 0x07 => {
+                let mask = if sid == 0x03 { 0x08 } else { 0x04 };
+                let dtcs: Vec<_> = self.dtcs.iter().filter(|(_, s)| s & mask != 0).collect();
+                let mut response = vec![sid + 0x40, dtcs.len() as u8];
+                for (dtc, _) in dtcs {
+                    response.extend_from_slice(&dtc.to_be_bytes()[1..3]);
+                }
+                Some(response)
+            }
+            0x04 => {
+                self.dtcs.clear();
+                Some(vec![0x44])
+            }
+            0x09 if sub == 0x02 => {
+                let mut response = vec![0x49, 0x02, 0x01];
+                response.extend_from_slice(self.dids.get(&0xF190)?);
+                Some(response)
+            }
+            0x31 => self.routine(request, now_us),
+            0x34 => self.request_download(request),
+            0x36 => self.transfer_data(request),
+            0x37 => {
+                let Some(download) = self.download.take() else {
+                    return negative(sid, 0x24);
+                };
+                self.memory.push(Segment {
+                    address: download.address,
+                    data: download.data,
+                });
+                Some(vec![0x77])
+            }
+            // 有 SID 但不認得 (包含未處理的長度錯誤) 一律 serviceNotSupported
+            _ => negative(sid, 0x11),
+        }
+    }
+
+    // 支援的 PID 回位元遮罩，其餘回隨時間變化的數值；都不支援就不回
+    fn obd_current_data(&self, request: &[u8], now_us: u64) -> Option<Vec<u8>> {
+        let t = (now_us / 10_000) as u32;
+        let mut response = vec![0x41];
+        for &pid in &request[1..] {
+            let data = if pid % 0x20 == 0 {
+                let mut mask = 0u32;
+                for &p in OBD_PIDS.iter().filter(|&&p| p > pid) {
+                    match p - pid {
+                        offset @ 1..=32 => mask |= 1 << (32 - offset),
+                        _ => mask |= 1,
+                    }
+                }
+                if mask == 0 && pid != 0 {
+                    continue;
+                }
+                mask.to_be_bytes().to_vec()
+            } else if OBD_PIDS.contains(&pid) {
+                let rpm = 800 + t % 2400;
+                match pid {
+                    0x01 => vec![0x80 | self.dtcs.len() as u8, 0x07, 0xE5, 0x00],
+                    0x04 => vec![(t % 200) as u8],
+                    0x05 => vec![40 + 90],
+                    0x0C => (rpm as u16 * 4).to_be_bytes().to_vec(),
+                    0x0D => vec![(rpm / 40) as u8],
+                    0x0F => vec![40 + 25],
+                    0x11 => vec![(t * 3 % 255) as u8],
+                    0x1F => ((now_us / 1_000_000) as u16).to_be_bytes().to_vec(),
+                    0x2F => vec![180],
+                    _ => 13_800u16.to_be_bytes().to_vec(),
+                }
+            } else {
+                continue;
+            };
+            response.push(pid);
+            response.extend_from_slice(&data);
+        }
+        (response.len() > 1).then_some(response)
+    }
+
+    fn routine(&mut self, request: &[u8], now_us: u64) -> Option<Vec<u8>> {
+        if request.len() < 4 {
+            return negative(0x31, 0x13);
+        }
+        let routine = u16::from_be_bytes([request[2], request[3]]);
+        let ok = vec![0x71, request[1], request[2], request[3], 0x00];
+        match routine {
+            ERASE_ROUTINE => {
+                if self.session != 0x02 {
+                    return negative(0x31, 0x22);
+                }
+                if !self.unlocked {
+                    return negative(0x31, 0x33);
+                }
+                // 參數為 [0x44, 位址 4 bytes, 長度 4 bytes]，沒有參數就全部抹除
+                match request.get(5..13) {
+                    Some(range) => {
+                        let start =
+                            u32::from_be_bytes([range[0], range[1], range[2], range[3]]) as u64;
+                        let end = start
+                            + u32::from_be_bytes([range[4], range[5], range[6], range[7]]) as u64;
+                        self.memory.retain(|s| {
+                            let address = s.address as u64;
+                            address + s.data.len() as u64 <= start || address >= end
+                        });
+                    }
+                    None => self.memory.clear(),
+                }
+                self.delayed = Some((now_us + ERASE_TIME_US, ok));
+                negative(0x31, 0x78)
+            }
+            CHECK_ROUTINE => Some(ok),
+            _ => negative(0x31, 0x31),
+        }
+    }
+
+    fn request_download(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        if self.session != 0x02 {
+            return negative(0x34, 0x22);
+        }
+        if !self.unlocked {
+            return negative(0x34, 0x33);
+        }
+        let format = *request.get(2)?;
+        let (address_len, size_len) = ((format & 0x0F) as usize, (format >> 4) as usize);
+        if request.len() != 3 + address_len + size_len || address_len > 4 || size_len > 4 {
+            return negative(0x34, 0x13);
+        }
+        let field = |bytes: &[u8]| bytes.iter().fold(0u32, |acc, b| acc << 8 | *b as u32);
+        let address = field(&request[3..3 + address_len]);
+        let size = field(&request[3 + address_len..]) as usize;
+        self.download = Some(Download {
+            address,
+            size,
+            data: Vec::with_capacity(size),
+            bsc: 0,
+        });
+        let max = MAX_BLOCK_LENGTH.to_be_bytes();
+        Some(vec![0x74, 0x20, max[0], max[1]])
+    }
+
+    fn transfer_data(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let Some(download) = self.download.as_mut() else {
+            return negative(0x36, 0x24);
+        };
+        if request.len() < 2 || request.len() > MAX_BLOCK_LENGTH as usize {
+            return negative(0x36, 0x13);
+        }
+        let bsc = request[1];
+        // 重送上一個已接受的區塊：直接回正回應，不重複寫入
+        if bsc == download.bsc && !download.data.is_empty() {
+            return Some(vec![0x76, bsc]);
+        }
+        if bsc != download.bsc.wrapping_add(1) {
+            return negative(0x36, 0x73);
+        }
+        if download.data.len() + request.len() - 2 > download.size {
+            return negative(0x36, 0x71);
+        }
+        download.data.extend_from_slice(&request[2..]);
+        download.bsc = bsc;
+        Some(vec![0x76, bsc])
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "請求 0x{:03X} / 回應 0x{:03X}，金鑰 = 種子 XOR 0x{:08X}",
+            self.link.config.rx_id, self.link.config.tx_id, KEY_MASK
+        ));
+        ui.label(format!(
+            "Session 0x{:02X}，{}",
+            self.session,
+            if self.unlocked {
+                "已解鎖"
+            } else {
+                "未解鎖"
+            }
+        ));
+        if let Some(download) = &self.download {
+            ui.label(format!(
+                "下載中 0x{:08X}: {}/{} bytes",
+                download.address,
+                download.data.len(),
+                download.size
+            ));
+        }
+        for segment in &self.memory {
+            let sum = segment
+                .data
+                .iter()
+                .fold(0u32, |acc, b| acc.wrapping_add(*b as u32));
+            ui.monospace(format!(
+                "0x{:08X}  {} bytes  總和 0x{:08X}",
+                segment.address,
+                segment.data.len(),
+                sum
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::FirmwareImage;
+    use crate::flash::{FlashConfig, FlashJob};
+    use crate::security::{KeyAlgorithm, KeyScript};
+    use crate::uds::{Exchange, UdsClient};
+
+    fn intel_hex(address: u32, data: &[u8]) -> String {
+        let mut text = String::new();
+        let mut record = |kind: u8, offset: u16, bytes: &[u8]| {
+            let mut line = vec![bytes.len() as u8];
+            line.extend_from_slice(&offset.to_be_bytes());
+            line.push(kind);
+            line.extend_from_slice(bytes);
+            let sum = line.iter().fold(0u8, |s, b| s.wrapping_add(*b));
+            line.push(sum.wrapping_neg());
+            text.push(':');
+            text.extend(line.iter().map(|b| format!("{:02X}", b)));
+            text.push('\n');
+        };
+        let mut upper = None;
+        for (i, chunk) in data.chunks(16).enumerate() {
+            let a = address + i as u32 * 16;
+            if upper != Some(a >> 16) {
+                upper = Some(a >> 16);
+                record(0x04, 0, &((a >> 16) as u16).to_be_bytes());
+            }
+            record(0x00, a as u16, chunk);
+        }
+        record(0x01, 0, &[]);
+        text
+    }
+
+    fn srecord(address: u32, data: &[u8]) -> String {
+        let mut text = String::new();
+        for (i, chunk) in data.chunks(32).enumerate() {
+            let mut line = vec![chunk.len() as u8 + 5];
+            line.extend_from_slice(&(address + i as u32 * 32).to_be_bytes());
+            line.extend_from_slice(chunk);
+            let sum = line.iter().fold(0u8, |s, b| s.wrapping_add(*b));
+            line.push(!sum);
+            text.push_str("S3");
+            text.extend(line.iter().map(|b| format!("{:02X}", b)));
+            text.push('\n');
+        }
+        text.push_str("S70500000000FA\n");
+        text
+    }
+
+    /// 刷寫一個映像檔；drop_block 指定的 TransferData 區塊第一次送出時丟掉，模擬傳輸失敗
+    fn flash(image: FirmwareImage, drop_block: Option<u8>) -> (SimEcu, Vec<Exchange>, usize) {
+        let algorithm = KeyAlgorithm::Script(KeyScript::parse("xor 0x5A5A5A5A").unwrap());
+        let mut client = UdsClient::default();
+        let mut ecu = SimEcu::default();
+        let mut job = FlashJob::new(image, FlashConfig::default(), &mut client, 0);
+        let mut exchanges = Vec::new();
+        let mut drop_block = drop_block;
+        let mut resumes = 0;
+        let mut now = 0;
+        for _ in 0..20_000 {
+            now += 5_000;
+            client.poll(now);
+            ecu.poll(now);
+            for frame in std::mem::take(&mut client.link.outbox) {
+                // 多幀 TransferData 的第一幀：[0x1L, L, 0x36, 序號, ...]
+                let first = frame.data[0] & 0xF0 == 0x10 && frame.data[2] == 0x36;
+                if first && drop_block == Some(frame.data[3]) {
+                    drop_block = None;
+                    continue;
+                }
+                ecu.handle(&frame, now);
+            }
+            for frame in std::mem::take(&mut ecu.link.outbox) {
+                client.handle(&frame, now);
+            }
+            while let Some(exchange) = client.completed.pop_front() {
+                job.on_exchange(&exchange, Some(&algorithm), &mut client, now);
+                exchanges.push(exchange);
+            }
+            if job.error.is_some() {
+                resumes += 1;
+                job.resume(&mut client);
+            }
+            if job.is_done() {
+                break;
+            }
+        }
+        assert!(job.is_done(), "{:?}", job.error);
+        (ecu, exchanges, resumes)
+    }
+
+    fn check(ecu: &SimEcu, exchanges: &[Exchange], image: &FirmwareImage) {
+        assert_eq!(ecu.memory, image.segments);
+        // 抹除先回 NRC 0x78，之後才是正回應
+        let erase = exchanges
+            .iter()
+            .find(|e| e.request.get(..4) == Some(&[0x31, 0x01, 0xFF, 0x00]));
+        assert!(erase.is_some_and(|e| e.pending_count > 0));
+        // 每個 TransferData 都用滿 maxNumberOfBlockLength，只有區段最後一塊可以比較短
+        let blocks: Vec<usize> = exchanges
+            .iter()
+            .filter(|e| e.request[0] == 0x36)
+            .map(|e| e.request.len())
+            .collect();
+        let full = MAX_BLOCK_LENGTH as usize;
+        let expected: Vec<usize> = image
+            .segments
+            .iter()
+            .flat_map(|s| s.data.chunks(full - 2).map(|c| c.len() + 2))
+            .collect();
+        assert_eq!(blocks, expected);
+    }
+
+    fn load(name: &str, contents: &[u8], base_address: u32) -> FirmwareImage {
+        let path = std::env::temp_dir().join(format!("sim_ecu_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let image = FirmwareImage::load(path.to_str().unwrap(), base_address);
+        let _ = std::fs::remove_file(&path);
+        image.unwrap()
+    }
+
+    #[test]
+    fn flash_intel_hex_srecord_and_binary() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+        // 跨過 64 KB 邊界，需要第二筆 04 記錄
+        let hex = load("image.hex", intel_hex(0x0800_F000, &data).as_bytes(), 0);
+        let srec = load(
+            "image.s37",
+            srecord(0x0002_0000, &data[..3000]).as_bytes(),
+            0,
+        );
+        let bin = load("image.bin", &data[..2500], 0x0004_0000);
+        assert_eq!(hex.segments[0].address, 0x0800_F000);
+        assert_eq!(srec.segments[0].data, data[..3000]);
+        assert_eq!(bin.segments[0].address, 0x0004_0000);
+
+        for image in [hex, srec, bin] {
+            let (ecu, exchanges, resumes) = flash(image.clone(), None);
+            assert_eq!(resumes, 0);
+            check(&ecu, &exchanges, &image);
+        }
+    }
+
+    #[test]
+    fn flash_resumes_after_failure() {
+        let mut image = FirmwareImage::default();
+        image.segments.push(Segment {
+            address: 0x1000,
+            data: (0..5000u32).map(|i| i as u8).collect(),
+        });
+        image.segments.push(Segment {
+            address: 0x9000,
+            data: vec![0xAA; 100],
+        });
+        let (ecu, exchanges, resumes) = flash(image.clone(), Some(3));
+        assert_eq!(resumes, 1);
+        assert_eq!(ecu.memory, image.segments);
+        // 失敗的區塊 3 重送時沿用同一個序號
+        let block3: Vec<_> = exchanges
+            .iter()
+            .filter(|e| e.request.get(..2) == Some(&[0x36, 3]))
+            .collect();
+        assert_eq!(block3.len(), 2);
+        assert!(block3[1].outcome == crate::uds::Outcome::Positive(vec![0x76, 3]));
+    }
+}
@@ -0,0 +1,517 @@
+use crate::canbus::CanFrame;
+
+// 模擬記憶體從這個位址開始
+const MEMORY_BASE: u32 = 0x1000;
+const MEMORY_SIZE: usize = 0x200;
+// 事件通道 0 的週期
+const EVENT_PERIOD_US: u64 = 10_000;
+const MAX_DAQ: u16 = 4;
+const MAX_ODT_ENTRY_SIZE: u8 = 4;
+
+// 量測變數的位址 (標定參數放在 0x1100 之後)
+const COUNTER_ADDRESS: u32 = 0x1000;
+const SINE_ADDRESS: u32 = 0x1004;
+const RAMP_ADDRESS: u32 = 0x1008;
+const GAIN_ADDRESS: u32 = 0x1100;
+const CURVE_ADDRESS: u32 = 0x1110;
+const MAP_ADDRESS: u32 = 0x1130;
+
+#[derive(Default)]
+struct DaqList {
+    // 每個 ODT 的 (位址, 長度)
+    odts: Vec<Vec<(u32, u8)>>,
+    timestamp: bool,
+    event: u16,
+    prescaler: u8,
+    counter: u8,
+    selected: bool,
+    running: bool,
+    first_pid: u8,
+}
+
+/// 掛在虛擬匯流排上的 XCP 從站，CRO 0x7F0、DTO 0x7F1，Intel 位元組順序
+///
+/// 0x1000 起是每 10 ms 更新的量測值 (u16 計數器、f32 正弦、u8 斜坡)，
+/// 0x1100 起是標定參數 (f32 增益、8 點 u16 曲線、4×4 u8 圖表)。
+pub struct SimXcp {
+    pub cro_id: u32,
+    pub dto_id: u32,
+    pub channel: u32,
+    pub outbox: Vec<CanFrame>,
+    connected: bool,
+    memory: Vec<u8>,
+    mta: u32,
+    daq_lists: Vec<DaqList>,
+    // SET_DAQ_PTR 設定的 (DAQ, ODT, entry)
+    daq_pointer: (usize, usize, usize),
+    synchronized: bool,
+    start_us: Option<u64>,
+    next_event_us: u64,
+    ticks: u32,
+}
+
+impl Default for SimXcp {
+    fn default() -> Self {
+        let mut sim = Self {
+            cro_id: 0x7F0,
+            dto_id: 0x7F1,
+            channel: 0,
+            outbox: Vec::new(),
+            connected: false,
+            memory: vec![0; MEMORY_SIZE],
+            mta: 0,
+            daq_lists: Vec::new(),
+            daq_pointer: (0, 0, 0),
+            synchronized: false,
+            start_us: None,
+            next_event_us: 0,
+            ticks: 0,
+        };
+        sim.store(GAIN_ADDRESS, &1.5f32.to_le_bytes());
+        for i in 0..8u16 {
+            sim.store(CURVE_ADDRESS + i as u32 * 2, &(i * 100).to_le_bytes());
+        }
+        for i in 0..16u8 {
+            sim.store(MAP_ADDRESS + i as u32, &[(i / 4) * 10 + i % 4]);
+        }
+        sim
+    }
+}
+
+fn error(code: u8) -> Vec<u8> {
+    vec![0xFE, code]
+}
+
+impl SimXcp {
+    fn range(&self, address: u32, len: usize) -> Option<std::ops::Range<usize>> {
+        let start = address.checked_sub(MEMORY_BASE)? as usize;
+        (start + len <= MEMORY_SIZE).then_some(start..start + len)
+    }
+
+    fn store(&mut self, address: u32, data: &[u8]) {
+        if let Some(range) = self.range(address, data.len()) {
+            self.memory[range].copy_from_slice(data);
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        let mut bytes = [0u8; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: self.dto_id,
+            len: data.len() as u8,
+            data: bytes,
+            ..Default::default()
+        });
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame) {
+        if frame.channel != self.channel
+            || frame.id != self.cro_id
+            || frame.extended
+            || frame.remote
+            || frame.len == 0
+        {
+            return;
+        }
+        let request = frame.payload().to_vec();
+        if let Some(response) = self.respond(&request) {
+            self.send(&response);
+        }
+    }
+
+    fn respond(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let command = request[0];
+        // 未連線時只接受 CONNECT
+        if command == 0xFF {
+            self.connected = true;
+            return Some(vec![0xFF, 0x05, 0x00, 8, 8, 0, 1, 1]);
+        }
+        if !self.connected {
+            return None;
+        }
+        let byte = |i: usize| request.get(i).copied().unwrap_or(0);
+        let word = |i: usize| u16::from_le_bytes([byte(i), byte(i + 1)]);
+        let dword = |i: usize| u32::from_le_bytes([byte(i), byte(i + 1), byte(i + 2), byte(i + 3)]);
+        match command {
+            // DISCONNECT
+            0xFE => {
+                self.connected = false;
+                self.stop_all();
+                Some(vec![0xFF])
+            }
+            // GET_STATUS
+            0xFD => {
+                let running = if self.daq_lists.iter().any(|d| d.running) {
+                    0x40
+                } else {
+                    0x00
+                };
+                Some(vec![0xFF, running, 0x00, 0x00, 0x00, 0x00])
+            }
+            // SET_MTA
+            0xF6 => {
+                self.mta = dword(4);
+                Some(vec![0xFF])
+            }
+            // UPLOAD
+            0xF5 => {
+                let len = byte(1) as usize;
+                if len > 7 {
+                    return Some(error(0x22));
+                }
+                let response = self.upload(self.mta, len);
+                if response.as_ref().is_some_and(|r| r[0] == 0xFF) {
+                    self.mta += len as u32;
+                }
+                response
+            }
+            // SHORT_UPLOAD
+            0xF4 => {
+                let len = byte(1) as usize;
+                if len > 7 {
+                    return Some(error(0x22));
+                }
+                self.upload(dword(4), len)
+            }
+            // DOWNLOAD
+            0xF0 => {
+                let len = byte(1) as usize;
+                if len > 6 || request.len() < 2 + len {
+                    return Some(error(0x21));
+                }
+                let Some(range) = self.range(self.mta, len) else {
+                    return Some(error(0x24));
+                };
+                self.memory[range].copy_from_slice(&request[2..2 + len]);
+                self.mta += len as u32;
+                Some(vec![0xFF])
+            }
+            // GET_DAQ_PROCESSOR_INFO：動態 DAQ
+            0xDA => {
+                let [low, high] = MAX_DAQ.to_le_bytes();
+                Some(vec![0xFF, 0x11, low, high, 1, 0, 0, 0x10])
+            }
+            // GET_DAQ_RESOLUTION_INFO：4 bytes 時間戳記，單位 1 µs
+            0xD9 => Some(vec![
+                0xFF,
+                1,
+                MAX_ODT_ENTRY_SIZE,
+                1,
+                MAX_ODT_ENTRY_SIZE,
+                0x34,
+                1,
+                0,
+            ]),
+            // FREE_DAQ
+            0xD6 => {
+                self.stop_all();
+                self.daq_lists.clear();
+                Some(vec![0xFF])
+            }
+            // ALLOC_DAQ
+            0xD5 => {
+                let count = word(2);
+                if count > MAX_DAQ {
+                    return Some(error(0x30));
+                }
+                self.daq_lists = (0..count).map(|_| DaqList::default()).collect();
+                Some(vec![0xFF])
+            }
+            // ALLOC_ODT
+            0xD4 => match self.daq_lists.get_mut(word(2) as usize) {
+                Some(list) => {
+                    list.odts = vec![Vec::new(); byte(4) as usize];
+                    Some(vec![0xFF])
+                }
+                None => Some(error(0x29)),
+            },
+            // ALLOC_ODT_ENTRY
+            0xD3 => match self
+                .daq_lists
+                .get_mut(word(2) as usize)
+                .and_then(|list| list.odts.get_mut(byte(4) as usize))
+            {
+                Some(odt) => {
+                    *odt = vec![(0, 0); byte(5) as usize];
+                    Some(vec![0xFF])
+                }
+                None => Some(error(0x29)),
+            },
+            // SET_DAQ_PTR
+            0xE2 => {
+                self.daq_pointer = (word(2) as usize, byte(4) as usize, byte(5) as usize);
+                Some(vec![0xFF])
+            }
+            // WRITE_DAQ
+            0xE1 => {
+                let size = byte(2);
+                if size > MAX_ODT_ENTRY_SIZE || self.range(dword(4), size as usize).is_none() {
+                    return Some(error(0x22));
+                }
+                let (daq, odt, entry) = self.daq_pointer;
+                match self
+                    .daq_lists
+                    .get_mut(daq)
+                    .and_then(|list| list.odts.get_mut(odt))
+                    .and_then(|odt| odt.get_mut(entry))
+                {
+                    Some(slot) => {
+                        *slot = (dword(4), size);
+                        self.daq_pointer.2 += 1;
+                        Some(vec![0xFF])
+                    }
+                    None => Some(error(0x22)),
+                }
+            }
+            // SET_DAQ_LIST_MODE
+            0xE0 => match self.daq_lists.get_mut(word(2) as usize) {
+                Some(list) => {
+                    list.timestamp = byte(1) & 0x10 != 0;
+                    list.event = word(4);
+                    list.prescaler = byte(6).max(1);
+                    Some(vec![0xFF])
+                }
+                None => Some(error(0x22)),
+            },
+            // START_STOP_DAQ_LIST：回傳第一個 PID
+            0xDE => {
+                let daq = word(2) as usize;
+                let first_pid = self.daq_lists[..daq.min(self.daq_lists.len())]
+                    .iter()
+                    .map(|d| d.odts.len())
+                    .sum::<usize>() as u8;
+                let synchronized = self.synchronized;
+                match self.daq_lists.get_mut(daq) {
+                    Some(list) if list.odts.is_empty() => Some(error(0x2A)),
+                    Some(list) => {
+                        list.first_pid = first_pid;
+                        match byte(1) {
+                            0 => list.running = false,
+                            1 => list.running = synchronized,
+                            _ => list.selected = true,
+                        }
+                        Some(vec![0xFF, first_pid])
+                    }
+                    None => Some(error(0x22)),
+                }
+            }
+            // START_STOP_SYNCH
+            0xDD => {
+                match byte(1) {
+                    0 => self.stop_all(),
+                    1 => {
+                        self.synchronized = true;
+                        for list in self.daq_lists.iter_mut().filter(|d| d.selected) {
+                            list.running = true;
+                            list.selected = false;
+                        }
+                    }
+                    _ => {
+                        for list in self.daq_lists.iter_mut().filter(|d| d.selected) {
+                            list.running = false;
+                            list.selected = false;
+                        }
+                    }
+                }
+                Some(vec![0xFF])
+            }
+            _ => Some(error(0x20)),
+        }
+    }
+
+    fn upload(&self, address: u32, len: usize) -> Option<Vec<u8>> {
+        match self.range(address, len) {
+            Some(range) => Some([&[0xFF][..], &self.memory[range]].concat()),
+            None => Some(error(0x24)),
+        }
+    }
+
+    fn stop_all(&mut self) {
+        self.synchronized = false;
+        for list in &mut self.daq_lists {
+            list.running = false;
+            list.selected = false;
+        }
+    }
+
+    /// 每 10 ms 更新量測值並觸發事件通道 0
+    pub fn poll(&mut self, now_us: u64) {
+        let start_us = *self.start_us.get_or_insert(now_us);
+        if now_us < self.next_event_us {
+            return;
+        }
+        self.next_event_us = now_us + EVENT_PERIOD_US;
+        self.ticks = self.ticks.wrapping_add(1);
+
+        // 1. **量測值**
+        let seconds = (now_us - start_us) as f64 / 1e6;
+        let gain = self.range(GAIN_ADDRESS, 4).unwrap();
+        let gain = f32::from_le_bytes(self.memory[gain].try_into().unwrap());
+        self.store(COUNTER_ADDRESS, &(self.ticks as u16).to_le_bytes());
+        self.store(
+            SINE_ADDRESS,
+            &(gain * (seconds * std::f64::consts::TAU).sin() as f32).to_le_bytes(),
+        );
+        self.store(RAMP_ADDRESS, &[self.ticks as u8]);
+
+        // 2. **DAQ**
+        let timestamp = ((now_us - start_us) as u32).to_le_bytes();
+        let mut frames = Vec::new();
+        for list in self
+            .daq_lists
+            .iter_mut()
+            .filter(|d| d.running && d.event == 0)
+        {
+            list.counter += 1;
+            if list.counter < list.prescaler {
+                continue;
+            }
+            list.counter = 0;
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let mut data = vec![list.first_pid + odt as u8];
+                if odt == 0 && list.timestamp {
+                    data.extend_from_slice(&timestamp);
+                }
+                for &(address, size) in entries {
+                    let start = (address - MEMORY_BASE) as usize;
+                    data.extend_from_slice(&self.memory[start..start + size as usize]);
+                }
+                data.truncate(8);
+                frames.push(data);
+            }
+        }
+        for data in frames {
+            self.send(&data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::{DaqVariable, ValueType};
+    use crate::xcp::{Tag, XcpMaster, XcpResult};
+
+    /// 主站與模擬從站每 1 ms 交換一次，回傳這段時間完成的結果
+    fn run(master: &mut XcpMaster, sim: &mut SimXcp, now: &mut u64, ms: usize) -> Vec<XcpResult> {
+        for _ in 0..ms {
+            *now += 1_000;
+            master.poll(*now);
+            sim.poll(*now);
+            for frame in std::mem::take(&mut master.outbox) {
+                sim.handle(&frame);
+            }
+            for frame in std::mem::take(&mut sim.outbox) {
+                master.handle(&CanFrame {
+                    timestamp_us: *now,
+                    ..frame
+                });
+            }
+        }
+        master.completed.drain(..).collect()
+    }
+
+    #[test]
+    fn connect_upload_and_download() {
+        let (mut master, mut sim, mut now) = (XcpMaster::default(), SimXcp::default(), 0);
+        master.connect();
+        master.read(0x1110, 0, 16, Tag::Upload(1));
+        let results = run(&mut master, &mut sim, &mut now, 50);
+        assert_eq!(results[0].tag, Tag::Connect);
+        assert_eq!(master.slave.unwrap().max_cto, 8);
+        // 超過一個 SHORT_UPLOAD 的長度會分段讀回
+        let curve = results[1].result.clone().unwrap();
+        assert_eq!(curve.len(), 16);
+        assert_eq!(ValueType::U16.decode(&curve[14..], false), 700.0);
+
+        master.write(
+            0x1100,
+            0,
+            &ValueType::F32.encode(2.0, false),
+            Tag::Download(2),
+        );
+        master.write(
+            0x1110,
+            0,
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            Tag::Download(3),
+        );
+        master.read(0x1100, 0, 4, Tag::Upload(2));
+        master.read(0x1110, 0, 10, Tag::Upload(3));
+        master.read(0x3000, 0, 4, Tag::Upload(4));
+        let results = run(&mut master, &mut sim, &mut now, 50);
+        assert_eq!(results.len(), 5);
+        assert!(results[..2].iter().all(|r| r.result.is_ok()));
+        let gain = results[2].result.as_ref().unwrap();
+        assert_eq!(ValueType::F32.decode(gain, false), 2.0);
+        assert_eq!(results[3].result, Ok(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]));
+        assert!(results[4]
+            .result
+            .as_ref()
+            .unwrap_err()
+            .contains("ACCESS_DENIED"));
+    }
+
+    #[test]
+    fn daq_samples_with_timestamps() {
+        let (mut master, mut sim, mut now) = (XcpMaster::default(), SimXcp::default(), 0);
+        master.connect();
+        run(&mut master, &mut sim, &mut now, 10);
+
+        let variables = vec![
+            DaqVariable {
+                key: 0,
+                address: 0x1000,
+                extension: 0,
+                size: 2,
+            },
+            DaqVariable {
+                key: 1,
+                address: 0x1004,
+                extension: 0,
+                size: 4,
+            },
+            DaqVariable {
+                key: 2,
+                address: 0x1008,
+                extension: 0,
+                size: 1,
+            },
+            // 跨 ODT 的 4×4 map
+            DaqVariable {
+                key: 3,
+                address: 0x1130,
+                extension: 0,
+                size: 16,
+            },
+        ];
+        master.start_daq(variables, 0, 2);
+        let results = run(&mut master, &mut sim, &mut now, 300);
+        // 設定只回報一次，在清單啟動之後
+        let setups: Vec<_> = results.iter().filter(|r| r.tag == Tag::DaqSetup).collect();
+        assert_eq!(setups.len(), 1);
+        assert!(setups[0].result.is_ok());
+        assert!(master.daq_running);
+
+        let samples: Vec<_> = master.samples.drain(..).collect();
+        assert!(samples.len() >= 4 * 10, "{}", samples.len());
+        let counters: Vec<_> = samples.iter().filter(|s| s.key == 0).collect();
+        // 預分頻 2：每 20 ms 一筆，從站時間戳記與計數器一致
+        let dt = counters[1].slave_time_us.unwrap() - counters[0].slave_time_us.unwrap();
+        assert_eq!(dt, 20_000);
+        let c0 = ValueType::U16.decode(&counters[0].raw, false);
+        let c1 = ValueType::U16.decode(&counters[1].raw, false);
+        assert_eq!(c1 - c0, 2.0);
+        let map = &samples.iter().find(|s| s.key == 3).unwrap().raw;
+        assert_eq!(map[5], 11);
+
+        master.stop_daq();
+        run(&mut master, &mut sim, &mut now, 50);
+        assert!(!master.daq_running);
+        master.samples.clear();
+        run(&mut master, &mut sim, &mut now, 50);
+        assert!(master.samples.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::canbus::CanFrame;
+use crate::dbc::Dbc;
+use eframe::egui;
+use egui::text::LayoutJob;
+use egui::{Color32, FontId, TextFormat};
+use std::collections::BTreeMap;
+
+/// 固定模式下每個 (通道, ID) 一列的統計
+#[derive(Debug, Clone)]
+pub struct IdStats {
+    pub last: CanFrame,
+    pub count: u64,
+    pub changed_mask: u8,
+    // 最近一次的間隔
+    pub period_us: Option<u64>,
+    pub min_us: u64,
+    pub max_us: u64,
+    // Welford 累計平均與變異數
+    intervals: u64,
+    mean_us: f64,
+    m2: f64,
+}
+
+impl IdStats {
+    fn new(frame: &CanFrame) -> Self {
+        Self {
+            last: *frame,
+            count: 1,
+            changed_mask: 0,
+            period_us: None,
+            min_us: u64::MAX,
+            max_us: 0,
+            intervals: 0,
+            mean_us: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    fn update(&mut self, frame: &CanFrame) {
+        let interval = frame.timestamp_us.saturating_sub(self.last.timestamp_us);
+        self.period_us = Some(interval);
+        self.min_us = self.min_us.min(interval);
+        self.max_us = self.max_us.max(interval);
+        self.intervals += 1;
+        let delta = interval as f64 - self.mean_us;
+        self.mean_us += delta / self.intervals as f64;
+        self.m2 += delta * (interval as f64 - self.mean_us);
+
+        self.changed_mask = 0;
+        for i in 0..8 {
+            let was_present = i < self.last.len as usize;
+            let present = i < frame.len as usize;
+            if present && (!was_present || self.last.data[i] != frame.data[i]) {
+                self.changed_mask |= 1 << i;
+            }
+        }
+        self.last = *frame;
+        self.count += 1;
+    }
+
+    pub fn mean_us(&self) -> Option<f64> {
+        (self.intervals > 0).then_some(self.mean_us)
+    }
+
+    pub fn std_dev_us(&self) -> Option<f64> {
+        (self.intervals > 1).then(|| (self.m2 / (self.intervals - 1) as f64).sqrt())
+    }
+}
+
+#[derive(Default)]
+pub struct TraceOverview {
+    pub rows: BTreeMap<(u32, bool, u32), IdStats>,
+}
+
+fn ms(us: f64) -> String {
+    format!("{:.1}", us / 1000.0)
+}
+
+impl TraceOverview {
+    pub fn update(&mut self, frame: &CanFrame) {
+        self.rows
+            .entry((frame.channel, frame.extended, frame.id))
+            .and_modify(|row| row.update(frame))
+            .or_insert_with(|| IdStats::new(frame));
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    /// now 為計算「距上次」的時間基準，凍結畫面時傳入凍結當下的時間
+    pub fn ui(&self, ui: &mut egui::Ui, dbc: Option<&Dbc>, now: u64) {
+        let highlight = Color32::from_rgb(255, 140, 0);
+        let normal = ui.visuals().text_color();
+
+        egui::Grid::new("trace_overview_grid")
+            .striped(true)
+            .num_columns(12)
+            .show(ui, |ui| {
+                for header in [
+                    "通道",
+                    "ID",
+                    "名稱",
+                    "DLC",
+                    "資料",
+                    "次數",
+                    "週期 ms",
+                    "平均 ms",
+                    "最小 ms",
+                    "最大 ms",
+                    "標準差 ms",
+                    "距上次 ms",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+
+                for row in self.rows.values() {
+                    let frame = &row.last;
+                    ui.label(frame.channel.to_string());
+                    if frame.extended {
+                        ui.monospace(format!("{:08X}", frame.id));
+                    } else {
+                        ui.monospace(format!("{:03X}", frame.id));
+                    }
+                    let name = dbc
+                        .and_then(|d| d.message(frame.id, frame.extended))
+                        .map(|m| m.name.as_str())
+                        .unwrap_or("");
+                    ui.label(name);
+                    ui.label(frame.len.to_string());
+
+                    // 與上一幀不同的位元組以顏色標示
+                    let mut job = LayoutJob::default();
+                    let format = |color| TextFormat::simple(FontId::monospace(12.0), color);
+                    if frame.remote {
+                        job.append("RTR", 0.0, format(normal));
+                    } else {
+                        for (i, b) in frame.payload().iter().enumerate() {
+                            let changed = row.changed_mask & (1 << i) != 0;
+                            job.append(
+                                &format!("{:02X}", b),
+                                if i == 0 { 0.0 } else { 6.0 },
+                                format(if changed { highlight } else { normal }),
+                            );
+                        }
+                    }
+                    ui.label(job);
+
+                    ui.label(row.count.to_string());
+                    ui.label(row.period_us.map(|p| ms(p as f64)).unwrap_or_default());
+                    ui.label(row.mean_us().map(ms).unwrap_or_default());
+                    let has_interval = row.period_us.is_some();
+                    ui.label(if has_interval {
+                        ms(row.min_us as f64)
+                    } else {
+                        String::new()
+                    });
+                    ui.label(if has_interval {
+                        ms(row.max_us as f64)
+                    } else {
+                        String::new()
+                    });
+                    ui.label(row.std_dev_us().map(ms).unwrap_or_default());
+                    ui.label(ms(now.saturating_sub(frame.timestamp_us) as f64));
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp_us: u64, payload: &[u8]) -> CanFrame {
+        let mut data = [0u8; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        CanFrame {
+            timestamp_us,
+            id: 0x100,
+            len: payload.len() as u8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn period_statistics() {
+        let mut overview = TraceOverview::default();
+        overview.update(&frame(0, &[0]));
+        let row = &overview.rows[&(0, false, 0x100)];
+        assert_eq!((row.count, row.period_us, row.mean_us()), (1, None, None));
+
+        // 間隔 10、20、30、40 ms
+        overview.update(&frame(10_000, &[0]));
+        let row = &overview.rows[&(0, false, 0x100)];
+        assert_eq!(row.mean_us(), Some(10_000.0));
+        assert_eq!(row.std_dev_us(), None);
+        for t in [30_000, 60_000, 100_000] {
+            overview.update(&frame(t, &[0]));
+        }
+        let row = &overview.rows[&(0, false, 0x100)];
+        assert_eq!(row.count, 5);
+        assert_eq!(row.period_us, Some(40_000));
+        assert_eq!((row.min_us, row.max_us), (10_000, 40_000));
+        assert_eq!(row.mean_us(), Some(25_000.0));
+        // 樣本標準差：偏差 ±5、±15 ms，平方和 500 ms² / 3
+        let expected = (500.0f64 / 3.0).sqrt() * 1000.0;
+        assert!((row.std_dev_us().unwrap() - expected).abs() < 1e-6);
+
+        // 擴展 ID 與其他通道各自一列
+        overview.update(&CanFrame {
+            extended: true,
+            ..frame(110_000, &[0])
+        });
+        overview.update(&CanFrame {
+            channel: 1,
+            ..frame(110_000, &[0])
+        });
+        assert_eq!(overview.rows.len(), 3);
+        assert_eq!(overview.rows[&(0, false, 0x100)].count, 5);
+    }
+
+    #[test]
+    fn changed_bytes() {
+        let mut stats = IdStats::new(&frame(0, &[1, 2, 3, 4]));
+        assert_eq!(stats.changed_mask, 0);
+        stats.update(&frame(1_000, &[1, 9, 3, 4]));
+        assert_eq!(stats.changed_mask, 0b0010);
+        // 沒有變化時清掉
+        stats.update(&frame(2_000, &[1, 9, 3, 4]));
+        assert_eq!(stats.changed_mask, 0);
+        stats.update(&frame(3_000, &[5, 9, 3, 8]));
+        assert_eq!(stats.changed_mask, 0b1001);
+        // 變長時新增的位元組算變化，變短時不算
+        stats.update(&frame(4_000, &[5, 9, 3, 8, 0]));
+        assert_eq!(stats.changed_mask, 0b1_0000);
+        stats.update(&frame(5_000, &[5, 9, 3]));
+        assert_eq!(stats.changed_mask, 0);
+        assert_eq!(stats.last.len, 3);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::canbus::CanFrame;
+use crate::dbc::Dbc;
+use crate::filter::DisplayFilter;
+use crate::frame_store::FrameStore;
+use crate::j1939::Definitions;
+use eframe::egui;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Sense, TextStyle, Vec2};
+use std::collections::HashSet;
+
+const SCROLLBAR_WIDTH: f32 = 12.0;
+// 序號欄左側點這個寬度內會展開/收合訊號
+const EXPANDER_WIDTH: f32 = 18.0;
+const COLUMNS: [(&str, f32); 8] = [
+    ("序號", 80.0),
+    ("時間 s", 110.0),
+    ("通道", 40.0),
+    ("ID", 80.0),
+    ("類型", 48.0),
+    ("DLC", 36.0),
+    ("資料", 200.0),
+    ("名稱", 160.0),
+];
+
+/// 虛擬化的滾動表格，只繪製目前可見的列，百萬幀也不會拖慢畫面
+pub struct TraceTable {
+    // 自動捲到最新；使用者往上捲時暫停，捲回底部時恢復
+    follow: bool,
+    top_row: usize,
+    wheel_accum: f32,
+    dragging_bar: bool,
+    jump_s: f64,
+    scroll_to_row: Option<usize>,
+    // 以絕對序號記錄，舊幀被丟棄後選取仍指向同一幀
+    selected: Option<u64>,
+    // 展開訊號樹的幀 (絕對序號)
+    expanded: HashSet<u64>,
+}
+
+impl Default for TraceTable {
+    fn default() -> Self {
+        Self {
+            follow: true,
+            top_row: 0,
+            wheel_accum: 0.0,
+            dragging_bar: false,
+            jump_s: 0.0,
+            scroll_to_row: None,
+            selected: None,
+            expanded: HashSet::new(),
+        }
+    }
+}
+
+fn kind(frame: &CanFrame) -> &'static str {
+    if frame.error {
+        "ERR"
+    } else if frame.remote {
+        if frame.extended {
+            "EXT R"
+        } else {
+            "STD R"
+        }
+    } else if frame.extended {
+        "EXT"
+    } else {
+        "STD"
+    }
+}
+
+fn cells(
+    index: u64,
+    frame: &CanFrame,
+    start_us: u64,
+    dbc: Option<&Dbc>,
+    j1939: Option<&Definitions>,
+) -> [String; 8] {
+    let id = if frame.extended {
+        format!("{:08X}", frame.id)
+    } else {
+        format!("{:03X}", frame.id)
+    };
+    let data = if frame.remote {
+        String::new()
+    } else {
+        frame
+            .payload()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    // DBC 沒定義的 29-bit ID，啟用 J1939 時改顯示 PGN 與位址
+    let name = dbc
+        .and_then(|d| d.message(frame.id, frame.extended))
+        .map(|m| m.name.clone())
+        .or_else(|| {
+            j1939
+                .filter(|_| frame.extended && !frame.error)
+                .map(|defs| defs.describe(frame.id))
+        })
+        .unwrap_or_default();
+    [
+        index.to_string(),
+        format!(
+            "{:.6}",
+            frame.timestamp_us.saturating_sub(start_us) as f64 / 1e6
+        ),
+        frame.channel.to_string(),
+        id,
+        kind(frame).to_string(),
+        frame.len.to_string(),
+        data,
+        name,
+    ]
+}
+
+fn paint_row<S: AsRef<str>>(
+    painter: &Painter,
+    min: Pos2,
+    height: f32,
+    texts: &[S],
+    color: Color32,
+) {
+    let mut x = min.x;
+    for ((_, w), text) in COLUMNS.iter().zip(texts) {
+        let cell = Rect::from_min_size(egui::pos2(x, min.y), Vec2::new(w - 4.0, height));
+        painter
+            .with_clip_rect(cell.intersect(painter.clip_rect()))
+            .text(
+                egui::pos2(x + 2.0, min.y + height / 2.0),
+                Align2::LEFT_CENTER,
+                text.as_ref(),
+                FontId::monospace(12.0),
+                color,
+            );
+        x += w;
+    }
+}
+
+/// 表格的列來源：已掃描過的緩衝區，或顯示篩選後的絕對序號
+struct Rows<'a> {
+    store: &'a FrameStore,
+    filtered: Option<&'a [u64]>,
+    // 顯示篩選掃描到的位置，凍結時停在凍結當下
+    end: u64,
+}
+
+impl Rows<'_> {
+    fn len(&self) -> usize {
+        match self.filtered {
+            Some(f) => f.len(),
+            None => {
+                (self.end.saturating_sub(self.store.first_index()) as usize).min(self.store.len())
+            }
+        }
+    }
+
+    fn index(&self, row: usize) -> Option<u64> {
+        match self.filtered {
+            Some(f) => f.get(row).copied(),
+            None => (row < self.len()).then(|| self.store.first_index() + row as u64),
+        }
+    }
+
+    fn frame(&self, index: u64) -> Option<&CanFrame> {
+        let offset = index.checked_sub(self.store.first_index())?;
+        self.store.get(offset as usize)
+    }
+
+    fn row_at_time(&self, timestamp_us: u64) -> usize {
+        match self.filtered {
+            Some(f) => f.partition_point(|&i| {
+                self.frame(i)
+                    .is_some_and(|frame| frame.timestamp_us < timestamp_us)
+            }),
+            None => self.store.index_at_time(timestamp_us).min(self.len()),
+        }
+    }
+}
+
+/// DBC 有定義的訊息才有訊號可以展開
+fn expandable(frame: &CanFrame, dbc: Option<&Dbc>) -> bool {
+    !frame.error
+        && !frame.remote
+        && dbc.is_some_and(|dbc| dbc.message(frame.id, frame.extended).is_some())
+}
+
+impl TraceTable {
+    fn line_count(&self, index: u64, frame: &CanFrame, dbc: Option<&Dbc>) -> usize {
+        if !self.expanded.contains(&index) {
+            return 1;
+        }
+        1 + dbc
+            .and_then(|dbc| dbc.decode(frame))
+            .map_or(0, |(_, signals)| signals.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.selected = None;
+        self.expanded.clear();
+        self.follow = true;
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        store: &FrameStore,
+        view: &DisplayFilter,
+        dbc: Option<&Dbc>,
+        j1939: Option<&Definitions>,
+        height: f32,
+    ) {
+        let rows = Rows {
+            store,
+            filtered: view.rows(),
+            end: view.scanned_end(),
+        };
+        let start_us = store.start_us().unwrap_or(0);
+        let total_rows = rows.len();
+
+        // 1. **控制列**：自動捲動與跳到指定時間
+        ui.horizontal(|ui| {
+            if self.follow {
+                ui.label("自動捲動");
+            } else {
+                ui.weak("自動捲動已暫停");
+                if ui.button("捲到最新").clicked() {
+                    self.follow = true;
+                }
+            }
+            ui.separator();
+            ui.label("跳到 (s):");
+            // 範圍限制在擷取的時間長度內
+            let span_s = store.last().map_or(0.0, |f| {
+                f.timestamp_us.saturating_sub(start_us) as f64 / 1e6
+            });
+            ui.add(
+                egui::DragValue::new(&mut self.jump_s)
+                    .range(0.0..=span_s)
+                    .speed(0.1),
+            );
+            if ui.button("跳到").clicked() && total_rows > 0 {
+                let target = start_us.saturating_add((self.jump_s * 1e6) as u64);
+                let row = rows.row_at_time(target).min(total_rows - 1);
+                self.scroll_to_row = Some(row);
+                self.selected = rows.index(row);
+                self.follow = false;
+            }
+        });
+
+        // 2. **表頭**
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + 2.0;
+        let headers: Vec<&str> = COLUMNS.iter().map(|(h, _)| *h).collect();
+        paint_row(
+            ui.painter(),
+            ui.cursor().min,
+            row_height,
+            &headers,
+            ui.visuals().strong_text_color(),
+        );
+        ui.add_space(row_height);
+
+        // 3. **以整數列號捲動**：f32 的捲動位移在上百萬列時精度不夠，自己畫捲軸
+        let width: f32 = COLUMNS.iter().map(|(_, w)| w).sum::<f32>() + SCROLLBAR_WIDTH;
+        let (rect, response) =
+            ui.allocate_exact_size(Vec2::new(width, height), Sense::click_and_drag());
+        let visible_rows = ((height / row_height) as usize).max(1);
+        // 展開的列會多佔幾行，從最後一列往回算出最底部的起始列
+        let mut max_top = total_rows;
+        let mut used = 0;
+        while max_top > 0 && used < visible_rows {
+            let lines = rows
+                .index(max_top - 1)
+                .and_then(|index| Some(self.line_count(index, rows.frame(index)?, dbc)))
+                .unwrap_or(1);
+            if used > 0 && used + lines > visible_rows {
+                break;
+            }
+            used += lines;
+            max_top -= 1;
+        }
+
+        let mut user_scrolled = false;
+        if response.hovered() {
+            self.wheel_accum += ui.input(|i| i.smooth_scroll_delta.y) / row_height;
+            let (page_up, page_down, home, end) = ui.input(|i| {
+                (
+                    i.key_pressed(egui::Key::PageUp),
+                    i.key_pressed(egui::Key::PageDown),
+                    i.key_pressed(egui::Key::Home),
+                    i.key_pressed(egui::Key::End),
+                )
+            });
+            if page_up {
+                self.wheel_accum += visible_rows as f32;
+            }
+            if page_down {
+                self.wheel_accum -= visible_rows as f32;
+            }
+            if home {
+                self.top_row = 0;
+                user_scrolled = true;
+            }
+            if end {
+                self.top_row = max_top;
+                user_scrolled = true;
+            }
+        }
+        let steps = self.wheel_accum.trunc();
+        if steps != 0.0 {
+            self.wheel_accum -= steps;
+            self.top_row = if steps > 0.0 {
+                self.top_row.saturating_sub(steps as usize)
+            } else {
+                self.top_row.saturating_add((-steps) as usize)
+            };
+            user_scrolled = true;
+        }
+
+        let track = Rect::from_min_max(
+            egui::pos2(rect.right() - SCROLLBAR_WIDTH, rect.top()),
+            rect.max,
+        );
+        let pointer = response.interact_pointer_pos();
+        if response.drag_started() || response.clicked() {
+            self.dragging_bar = pointer.is_some_and(|p| track.contains(p));
+        }
+        if self.dragging_bar {
+            if let Some(pos) = pointer {
+                let t = ((pos.y - track.top()) / track.height()).clamp(0.0, 1.0);
+                self.top_row = (t as f64 * max_top as f64).round() as usize;
+                user_scrolled = true;
+            }
+        }
+
+        if let Some(row) = self.scroll_to_row.take() {
+            self.top_row = row.saturating_sub(visible_rows / 2);
+        } else if user_scrolled {
+            // 往上捲就暫停跟隨，捲回最底再恢復
+            self.follow = self.top_row >= max_top;
+        } else if self.follow {
+            self.top_row = max_top;
+        }
+        self.top_row = self.top_row.min(max_top);
+
+        // 4. **只排版可見範圍內的列**：每列一行，展開的列底下每個訊號再一行
+        let mut lines: Vec<(usize, u64, Option<String>)> = Vec::new();
+        let mut row = self.top_row;
+        while lines.len() < visible_rows && row < total_rows {
+            let Some((index, frame)) = rows.index(row).and_then(|i| Some((i, rows.frame(i)?)))
+            else {
+                break;
+            };
+            lines.push((row, index, None));
+            if self.expanded.contains(&index) {
+                if let Some((_, signals)) = dbc.and_then(|dbc| dbc.decode(frame)) {
+                    lines.extend(signals.iter().map(|decoded| {
+                        let text = format!("{} = {}", decoded.signal.name, decoded.display_value());
+                        (row, index, Some(text))
+                    }));
+                }
+            }
+            row += 1;
+        }
+        lines.truncate(visible_rows);
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        let text_color = ui.visuals().text_color();
+        // 訊號從 ID 欄開始畫，跨過後面的欄位
+        let signal_x = rect.left() + COLUMNS[..3].iter().map(|(_, w)| w).sum::<f32>();
+        for (i, (row, index, signal)) in lines.iter().enumerate() {
+            let top = rect.top() + i as f32 * row_height;
+            let row_rect = Rect::from_min_size(
+                egui::pos2(rect.left(), top),
+                Vec2::new(width - SCROLLBAR_WIDTH, row_height),
+            );
+            if self.selected == Some(*index) && signal.is_none() {
+                painter.rect_filled(row_rect, 0.0, ui.visuals().selection.bg_fill);
+            } else if row % 2 == 1 {
+                painter.rect_filled(row_rect, 0.0, ui.visuals().faint_bg_color);
+            }
+            let Some(frame) = rows.frame(*index) else {
+                continue;
+            };
+            match signal {
+                Some(text) => {
+                    painter.text(
+                        egui::pos2(signal_x, top + row_height / 2.0),
+                        Align2::LEFT_CENTER,
+                        text,
+                        FontId::monospace(12.0),
+                        ui.visuals().weak_text_color(),
+                    );
+                }
+                None => {
+                    let mut texts = cells(*index, frame, start_us, dbc, j1939);
+                    if expandable(frame, dbc) {
+                        let arrow = if self.expanded.contains(index) {
+                            "▼"
+                        } else {
+                            "▶"
+                        };
+                        texts[0] = format!("{} {}", arrow, texts[0]);
+                    }
+                    paint_row(&painter, row_rect.min, row_height, &texts, text_color);
+                }
+            }
+        }
+
+        // 捲軸
+        painter.rect_filled(track, 0.0, ui.visuals().widgets.inactive.bg_fill);
+        if total_rows > visible_rows {
+            let handle_h = (track.height() * visible_rows as f32 / total_rows as f32).max(16.0);
+            let t = self.top_row as f32 / max_top.max(1) as f32;
+            let handle_top = track.top() + t * (track.height() - handle_h);
+            painter.rect_filled(
+                Rect::from_min_size(
+                    egui::pos2(track.left() + 2.0, handle_top),
+                    Vec2::new(SCROLLBAR_WIDTH - 4.0, handle_h),
+                ),
+                3.0,
+                ui.visuals().widgets.active.bg_fill,
+            );
+        }
+
+        if response.clicked() && !self.dragging_bar {
+            if let Some(pos) = pointer {
+                let line = ((pos.y - rect.top()) / row_height) as usize;
+                if let Some((_, index, signal)) = lines.get(line) {
+                    let index = *index;
+                    let on_arrow = signal.is_none()
+                        && pos.x < rect.left() + EXPANDER_WIDTH
+                        && rows.frame(index).is_some_and(|f| expandable(f, dbc));
+                    if on_arrow {
+                        if !self.expanded.remove(&index) {
+                            self.expanded.insert(index);
+                        }
+                    } else {
+                        self.selected = (self.selected != Some(index)).then_some(index);
+                    }
+                }
+            }
+        }
+        if !response.dragged() {
+            self.dragging_bar = false;
+        }
+
+        // 5. **選取列的訊號解碼**
+        let Some(frame) = self.selected.and_then(|index| rows.frame(index)) else {
+            return;
+        };
+        ui.separator();
+        let decoded = dbc.and_then(|dbc| dbc.decode(frame));
+        let Some((message, signals)) = decoded else {
+            ui.label(frame.to_string());
+            return;
+        };
+        let header = egui::CollapsingHeader::new(format!(
+            "ID=0x{:X} {}, Data={:?}",
+            frame.id,
+            message.name,
+            frame.payload()
+        ))
+        .id_salt("trace_table_selected")
+        .default_open(true)
+        .show(ui, |ui| {
+            for decoded in &signals {
+                let label = ui.label(format!(
+                    "{} = {}",
+                    decoded.signal.name,
+                    decoded.display_value()
+                ));
+                let signal = decoded.signal;
+                let mut hover = format!(
+                    "raw={}  範圍 [{}, {}]  接收: {}",
+                    decoded.raw,
+                    signal.min,
+                    signal.max,
+                    signal.receivers.join(", ")
+                );
+                if let Some(comment) = &signal.comment {
+                    hover.push('\n');
+                    hover.push_str(comment);
+                }
+                label.on_hover_text(hover);
+            }
+        });
+        let mut hover = format!("DLC {}  發送: {}", message.dlc, message.transmitter);
+        if let Some(comment) = &message.comment {
+            hover.push('\n');
+            hover.push_str(comment);
+        }
+        header.header_response.on_hover_text(hover);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::canbus::CanFrame;
+use std::io::{self, BufRead, Write};
+
+// OLE 日期 (1899-12-30 起算的天數) 與 Unix epoch 相差的天數
+const OLE_EPOCH_OFFSET_DAYS: f64 = 25569.0;
+const US_PER_DAY: f64 = 86_400_000_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrcVersion {
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+    V2_0,
+    V2_1,
+}
+
+impl TrcVersion {
+    pub const ALL: [TrcVersion; 6] = [
+        TrcVersion::V1_0,
+        TrcVersion::V1_1,
+        TrcVersion::V1_2,
+        TrcVersion::V1_3,
+        TrcVersion::V2_0,
+        TrcVersion::V2_1,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrcVersion::V1_0 => "1.0",
+            TrcVersion::V1_1 => "1.1",
+            TrcVersion::V1_2 => "1.2",
+            TrcVersion::V1_3 => "1.3",
+            TrcVersion::V2_0 => "2.0",
+            TrcVersion::V2_1 => "2.1",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.name() == s.trim())
+    }
+
+    fn is_v2(&self) -> bool {
+        matches!(self, TrcVersion::V2_0 | TrcVersion::V2_1)
+    }
+
+    // 各版本的欄位順序，代號沿用 2.1 的 $COLUMNS 定義
+    fn default_columns(&self) -> &'static str {
+        match self {
+            TrcVersion::V1_0 => "N,O,I,L,D",
+            TrcVersion::V1_1 => "N,O,T,I,L,D",
+            TrcVersion::V1_2 => "N,O,B,T,I,L,D",
+            TrcVersion::V1_3 => "N,O,B,T,I,R,L,D",
+            TrcVersion::V2_0 => "N,O,T,I,d,l,D",
+            TrcVersion::V2_1 => "N,O,T,B,I,d,R,L,D",
+        }
+    }
+}
+
+fn invalid(line_no: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("trc 第 {} 行: {}", line_no, msg),
+    )
+}
+
+fn format_id(frame: &CanFrame) -> String {
+    if frame.extended {
+        format!("{:08X}", frame.id)
+    } else {
+        format!("{:04X}", frame.id)
+    }
+}
+
+fn format_data(frame: &CanFrame) -> String {
+    frame
+        .payload()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 依指定版本寫出 PCAN-View trace；時間偏移以第一幀為基準
+pub fn write_trc<'a, W: Write>(
+    w: &mut W,
+    frames: impl IntoIterator<Item = &'a CanFrame>,
+    version: TrcVersion,
+) -> io::Result<()> {
+    let mut frames = frames.into_iter().peekable();
+    // 1. **計算 $STARTTIME**，捨去到 10 位小數後再當作偏移基準，讀回時才不會有誤差
+    let first_us = frames.peek().map(|f| f.timestamp_us).unwrap_or(0);
+    let start_days = ((first_us as f64 / US_PER_DAY + OLE_EPOCH_OFFSET_DAYS) * 1e10).floor() / 1e10;
+    let base_us = ((start_days - OLE_EPOCH_OFFSET_DAYS) * US_PER_DAY).round() as i64;
+
+    // 2. **檔頭**
+    // 1.0 沒有 FILEVERSION，讀取端沒看到就當作 1.0
+    if version != TrcVersion::V1_0 {
+        writeln!(w, ";$FILEVERSION={}", version.name())?;
+    }
+    writeln!(w, ";$STARTTIME={:.10}", start_days)?;
+    if version == TrcVersion::V2_1 {
+        writeln!(w, ";$COLUMNS={}", version.default_columns())?;
+    }
+    writeln!(w, ";")?;
+    writeln!(w, ";   Generated by {}", env!("CARGO_PKG_NAME"))?;
+    writeln!(w, ";   Columns: {}", version.default_columns())?;
+    writeln!(w, ";")?;
+
+    // 3. **每一幀一行**
+    for (i, frame) in frames.enumerate() {
+        let n = i + 1;
+        let offset_ms = (frame.timestamp_us as i64 - base_us) as f64 / 1000.0;
+        let bus = frame.channel + 1;
+        let id = format_id(frame);
+        let len = frame.len.min(8);
+        let data = if frame.remote {
+            "RTR".to_string()
+        } else {
+            format_data(frame)
+        };
+
+        let line = match version {
+            TrcVersion::V1_0 => {
+                format!("{:>6}) {:>9.0}  {:>8}  {}  {}", n, offset_ms, id, len, data)
+            }
+            TrcVersion::V1_1 => {
+                let kind = if frame.error { "Error" } else { "Rx" };
+                format!(
+                    "{:>6}) {:>11.1}  {:<5}  {:>8}  {}  {}",
+                    n, offset_ms, kind, id, len, data
+                )
+            }
+            TrcVersion::V1_2 | TrcVersion::V1_3 => {
+                let kind = if frame.error { "Error" } else { "Rx" };
+                let reserved = if version == TrcVersion::V1_3 {
+                    " -"
+                } else {
+                    ""
+                };
+                format!(
+                    "{:>6}) {:>13.3} {}  {:<5}  {:>8}{}  {}  {}",
+                    n, offset_ms, bus, kind, id, reserved, len, data
+                )
+            }
+            TrcVersion::V2_0 | TrcVersion::V2_1 => {
+                let kind = if frame.error {
+                    "ER"
+                } else if frame.remote {
+                    "RR"
+                } else {
+                    "DT"
+                };
+                let data = if frame.remote {
+                    String::new()
+                } else {
+                    format_data(frame)
+                };
+                let id = if frame.error { "-".to_string() } else { id };
+                if version == TrcVersion::V2_0 && frame.error {
+                    format!("{:>7} {:>13.3} ER {:>8} Rx {}", n, offset_ms, id, data)
+                } else if version == TrcVersion::V2_0 {
+                    format!(
+                        "{:>7} {:>13.3} {} {:>8} Rx {}  {}",
+                        n, offset_ms, kind, id, len, data
+                    )
+                } else {
+                    format!(
+                        "{:>7} {:>13.3} {} {:<2} {:>8} Rx - {:<2} {}",
+                        n, offset_ms, kind, bus, id, len, data
+                    )
+                }
+            }
+        };
+        writeln!(w, "{}", line.trim_end())?;
+    }
+
+    w.flush()
+}
+
+/// 讀取 1.0 ~ 2.1 版的 trc，狀態、事件類型的行會被略過
+pub fn read_trc<R: BufRead>(r: R) -> io::Result<Vec<CanFrame>> {
+    let mut version = TrcVersion::V1_0;
+    let mut columns = parse_columns(version.default_columns());
+    let mut explicit_columns = false;
+    let mut base_us: i64 = 0;
+    let mut frames = Vec::new();
+
+    for (idx, line) in r.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // 1. **檔頭關鍵字**
+        if let Some(meta) = line.strip_prefix(";$") {
+            let (key, value) = meta.split_once('=').unwrap_or((meta, ""));
+            match key.trim() {
+                "FILEVERSION" => {
+                    version = TrcVersion::parse(value)
+                        .ok_or_else(|| invalid(line_no, "不支援的 FILEVERSION"))?;
+                    if !explicit_columns {
+                        columns = parse_columns(version.default_columns());
+                    }
+                }
+                "STARTTIME" => {
+                    let days: f64 = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| invalid(line_no, "STARTTIME 格式錯誤"))?;
+                    base_us = ((days - OLE_EPOCH_OFFSET_DAYS) * US_PER_DAY).round() as i64;
+                }
+                "COLUMNS" => {
+                    columns = parse_columns(value);
+                    explicit_columns = true;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(frame) = parse_row(line, &columns, version, base_us, line_no)? {
+            frames.push(frame);
+        }
+    }
+
+    Ok(frames)
+}
+
+fn parse_columns(value: &str) -> Vec<char> {
+    value
+        .split(',')
+        .filter_map(|c| c.trim().chars().next())
+        .collect()
+}
+
+fn parse_hex_u8(token: &str, line_no: usize) -> io::Result<u8> {
+    u8::from_str_radix(token, 16).map_err(|_| invalid(line_no, "資料位元組格式錯誤"))
+}
+
+/// Warng、ST、EC、EV 等狀態行不是 CAN 幀
+fn is_frame_kind(kind: &str) -> bool {
+    matches!(kind, "Rx" | "Tx" | "DT" | "RR" | "Error" | "ER")
+}
+
+fn parse_row(
+    line: &str,
+    columns: &[char],
+    version: TrcVersion,
+    base_us: i64,
+    line_no: usize,
+) -> io::Result<Option<CanFrame>> {
+    let mut tokens = line.split_whitespace().peekable();
+    let mut frame = CanFrame::default();
+    let mut kind = if version.is_v2() { "DT" } else { "Rx" };
+    let mut len: Option<u8> = None;
+    let mut data = Vec::new();
+
+    for &col in columns {
+        match col {
+            'N' => {
+                tokens.next();
+            }
+            'O' => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| invalid(line_no, "缺少時間偏移"))?;
+                let offset_ms: f64 = token
+                    .parse()
+                    .map_err(|_| invalid(line_no, "時間偏移格式錯誤"))?;
+                frame.timestamp_us = (base_us + (offset_ms * 1000.0).round() as i64).max(0) as u64;
+            }
+            'T' => {
+                kind = tokens
+                    .next()
+                    .ok_or_else(|| invalid(line_no, "缺少訊息類型"))?;
+                // 狀態與事件行後面的欄位格式不固定，先判斷類型再解析 ID
+                if !is_frame_kind(kind) {
+                    return Ok(None);
+                }
+            }
+            'B' => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| invalid(line_no, "缺少 Bus 欄位"))?;
+                let bus: u32 = token.parse().unwrap_or(1);
+                frame.channel = bus.saturating_sub(1);
+            }
+            'I' => {
+                // 錯誤幀在 2.x 沒有 ID，可能直接接著 Rx/Tx 方向欄位
+                if matches!(tokens.peek(), Some(&"Rx") | Some(&"Tx")) {
+                    continue;
+                }
+                let token = tokens.next().ok_or_else(|| invalid(line_no, "缺少 ID"))?;
+                if token != "-" {
+                    frame.id = u32::from_str_radix(token, 16)
+                        .map_err(|_| invalid(line_no, "ID 格式錯誤"))?;
+                    frame.extended = token.len() > 4 || frame.id > 0x7FF;
+                }
+            }
+            'd' | 'R' => {
+                tokens.next();
+            }
+            'L' | 'l' => {
+                // 2.0 的錯誤幀沒有長度欄位
+                if kind == "ER" && version == TrcVersion::V2_0 {
+                    continue;
+                }
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| invalid(line_no, "缺少資料長度"))?;
+                len = Some(
+                    token
+                        .parse()
+                        .map_err(|_| invalid(line_no, "資料長度格式錯誤"))?,
+                );
+            }
+            'D' => {
+                for token in tokens.by_ref() {
+                    if token == "RTR" {
+                        frame.remote = true;
+                        break;
+                    }
+                    if token.len() != 2 {
+                        break;
+                    }
+                    data.push(parse_hex_u8(token, line_no)?);
+                }
+            }
+            _ => {
+                tokens.next();
+            }
+        }
+    }
+
+    match kind {
+        "RR" => frame.remote = true,
+        "Error" | "ER" => frame.error = true,
+        _ => {}
+    }
+
+    let len = len.unwrap_or(data.len() as u8).min(8);
+    frame.len = len;
+    if !frame.remote {
+        let n = data.len().min(len as usize);
+        frame.data[..n].copy_from_slice(&data[..n]);
+        if frame.error {
+            frame.len = n as u8;
+        }
+    }
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_status_and_event_records() {
+        let trc = "\
+;$FILEVERSION=2.1
+;$STARTTIME=45000.5
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+      1        0.100 DT 1      0123 Rx - 2    11 22
+      2        0.200 ST 1      BUSHEAVY
+      3        0.300 EV 1      Trigger fired
+      4        0.400 EC 1      Rx 4 0
+      5        0.500 DT 2  18FEF100 Tx - 1    AA
+";
+        let frames = read_trc(trc.as_bytes()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            (frames[0].id, frames[0].payload()),
+            (0x123, &[0x11, 0x22][..])
+        );
+        assert_eq!((frames[1].channel, frames[1].extended), (1, true));
+    }
+
+    fn frame(timestamp_us: u64, channel: u32, id: u32, payload: &[u8]) -> CanFrame {
+        let mut data = [0u8; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        CanFrame {
+            timestamp_us,
+            channel,
+            id,
+            extended: id > 0x7FF,
+            len: payload.len() as u8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let start = 1_700_000_000_123_456;
+        let frames = [
+            frame(start, 0, 0x123, &[0x01, 0x02, 0x03]),
+            frame(start + 1_234_567, 1, 0x18FE_F100, &[0xAA; 8]),
+            CanFrame {
+                remote: true,
+                len: 4,
+                ..frame(start + 2_000_250, 0, 0x7FF, &[])
+            },
+            CanFrame {
+                error: true,
+                ..frame(start + 3_500_001, 1, 0, &[0x04, 0x10])
+            },
+        ];
+        for version in TrcVersion::ALL {
+            let mut text = Vec::new();
+            write_trc(&mut text, &frames, version).unwrap();
+            let text = String::from_utf8(text).unwrap();
+            assert_eq!(
+                text.contains(";$FILEVERSION="),
+                version != TrcVersion::V1_0,
+                "{}",
+                version.name()
+            );
+            let read = read_trc(text.as_bytes()).unwrap();
+            assert_eq!(read.len(), frames.len(), "{}", version.name());
+
+            // 1.0 以 ms、1.1 以 0.1 ms 記錄，其他版本到 µs
+            let tolerance = match version {
+                TrcVersion::V1_0 => 500,
+                TrcVersion::V1_1 => 50,
+                _ => 0,
+            };
+            // 只有 1.2、1.3 與 2.1 有 Bus 欄位
+            let has_bus = matches!(
+                version,
+                TrcVersion::V1_2 | TrcVersion::V1_3 | TrcVersion::V2_1
+            );
+            for (written, read) in frames.iter().zip(&read) {
+                let context = format!("{} {:?}", version.name(), read);
+                assert!(
+                    written.timestamp_us.abs_diff(read.timestamp_us) <= tolerance,
+                    "{}",
+                    context
+                );
+                let channel = if has_bus { written.channel } else { 0 };
+                assert_eq!(read.channel, channel, "{}", context);
+                assert_eq!(read.remote, written.remote, "{}", context);
+                // 1.0 沒有類型欄位，錯誤幀讀回來是一般資料幀
+                let error = written.error && version != TrcVersion::V1_0;
+                assert_eq!(read.error, error, "{}", context);
+                if !written.error {
+                    assert_eq!(
+                        (read.id, read.extended),
+                        (written.id, written.extended),
+                        "{}",
+                        context
+                    );
+                }
+                assert_eq!(read.len, written.len, "{}", context);
+                if !written.remote {
+                    assert_eq!(read.payload(), written.payload(), "{}", context);
+                }
+            }
+        }
+    }
+}
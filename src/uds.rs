@@ -0,0 +1,847 @@
+use crate::canbus::{parse_hex_bytes, CanFrame};
+use crate::flash::FlashPanel;
+use crate::isotp::{IsoTpEvent, IsoTpLink};
+use crate::security::SecurityPanel;
+use eframe::egui;
+use std::collections::VecDeque;
+
+pub const SID_SESSION_CONTROL: u8 = 0x10;
+pub const SID_ECU_RESET: u8 = 0x11;
+pub const SID_CLEAR_DTC: u8 = 0x14;
+pub const SID_READ_DTC: u8 = 0x19;
+pub const SID_READ_DID: u8 = 0x22;
+pub const SID_WRITE_DID: u8 = 0x2E;
+pub const SID_ROUTINE_CONTROL: u8 = 0x31;
+pub const SID_TESTER_PRESENT: u8 = 0x3E;
+const SID_NEGATIVE: u8 = 0x7F;
+const NRC_RESPONSE_PENDING: u8 = 0x78;
+// 正回應的 SID = 請求 SID + 0x40
+const POSITIVE_OFFSET: u8 = 0x40;
+// ReadDTCInformation reportDTCByStatusMask
+const REPORT_DTC_BY_STATUS_MASK: u8 = 0x02;
+const HISTORY_LEN: usize = 200;
+
+pub fn service_name(sid: u8) -> &'static str {
+    match sid {
+        0x10 => "DiagnosticSessionControl",
+        0x11 => "ECUReset",
+        0x14 => "ClearDiagnosticInformation",
+        0x19 => "ReadDTCInformation",
+        0x22 => "ReadDataByIdentifier",
+        0x23 => "ReadMemoryByAddress",
+        0x27 => "SecurityAccess",
+        0x28 => "CommunicationControl",
+        0x2E => "WriteDataByIdentifier",
+        0x2F => "InputOutputControlByIdentifier",
+        0x31 => "RoutineControl",
+        0x34 => "RequestDownload",
+        0x35 => "RequestUpload",
+        0x36 => "TransferData",
+        0x37 => "RequestTransferExit",
+        0x3D => "WriteMemoryByAddress",
+        0x3E => "TesterPresent",
+        0x85 => "ControlDTCSetting",
+        _ => "未知服務",
+    }
+}
+
+pub fn nrc_name(nrc: u8) -> &'static str {
+    match nrc {
+        0x10 => "generalReject",
+        0x11 => "serviceNotSupported",
+        0x12 => "subFunctionNotSupported",
+        0x13 => "incorrectMessageLengthOrInvalidFormat",
+        0x14 => "responseTooLong",
+        0x21 => "busyRepeatRequest",
+        0x22 => "conditionsNotCorrect",
+        0x24 => "requestSequenceError",
+        0x25 => "noResponseFromSubnetComponent",
+        0x26 => "failurePreventsExecutionOfRequestedAction",
+        0x31 => "requestOutOfRange",
+        0x33 => "securityAccessDenied",
+        0x35 => "invalidKey",
+        0x36 => "exceedNumberOfAttempts",
+        0x37 => "requiredTimeDelayNotExpired",
+        0x70 => "uploadDownloadNotAccepted",
+        0x71 => "transferDataSuspended",
+        0x72 => "generalProgrammingFailure",
+        0x73 => "wrongBlockSequenceCounter",
+        0x78 => "requestCorrectlyReceived-ResponsePending",
+        0x7E => "subFunctionNotSupportedInActiveSession",
+        0x7F => "serviceNotSupportedInActiveSession",
+        0x81 => "rpmTooHigh",
+        0x82 => "rpmTooLow",
+        0x83 => "engineIsRunning",
+        0x84 => "engineIsNotRunning",
+        0x85 => "engineRunTimeTooLow",
+        0x86 => "temperatureTooHigh",
+        0x87 => "temperatureTooLow",
+        0x88 => "vehicleSpeedTooHigh",
+        0x89 => "vehicleSpeedTooLow",
+        0x8A => "throttle/PedalTooHigh",
+        0x8B => "throttle/PedalTooLow",
+        0x8C => "transmissionRangeNotInNeutral",
+        0x8D => "transmissionRangeNotInGear",
+        0x8F => "brakeSwitchesNotClosed",
+        0x90 => "shifterLeverNotInPark",
+        0x91 => "torqueConverterClutchLocked",
+        0x92 => "voltageTooHigh",
+        0x93 => "voltageTooLow",
+        _ => "未知 NRC",
+    }
+}
+
+// 有子功能、可以用最高位元抑制正回應的服務
+fn suppresses_response(request: &[u8]) -> bool {
+    matches!(
+        request.first(),
+        Some(0x10 | 0x11 | 0x27 | 0x28 | 0x31 | 0x3E | 0x85 | 0x87)
+    ) && request.get(1).is_some_and(|sub| sub & 0x80 != 0)
+}
+
+pub fn read_did_request(did: u16) -> Vec<u8> {
+    let did = did.to_be_bytes();
+    vec![SID_READ_DID, did[0], did[1]]
+}
+
+pub fn write_did_request(did: u16, data: &[u8]) -> Vec<u8> {
+    let mut request = vec![SID_WRITE_DID];
+    request.extend_from_slice(&did.to_be_bytes());
+    request.extend_from_slice(data);
+    request
+}
+
+pub fn routine_request(sub: u8, routine: u16, data: &[u8]) -> Vec<u8> {
+    let mut request = vec![SID_ROUTINE_CONTROL, sub];
+    request.extend_from_slice(&routine.to_be_bytes());
+    request.extend_from_slice(data);
+    request
+}
+
+/// DTC 顯示成 SAE J2012 形式，例如 P0123-45
+pub fn format_dtc(dtc: u32) -> String {
+    let letter = ['P', 'C', 'B', 'U'][(dtc >> 22 & 0x3) as usize];
+    format!(
+        "{}{:01X}{:03X}-{:02X}",
+        letter,
+        dtc >> 20 & 0x3,
+        dtc >> 8 & 0xFFF,
+        dtc & 0xFF
+    )
+}
+
+/// 解析 59 02 回應裡的 (DTC, 狀態) 清單
+pub fn decode_dtc_records(response: &[u8]) -> Vec<(u32, u8)> {
+    if response.len() < 3 || response[1] != REPORT_DTC_BY_STATUS_MASK {
+        return Vec::new();
+    }
+    response[3..]
+        .chunks_exact(4)
+        .map(|r| ((r[0] as u32) << 16 | (r[1] as u32) << 8 | r[2] as u32, r[3]))
+        .collect()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Positive(Vec<u8>),
+    Negative(u8),
+    // 請求抑制了正回應，送出即完成
+    Suppressed,
+    Timeout,
+    Transport(String),
+}
+
+impl Outcome {
+    pub fn describe(&self, request: &[u8]) -> String {
+        match self {
+            Outcome::Positive(data) => describe_positive(request, data),
+            Outcome::Negative(nrc) => format!("NRC 0x{:02X} {}", nrc, nrc_name(*nrc)),
+            Outcome::Suppressed => "已送出 (不要求回應)".to_string(),
+            Outcome::Timeout => "逾時沒有回應".to_string(),
+            Outcome::Transport(e) => e.clone(),
+        }
+    }
+}
+
+fn describe_positive(request: &[u8], data: &[u8]) -> String {
+    let raw = hex(data);
+    match (request.first(), data) {
+        // P2 以 1 ms、P2* 以 10 ms 為單位
+        (Some(&SID_SESSION_CONTROL), [_, session, p2_hi, p2_lo, star_hi, star_lo, ..]) => {
+            format!(
+                "{}  (session 0x{:02X}, P2 {} ms, P2* {} ms)",
+                raw,
+                session,
+                u16::from_be_bytes([*p2_hi, *p2_lo]),
+                u16::from_be_bytes([*star_hi, *star_lo]) as u32 * 10
+            )
+        }
+        (Some(&SID_READ_DID), [_, hi, lo, value @ ..]) => {
+            let text: String = value
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("DID {:02X}{:02X} = {}  \"{}\"", hi, lo, hex(value), text)
+        }
+        (Some(&SID_READ_DTC), _) if request.get(1) == Some(&REPORT_DTC_BY_STATUS_MASK) => {
+            let dtcs = decode_dtc_records(data);
+            let list: Vec<String> = dtcs
+                .iter()
+                .map(|(dtc, status)| format!("{} ({:02X})", format_dtc(*dtc), status))
+                .collect();
+            format!("{} 筆 DTC: {}", dtcs.len(), list.join(", "))
+        }
+        _ => raw,
+    }
+}
+
+/// 一筆完成的請求與結果
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub timestamp_us: u64,
+    pub request: Vec<u8>,
+    pub outcome: Outcome,
+    pub elapsed_ms: u64,
+    // 收到幾次 NRC 0x78
+    pub pending_count: u32,
+}
+
+struct Pending {
+    request: Vec<u8>,
+    started_us: u64,
+    // 請求送完才開始計 P2
+    deadline_us: Option<u64>,
+    pending_count: u32,
+}
+
+/// UDS 用戶端：一次一個請求，其餘排隊，結果放在 completed
+pub struct UdsClient {
+    pub link: IsoTpLink,
+    pub p2_ms: u64,
+    pub p2_star_ms: u64,
+    pub tester_present: bool,
+    pub tester_present_ms: u64,
+    pub completed: VecDeque<Exchange>,
+    queue: VecDeque<Vec<u8>>,
+    pending: Option<Pending>,
+    last_activity_us: u64,
+}
+
+impl Default for UdsClient {
+    fn default() -> Self {
+        Self {
+            link: IsoTpLink::default(),
+            p2_ms: 1000,
+            p2_star_ms: 5000,
+            tester_present: false,
+            tester_present_ms: 2000,
+            completed: VecDeque::new(),
+            queue: VecDeque::new(),
+            pending: None,
+            last_activity_us: 0,
+        }
+    }
+}
+
+impl UdsClient {
+    pub fn request(&mut self, request: Vec<u8>) {
+        if !request.is_empty() {
+            self.queue.push_back(request);
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.queue.is_empty()
+    }
+
+    pub fn cancel(&mut self) {
+        self.queue.clear();
+        self.pending = None;
+        self.link.reset();
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame, now_us: u64) {
+        // 沒有請求時不接收，免得和其他連線重複送 FC
+        if self.pending.is_none() {
+            return;
+        }
+        self.link.handle(frame, now_us);
+    }
+
+    fn finish(&mut self, outcome: Outcome, now_us: u64) {
+        if let Some(pending) = self.pending.take() {
+            self.completed.push_back(Exchange {
+                timestamp_us: pending.started_us,
+                request: pending.request,
+                outcome,
+                elapsed_ms: now_us.saturating_sub(pending.started_us) / 1000,
+                pending_count: pending.pending_count,
+            });
+        }
+    }
+
+    fn on_response(&mut self, data: Vec<u8>, now_us: u64) {
+        let Some(pending) = self.pending.as_mut() else {
+            return;
+        };
+        let sid = pending.request[0];
+        if data.len() >= 3 && data[0] == SID_NEGATIVE && data[1] == sid {
+            if data[2] == NRC_RESPONSE_PENDING {
+                pending.pending_count += 1;
+                pending.deadline_us = Some(now_us + self.p2_star_ms * 1000);
+            } else {
+                self.finish(Outcome::Negative(data[2]), now_us);
+            }
+        } else if data.first() == Some(&(sid.wrapping_add(POSITIVE_OFFSET))) {
+            self.finish(Outcome::Positive(data), now_us);
+        }
+        // 其他回應 (例如前一個請求逾時後才到) 直接丟掉
+    }
+
+    /// 每個畫面呼叫一次：推進 ISO-TP、處理回應與逾時、送出下一個請求
+    pub fn poll(&mut self, now_us: u64) {
+        self.link.poll(now_us);
+
+        // 1. **處理傳輸層事件**
+        while let Some(event) = self.link.events.pop_front() {
+            match event {
+                IsoTpEvent::Sent => {
+                    if let Some(pending) = self.pending.as_mut() {
+                        if suppresses_response(&pending.request) {
+                            self.finish(Outcome::Suppressed, now_us);
+                        } else if pending.deadline_us.is_none() {
+                            pending.deadline_us = Some(now_us + self.p2_ms * 1000);
+                        }
+                    }
+                }
+                // 回應已開始傳，剩下交給 ISO-TP 的 N_Cr
+                IsoTpEvent::FirstFrame(_) => {
+                    if let Some(pending) = self.pending.as_mut() {
+                        pending.deadline_us = Some(now_us + self.p2_star_ms * 1000);
+                    }
+                }
+                IsoTpEvent::Received(data) => self.on_response(data, now_us),
+                IsoTpEvent::Error(e) => self.finish(Outcome::Transport(e), now_us),
+            }
+        }
+
+        // 2. **P2 / P2* 逾時**
+        if self
+            .pending
+            .as_ref()
+            .and_then(|p| p.deadline_us)
+            .is_some_and(|deadline| now_us > deadline)
+        {
+            self.finish(Outcome::Timeout, now_us);
+        }
+
+        if self.pending.is_some() || self.link.is_busy() {
+            return;
+        }
+
+        // 3. **下一個請求**
+        if let Some(request) = self.queue.pop_front() {
+            self.last_activity_us = now_us;
+            let result = self.link.send(&request, now_us);
+            self.pending = Some(Pending {
+                request,
+                started_us: now_us,
+                deadline_us: None,
+                pending_count: 0,
+            });
+            if let Err(e) = result {
+                self.finish(Outcome::Transport(e), now_us);
+            }
+            return;
+        }
+
+        // 4. **Tester Present 保持連線** (3E 80 不要求回應)
+        if self.tester_present
+            && now_us.saturating_sub(self.last_activity_us) >= self.tester_present_ms * 1000
+        {
+            self.last_activity_us = now_us;
+            let _ = self.link.send(&[SID_TESTER_PRESENT, 0x80], now_us);
+        }
+    }
+}
+
+/// UDS 診斷面板
+pub struct UdsPanel {
+    pub client: UdsClient,
+    security: SecurityPanel,
+    flash: FlashPanel,
+    history: VecDeque<Exchange>,
+    session: u8,
+    reset_type: u8,
+    did_text: String,
+    write_text: String,
+    dtc_mask: u8,
+    dtcs: Vec<(u32, u8)>,
+    routine_sub: u8,
+    routine_text: String,
+    routine_data: String,
+    raw_text: String,
+}
+
+impl Default for UdsPanel {
+    fn default() -> Self {
+        Self {
+            client: UdsClient::default(),
+            security: SecurityPanel::default(),
+            flash: FlashPanel::default(),
+            history: VecDeque::new(),
+            session: 0x03,
+            reset_type: 0x01,
+            did_text: "F190".to_string(),
+            write_text: String::new(),
+            dtc_mask: 0xFF,
+            dtcs: Vec::new(),
+            routine_sub: 0x01,
+            routine_text: "FF00".to_string(),
+            routine_data: String::new(),
+            raw_text: "22 F1 90".to_string(),
+        }
+    }
+}
+
+fn parse_u16_hex(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    u16::from_str_radix(text.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("無效的十六進位數值: {}", text))
+}
+
+impl UdsPanel {
+    /// 取出完成的請求放進歷史
+    pub fn process(&mut self, now_us: u64, log: &mut Vec<String>) {
+        while let Some(exchange) = self.client.completed.pop_front() {
+            // 刷寫中由刷寫流程處理解鎖，不做自動解鎖重送
+            if self.flash.is_running() {
+                self.security.on_session_change(&exchange);
+                let algorithm = self.security.algorithm();
+                self.flash
+                    .on_exchange(&exchange, algorithm, &mut self.client, now_us, log);
+            } else {
+                self.security.on_exchange(&exchange, &mut self.client, log);
+            }
+            if let Outcome::Positive(data) = &exchange.outcome {
+                if exchange.request.first() == Some(&SID_READ_DTC) {
+                    self.dtcs = decode_dtc_records(data);
+                }
+            }
+            self.history.push_back(exchange);
+            if self.history.len() > HISTORY_LEN {
+                self.history.pop_front();
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool, now_us: u64, log: &mut Vec<String>) {
+        egui::CollapsingHeader::new("連線設定").show(ui, |ui| {
+            self.client.link.config.ui(ui, "uds_isotp");
+            ui.horizontal(|ui| {
+                ui.label("P2 / P2* ms:");
+                ui.add(egui::DragValue::new(&mut self.client.p2_ms).range(10..=10_000));
+                ui.add(egui::DragValue::new(&mut self.client.p2_star_ms).range(100..=60_000));
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.client.tester_present, "Tester Present");
+            ui.add(
+                egui::DragValue::new(&mut self.client.tester_present_ms)
+                    .range(500..=5000)
+                    .suffix(" ms"),
+            );
+            if !self.client.is_idle() {
+                ui.spinner();
+                if ui.button("取消").clicked() {
+                    self.client.cancel();
+                }
+            }
+        });
+        // 刷寫中不能手動送請求，避免插進刷寫流程或中途切換工作階段
+        let manual = can_send && !self.flash.is_running();
+        egui::CollapsingHeader::new("SecurityAccess").show(ui, |ui| {
+            self.security.ui(ui, &mut self.client, manual, log);
+        });
+        egui::CollapsingHeader::new("刷寫").show(ui, |ui| {
+            self.flash.ui(ui, &mut self.client, can_send, now_us, log);
+        });
+        ui.separator();
+
+        let mut request = None;
+        ui.add_enabled_ui(manual, |ui| {
+            egui::Grid::new("uds_services")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    // 1. **工作階段與重置**
+                    ui.label("Session:");
+                    ui.horizontal(|ui| {
+                        for (value, name) in
+                            [(0x01, "Default"), (0x02, "Programming"), (0x03, "Extended")]
+                        {
+                            ui.selectable_value(&mut self.session, value, name);
+                        }
+                        if ui.button("切換").clicked() {
+                            request = Some(vec![SID_SESSION_CONTROL, self.session]);
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("ECU Reset:");
+                    ui.horizontal(|ui| {
+                        for (value, name) in [(0x01, "Hard"), (0x02, "KeyOffOn"), (0x03, "Soft")] {
+                            ui.selectable_value(&mut self.reset_type, value, name);
+                        }
+                        if ui.button("重置").clicked() {
+                            request = Some(vec![SID_ECU_RESET, self.reset_type]);
+                        }
+                    });
+                    ui.end_row();
+
+                    // 2. **DID 讀寫**
+                    ui.label("DID:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.did_text).desired_width(50.0));
+                        if ui.button("讀取").clicked() {
+                            match parse_u16_hex(&self.did_text) {
+                                Ok(did) => request = Some(read_did_request(did)),
+                                Err(e) => log.push(e),
+                            }
+                        }
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.write_text)
+                                .hint_text("寫入資料 hex"),
+                        );
+                        if ui.button("寫入").clicked() {
+                            match parse_u16_hex(&self.did_text).and_then(|did| {
+                                Ok(write_did_request(did, &parse_hex_bytes(&self.write_text)?))
+                            }) {
+                                Ok(r) => request = Some(r),
+                                Err(e) => log.push(e),
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    // 3. **DTC**
+                    ui.label("DTC:");
+                    ui.horizontal(|ui| {
+                        ui.label("狀態遮罩");
+                        ui.add(
+                            egui::DragValue::new(&mut self.dtc_mask).hexadecimal(2, false, true),
+                        );
+                        if ui.button("讀取 DTC").clicked() {
+                            request =
+                                Some(vec![SID_READ_DTC, REPORT_DTC_BY_STATUS_MASK, self.dtc_mask]);
+                        }
+                        if ui.button("清除全部 DTC").clicked() {
+                            request = Some(vec![SID_CLEAR_DTC, 0xFF, 0xFF, 0xFF]);
+                        }
+                    });
+                    ui.end_row();
+
+                    // 4. **RoutineControl**
+                    ui.label("Routine:");
+                    ui.horizontal(|ui| {
+                        for (value, name) in [(0x01, "Start"), (0x02, "Stop"), (0x03, "Results")] {
+                            ui.selectable_value(&mut self.routine_sub, value, name);
+                        }
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.routine_text).desired_width(50.0),
+                        );
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.routine_data)
+                                .hint_text("參數 hex"),
+                        );
+                        if ui.button("執行").clicked() {
+                            match parse_u16_hex(&self.routine_text).and_then(|id| {
+                                Ok(routine_request(
+                                    self.routine_sub,
+                                    id,
+                                    &parse_hex_bytes(&self.routine_data)?,
+                                ))
+                            }) {
+                                Ok(r) => request = Some(r),
+                                Err(e) => log.push(e),
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    // 5. **任意請求**
+                    ui.label("原始請求:");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.raw_text);
+                        if ui.button("送出").clicked() {
+                            match parse_hex_bytes(&self.raw_text) {
+                                Ok(r) if !r.is_empty() => request = Some(r),
+                                Ok(_) => log.push("UDS: 請求是空的".to_string()),
+                                Err(e) => log.push(e),
+                            }
+                        }
+                    });
+                    ui.end_row();
+                });
+        });
+
+        if !self.dtcs.is_empty() {
+            ui.separator();
+            ui.label(format!("DTC ({} 筆):", self.dtcs.len()));
+            for (dtc, status) in &self.dtcs {
+                ui.monospace(format!(
+                    "{}  0x{:06X}  狀態 {:08b}",
+                    format_dtc(*dtc),
+                    dtc,
+                    status
+                ));
+            }
+        }
+
+        // 6. **請求歷史**
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("歷史:");
+            if ui.button("清除").clicked() {
+                self.history.clear();
+            }
+        });
+        egui::ScrollArea::vertical()
+            .id_salt("uds_history")
+            .max_height(300.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                let start = self.history.front().map_or(0, |e| e.timestamp_us);
+                egui::Grid::new("uds_history_grid")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        for exchange in &self.history {
+                            ui.monospace(format!(
+                                "{:.3}",
+                                (exchange.timestamp_us - start) as f64 / 1e6
+                            ));
+                            ui.monospace(hex(&exchange.request))
+                                .on_hover_text(service_name(exchange.request[0]));
+                            let text = exchange.outcome.describe(&exchange.request);
+                            match exchange.outcome {
+                                Outcome::Positive(_) | Outcome::Suppressed => ui.monospace(text),
+                                _ => ui.colored_label(ui.visuals().error_fg_color, text),
+                            };
+                            ui.label(if exchange.pending_count > 0 {
+                                format!(
+                                    "{} ms (0x78 ×{})",
+                                    exchange.elapsed_ms, exchange.pending_count
+                                )
+                            } else {
+                                format!("{} ms", exchange.elapsed_ms)
+                            });
+                            if ui
+                                .add_enabled(manual, egui::Button::new("重送").small())
+                                .clicked()
+                            {
+                                request = Some(exchange.request.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(request) = request {
+            self.client.request(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::{FirmwareImage, Segment};
+    use crate::flash::{FlashConfig, FlashJob};
+
+    fn first_frame() -> CanFrame {
+        CanFrame {
+            id: 0x7E8,
+            len: 8,
+            data: [0x10, 0x14, 0x62, 0xF1, 0x90, 0x57, 0x30, 0x4C],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn idle_client_ignores_responses() {
+        // 別的連線的多幀回應不該讓閒置的用戶端回 FC
+        let mut client = UdsClient::default();
+        client.handle(&first_frame(), 1_000);
+        client.poll(1_000);
+        assert!(client.link.outbox.is_empty());
+
+        client.request(read_did_request(0xF190));
+        client.poll(2_000);
+        assert_eq!(client.link.outbox.len(), 1);
+        client.link.outbox.clear();
+        client.handle(&first_frame(), 3_000);
+        client.poll(3_000);
+        assert_eq!(client.link.outbox.len(), 1);
+        assert_eq!(client.link.outbox[0].data[0] >> 4, 0x3);
+    }
+
+    // ECU 的單幀回應
+    fn single_frame(payload: &[u8]) -> CanFrame {
+        let mut data = [0xCC; 8];
+        data[0] = payload.len() as u8;
+        data[1..1 + payload.len()].copy_from_slice(payload);
+        CanFrame {
+            id: 0x7E8,
+            len: 8,
+            data,
+            ..Default::default()
+        }
+    }
+
+    // 送出請求並收下 Sent 事件，回傳 P2 開始計時的時間
+    fn send(client: &mut UdsClient, request: Vec<u8>) -> u64 {
+        client.request(request);
+        client.poll(0);
+        assert_eq!(client.link.outbox.drain(..).count(), 1);
+        client.poll(1_000);
+        1_000
+    }
+
+    #[test]
+    fn negative_response_decoding() {
+        let mut client = UdsClient::default();
+        let now = send(&mut client, read_did_request(0xF190));
+        // 別的服務的 NRC 不算
+        client.handle(&single_frame(&[0x7F, 0x2E, 0x31]), now);
+        client.poll(now);
+        assert!(client.completed.is_empty());
+        client.handle(&single_frame(&[0x7F, 0x22, 0x31]), now);
+        client.poll(now);
+        let exchange = client.completed.pop_front().unwrap();
+        assert_eq!(exchange.outcome, Outcome::Negative(0x31));
+        assert_eq!(
+            exchange.outcome.describe(&exchange.request),
+            "NRC 0x31 requestOutOfRange"
+        );
+        assert_eq!(nrc_name(0x33), "securityAccessDenied");
+        assert_eq!(nrc_name(0x7F), "serviceNotSupportedInActiveSession");
+        assert_eq!(nrc_name(0x42), "未知 NRC");
+    }
+
+    #[test]
+    fn response_pending_extends_to_p2_star() {
+        let mut client = UdsClient {
+            p2_ms: 100,
+            p2_star_ms: 500,
+            ..Default::default()
+        };
+        let now = send(&mut client, vec![SID_ROUTINE_CONTROL, 0x01, 0xFF, 0x00]);
+        client.handle(&single_frame(&[0x7F, 0x31, 0x78]), now + 50_000);
+        client.poll(now + 50_000);
+        // 超過 P2 但還在 P2* 內
+        client.poll(now + 300_000);
+        assert!(client.completed.is_empty());
+        // 第二次 0x78 從收到時重新計 P2*
+        client.handle(&single_frame(&[0x7F, 0x31, 0x78]), now + 400_000);
+        client.poll(now + 400_000);
+        client.poll(now + 900_000);
+        assert!(client.completed.is_empty());
+        client.poll(now + 900_001);
+        let exchange = client.completed.pop_front().unwrap();
+        assert_eq!(exchange.outcome, Outcome::Timeout);
+        assert_eq!(exchange.pending_count, 2);
+        assert!(client.is_idle());
+    }
+
+    #[test]
+    fn suppressed_positive_response() {
+        let mut client = UdsClient::default();
+        client.request(vec![SID_SESSION_CONTROL, 0x83]);
+        client.poll(0);
+        assert_eq!(client.link.outbox[0].data[..3], [0x02, 0x10, 0x83]);
+        client.link.outbox.clear();
+        client.poll(1_000);
+        let exchange = client.completed.pop_front().unwrap();
+        assert_eq!(exchange.outcome, Outcome::Suppressed);
+        assert!(client.is_idle());
+
+        // 沒有子功能的服務不管最高位元，一樣等回應
+        let now = send(&mut client, vec![SID_READ_DID, 0x80, 0x00]);
+        assert!(client.completed.is_empty());
+        client.handle(&single_frame(&[0x62, 0x80, 0x00, 0x01]), now);
+        client.poll(now);
+        assert_eq!(
+            client.completed.pop_front().unwrap().outcome,
+            Outcome::Positive(vec![0x62, 0x80, 0x00, 0x01])
+        );
+    }
+
+    #[test]
+    fn dtc_formatting() {
+        assert_eq!(format_dtc(0x012345), "P0123-45");
+        assert_eq!(format_dtc(0x4A1B02), "C0A1B-02");
+        assert_eq!(format_dtc(0x923456), "B1234-56");
+        assert_eq!(format_dtc(0xC10001), "U0100-01");
+    }
+
+    #[test]
+    fn dtc_records() {
+        // 59 02 <可用狀態遮罩> 後面每筆 DTC 3 bytes + 狀態；不完整的尾巴丟掉
+        let response = [
+            0x59, 0x02, 0xFF, 0x01, 0x23, 0x45, 0x08, 0xC1, 0x00, 0x01, 0x2F, 0xAA,
+        ];
+        assert_eq!(
+            decode_dtc_records(&response),
+            [(0x012345, 0x08), (0xC10001, 0x2F)]
+        );
+        assert!(decode_dtc_records(&[0x59, 0x02, 0xFF]).is_empty());
+        assert!(decode_dtc_records(&[0x59, 0x02]).is_empty());
+        assert!(decode_dtc_records(&[0x59, 0x0A, 0xFF, 0x01, 0x23, 0x45, 0x08]).is_empty());
+        assert_eq!(
+            describe_positive(&[SID_READ_DTC, 0x02, 0xFF], &response),
+            "2 筆 DTC: P0123-45 (08), U0100-01 (2F)"
+        );
+    }
+
+    #[test]
+    fn flash_session_change_clears_unlock() {
+        let mut panel = UdsPanel::default();
+        panel.security.unlocked = Some(0x01);
+        let image = FirmwareImage {
+            segments: vec![Segment {
+                address: 0x0800_0000,
+                data: vec![0; 16],
+            }],
+        };
+        panel.flash.job = Some(FlashJob::new(
+            image,
+            FlashConfig::default(),
+            &mut panel.client,
+            0,
+        ));
+        // 刷寫的 10 02 完成：請求交給刷寫流程，解鎖狀態也要清掉
+        panel.client.completed.push_back(Exchange {
+            timestamp_us: 0,
+            request: vec![SID_SESSION_CONTROL, 0x02],
+            outcome: Outcome::Positive(vec![0x50, 0x02, 0x00, 0x32, 0x01, 0xF4]),
+            elapsed_ms: 0,
+            pending_count: 0,
+        });
+        let mut log = Vec::new();
+        panel.process(1_000, &mut log);
+        assert!(panel.flash.is_running());
+        assert_eq!(panel.security.unlocked, None);
+    }
+}
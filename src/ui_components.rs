@@ -1,13 +1,39 @@
-use crate::canbus::CanApp;
+use crate::bus_stats::BusStats;
+use crate::canbus::{now_us, CanApp, CanFrame};
+use crate::canopen::CanOpenPanel;
+use crate::ccp::CcpPanel;
+use crate::dbc::Dbc;
+use crate::ecu_scan::EcuScanner;
+use crate::filter::DisplayFilter;
+use crate::frame_store::{FrameStore, DEFAULT_BUDGET_MB};
+use crate::isotp::IsoTpPanel;
+use crate::j1939::J1939Panel;
+use crate::j1939_node::J1939Node;
+use crate::mdf4::{self, Mdf4Writer};
+use crate::nmea2000::Nmea2000Panel;
+use crate::obd::ObdPanel;
+use crate::pcapng;
+use crate::plot::PlotPanel;
+use crate::replay::ReplayPanel;
+use crate::trace_overview::TraceOverview;
+use crate::trace_table::TraceTable;
+use crate::trc::{self, TrcVersion};
+use crate::uds::UdsPanel;
+use crate::virtual_bus::VirtualBus;
+use crate::xcp::XcpPanel;
 use eframe::egui::{self, ScrollArea};
 use egui::{Align, Color32, TextStyle};
 use flume::{Receiver, Sender};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 
 #[derive(Clone)]
 pub struct BaudRateOption {
     pub name: &'static str,
     pub timing0: u8,
     pub timing1: u8,
+    pub bitrate: u32,
 }
 
 pub struct MyApp {
@@ -16,15 +42,54 @@ pub struct MyApp {
     pub dev_index: u32,
     pub can_channel: u32,
     pub log: Vec<String>,
-    pub received_data: Vec<String>,
+    pub capture: FrameStore,
+    pub capture_budget_mb: usize,
+    pub trace_path: String,
+    pub trc_version: TrcVersion,
+    pub mdf_logger: Option<Mdf4Writer<BufWriter<File>>>,
+    pub dbc_path: String,
+    pub dbc: Option<Dbc>,
     pub log_tx: Sender<String>,
     pub log_rx: Receiver<String>,
-    pub data_tx: Sender<String>,
-    pub data_rx: Receiver<String>,
+    pub data_tx: Sender<CanFrame>,
+    pub data_rx: Receiver<CanFrame>,
+    pub tx_sender: Option<Sender<CanFrame>>,
     pub baud_options: Vec<BaudRateOption>,
     pub selected_baud: usize,
     pub device_open: bool,
     pub receiving: bool,
+    pub show_replay: bool,
+    pub replay: ReplayPanel,
+    pub show_plot: bool,
+    pub plot: PlotPanel,
+    pub trace_fixed: bool,
+    pub overview: TraceOverview,
+    pub trace_table: TraceTable,
+    pub display_filter: DisplayFilter,
+    // 凍結畫面：(凍結時間, 凍結當下的絕對幀數)；接收與記錄照常進行
+    pub frozen: Option<(u64, u64)>,
+    pub show_stats: bool,
+    pub stats: BusStats,
+    pub show_j1939: bool,
+    pub j1939: J1939Panel,
+    pub j1939_node: J1939Node,
+    pub show_nmea2000: bool,
+    pub nmea2000: Nmea2000Panel,
+    pub show_isotp: bool,
+    pub isotp: IsoTpPanel,
+    pub show_uds: bool,
+    pub uds: UdsPanel,
+    pub show_obd: bool,
+    pub obd: ObdPanel,
+    pub show_ecu_scan: bool,
+    pub ecu_scan: EcuScanner,
+    pub show_canopen: bool,
+    pub canopen: CanOpenPanel,
+    pub show_xcp: bool,
+    pub xcp: XcpPanel,
+    pub show_ccp: bool,
+    pub ccp: CcpPanel,
+    pub virtual_bus: Option<VirtualBus>,
 }
 
 impl Default for MyApp {
@@ -34,56 +99,67 @@ impl Default for MyApp {
                 name: "10 Kbps",
                 timing0: 0x31,
                 timing1: 0x1C,
+                bitrate: 10_000,
             },
             BaudRateOption {
                 name: "20 Kbps",
                 timing0: 0x18,
                 timing1: 0x1C,
+                bitrate: 20_000,
             },
             BaudRateOption {
                 name: "40 Kbps",
                 timing0: 0x87,
                 timing1: 0xFF,
+                bitrate: 40_000,
             },
             BaudRateOption {
                 name: "50 Kbps",
                 timing0: 0x09,
                 timing1: 0x1C,
+                bitrate: 50_000,
             },
             BaudRateOption {
                 name: "80 Kbps",
                 timing0: 0x83,
                 timing1: 0xFF,
+                bitrate: 80_000,
             },
             BaudRateOption {
                 name: "100 Kbps",
                 timing0: 0x04,
                 timing1: 0x1C,
+                bitrate: 100_000,
             },
             BaudRateOption {
                 name: "125 Kbps",
                 timing0: 0x03,
                 timing1: 0x1C,
+                bitrate: 125_000,
             },
             BaudRateOption {
                 name: "200 Kbps",
                 timing0: 0x81,
                 timing1: 0xFA,
+                bitrate: 200_000,
             },
             BaudRateOption {
                 name: "250 Kbps",
                 timing0: 0x01,
                 timing1: 0x1C,
+                bitrate: 250_000,
             }, // 預設
             BaudRateOption {
                 name: "500 Kbps",
                 timing0: 0x00,
                 timing1: 0x1C,
+                bitrate: 500_000,
             },
             BaudRateOption {
                 name: "1000 Kbps",
                 timing0: 0x00,
                 timing1: 0x14,
+                bitrate: 1_000_000,
             },
         ];
 
@@ -96,15 +172,53 @@ impl Default for MyApp {
             dev_index: 0,
             can_channel: 0,
             log: Vec::new(),
-            received_data: Vec::new(),
+            capture: FrameStore::default(),
+            capture_budget_mb: DEFAULT_BUDGET_MB,
+            trace_path: "trace.pcapng".to_string(),
+            trc_version: TrcVersion::V2_1,
+            mdf_logger: None,
+            dbc_path: String::new(),
+            dbc: None,
             log_tx,
             log_rx,
             data_tx,
             data_rx,
+            tx_sender: None,
             baud_options,
             selected_baud: 8,
             device_open: false,
             receiving: false,
+            show_replay: false,
+            replay: ReplayPanel::default(),
+            show_plot: false,
+            plot: PlotPanel::default(),
+            trace_fixed: false,
+            overview: TraceOverview::default(),
+            trace_table: TraceTable::default(),
+            display_filter: DisplayFilter::default(),
+            frozen: None,
+            show_stats: false,
+            stats: BusStats::default(),
+            show_j1939: false,
+            j1939: J1939Panel::default(),
+            j1939_node: J1939Node::default(),
+            show_nmea2000: false,
+            nmea2000: Nmea2000Panel::default(),
+            show_isotp: false,
+            isotp: IsoTpPanel::default(),
+            show_uds: false,
+            uds: UdsPanel::default(),
+            show_obd: false,
+            obd: ObdPanel::default(),
+            show_ecu_scan: false,
+            ecu_scan: EcuScanner::default(),
+            show_canopen: false,
+            canopen: CanOpenPanel::default(),
+            show_xcp: false,
+            xcp: XcpPanel::default(),
+            show_ccp: false,
+            ccp: CcpPanel::default(),
+            virtual_bus: None,
         }
     }
 }
@@ -123,14 +237,94 @@ impl MyApp {
             }
         }
 
-        while let Ok(msg) = self.data_rx.try_recv() {
-            self.received_data.push(msg);
-            if self.received_data.len() > 100 {
-                self.received_data
-                    .drain(0..(self.received_data.len() - 100));
+        if let Some(bus) = self.virtual_bus.as_mut() {
+            bus.poll(now_us(), self.can_channel, &self.data_tx);
+        }
+
+        while let Ok(frame) = self.data_rx.try_recv() {
+            if let Some(logger) = self.mdf_logger.as_mut() {
+                if let Err(e) = logger.write_frame(&frame) {
+                    self.log.push(format!("MF4 記錄失敗: {}", e));
+                    self.mdf_logger = None;
+                }
             }
+            self.stats.update(&frame);
+            self.j1939_node.handle(&frame, now_us());
+            self.isotp.handle(&frame, now_us());
+            self.uds.client.handle(&frame, now_us());
+            self.obd.client.handle(&frame, now_us());
+            self.ecu_scan.handle(&frame, now_us());
+            self.canopen.handle(&frame, now_us());
+            self.xcp.master.handle(&frame);
+            self.ccp.master.handle(&frame);
+            self.capture.push(frame);
         }
 
+        // 協定層只在這裡推進，要送的幀統一交給傳送路徑
+        let channel = self.can_channel;
+        self.j1939_node.channel = channel;
+        self.j1939_node.poll(now_us());
+        self.log.append(&mut self.j1939_node.events);
+        self.isotp.link.channel = channel;
+        self.isotp.link.poll(now_us());
+        self.isotp.process_events(now_us(), &mut self.log);
+        self.uds.client.link.channel = channel;
+        self.uds.client.poll(now_us());
+        self.uds.process(now_us(), &mut self.log);
+        self.obd.client.set_channel(channel);
+        self.obd.client.poll(now_us());
+        self.obd.process(now_us(), &mut self.log);
+        self.ecu_scan.channel = channel;
+        self.ecu_scan.poll(now_us());
+        self.log.append(&mut self.ecu_scan.events);
+        self.canopen.master.channel = channel;
+        self.canopen.master.poll(now_us());
+        self.canopen.process(&mut self.log);
+        self.xcp.master.channel = channel;
+        self.xcp.master.poll(now_us());
+        self.xcp.process(now_us(), &mut self.log);
+        self.ccp.master.channel = channel;
+        self.ccp.master.poll(now_us());
+        self.ccp.process(now_us(), &mut self.log);
+        let outgoing = self
+            .j1939_node
+            .outbox
+            .drain(..)
+            .map(|frame| CanFrame { channel, ..frame })
+            .chain(self.isotp.link.outbox.drain(..))
+            .chain(self.uds.client.link.outbox.drain(..))
+            .chain(self.obd.client.drain_outbox())
+            .chain(self.ecu_scan.drain_outbox())
+            .chain(self.canopen.master.drain_outbox())
+            .chain(self.xcp.master.outbox.drain(..))
+            .chain(self.ccp.master.outbox.drain(..));
+        match &self.tx_sender {
+            Some(tx) => outgoing.for_each(|frame| {
+                let _ = tx.send(frame);
+            }),
+            None => drop(outgoing),
+        }
+        self.stats.bitrate = self.baud_options[self.selected_baud].bitrate;
+        if self.receiving {
+            self.stats.tick(now_us());
+        }
+
+        // 曲線與固定模式只吃符合顯示篩選的幀；凍結時停在凍結當下
+        let until = self.frozen.map_or(u64::MAX, |(_, end)| end);
+        let (plot, overview, j1939, nmea2000) = (
+            &mut self.plot,
+            &mut self.overview,
+            &mut self.j1939,
+            &mut self.nmea2000,
+        );
+        self.display_filter
+            .scan(&self.capture, self.dbc.as_ref(), until, |frame| {
+                plot.feed(frame);
+                overview.update(frame);
+                j1939.feed(frame);
+                nmea2000.feed(frame);
+            });
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let mut fonts = egui::FontDefinitions::default();
             fonts
@@ -191,6 +385,13 @@ impl MyApp {
                         self.can_channel,
                         self.log_tx.clone(),
                     );
+                    if self.device_open {
+                        self.tx_sender = Some(self.can_app.start_transmitter(
+                            self.dev_type,
+                            self.dev_index,
+                            self.log_tx.clone(),
+                        ));
+                    }
                 }
 
                 if ui.button("接收").clicked() {
@@ -217,6 +418,27 @@ impl MyApp {
                     self.can_app
                         .close_device(self.dev_type, self.dev_index, self.log_tx.clone());
                     self.device_open = false;
+                    self.tx_sender = None;
+                }
+                if ui
+                    .add_enabled(
+                        !self.device_open,
+                        egui::SelectableLabel::new(self.virtual_bus.is_some(), "虛擬匯流排"),
+                    )
+                    .on_hover_text("不接硬體，送出的幀直接回到接收路徑，並掛一個模擬 UDS ECU")
+                    .clicked()
+                {
+                    if self.virtual_bus.take().is_some() {
+                        self.tx_sender = None;
+                        self.receiving = false;
+                        self.log.push("虛擬匯流排已關閉".to_string());
+                    } else {
+                        let (bus, tx) = VirtualBus::start();
+                        self.virtual_bus = Some(bus);
+                        self.tx_sender = Some(tx);
+                        self.receiving = true;
+                        self.log.push("虛擬匯流排已啟動".to_string());
+                    }
                 }
                 if ui.button("板卡資訊").clicked() {
                     self.can_app.read_board_info(
@@ -225,6 +447,18 @@ impl MyApp {
                         self.log_tx.clone(),
                     );
                 }
+                ui.toggle_value(&mut self.show_replay, "回放");
+                ui.toggle_value(&mut self.show_plot, "曲線");
+                ui.toggle_value(&mut self.show_stats, "統計");
+                ui.toggle_value(&mut self.show_j1939, "J1939");
+                ui.toggle_value(&mut self.show_nmea2000, "NMEA 2000");
+                ui.toggle_value(&mut self.show_isotp, "ISO-TP");
+                ui.toggle_value(&mut self.show_uds, "UDS");
+                ui.toggle_value(&mut self.show_obd, "OBD-II");
+                ui.toggle_value(&mut self.show_ecu_scan, "ECU 掃描");
+                ui.toggle_value(&mut self.show_canopen, "CANopen");
+                ui.toggle_value(&mut self.show_xcp, "XCP");
+                ui.toggle_value(&mut self.show_ccp, "CCP");
             });
 
             ui.add_space(10.0);
@@ -256,6 +490,84 @@ impl MyApp {
                     );
                 }
             });
+
+            ui.horizontal(|ui| {
+                ui.label("檔案:");
+                ui.text_edit_singleline(&mut self.trace_path);
+                if self.trace_extension() == "trc" {
+                    egui::ComboBox::from_id_salt("trc_version")
+                        .selected_text(format!("TRC {}", self.trc_version.name()))
+                        .show_ui(ui, |ui| {
+                            for version in TrcVersion::ALL {
+                                ui.selectable_value(
+                                    &mut self.trc_version,
+                                    version,
+                                    format!("TRC {}", version.name()),
+                                );
+                            }
+                        });
+                }
+                if ui.button("匯出").clicked() {
+                    self.export_trace();
+                }
+                if ui.button("匯入").clicked() {
+                    self.import_trace();
+                }
+                if ui.button("清除").clicked() {
+                    self.capture.clear();
+                    self.stats.clear();
+                    self.refilter();
+                }
+                ui.label(format!("已擷取 {} 幀", self.capture.len()));
+                ui.label("上限 (MB):");
+                if ui
+                    .add(egui::DragValue::new(&mut self.capture_budget_mb).range(16..=65536))
+                    .changed()
+                {
+                    self.capture.set_budget_mb(self.capture_budget_mb);
+                }
+                ui.label(format!(
+                    "使用 {:.0} MB, 最多 {} 幀",
+                    self.capture.memory_bytes() as f64 / (1024.0 * 1024.0),
+                    self.capture.budget_frames()
+                ));
+                ui.separator();
+                if self.mdf_logger.is_none() {
+                    if ui.button("開始記錄 MF4").clicked() {
+                        self.start_mdf_logging();
+                    }
+                } else {
+                    if ui.button("停止記錄 MF4").clicked() {
+                        self.stop_mdf_logging();
+                    }
+                    if let Some(logger) = &self.mdf_logger {
+                        ui.label(format!("記錄中: {} 幀", logger.frame_count()));
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("DBC:");
+                ui.text_edit_singleline(&mut self.dbc_path);
+                if ui.button("載入 DBC").clicked() {
+                    match Dbc::load(&self.dbc_path) {
+                        Ok(dbc) => {
+                            self.log
+                                .push(format!("DBC 已載入: {} 個訊息", dbc.messages.len()));
+                            self.dbc = Some(dbc);
+                            self.refilter();
+                        }
+                        Err(e) => self.log.push(e),
+                    }
+                }
+                if let Some(dbc) = &self.dbc {
+                    ui.label(format!("{} 個訊息", dbc.messages.len()));
+                    if ui.button("卸載").clicked() {
+                        self.dbc = None;
+                        self.refilter();
+                    }
+                }
+            });
             ui.separator();
 
             // let rec_text = MyApp::get_last_lines(&self.received_data, 8);
@@ -293,29 +605,275 @@ impl MyApp {
                         }
                     });
                 ui.separator();
-                ui.label("接收的資料:");
+                ui.horizontal(|ui| {
+                    ui.label("接收的資料:");
+                    ui.selectable_value(&mut self.trace_fixed, false, "滾動");
+                    ui.selectable_value(&mut self.trace_fixed, true, "固定");
+                    ui.separator();
+                    let pending = self.display_filter.pending(&self.capture);
+                    if self.frozen.is_some() {
+                        if ui.button("解除凍結").clicked() {
+                            self.frozen = None;
+                        }
+                        ui.colored_label(
+                            Color32::from_rgb(255, 140, 0),
+                            format!("畫面已凍結，背景已累積 {} 幀", pending),
+                        );
+                    } else {
+                        if ui.button("凍結").clicked() {
+                            let end = self.capture.first_index() + self.capture.len() as u64;
+                            self.frozen = Some((now_us(), end));
+                        }
+                        if pending > 0 {
+                            ui.spinner();
+                            ui.label(format!("處理中，剩 {} 幀", pending));
+                        }
+                    }
+                });
+                if self.display_filter.ui(ui) {
+                    self.refilter();
+                }
 
                 let row_height = ui.text_style_height(&TextStyle::Body);
-                let visible_lines = 10;
+                let visible_lines = 20;
                 let scroll_height = row_height * visible_lines as f32;
 
-                ScrollArea::vertical()
-                    .id_salt("received_data_scroll")
-                    .max_height(scroll_height)
-                    .min_scrolled_height(scroll_height)
-                    .auto_shrink([true; 2])
-                    .stick_to_bottom(true)
-                    .show(ui, |ui| {
-                        ui.allocate_space(egui::vec2(ui.available_width(), scroll_height));
-                        for line in self.received_data.iter().rev().take(1000).rev() {
-                            ui.label(line);
-                        }
-                    });
+                if self.trace_fixed {
+                    ScrollArea::both()
+                        .id_salt("trace_overview_scroll")
+                        .max_height(scroll_height)
+                        .min_scrolled_height(scroll_height)
+                        .show(ui, |ui| {
+                            let now = self.frozen.map_or_else(now_us, |(at, _)| at);
+                            self.overview.ui(ui, self.dbc.as_ref(), now);
+                        });
+                } else {
+                    self.trace_table.ui(
+                        ui,
+                        &self.capture,
+                        &self.display_filter,
+                        self.dbc.as_ref(),
+                        self.j1939.enabled.then_some(&self.j1939.definitions),
+                        scroll_height,
+                    );
+                }
             });
         });
 
+        egui::Window::new("回放")
+            .open(&mut self.show_replay)
+            .show(ctx, |ui| {
+                self.replay.ui(
+                    ui,
+                    &self.capture,
+                    self.tx_sender.as_ref(),
+                    self.can_channel,
+                    &mut self.log,
+                );
+            });
+
+        egui::Window::new("曲線")
+            .open(&mut self.show_plot)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                self.plot.ui(ui, self.dbc.as_ref());
+            });
+
+        egui::Window::new("匯流排統計")
+            .open(&mut self.show_stats)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.stats.ui(ui, self.dbc.as_ref());
+            });
+
+        egui::Window::new("J1939")
+            .open(&mut self.show_j1939)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                self.j1939.ui(ui, &mut self.log);
+                ui.separator();
+                egui::CollapsingHeader::new("節點模擬").show(ui, |ui| {
+                    self.j1939_node.ui(ui, self.tx_sender.is_some(), now_us());
+                });
+            });
+
+        egui::Window::new("NMEA 2000")
+            .open(&mut self.show_nmea2000)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                self.nmea2000.ui(ui, &mut self.log);
+            });
+
+        egui::Window::new("ISO-TP")
+            .open(&mut self.show_isotp)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.isotp
+                    .ui(ui, self.tx_sender.is_some(), now_us(), &mut self.log);
+            });
+
+        egui::Window::new("UDS 診斷")
+            .open(&mut self.show_uds)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                self.uds
+                    .ui(ui, self.tx_sender.is_some(), now_us(), &mut self.log);
+            });
+
+        egui::Window::new("OBD-II")
+            .open(&mut self.show_obd)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.obd.ui(ui, self.tx_sender.is_some());
+            });
+
+        let mut apply = None;
+        egui::Window::new("ECU 掃描")
+            .open(&mut self.show_ecu_scan)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                apply = self.ecu_scan.ui(ui, self.tx_sender.is_some());
+            });
+        // 把掃到的 ID 組套用到 UDS 連線設定
+        if let Some((tx_id, rx_id, extended)) = apply {
+            let config = &mut self.uds.client.link.config;
+            config.tx_id = tx_id;
+            config.rx_id = rx_id;
+            config.extended_id = extended;
+            self.uds.client.cancel();
+            self.show_uds = true;
+        }
+
+        egui::Window::new("CANopen")
+            .open(&mut self.show_canopen)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                self.canopen.ui(ui, self.tx_sender.is_some(), now_us());
+            });
+
+        egui::Window::new("XCP")
+            .open(&mut self.show_xcp)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                self.xcp.ui(ui, self.tx_sender.is_some());
+            });
+
+        egui::Window::new("CCP")
+            .open(&mut self.show_ccp)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                self.ccp.ui(ui, self.tx_sender.is_some());
+            });
+
+        if let Some(bus) = &self.virtual_bus {
+            egui::Window::new("模擬 ECU")
+                .default_width(360.0)
+                .show(ctx, |ui| bus.ecu.ui(ui));
+        }
+
         ctx.request_repaint();
     }
+
+    fn trace_extension(&self) -> String {
+        Path::new(&self.trace_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase()
+    }
+
+    /// 顯示篩選或 DBC 改變後，清掉衍生的顯示並從頭重新掃描
+    fn refilter(&mut self) {
+        self.display_filter.reset();
+        self.plot.clear();
+        self.overview.clear();
+        self.trace_table.clear();
+        self.j1939.clear();
+        self.nmea2000.clear();
+    }
+
+    /// 只匯出符合顯示篩選的幀
+    pub fn export_trace(&mut self) {
+        let dbc = self.dbc.as_ref();
+        let filter = &self.display_filter;
+        let frames = self.capture.iter().filter(|f| filter.matches(f, dbc));
+        let count = frames.clone().count();
+        let result = File::create(&self.trace_path).and_then(|file| {
+            let mut w = BufWriter::new(file);
+            match self.trace_extension().as_str() {
+                "pcapng" => pcapng::write_pcapng(&mut w, frames),
+                "trc" => trc::write_trc(&mut w, frames, self.trc_version),
+                "mf4" | "mdf" => mdf4::write_mdf4(&mut w, frames),
+                ext => Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("不支援的檔案格式: .{}", ext),
+                )),
+            }
+        });
+        match result {
+            Ok(()) => self
+                .log
+                .push(format!("已匯出 {} 幀到 {}", count, self.trace_path)),
+            Err(e) => self.log.push(format!("匯出失敗: {}", e)),
+        }
+    }
+
+    /// 直接把接收到的幀串流寫進 MF4，不經過記憶體中的 capture
+    pub fn start_mdf_logging(&mut self) {
+        let path = Path::new(&self.trace_path).with_extension("mf4");
+        let result =
+            File::create(&path).and_then(|file| Mdf4Writer::new(BufWriter::new(file), now_us()));
+        match result {
+            Ok(logger) => {
+                self.log.push(format!("開始記錄 MF4: {}", path.display()));
+                self.mdf_logger = Some(logger);
+            }
+            Err(e) => self.log.push(format!("無法建立 MF4: {}", e)),
+        }
+    }
+
+    pub fn stop_mdf_logging(&mut self) {
+        if let Some(logger) = self.mdf_logger.take() {
+            let count = logger.frame_count();
+            let skipped = logger.skipped_count();
+            match logger.finish() {
+                Ok(_) => self.log.push(format!(
+                    "MF4 記錄完成: {} 幀 (略過遠端/錯誤幀與無法記錄的通道 {} 幀)",
+                    count, skipped
+                )),
+                Err(e) => self.log.push(format!("MF4 結束失敗: {}", e)),
+            }
+        }
+    }
+
+    pub fn import_trace(&mut self) {
+        let result = File::open(&self.trace_path).and_then(|file| {
+            let mut r = BufReader::new(file);
+            match self.trace_extension().as_str() {
+                "pcapng" => pcapng::read_pcapng(&mut r),
+                "trc" => trc::read_trc(r),
+                "mf4" | "mdf" => mdf4::read_mdf4(&mut r),
+                ext => Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("不支援的檔案格式: .{}", ext),
+                )),
+            }
+        });
+        match result {
+            Ok(frames) => {
+                self.log
+                    .push(format!("已從 {} 匯入 {} 幀", self.trace_path, frames.len()));
+                self.capture.clear();
+                self.stats.clear();
+                for frame in &frames {
+                    self.stats.update(frame);
+                }
+                self.capture.extend(frames);
+                self.refilter();
+            }
+            Err(e) => self.log.push(format!("匯入失敗: {}", e)),
+        }
+    }
 }
 
 impl eframe::App for MyApp {
@@ -0,0 +1,67 @@
+use crate::canbus::CanFrame;
+use crate::sim_ccp::SimCcp;
+use crate::sim_ecu::SimEcu;
+use crate::sim_xcp::SimXcp;
+use flume::{Receiver, Sender};
+
+/// 不接硬體的虛擬匯流排：送出的幀立刻回到接收路徑，同時交給模擬節點
+///
+/// 在 UI 執行緒上每個畫面 poll 一次，模擬節點的回應也走同一條接收路徑，
+/// 所以追蹤、統計與各協定面板看到的和接真實裝置時一樣。
+pub struct VirtualBus {
+    tx_rx: Receiver<CanFrame>,
+    pub ecu: SimEcu,
+    pub xcp: SimXcp,
+    pub ccp: SimCcp,
+}
+
+impl VirtualBus {
+    /// 回傳匯流排與給傳送路徑用的 Sender
+    pub fn start() -> (Self, Sender<CanFrame>) {
+        let (tx, tx_rx) = flume::unbounded();
+        (
+            Self {
+                tx_rx,
+                ecu: SimEcu::default(),
+                xcp: SimXcp::default(),
+                ccp: SimCcp::default(),
+            },
+            tx,
+        )
+    }
+
+    pub fn poll(&mut self, now_us: u64, channel: u32, data_tx: &Sender<CanFrame>) {
+        // 1. **送出的幀回送並交給模擬節點**
+        self.ecu.link.channel = channel;
+        self.xcp.channel = channel;
+        self.ccp.channel = channel;
+        while let Ok(frame) = self.tx_rx.try_recv() {
+            let frame = CanFrame {
+                timestamp_us: now_us,
+                ..frame
+            };
+            self.ecu.handle(&frame, now_us);
+            self.xcp.handle(&frame);
+            self.ccp.handle(&frame);
+            let _ = data_tx.send(frame);
+        }
+
+        // 2. **模擬節點的回應**
+        self.ecu.poll(now_us);
+        self.xcp.poll(now_us);
+        self.ccp.poll(now_us);
+        let outbox = self
+            .ecu
+            .link
+            .outbox
+            .drain(..)
+            .chain(self.xcp.outbox.drain(..))
+            .chain(self.ccp.outbox.drain(..));
+        for frame in outbox {
+            let _ = data_tx.send(CanFrame {
+                timestamp_us: now_us,
+                ..frame
+            });
+        }
+    }
+}
@@ -0,0 +1,1064 @@
+use crate::a2l::{A2l, CharacteristicKind, Layout};
+use crate::canbus::CanFrame;
+use crate::measurement::{
+    Access, CommandQueue, CommandResult, DaqSample, DaqVariable, OdtEntry, OdtLayout, Variable,
+    VariableTable,
+};
+use eframe::egui;
+use std::collections::VecDeque;
+
+// 命令碼 (ASAM MCD-1 XCP)
+const CMD_CONNECT: u8 = 0xFF;
+const CMD_DISCONNECT: u8 = 0xFE;
+const CMD_GET_STATUS: u8 = 0xFD;
+const CMD_SET_MTA: u8 = 0xF6;
+const CMD_SHORT_UPLOAD: u8 = 0xF4;
+const CMD_DOWNLOAD: u8 = 0xF0;
+const CMD_SHORT_DOWNLOAD: u8 = 0xED;
+const CMD_SET_DAQ_PTR: u8 = 0xE2;
+const CMD_WRITE_DAQ: u8 = 0xE1;
+const CMD_SET_DAQ_LIST_MODE: u8 = 0xE0;
+const CMD_START_STOP_DAQ_LIST: u8 = 0xDE;
+const CMD_START_STOP_SYNCH: u8 = 0xDD;
+const CMD_GET_DAQ_RESOLUTION_INFO: u8 = 0xD9;
+const CMD_FREE_DAQ: u8 = 0xD6;
+const CMD_ALLOC_DAQ: u8 = 0xD5;
+const CMD_ALLOC_ODT: u8 = 0xD4;
+const CMD_ALLOC_ODT_ENTRY: u8 = 0xD3;
+// 從站回傳的封包識別
+const PID_RESPONSE: u8 = 0xFF;
+const PID_ERROR: u8 = 0xFE;
+const PID_EVENT: u8 = 0xFD;
+const PID_SERVICE: u8 = 0xFC;
+// SET_DAQ_LIST_MODE：DAQ 封包帶時間戳記
+const DAQ_MODE_TIMESTAMP: u8 = 0x10;
+
+pub fn error_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "ERR_CMD_SYNCH",
+        0x10 => "ERR_CMD_BUSY",
+        0x11 => "ERR_DAQ_ACTIVE",
+        0x12 => "ERR_PGM_ACTIVE",
+        0x20 => "ERR_CMD_UNKNOWN",
+        0x21 => "ERR_CMD_SYNTAX",
+        0x22 => "ERR_OUT_OF_RANGE",
+        0x23 => "ERR_WRITE_PROTECTED",
+        0x24 => "ERR_ACCESS_DENIED",
+        0x25 => "ERR_ACCESS_LOCKED",
+        0x26 => "ERR_PAGE_NOT_VALID",
+        0x27 => "ERR_MODE_NOT_VALID",
+        0x28 => "ERR_SEGMENT_NOT_VALID",
+        0x29 => "ERR_SEQUENCE",
+        0x2A => "ERR_DAQ_CONFIG",
+        0x30 => "ERR_MEMORY_OVERFLOW",
+        0x31 => "ERR_GENERIC",
+        0x32 => "ERR_VERIFY",
+        _ => "未知錯誤",
+    }
+}
+
+/// CONNECT 回應的從站參數
+#[derive(Debug, Clone, Copy)]
+pub struct SlaveInfo {
+    pub resource: u8,
+    pub max_cto: u8,
+    pub max_dto: u16,
+    pub big_endian: bool,
+    pub protocol_version: u8,
+}
+
+/// 命令的用途，完成時跟結果一起回傳
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Connect,
+    Disconnect,
+    Status,
+    // 呼叫端自訂的鍵 (例如變數索引)
+    Upload(usize),
+    Download(usize),
+    // 目前開啟的標定參數整塊讀取與單格寫入
+    Calibration,
+    CalibrationWrite,
+    DaqSetup,
+    DaqStop,
+}
+
+pub type XcpResult = CommandResult<Tag>;
+
+/// 動態 DAQ 清單：ODT 配置加上時間戳記
+struct DaqLayout {
+    odts: OdtLayout,
+    // 時間戳記位元組數與每個 tick 的微秒數
+    timestamp_size: usize,
+    tick_us: f64,
+    first_pid: Option<u8>,
+    cycle_time_us: Option<u64>,
+    // 從站時間戳記展開成 64 位元
+    last_timestamp: u64,
+    wraps: u64,
+}
+
+/// XCP on CAN 主站：一次一個命令，其餘排隊；DAQ 封包解成 DaqSample
+pub struct XcpMaster {
+    pub cro_id: u32,
+    pub dto_id: u32,
+    pub extended_id: bool,
+    pub channel: u32,
+    pub timeout_ms: u64,
+    pub slave: Option<SlaveInfo>,
+    pub daq_running: bool,
+    pub outbox: Vec<CanFrame>,
+    pub completed: VecDeque<XcpResult>,
+    pub samples: VecDeque<DaqSample>,
+    pub events: Vec<String>,
+    commands: CommandQueue<Tag>,
+    daq: Option<DaqLayout>,
+    // 等 GET_DAQ_RESOLUTION_INFO 回來才能排 ODT
+    daq_request: Option<(Vec<DaqVariable>, u16, u8)>,
+}
+
+impl Default for XcpMaster {
+    fn default() -> Self {
+        Self {
+            cro_id: 0x7F0,
+            dto_id: 0x7F1,
+            extended_id: false,
+            channel: 0,
+            timeout_ms: 1000,
+            slave: None,
+            daq_running: false,
+            outbox: Vec::new(),
+            completed: VecDeque::new(),
+            samples: VecDeque::new(),
+            events: Vec::new(),
+            commands: CommandQueue::default(),
+            daq: None,
+            daq_request: None,
+        }
+    }
+}
+
+impl XcpMaster {
+    pub fn is_idle(&self) -> bool {
+        self.commands.is_idle()
+    }
+
+    fn big_endian(&self) -> bool {
+        self.slave.is_some_and(|s| s.big_endian)
+    }
+
+    fn max_cto(&self) -> usize {
+        self.slave.map_or(8, |s| s.max_cto as usize).min(8)
+    }
+
+    fn word(&self, value: u16) -> [u8; 2] {
+        if self.big_endian() {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    fn dword(&self, value: u32) -> [u8; 4] {
+        if self.big_endian() {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    }
+
+    pub fn connect(&mut self) {
+        self.commands
+            .enqueue(vec![vec![CMD_CONNECT, 0x00]], Tag::Connect);
+    }
+
+    pub fn disconnect(&mut self) {
+        self.commands
+            .enqueue(vec![vec![CMD_DISCONNECT]], Tag::Disconnect);
+    }
+
+    pub fn get_status(&mut self) {
+        self.commands
+            .enqueue(vec![vec![CMD_GET_STATUS]], Tag::Status);
+    }
+
+    /// 讀取任意長度：拆成多個 SHORT_UPLOAD
+    pub fn read(&mut self, address: u32, extension: u8, len: usize, tag: Tag) {
+        let chunk = self.max_cto() - 1;
+        let commands = (0..len)
+            .step_by(chunk)
+            .map(|offset| {
+                let n = (len - offset).min(chunk) as u8;
+                let mut command = vec![CMD_SHORT_UPLOAD, n, 0, extension];
+                command.extend_from_slice(&self.dword(address + offset as u32));
+                command
+            })
+            .collect();
+        self.commands.enqueue(commands, tag);
+    }
+
+    /// 寫入：放得下就用 SHORT_DOWNLOAD，否則 SET_MTA 加上多個 DOWNLOAD
+    pub fn write(&mut self, address: u32, extension: u8, data: &[u8], tag: Tag) {
+        let max_cto = self.max_cto();
+        let mut commands = Vec::new();
+        if data.len() + 8 <= max_cto {
+            let mut command = vec![CMD_SHORT_DOWNLOAD, data.len() as u8, 0, extension];
+            command.extend_from_slice(&self.dword(address));
+            command.extend_from_slice(data);
+            commands.push(command);
+        } else {
+            let mut command = vec![CMD_SET_MTA, 0, 0, extension];
+            command.extend_from_slice(&self.dword(address));
+            commands.push(command);
+            for chunk in data.chunks(max_cto - 2) {
+                let mut command = vec![CMD_DOWNLOAD, chunk.len() as u8];
+                command.extend_from_slice(chunk);
+                commands.push(command);
+            }
+        }
+        self.commands.enqueue(commands, tag);
+    }
+
+    /// 設定一個動態 DAQ 清單並啟動；先查詢解析度資訊再配置 ODT
+    pub fn start_daq(&mut self, variables: Vec<DaqVariable>, event: u16, prescaler: u8) {
+        self.daq = None;
+        self.daq_request = Some((variables, event, prescaler));
+        self.commands
+            .enqueue(vec![vec![CMD_GET_DAQ_RESOLUTION_INFO]], Tag::DaqSetup);
+    }
+
+    pub fn stop_daq(&mut self) {
+        self.commands
+            .enqueue(vec![vec![CMD_START_STOP_SYNCH, 0x00]], Tag::DaqStop);
+    }
+
+    // 依最大 DTO 與最大 ODT entry 長度把變數切成 ODT
+    fn configure_daq(&mut self, resolution: &[u8]) {
+        let Some((variables, event, prescaler)) = self.daq_request.take() else {
+            return;
+        };
+        let max_entry = (resolution.get(2).copied().unwrap_or(1) as usize).max(1);
+        let mode = resolution.get(5).copied().unwrap_or(0);
+        let timestamp_size = match mode & 0x07 {
+            size @ (1 | 2 | 4) => size as usize,
+            _ => 0,
+        };
+        // TIMESTAMP_TICKS 和其他多位元組欄位一樣依從站的位元組順序
+        let ticks = resolution.get(6..8).map_or(1, |b| {
+            let b = [b[0], b[1]];
+            if self.big_endian() {
+                u16::from_be_bytes(b)
+            } else {
+                u16::from_le_bytes(b)
+            }
+            .max(1)
+        });
+        // 單位欄位是 10 的次方奈秒
+        let tick_us = 10f64.powi((mode >> 4) as i32) * ticks as f64 / 1000.0;
+        let max_dto = self.slave.map_or(8, |s| s.max_dto as usize).min(8);
+
+        let mut odts: Vec<Vec<OdtEntry>> = vec![Vec::new()];
+        // 第一個 ODT 要放 PID、時間戳記與至少一個位元組的資料
+        let Some(mut room) = max_dto
+            .checked_sub(1 + timestamp_size)
+            .filter(|&room| room > 0)
+        else {
+            self.completed.push_back(XcpResult {
+                tag: Tag::DaqSetup,
+                result: Err(format!(
+                    "MAX_DTO {} 放不下 PID 與 {} bytes 時間戳記",
+                    max_dto, timestamp_size
+                )),
+            });
+            return;
+        };
+        for (index, variable) in variables.iter().enumerate() {
+            let mut offset = 0;
+            while offset < variable.size {
+                if room == 0 {
+                    odts.push(Vec::new());
+                    room = max_dto - 1;
+                }
+                let size = (variable.size - offset).min(max_entry).min(room);
+                odts.last_mut().unwrap().push(OdtEntry {
+                    variable: index,
+                    offset,
+                    size,
+                });
+                offset += size;
+                room -= size;
+            }
+        }
+
+        let daq = self.word(0);
+        let mut commands = vec![
+            vec![CMD_FREE_DAQ],
+            [&[CMD_ALLOC_DAQ, 0][..], &self.word(1)].concat(),
+        ];
+        commands.push([&[CMD_ALLOC_ODT, 0][..], &daq, &[odts.len() as u8]].concat());
+        for (odt, entries) in odts.iter().enumerate() {
+            commands.push(
+                [
+                    &[CMD_ALLOC_ODT_ENTRY, 0][..],
+                    &daq,
+                    &[odt as u8, entries.len() as u8],
+                ]
+                .concat(),
+            );
+        }
+        for (odt, entries) in odts.iter().enumerate() {
+            commands.push([&[CMD_SET_DAQ_PTR, 0][..], &daq, &[odt as u8, 0]].concat());
+            for entry in entries {
+                let variable = &variables[entry.variable];
+                let mut command = vec![CMD_WRITE_DAQ, 0xFF, entry.size as u8, variable.extension];
+                command.extend_from_slice(&self.dword(variable.address + entry.offset as u32));
+                commands.push(command);
+            }
+        }
+        let mode = if timestamp_size > 0 {
+            DAQ_MODE_TIMESTAMP
+        } else {
+            0
+        };
+        commands.push(
+            [
+                &[CMD_SET_DAQ_LIST_MODE, mode][..],
+                &daq,
+                &self.word(event),
+                &[prescaler.max(1), 0],
+            ]
+            .concat(),
+        );
+        commands.push([&[CMD_START_STOP_DAQ_LIST, 0x02][..], &daq].concat());
+        commands.push(vec![CMD_START_STOP_SYNCH, 0x01]);
+
+        self.daq = Some(DaqLayout {
+            odts: OdtLayout::new(variables, odts),
+            timestamp_size,
+            tick_us,
+            first_pid: None,
+            cycle_time_us: None,
+            last_timestamp: 0,
+            wraps: 0,
+        });
+        self.commands.enqueue(commands, Tag::DaqSetup);
+    }
+
+    pub fn handle(&mut self, frame: &CanFrame) {
+        if frame.channel != self.channel
+            || frame.id != self.dto_id
+            || frame.extended != self.extended_id
+            || frame.remote
+            || frame.error
+        {
+            return;
+        }
+        let data = frame.payload();
+        let Some(&pid) = data.first() else {
+            return;
+        };
+        match pid {
+            PID_RESPONSE | PID_ERROR => self.on_response(data),
+            PID_EVENT => self.events.push(format!(
+                "XCP 事件 0x{:02X}",
+                data.get(1).copied().unwrap_or(0)
+            )),
+            PID_SERVICE => self.events.push(format!(
+                "XCP 服務請求 0x{:02X}",
+                data.get(1).copied().unwrap_or(0)
+            )),
+            _ => self.on_daq(pid, data, frame.timestamp_us),
+        }
+    }
+
+    fn on_response(&mut self, data: &[u8]) {
+        let Some(queued) = self.commands.complete() else {
+            return;
+        };
+        if data[0] == PID_ERROR {
+            let code = data.get(1).copied().unwrap_or(0xFF);
+            self.commands.abort(&queued);
+            if queued.tag == Tag::DaqSetup {
+                self.daq = None;
+            }
+            self.completed.push_back(XcpResult {
+                tag: queued.tag,
+                result: Err(format!(
+                    "0x{:02X} {} (命令 0x{:02X})",
+                    code,
+                    error_name(code),
+                    queued.command[0]
+                )),
+            });
+            return;
+        }
+
+        // 1. **主站自己需要的回應**
+        match queued.command[0] {
+            CMD_CONNECT if data.len() >= 8 => {
+                let big_endian = data[2] & 0x01 != 0;
+                let max_dto = if big_endian {
+                    u16::from_be_bytes([data[4], data[5]])
+                } else {
+                    u16::from_le_bytes([data[4], data[5]])
+                };
+                self.slave = Some(SlaveInfo {
+                    resource: data[1],
+                    max_cto: data[3],
+                    max_dto,
+                    big_endian,
+                    protocol_version: data[6],
+                });
+                self.daq_running = false;
+            }
+            CMD_DISCONNECT => {
+                self.slave = None;
+                self.daq_running = false;
+            }
+            CMD_GET_DAQ_RESOLUTION_INFO => self.configure_daq(data),
+            CMD_START_STOP_DAQ_LIST if data.len() >= 2 => {
+                if let Some(daq) = self.daq.as_mut() {
+                    daq.first_pid = Some(data[1]);
+                }
+            }
+            CMD_START_STOP_SYNCH => self.daq_running = queued.command[1] == 0x01,
+            _ => {}
+        }
+
+        // 2. **回報**
+        let upload = queued.command[0] == CMD_SHORT_UPLOAD;
+        if upload {
+            // SHORT_UPLOAD 回應可能補滿 8 bytes，只取要求的長度
+            let requested = (queued.command[1] as usize).min(data.len() - 1);
+            self.commands
+                .accumulated
+                .extend_from_slice(&data[1..1 + requested]);
+        }
+        // GET_DAQ_RESOLUTION_INFO 之後的設定命令才是這組的結尾
+        if queued.last && queued.command[0] != CMD_GET_DAQ_RESOLUTION_INFO {
+            let result = if upload {
+                std::mem::take(&mut self.commands.accumulated)
+            } else {
+                data.to_vec()
+            };
+            self.completed.push_back(XcpResult {
+                tag: queued.tag,
+                result: Ok(result),
+            });
+        }
+    }
+
+    fn on_daq(&mut self, pid: u8, data: &[u8], host_time_us: u64) {
+        let big_endian = self.big_endian();
+        let Some(daq) = self.daq.as_mut() else {
+            return;
+        };
+        let Some(first_pid) = daq.first_pid else {
+            return;
+        };
+        let Some(odt) = pid.checked_sub(first_pid).map(|o| o as usize) else {
+            return;
+        };
+        if odt >= daq.odts.odt_count() {
+            return;
+        }
+        let mut payload = &data[1..];
+        if odt == 0 {
+            daq.cycle_time_us = None;
+            if daq.timestamp_size > 0 {
+                let Some(bytes) = payload.get(..daq.timestamp_size) else {
+                    return;
+                };
+                let mut raw = [0u8; 8];
+                raw[..bytes.len()].copy_from_slice(bytes);
+                let mut timestamp = u64::from_le_bytes(raw);
+                if big_endian {
+                    raw[..bytes.len()].reverse();
+                    timestamp = u64::from_le_bytes(raw);
+                }
+                // 時間戳記會繞回，記錄繞回次數展開
+                if timestamp < daq.last_timestamp {
+                    daq.wraps += 1;
+                }
+                daq.last_timestamp = timestamp;
+                let ticks = timestamp + (daq.wraps << (daq.timestamp_size * 8));
+                daq.cycle_time_us = Some((ticks as f64 * daq.tick_us) as u64);
+                payload = &payload[daq.timestamp_size..];
+            }
+        }
+        daq.odts.feed(
+            odt,
+            payload,
+            daq.cycle_time_us,
+            host_time_us,
+            &mut self.samples,
+        );
+    }
+
+    /// 每個畫面呼叫一次：檢查逾時並送出下一個命令
+    pub fn poll(&mut self, now_us: u64) {
+        let Some((command, _)) = self
+            .commands
+            .poll(now_us, self.timeout_ms, &mut self.completed)
+        else {
+            return;
+        };
+        let mut data = [0u8; 8];
+        data[..command.len()].copy_from_slice(command);
+        self.outbox.push(CanFrame {
+            channel: self.channel,
+            id: self.cro_id,
+            extended: self.extended_id,
+            len: 8,
+            data,
+            ..Default::default()
+        });
+    }
+}
+
+/// 開啟中的標定參數
+struct CalibrationEditor {
+    // A2L 中 CHARACTERISTIC 的索引
+    index: usize,
+    layout: Layout,
+    data: Option<Vec<u8>>,
+    selected: (usize, usize),
+    edit_text: String,
+}
+
+/// XCP 面板：連線、變數表、輪詢與 DAQ
+pub struct XcpPanel {
+    pub master: XcpMaster,
+    pub table: VariableTable,
+    event_channel: u16,
+    prescaler: u8,
+    status: String,
+    a2l_path: String,
+    a2l: Option<A2l>,
+    a2l_filter: String,
+    calibration: Option<CalibrationEditor>,
+}
+
+impl Default for XcpPanel {
+    fn default() -> Self {
+        Self {
+            master: XcpMaster::default(),
+            table: VariableTable::default(),
+            event_channel: 0,
+            prescaler: 1,
+            status: String::new(),
+            a2l_path: String::new(),
+            a2l: None,
+            a2l_filter: String::new(),
+            calibration: None,
+        }
+    }
+}
+
+impl XcpPanel {
+    /// 處理完成的命令與 DAQ 樣本
+    pub fn process(&mut self, now_us: u64, log: &mut Vec<String>) {
+        log.append(&mut self.master.events);
+        let big_endian = self.master.slave.is_some_and(|s| s.big_endian);
+        while let Some(result) = self.master.completed.pop_front() {
+            match (&result.tag, &result.result) {
+                (tag, Err(e)) => {
+                    log.push(format!("XCP {:?}: {}", tag, e));
+                    self.table.report(format!("{:?}: {}", tag, e));
+                }
+                (Tag::Upload(key), Ok(data)) => {
+                    self.table.set_value(*key, data, big_endian, now_us)
+                }
+                (Tag::Connect, Ok(_)) => {
+                    if let Some(slave) = self.master.slave {
+                        self.status = format!(
+                            "已連線: 資源 0x{:02X}，MAX_CTO {}，MAX_DTO {}，{}，協定版本 {}",
+                            slave.resource,
+                            slave.max_cto,
+                            slave.max_dto,
+                            if slave.big_endian {
+                                "Motorola"
+                            } else {
+                                "Intel"
+                            },
+                            slave.protocol_version
+                        );
+                    }
+                }
+                (Tag::Calibration, Ok(data)) => {
+                    if let Some(editor) = self.calibration.as_mut() {
+                        editor.data = Some(data.clone());
+                    }
+                }
+                (Tag::Disconnect, Ok(_)) => self.status = "未連線".to_string(),
+                (Tag::Status, Ok(data)) => {
+                    let session = data.get(1).copied().unwrap_or(0);
+                    self.status = format!(
+                        "工作階段狀態 0x{:02X}{}，資源保護 0x{:02X}",
+                        session,
+                        if session & 0x40 != 0 {
+                            " (DAQ 執行中)"
+                        } else {
+                            ""
+                        },
+                        data.get(2).copied().unwrap_or(0)
+                    );
+                }
+                (tag, Ok(_)) => self.table.report(format!("{:?}: 完成", tag)),
+            }
+        }
+
+        self.table
+            .apply_samples(&mut self.master.samples, big_endian);
+        let accesses = self.table.poll(now_us, self.master.is_idle());
+        self.access(accesses);
+    }
+
+    fn access(&mut self, accesses: Vec<Access>) {
+        for access in accesses {
+            match access {
+                Access::Read {
+                    key,
+                    address,
+                    extension,
+                    size,
+                } => self.master.read(address, extension, size, Tag::Upload(key)),
+                Access::Write {
+                    key,
+                    address,
+                    extension,
+                    data,
+                } => self
+                    .master
+                    .write(address, extension, &data, Tag::Download(key)),
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        // 1. **連線**
+        let connected = self.master.slave.is_some();
+        ui.add_enabled_ui(!connected, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("CRO ID:");
+                ui.add(egui::DragValue::new(&mut self.master.cro_id).hexadecimal(3, false, true));
+                ui.label("DTO ID:");
+                ui.add(egui::DragValue::new(&mut self.master.dto_id).hexadecimal(3, false, true));
+                ui.checkbox(&mut self.master.extended_id, "29 位元 ID");
+                ui.label("逾時:");
+                ui.add(
+                    egui::DragValue::new(&mut self.master.timeout_ms)
+                        .range(10..=10_000)
+                        .suffix(" ms"),
+                );
+            });
+        });
+        ui.add_enabled_ui(can_send, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("CONNECT").clicked() {
+                    self.master.connect();
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("GET_STATUS"))
+                    .clicked()
+                {
+                    self.master.get_status();
+                }
+                if ui
+                    .add_enabled(connected, egui::Button::new("DISCONNECT"))
+                    .clicked()
+                {
+                    self.table.polling = false;
+                    self.master.disconnect();
+                }
+                if !self.master.is_idle() {
+                    ui.spinner();
+                }
+            });
+        });
+        ui.label(&self.status);
+
+        // 2. **變數**
+        ui.separator();
+        let big_endian = self.master.slave.is_some_and(|s| s.big_endian);
+        let accesses = self.table.ui(
+            ui,
+            "xcp",
+            self.master.daq_running,
+            can_send && connected,
+            big_endian,
+        );
+        self.access(accesses);
+
+        // 3. **輪詢與 DAQ**
+        ui.separator();
+        ui.add_enabled_ui(can_send && connected, |ui| {
+            self.table.polling_ui(ui, "SHORT_UPLOAD 輪詢");
+            ui.horizontal(|ui| {
+                ui.label("事件通道:");
+                ui.add(egui::DragValue::new(&mut self.event_channel));
+                if let Some(event) = self
+                    .a2l
+                    .as_ref()
+                    .and_then(|a2l| a2l.events.iter().find(|e| e.channel == self.event_channel))
+                {
+                    ui.label(&event.name);
+                }
+                ui.label("預分頻:");
+                ui.add(egui::DragValue::new(&mut self.prescaler).range(1..=255));
+                if self.master.daq_running {
+                    if ui.button("停止 DAQ").clicked() {
+                        self.master.stop_daq();
+                    }
+                } else if ui.button("設定並啟動 DAQ").clicked() {
+                    let variables = self.table.daq_variables();
+                    if !variables.is_empty() {
+                        self.master
+                            .start_daq(variables, self.event_channel, self.prescaler);
+                    }
+                }
+            });
+        });
+        self.table.history_ui(ui, "xcp");
+
+        // 4. **A2L**
+        egui::CollapsingHeader::new("A2L")
+            .default_open(false)
+            .show(ui, |ui| self.a2l_ui(ui, can_send && connected));
+        if self.calibration.is_some() {
+            egui::CollapsingHeader::new("標定")
+                .default_open(true)
+                .show(ui, |ui| self.calibration_ui(ui, can_send && connected));
+        }
+    }
+
+    fn a2l_ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        ui.horizontal(|ui| {
+            ui.label("A2L:");
+            ui.text_edit_singleline(&mut self.a2l_path);
+            if ui.button("載入").clicked() {
+                match A2l::load(&self.a2l_path) {
+                    Ok(a2l) => {
+                        self.master.events.push(format!(
+                            "XCP: A2L 已載入: {} 個量測值，{} 個標定參數",
+                            a2l.measurements.len(),
+                            a2l.characteristics.len()
+                        ));
+                        for warning in &a2l.warnings {
+                            self.master.events.push(format!("XCP: {}", warning));
+                        }
+                        // IF_DATA 有 XCP on CAN 設定就直接套用
+                        if let Some((cro_id, dto_id, extended)) = a2l.can_ids {
+                            if self.master.slave.is_none() {
+                                self.master.cro_id = cro_id;
+                                self.master.dto_id = dto_id;
+                                self.master.extended_id = extended;
+                            }
+                        }
+                        self.a2l = Some(a2l);
+                        self.calibration = None;
+                    }
+                    Err(e) => self.master.events.push(format!("XCP: {}", e)),
+                }
+            }
+        });
+        let Some(a2l) = &self.a2l else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("篩選:");
+            ui.text_edit_singleline(&mut self.a2l_filter);
+        });
+        let filter = self.a2l_filter.to_lowercase();
+        let matches = |name: &str| filter.is_empty() || name.to_lowercase().contains(&filter);
+
+        ui.strong("量測值");
+        egui::ScrollArea::vertical()
+            .id_salt("xcp_a2l_measurements")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                for measurement in a2l.measurements.iter().filter(|m| matches(&m.name)) {
+                    ui.horizontal(|ui| {
+                        let added = self
+                            .table
+                            .variables
+                            .iter()
+                            .any(|v| v.name == measurement.name);
+                        if ui
+                            .add_enabled(
+                                !added && !self.master.daq_running,
+                                egui::Button::new("加入"),
+                            )
+                            .clicked()
+                        {
+                            let mut variable = Variable::new(
+                                measurement.name.clone(),
+                                measurement.address,
+                                measurement.extension,
+                                measurement.value_type,
+                            );
+                            variable.conversion = Some(a2l.compu_method(&measurement.conversion));
+                            self.table.add(variable);
+                        }
+                        ui.label(&measurement.name)
+                            .on_hover_text(&measurement.description);
+                        ui.monospace(format!(
+                            "{:X}:{:08X} {}",
+                            measurement.extension,
+                            measurement.address,
+                            measurement.value_type.name()
+                        ));
+                    });
+                }
+            });
+
+        ui.strong("標定參數");
+        egui::ScrollArea::vertical()
+            .id_salt("xcp_a2l_characteristics")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                for (index, characteristic) in a2l.characteristics.iter().enumerate() {
+                    if !matches(&characteristic.name) {
+                        continue;
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(can_send, egui::Button::new("開啟"))
+                            .clicked()
+                        {
+                            match a2l.layout(characteristic) {
+                                Ok(layout) => {
+                                    self.master.read(
+                                        characteristic.address,
+                                        characteristic.extension,
+                                        layout.size,
+                                        Tag::Calibration,
+                                    );
+                                    self.calibration = Some(CalibrationEditor {
+                                        index,
+                                        layout,
+                                        data: None,
+                                        selected: (0, 0),
+                                        edit_text: String::new(),
+                                    });
+                                }
+                                Err(e) => {
+                                    self.table.report(format!("{}: {}", characteristic.name, e))
+                                }
+                            }
+                        }
+                        ui.label(&characteristic.name)
+                            .on_hover_text(&characteristic.description);
+                        ui.label(format!("{:?}", characteristic.kind));
+                        ui.monospace(format!(
+                            "{:X}:{:08X}",
+                            characteristic.extension, characteristic.address
+                        ));
+                    });
+                }
+            });
+    }
+
+    fn calibration_ui(&mut self, ui: &mut egui::Ui, can_send: bool) {
+        let (Some(a2l), Some(editor)) = (&self.a2l, self.calibration.as_mut()) else {
+            return;
+        };
+        let characteristic = &a2l.characteristics[editor.index];
+        let conversion = a2l.compu_method(&characteristic.conversion);
+        let big_endian = self.master.slave.is_some_and(|s| s.big_endian);
+        ui.horizontal(|ui| {
+            ui.strong(&characteristic.name);
+            ui.label(&characteristic.description);
+            if ui
+                .add_enabled(can_send, egui::Button::new("重新讀取"))
+                .clicked()
+            {
+                self.master.read(
+                    characteristic.address,
+                    characteristic.extension,
+                    editor.layout.size,
+                    Tag::Calibration,
+                );
+            }
+        });
+        let Some(data) = &editor.data else {
+            ui.spinner();
+            return;
+        };
+
+        // 1. **軸點：固定軸、存在參數裡的軸，都沒有時用索引**
+        let layout = &editor.layout;
+        let axis_labels = |axis: usize| -> Vec<String> {
+            let count = layout.count(data, axis, big_endian);
+            let Some(descr) = characteristic.axes.get(axis) else {
+                return (0..count).map(|i| i.to_string()).collect();
+            };
+            let axis_conversion = a2l.compu_method(&descr.conversion);
+            let raw = descr
+                .fixed
+                .clone()
+                .or_else(|| layout.axis(data, axis, big_endian));
+            match raw {
+                Some(raw) => raw
+                    .iter()
+                    .take(count)
+                    .map(|&v| axis_conversion.format(v))
+                    .collect(),
+                None => (0..count).map(|i| i.to_string()).collect(),
+            }
+        };
+        let x_labels = axis_labels(0);
+        let y_labels = if characteristic.kind == CharacteristicKind::Map {
+            axis_labels(1)
+        } else {
+            vec![String::new()]
+        };
+
+        // 2. **數值表格**
+        egui::ScrollArea::both()
+            .id_salt("xcp_calibration")
+            .max_height(260.0)
+            .show(ui, |ui| {
+                egui::Grid::new("xcp_calibration_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        for label in &x_labels {
+                            ui.strong(label);
+                        }
+                        ui.end_row();
+                        for (y, y_label) in y_labels.iter().enumerate() {
+                            ui.strong(y_label);
+                            for x in 0..x_labels.len() {
+                                let text = layout
+                                    .value(data, x, y, big_endian)
+                                    .map_or("-".to_string(), |v| conversion.format(v));
+                                if ui
+                                    .selectable_label(editor.selected == (x, y), text)
+                                    .clicked()
+                                {
+                                    editor.selected = (x, y);
+                                    editor.edit_text = layout.value(data, x, y, big_endian).map_or(
+                                        String::new(),
+                                        |v| match conversion.decimals {
+                                            Some(decimals) => {
+                                                format!(
+                                                    "{:.*}",
+                                                    decimals,
+                                                    conversion.to_physical(v)
+                                                )
+                                            }
+                                            None => conversion.to_physical(v).to_string(),
+                                        },
+                                    );
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        // 3. **寫入選取的格子，寫完整塊讀回**
+        ui.horizontal(|ui| {
+            let (x, y) = editor.selected;
+            ui.label(format!("[{}, {}]", x, y));
+            ui.add(egui::TextEdit::singleline(&mut editor.edit_text).desired_width(100.0));
+            ui.label(&conversion.unit);
+            if ui
+                .add_enabled(can_send, egui::Button::new("寫入"))
+                .clicked()
+            {
+                let physical = conversion
+                    .parse(&editor.edit_text)
+                    .map(|raw| (raw, conversion.to_physical(raw)));
+                match physical {
+                    Some((raw, physical))
+                        if (characteristic.lower..=characteristic.upper).contains(&physical) =>
+                    {
+                        let bytes = layout.value_type.encode(raw, big_endian);
+                        self.master.write(
+                            characteristic.address + layout.value_offset(x, y) as u32,
+                            characteristic.extension,
+                            &bytes,
+                            Tag::CalibrationWrite,
+                        );
+                        self.master.read(
+                            characteristic.address,
+                            characteristic.extension,
+                            layout.size,
+                            Tag::Calibration,
+                        );
+                    }
+                    Some((_, physical)) => self.table.report(format!(
+                        "{}: {} 超出範圍 {} ~ {}",
+                        characteristic.name, physical, characteristic.lower, characteristic.upper
+                    )),
+                    None => self.table.report(format!(
+                        "{}: 無效的數值 {}",
+                        characteristic.name, editor.edit_text
+                    )),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn master(max_dto: u16, big_endian: bool, variables: Vec<DaqVariable>) -> XcpMaster {
+        XcpMaster {
+            slave: Some(SlaveInfo {
+                resource: 0x05,
+                max_cto: 8,
+                max_dto,
+                big_endian,
+                protocol_version: 1,
+            }),
+            daq_request: Some((variables, 0, 1)),
+            ..Default::default()
+        }
+    }
+
+    fn tick_us(big_endian: bool, resolution: &[u8]) -> f64 {
+        let mut master = master(8, big_endian, Vec::new());
+        master.configure_daq(resolution);
+        master.daq.unwrap().tick_us
+    }
+
+    #[test]
+    fn timestamp_ticks_follow_byte_order() {
+        // 4 bytes 時間戳記，單位 1 µs，每 tick 0x0102 個單位
+        let intel = [0xFF, 1, 7, 1, 7, 0x34, 0x02, 0x01];
+        let motorola = [0xFF, 1, 7, 1, 7, 0x34, 0x01, 0x02];
+        assert_eq!(tick_us(false, &intel), 258.0);
+        assert_eq!(tick_us(true, &motorola), 258.0);
+        // 0 tick 當成 1
+        assert_eq!(tick_us(true, &[0xFF, 1, 7, 1, 7, 0x34, 0, 0]), 1.0);
+    }
+
+    #[test]
+    fn small_max_dto_is_rejected() {
+        let variable = DaqVariable {
+            key: 0,
+            address: 0x1000,
+            extension: 0,
+            size: 4,
+        };
+        // 4 bytes 時間戳記加 PID 已經佔滿 5 bytes 的 DTO
+        for max_dto in [0, 1, 5] {
+            let mut master = master(max_dto, false, vec![variable]);
+            master.configure_daq(&[0xFF, 1, 7, 1, 7, 0x34, 1, 0]);
+            assert!(master.daq.is_none());
+            assert!(master.is_idle());
+            let result = master.completed.pop_front().unwrap();
+            assert_eq!(result.tag, Tag::DaqSetup);
+            assert!(result.result.unwrap_err().contains("MAX_DTO"));
+        }
+        // 沒有時間戳記時 2 bytes 的 DTO 每個 ODT 放一個位元組
+        let mut master = master(2, false, vec![variable]);
+        master.configure_daq(&[0xFF, 1, 7, 1, 7, 0, 1, 0]);
+        assert_eq!(master.daq.unwrap().odts.odt_count(), 4);
+    }
+}